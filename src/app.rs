@@ -1,42 +1,137 @@
-use crate::Cli;
+mod bench;
+mod commands;
+mod control;
+mod dashboard;
+mod deploy;
+mod migrate;
+mod remote;
+mod settings;
+mod sharing;
+mod site;
+mod users;
+mod validate;
+
+use crate::{
+    askpass,
+    backup::backup_config,
+    challenge::Challenge,
+    daemon::{remove_state, running_shares},
+    desktop,
+    dns::{DnsConfig, DnsRecord},
+    encryption,
+    endpoint::Endpoint,
+    error::{Error, Result},
+    external::ExternalServer,
+    host::parse_host,
+    linelog::LineLog,
+    logging,
+    login::Sessions,
+    project::{apply_project_config, find_project_config},
+    redirects::{Pattern, Redirects},
+    relay::{parse_size, Relay},
+    schedule::Schedule,
+    secrets,
+    server::{DownloadLimit, LiveReload, ServeOptions, Server, Uploads, Waker},
+    ssh_config,
+    stats::AccessStats,
+    vault,
+    watermark::Watermark,
+    webhooks::{NotificationConfig, Notifier},
+    Cli,
+};
+use commands::{
+    deserialize_commands, run_after_commands, run_before_commands, ConnectCommand, FailurePolicy,
+};
+pub use deploy::DeployMethod;
+use deploy::DeployOptions;
+use migrate::{parse_value, CONFIG_VERSION};
+use remote::ReverseProxy;
+use site::SiteBuild;
+use validate::expand_home;
 
 use std::{
     sync::OnceLock,
-    env::current_dir,
-    fmt::{Display, Formatter, Result},
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    path::PathBuf,
-    process::{exit, Child, Command},
+    collections::BTreeMap,
+    env::{current_dir, split_paths, temp_dir, var, var_os},
+    ffi::{OsStr, OsString},
+    fmt::{self, Display, Formatter},
+    fs::{create_dir_all, remove_file, File},
+    io::{copy, stdin, IsTerminal},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, ToSocketAddrs},
+    path::{Component, Path, PathBuf},
+    process::Command,
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::SyncSender,
         Arc,
     },
     thread::sleep,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use confy::{get_configuration_file_path, load, store};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use arboard::Clipboard;
+use confy::{get_configuration_file_path, load_path, ConfyError};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use humantime::{format_duration, parse_duration};
+use indicatif::{DecimalBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use inquire::{
     validator::{Validation, ValueRequiredValidator},
-    Confirm, CustomType, Editor, MultiSelect, Password, Text,
+    Confirm, CustomType, Editor, InquireError, MultiSelect, Password, Select, Text,
 };
-
-use openssh::{Session, SessionBuilder, Socket::TcpSocket};
+use jiff::Zoned;
+use openssh::{
+    Session, SessionBuilder,
+    Socket::{self, TcpSocket},
+};
+use qrcode::{render::unicode::Dense1x2, QrCode};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sha2::{Digest, Sha512};
-use tokio::runtime::Runtime;
+use tempfile::TempDir;
+use tokio::{runtime::Runtime, sync::Notify, time::timeout};
+use tracing::{debug, info, warn};
 
 static INFO_TEMPLATE: OnceLock::<ProgressStyle> = OnceLock::new();
 static WARNING_TEMPLATE: OnceLock::<ProgressStyle> = OnceLock::new();
 static SUCCESS_TEMPLATE: OnceLock::<ProgressStyle> = OnceLock::new();
-
-#[derive(Default, Debug, Serialize, Deserialize)]
+// Log plain lines instead of drawing spinners (--non-interactive, -v):
+static PLAIN_OUTPUT: OnceLock<bool> = OnceLock::new();
+// Config file given with --config, instead of confy's default location:
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// Server command used for `external_server`:
+// -H = show hidden files
+// -i = which network interface to use
+// -p port
+// -v = log requests, on stdout (logged, and written to `server_access_log`)
+// -u allows uploads, last so its optional value isn't taken from {dir}
+const MINISERVE_COMMAND: &str = "miniserve -H -v -i 127.0.0.1 -p {port} {auth} {dir} {upload}";
+// Request bodies the builtin server takes without --receive, unless `[serve] max_request_body`:
+const DEFAULT_MAX_REQUEST_BODY: u64 = 10_000_000;
+// Bits of a [serve] challenge: less stops nobody, more takes phones minutes:
+const CHALLENGE_BITS: std::ops::RangeInclusive<u32> = 8..=24;
+// How long a login of the [serve] login_page lasts, unless `login_session`:
+const LOGIN_SESSION: Duration = Duration::from_secs(12 * 60 * 60);
+// Last entry of the host picker, asks for one that ssh doesn't know yet:
+const OTHER_HOST: &str = "Another host…";
+
+// How long before the end of --duration a desktop notification warns about it:
+const DURATION_WARNING: Duration = Duration::from_secs(5 * 60);
+// Name of the SSH password in the keyring, with `keyring = true` and `password_auth = true`:
+const SSH_PASSWORD_SECRET: &str = "ssh/password";
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 struct Config {
-    // Commands that should be run locally before making the SSH-connection:
-    before_commands: Option<Vec<(String, String)>>,
+    // Schema of this file, older ones are upgraded when read (see migrate.rs):
+    version: Option<u32>,
+
+    // Commands that should be run locally before making the SSH-connection, each with its
+    // working directory and environment (see commands.rs):
+    #[serde(default, deserialize_with = "deserialize_commands")]
+    before_commands: Option<Vec<ConnectCommand>>,
     // Commands that should be run remotely after making the SSH-connection:
-    after_commands: Option<Vec<(String, String)>>,
+    #[serde(default, deserialize_with = "deserialize_commands")]
+    after_commands: Option<Vec<ConnectCommand>>,
 
     // SSH settings:
     host: String,
@@ -48,19 +143,315 @@ struct Config {
     // Port forwards:
     local_port: u16,
     remote_port: u16,
+    // Unix socket on the remote to forward to instead of remote_port, e.g. for a reverse proxy
+    // (its directory is created and a stale socket replaced):
+    remote_socket: Option<String>,
+    // Unix socket the builtin server listens on instead of local_port, which only the current
+    // user may connect to, e.g. "/run/user/1000/livetunnel.sock". The forward leads there, so
+    // other users of a shared machine can't reach the server around the login:
+    local_socket: Option<PathBuf>,
+
+    // Remote Ports (first, last) to pick a free one from when remote_port is taken, without it
+    // livetunnel stops instead:
+    remote_port_range: Option<(u16, u16)>,
 
     // users for auth:
     users: Vec<(String, String)>,
+    // Keep the password hashes of users, the DNS token and the SSH password (once asked for) in
+    // the OS keyring, the config only refers to them as "keyring:<name>" (default: false):
+    keyring: Option<bool>,
+
+    // Reverse proxy on the remote (see `livetunnel setup-remote`):
+    domain: Option<String>,
+    // Where the share can be reached from outside, with an optional path prefix, placeholders:
+    // {domain}, {port} (default: https://{domain}/ once a domain is set):
+    public_url: Option<String>,
+    // Show a QR code of it when sharing, e.g. to open it on a phone (default: false, --qr):
+    qr_code: Option<bool>,
+    // Desktop notifications when the tunnel is up, the SSH connection drops or reconnects and
+    // before `duration` ends (default: false, --notify):
+    desktop_notifications: Option<bool>,
+
+    // Heartbeat pings for uptime monitoring (healthchecks.io and the like):
+    heartbeat_url: Option<String>,
+    heartbeat_interval: Option<u64>,
+
+    // Stop sharing after this many bytes went through the tunnel:
+    transfer_cap: Option<u64>,
+    // Stop sharing after this long, e.g. "8h" (--duration for a single run):
+    duration: Option<String>,
+
+    // More ports forwarded through the same SSH connection as (local Port, remote Port), e.g. an
+    // API or websocket backend next to the shared files:
+    forwards: Option<Vec<(u16, u16)>>,
+
+    // Remote directory for `livetunnel deploy`:
+    deploy_path: Option<String>,
+    // How `livetunnel deploy` copies (default: "tar"), whether it deletes remote files that are
+    // gone locally (rsync only, default: false) and what it leaves out (e.g. ["*.log", ".git"]):
+    deploy_method: Option<DeployMethod>,
+    deploy_delete: Option<bool>,
+    deploy_exclude: Option<Vec<String>>,
+    // Request the share through every hop once it's up, like --check (default: false):
+    check_share: Option<bool>,
+    // Sync to deploy_path before sharing and on close, like --hybrid (default: false):
+    hybrid: Option<bool>,
+    // Write a vhost for the domain to "nginx" or "caddy" on the remote for every share and
+    // remove it on close (default: none, see `livetunnel setup-remote`):
+    provision_proxy: Option<ReverseProxy>,
+    // Let's Encrypt account, certbot gets a certificate for the domain with it (setup-remote,
+    // and every share with provision_proxy = "nginx"; Caddy does it on its own):
+    certbot_email: Option<String>,
+
+    // How often to try reconnecting when the SSH connection drops (default: 5, 0 disables):
+    reconnect_attempts: Option<u32>,
+    // What to do once those attempts failed without a terminal to ask: "quit" (default) or
+    // "retry" to keep trying until it's back:
+    on_disconnect: Option<DisconnectPolicy>,
+
+    // Seconds between keepalives through the SSH connection, so NATs and firewalls don't drop
+    // it while idle (default: 30, 0 disables):
+    keepalive_interval: Option<u64>,
+    // Unanswered keepalives before the connection counts as dead, for ssh's own ones as well
+    // (default: 3):
+    keepalive_count_max: Option<u32>,
+
+    // Log in with a password (asked for on start or read from LIVETUNNEL_SSH_PASSWORD) for
+    // servers without key authentication:
+    password_auth: Option<bool>,
+    // The password for it instead of asking, best as a reference so it's never on disk:
+    // "op://vault/item/field" (1Password CLI) or "bw://item/field" (Bitwarden CLI):
+    ssh_password: Option<String>,
+
+    // Serve with miniserve instead of the builtin server (default: false):
+    external_server: Option<bool>,
+
+    // External server to run instead, placeholders: {port}, {dir}, {auth}, {upload}
+    // (e.g. "python3 -m http.server {port} --bind 127.0.0.1 --directory {dir}"):
+    server_command: Option<String>,
+
+    // Uploads with --receive: the largest file in bytes (builtin server only) and the folders of
+    // the shared directory that take them (default: all):
+    upload_max_size: Option<u64>,
+    upload_folders: Option<Vec<PathBuf>>,
+
+    // Append the requests the external server logs to this file, e.g. miniserve's access log
+    // (all of its stdout and stderr is logged with -v or log_file either way):
+    server_access_log: Option<PathBuf>,
+
+    // How often the server is restarted when it exits (default: 3, 0 disables):
+    miniserve_restarts: Option<u32>,
+
+    // Build with the static site generator before sharing, like --build (default: false):
+    build: Option<bool>,
+    // Instead of the detected Hugo/Jekyll/Zola/mdBook preset: what to run in the shared
+    // directory and where it puts the site, relative to the shared directory:
+    build_command: Option<String>,
+    build_output: Option<PathBuf>,
+
+    // Editor for the setup assistant (default: $VISUAL, $EDITOR, then nano/vi/notepad):
+    editor: Option<String>,
+
+    // Log SSH events, command output and the server to this file as well, like --log-file
+    // (rotated at 10 MB, the last 3 are kept as .1 to .3):
+    log_file: Option<PathBuf>,
+
+    // A random subdomain per share, as [subdomains] section (TOML needs tables after plain
+    // values):
+    subdomains: Option<SubdomainConfig>,
+
+    // A DNS record for the domain of each share, removed on close, as [dns] section:
+    dns: Option<DnsConfig>,
+
+    // Webhooks (Slack, Discord or any JSON endpoint) told when a share starts, reconnects, its
+    // server crashes and when it stops, as [notifications] section:
+    notifications: Option<NotificationConfig>,
+
+    // Settings of the builtin server, as [serve] section:
+    serve: Option<ServeConfig>,
+
+    // Rebuild while sharing, as [watch] section (last, TOML needs tables after plain values):
+    watch: Option<WatchConfig>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SubdomainConfig {
+    // Shares are served on a random subdomain of it, e.g. "share.example.com" for
+    // "k7q2mx.share.example.com". Needs a wildcard DNS record pointing at the server:
+    base_domain: String,
+    // Gets a vhost for each share, removed on close: "nginx" or "caddy":
+    proxy: ReverseProxy,
+    // Instead of the builtin vhost, placeholders: {domain}, {upstream}:
+    template: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct ServeConfig {
+    // The reverse proxy talks HTTP/2 without TLS (h2c) to the builtin server, so assets load
+    // multiplexed through the tunnel. Caddy only, nginx can't (default: false):
+    http2: Option<bool>,
+    // Largest request body in bytes, larger ones are refused with 413 (default: 10 MB, no limit
+    // with --receive, where upload_max_size limits the files):
+    max_request_body: Option<u64>,
+    // Seconds a client may take for the headers of a request (default: 30) and a connection
+    // may go without any data moving (default: 60), against slow and stalled clients. 0 disables:
+    header_timeout: Option<u64>,
+    idle_timeout: Option<u64>,
+    // Requests a single client (by the address the reverse proxy passes on) may have open at
+    // once, e.g. parallel downloads. More get "429 Too Many Requests" (default: no limit):
+    max_connections_per_client: Option<u32>,
+    // Stops the server once no request came in for so long, e.g. "30m", and starts it again with
+    // the next one, which gets a page that reloads in a moment. The tunnel stays up for it
+    // (default: never):
+    idle_pause: Option<String>,
+    // Image files served as the icon of every page and as apple-touch-icon.png, instead of the
+    // share's own, e.g. "~/brand/favicon.png" (default: the share's):
+    favicon: Option<PathBuf>,
+    apple_touch_icon: Option<PathBuf>,
+    // HTML put into the <head> of every page served, e.g. the script tag of Plausible or umami,
+    // so page views of a share show up there (default: none):
+    analytics: Option<String>,
+    // Text of a dismissible banner on top of every page, with {url}, {name} (of what's shared)
+    // and {expires} (the end of a --duration, "on close" without), e.g. "Preview shared via
+    // livetunnel, expires {expires}, do not distribute" (default: none):
+    banner: Option<String>,
+    // Pages link the files of the share as "app.css?v=<hash of the content>", which browsers
+    // then keep for good, while they check the pages themselves every time. Builtin server only
+    // (default: false):
+    hashed_assets: Option<bool>,
+    // Like static hosts: "/about" serves about.html, "/about.html" and "/about/" redirect there
+    // and directories serve their index.html instead of a listing (default: false):
+    pretty_urls: Option<bool>,
+    // Follows the rules of a Netlify-style _redirects file in the shared directory, read again
+    // once it changes (default: false):
+    redirects_file: Option<bool>,
+    // URL paths that open without logging in with -s, e.g. assets the protected pages need, like
+    // ["/assets/**", "/favicon.ico"]. `*` matches within a folder, `**` across them. Folders of
+    // [serve.folder_users] stay closed (default: none):
+    public_paths: Option<Vec<String>>,
+    // Visitors' browsers solve a proof of work of this many bits before they get anything, which
+    // lets them in for 12 hours, against scrapers of a share whose URL they found. 16 takes about
+    // a second, every bit more twice as long. Needs https, scripts with secret_header skip it
+    // (default: off):
+    challenge: Option<u32>,
+    // Where requests have to come from, by their Referer (or Origin), e.g. the portal a share is
+    // embedded in: ["https://portal.example.com/*"]. `*` matches anything. The share's own pages
+    // always may, others get "403 Forbidden" and are logged. Scripts with secret_header don't
+    // need one (default: from anywhere):
+    allowed_referrers: Option<Vec<String>>,
+    // With -s, users log in on a page instead of the browser's prompt for basic auth, which
+    // phones keep asking again. It keeps them logged in for login_session, e.g. "8h" (default:
+    // "12h"), and /.livetunnel/logout logs out. Scripts can still use basic auth (default: false):
+    login_page: Option<bool>,
+    login_session: Option<String>,
+
+    // Serving hours as [serve.schedule] section, outside of them visitors get a page that tells
+    // when the share is back while the tunnel stays up:
+    schedule: Option<ScheduleConfig>,
+    // Text stamped on served images and PDFs as [serve.watermark] section:
+    watermark: Option<WatermarkConfig>,
+    // A header that opens the share as [serve.secret_header] section, e.g. for CI fetching
+    // artifacts. Requests need it or (with -s) a login:
+    secret_header: Option<SecretHeaderConfig>,
+    // Folders of the shared directory only some users may open with -s, including their
+    // subdirectories, as [serve.folder_users] section, e.g. clientA = ["alice"]. The deepest one
+    // decides, the others are open to every user:
+    folder_users: Option<BTreeMap<String, Vec<String>>>,
+    // Redirect and rewrite rules as [[serve.redirects]] sections, applied in order before the
+    // ones of redirects_file:
+    redirects: Option<Vec<RedirectConfig>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SecretHeaderConfig {
+    // e.g. "X-Share-Key":
+    name: String,
+    // What it has to carry, e.g. generated with `openssl rand -hex 32`. Goes into the keyring
+    // with keyring = true:
+    token: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RedirectConfig {
+    // The path to match as glob ("*" within a folder, "**" across them), e.g. "/docs/**", or
+    // instead as regular expression in regex, e.g. "^/v(\\d+)/(.*)$":
+    from: Option<String>,
+    regex: Option<String>,
+    // A path of the share or (for redirects) a URL, with the groups of regex as $1, $2:
+    to: String,
+    // 301, 302, 303, 307 or 308 redirect, 200 serves `to` instead and 404 (or another 4xx)
+    // serves the page `to` with it (default: 301):
+    status: Option<u16>,
+    // Applies to paths the share has a file for as well (default: false):
+    force: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WatermarkConfig {
+    // With {user} (who logged in, "anonymous" without), {client} (the visitor's address), {date}
+    // and {session} (random for each share, logged at the start), e.g. "{user} {session}":
+    text: String,
+    // TrueType or OpenType file for the text on images (default: DejaVu Sans, Liberation Sans or
+    // Arial, whichever is installed). PDFs use Helvetica:
+    font: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ScheduleConfig {
+    // e.g. ["mon-fri"] or ["sat", "sun"] (default: every day):
+    days: Option<Vec<String>>,
+    // "HH:MM", an until before from ends the next day (default: all day):
+    from: Option<String>,
+    until: Option<String>,
+    // e.g. "Europe/Berlin" (default: the local one):
+    timezone: Option<String>,
+}
+
+impl ScheduleConfig {
+    fn parse(&self) -> std::result::Result<Schedule, String> {
+        Schedule::parse(
+            self.days.as_deref().unwrap_or_default(),
+            self.from.as_deref(),
+            self.until.as_deref(),
+            self.timezone.as_deref(),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WatchConfig {
+    // Run through the shell in the shared directory on changes, e.g. "hugo" or "npm run build"
+    // (default: none, only reload):
+    command: Option<String>,
+    // Watched recursively, relative to the shared directory (default: the shared directory).
+    // Keep the build output out of them:
+    paths: Option<Vec<PathBuf>>,
+    // Milliseconds without changes before building (default: 500):
+    debounce: Option<u64>,
+    // Reload HTML pages in the browsers after changes, builtin server only (default: true):
+    live_reload: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DisconnectPolicy {
+    Quit,
+    Retry,
 }
 
 enum OptionalFeatures {
     CmdBefore,
     CmdAfter,
     JumpHosts,
+    PasswordAuth,
+    RemoteSocket,
+    Heartbeat,
+    TransferCap,
+    ServerCommand,
 }
 
 impl Display for OptionalFeatures {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             OptionalFeatures::CmdBefore => write!(
                 f,
@@ -71,6 +462,91 @@ impl Display for OptionalFeatures {
                 "Run command (remotely) after establishing SSH connection"
             ),
             OptionalFeatures::JumpHosts => write!(f, "Use SSH jump-hosts"),
+            OptionalFeatures::PasswordAuth => {
+                write!(f, "Log in with a password instead of an SSH key")
+            }
+            OptionalFeatures::RemoteSocket => write!(
+                f,
+                "Forward to a Unix socket on the remote instead of a port"
+            ),
+            OptionalFeatures::Heartbeat => write!(
+                f,
+                "Ping a heartbeat URL (e.g. healthchecks.io) while the tunnel is up"
+            ),
+            OptionalFeatures::TransferCap => {
+                write!(
+                    f,
+                    "Limit the data transferred through the tunnel per session"
+                )
+            }
+            OptionalFeatures::ServerCommand => {
+                write!(
+                    f,
+                    "Use a custom server command instead of the builtin server"
+                )
+            }
+        }
+    }
+}
+
+// Asked for first when reconfiguring:
+enum ConfigSection {
+    Everything,
+    Connection,
+    Ports,
+    Users,
+    Features,
+}
+
+impl Display for ConfigSection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSection::Everything => write!(f, "Everything"),
+            ConfigSection::Connection => write!(f, "SSH connection (host, Port, user, keyfile)"),
+            ConfigSection::Ports => write!(f, "Ports (remote and local)"),
+            ConfigSection::Users => write!(f, "Users for secure sharing"),
+            ConfigSection::Features => write!(f, "Optional features"),
+        }
+    }
+}
+
+// Asked for when reconnecting gave up:
+enum DisconnectAction {
+    Reconnect,
+    Quit,
+}
+
+impl Display for DisconnectAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectAction::Reconnect => write!(f, "Try to reconnect"),
+            DisconnectAction::Quit => write!(f, "Quit livetunnel"),
+        }
+    }
+}
+
+enum ListAction {
+    Add,
+    Edit,
+    Remove,
+    MoveUp,
+    MoveDown,
+    Settings,
+    Done,
+}
+
+impl Display for ListAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ListAction::Add => write!(f, "Add a command"),
+            ListAction::Edit => write!(f, "Edit a command"),
+            ListAction::Remove => write!(f, "Remove a command"),
+            ListAction::MoveUp => write!(f, "Move a command up"),
+            ListAction::MoveDown => write!(f, "Move a command down"),
+            ListAction::Settings => {
+                write!(f, "Set what happens when a command fails or hangs")
+            }
+            ListAction::Done => write!(f, "Done"),
         }
     }
 }
@@ -78,500 +554,1820 @@ impl Display for OptionalFeatures {
 pub struct App {
     pub cli: Cli,
     config: Config,
+    // What is shared, a directory or a single file:
     directory: PathBuf,
     runtime: Runtime,
-    ssh_session: Session,
-    miniserve_handle: Option<Child>,
+    // Shared with the task that checks it while sharing:
+    ssh_session: Arc<Session>,
+    // Asked for once, reconnecting needs it again:
+    ssh_password: Option<String>,
+    miniserve_handle: Option<ExternalServer>,
+    server: Option<Server>,
+    // Holds the port while the builtin server is paused ([serve] idle_pause):
+    paused: Option<Waker>,
+    // Shared with every (re)start of the builtin server (--max-downloads):
+    download_limit: Option<Arc<DownloadLimit>>,
+    // Temporary directory the piped in data was read into (--stdin), removed on close:
+    stdin_buffer: Option<TempDir>,
+    // The directory of `livetunnel scratch`, deleted on close:
+    scratch_directory: Option<TempDir>,
+    // Set once the shared directory was built (--build), `directory` is its output then:
+    site_build: Option<SiteBuild>,
+    // Where and how a --hybrid share is synced to, again when it closes:
+    hybrid: Option<(String, DeployOptions)>,
+    // Created for the domain of this share ([dns]), removed on close:
+    dns_record: Option<DnsRecord>,
+    // Removes the reverse proxy config written for this share on close (script, with sudo):
+    provisioned_proxy: Option<(String, bool)>,
+    // Shared with every (re)start of the builtin server ([watch] with live reload):
+    live_reload: Option<LiveReload>,
+    // Serving hours of the builtin server ([serve.schedule]):
+    schedule: Option<Schedule>,
+    // [serve] banner with its placeholders filled, once sharing starts:
+    banner: Option<String>,
+    // [serve.watermark] of this share, with its session:
+    watermark: Option<Arc<Watermark>>,
+    // [serve] public_paths, open without logging in:
+    public: Option<GlobSet>,
+    // [[serve.redirects]] of the config:
+    redirects: Redirects,
+    // [serve] challenge, with the key of this share:
+    challenge: Option<Arc<Challenge>>,
+    // [serve] allowed_referrers:
+    referrers: Option<GlobSet>,
+    // Logins of the [serve] login_page, with the key of this share:
+    sessions: Option<Arc<Sessions>>,
+    // Requests served by every (re)start of the server, summed up on close:
+    stats: Arc<AccessStats>,
+    relay: Option<Relay>,
+    // Set once sharing started ([notifications]):
+    notifier: Option<Notifier>,
+    // Told the public URL once sharing started, for `Tunnel::spawn`:
+    pub started: Option<SyncSender<Option<String>>>,
     pub should_end: Arc<AtomicBool>,
+    // Wakes the main loop up when `should_end` is set from outside:
+    end_notify: Arc<Notify>,
+}
+
+// What a key asks for while sharing, in the default view and on the dashboard:
+enum Action {
+    RestartServer,
+    AddUser,
+    CopyUrl,
+    Quit,
 }
 
 impl App {
-    pub fn new(cli: Cli, end: Arc<AtomicBool>) -> Self {
+    pub fn new(cli: Cli, end: Arc<AtomicBool>, end_notify: Arc<Notify>) -> Result<Self> {
         let _ = INFO_TEMPLATE.set(ProgressStyle::with_template("ℹ {msg}").unwrap());
         let _ = WARNING_TEMPLATE.set(ProgressStyle::with_template("❗ {msg}").unwrap());
         let _ = SUCCESS_TEMPLATE.set(ProgressStyle::with_template("✓ {msg}").unwrap());
+        let _ = PLAIN_OUTPUT.set(cli.non_interactive || logging::verbose());
 
-        let mut config = if cli.reconfigure
-            || get_configuration_file_path("livetunnel", "livetunnel").is_err()
-        {
-            println!("ℹ Starting setup assistant:");
-            Self::build_config()
-        } else {
-            load("livetunnel", "livetunnel").unwrap()
-        };
-
-        if config.host.is_empty() {
-            println!("❗Config file Invalid, starting setup assistant:");
-            config = Self::build_config();
-        }
-
-        let directory = if let Some(dir) = cli.directory.clone() {
+        let directory = if let Some(dir) = cli.share.directory.clone() {
             if dir.exists() {
                 dir
             } else {
-                println!("❗Directory {:?} not found. Quitting.", dir);
-                exit(1);
+                return Err(Error::Config(format!("{:?} not found.", dir)));
             }
         } else {
-            current_dir().unwrap()
+            current_dir()?
         };
 
-        let runtime = Runtime::new().unwrap();
-
-        // Build SSH Connection from config:
-        let mut ssh_session_builder = SessionBuilder::default();
-        if let Some(port) = config.port {
-            ssh_session_builder.port(port);
+        // Settings from a .livetunnel.toml in the project override the user's config:
+        let project_config = find_project_config(&directory);
+        if let Some(path) = &project_config {
+            info!("Using project config '{}'", path.display());
         }
+        let with_project_config = |config: Config| -> Result<Config> {
+            match &project_config {
+                Some(path) => {
+                    let (config, ignored) = apply_project_config(&config, path).map_err(|err| {
+                        Error::Config(format!(
+                            "Project config '{}' is invalid: {}.",
+                            path.display(),
+                            err
+                        ))
+                    })?;
+                    if !ignored.is_empty() {
+                        warn!(
+                            "Ignoring {} of project config '{}', only your own config sets them",
+                            ignored.join(", "),
+                            path.display()
+                        );
+                    }
+                    Ok(config)
+                }
+                None => Ok(config),
+            }
+        };
 
-        if let Some(username) = config.username.clone() {
-            ssh_session_builder.user(username);
+        let needs_assistant = cli.share.reconfigure
+            || config_path().is_err()
+            || load_config()
+                .map(|config| config.host.is_empty())
+                .unwrap_or(true);
+        if needs_assistant && cli.non_interactive {
+            return Err(Error::Config(String::from(
+                "The setup assistant can't run with --non-interactive. Run `livetunnel config setup` first.",
+            )));
         }
 
-        if let Some(keyfile) = &config.keyfile {
-            ssh_session_builder.keyfile(keyfile);
-        }
+        let mut config = if cli.share.reconfigure || config_path().is_err() {
+            info!("Starting setup assistant:");
+            let previous = load_config().unwrap_or_default();
+            with_project_config(Self::build_config(&previous)?)?
+        } else {
+            with_project_config(load_config()?)?
+        };
 
-        if let Some(jump_hosts) = &config.jump_hosts {
-            ssh_session_builder.jump_hosts(jump_hosts);
+        if config.host.is_empty() {
+            warn!("Config file Invalid, starting setup assistant:");
+            let previous = load_config().unwrap_or_default();
+            config = with_project_config(Self::build_config(&previous)?)?;
         }
 
-        if let Some(ref commands) = config.before_commands {
-            let num_cmds = commands.len();
-            println!(
-                "ℹ Running {} command(s) before establishing SSH connection",
-                num_cmds
-            );
+        logging::emit(
+            "config_loaded",
+            json!({
+                "path": config_path().ok(),
+                "project_config": project_config,
+                "host": config.host,
+                "local_port": config.local_port,
+                "remote_port": config.remote_port,
+                "directory": directory,
+            }),
+        );
+
+        // --log-file was opened before anything was logged:
+        if let (None, Some(path)) = (&cli.log_file, &config.log_file) {
+            logging::log_to(path).map_err(|err| {
+                Error::Config(format!(
+                    "Could not open the log file '{}': {}",
+                    path.display(),
+                    err
+                ))
+            })?;
+        }
 
-            for (i, (program, args)) in commands.iter().enumerate() {
-                let pb = ProgressBar::new_spinner();
-                pb.set_message(format!(
-                    "[{}/{}] Running '{} {}'",
-                    i + 1,
-                    num_cmds,
-                    program,
-                    args
-                ));
-                pb.enable_steady_tick(Duration::from_millis(20));
+        // Flags only change this run, nothing of it is stored:
+        let overrides = &cli.connection;
+        if let Some(host_spec) = overrides.host.clone() {
+            config.host = host_spec.host;
+            if host_spec.user.is_some() {
+                config.username = host_spec.user;
+            }
+            if host_spec.port.is_some() {
+                config.port = host_spec.port;
+            }
+        }
+        if let Some(user) = &overrides.user {
+            config.username = Some(user.clone());
+        }
+        if let Some(keyfile) = &overrides.keyfile {
+            config.keyfile = Some(keyfile.clone());
+        }
+        if let Some(local_port) = overrides.local_port {
+            config.local_port = local_port;
+        }
+        if let Some(remote_port) = overrides.remote_port {
+            config.remote_port = remote_port;
+        }
 
-                let mut child_process = Command::new(program);
-                for arg in args.split(' ') {
-                    child_process.arg(arg);
-                }
+        let runtime = Runtime::new()?;
 
-                let output = match child_process.output() {
-                    Ok(output) => output,
-                    Err(err) => {
-                        pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                        pb.tick();
-                        pb.finish_with_message(format!(
-                            "[{}/{}] Error: '{} {}' produced an Error: {}",
-                            i + 1,
-                            num_cmds,
-                            program,
-                            args,
-                            err
-                        ));
-                        continue;
-                    }
-                };
-
-                if !output.status.success() {
-                    pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                    pb.tick();
-                    pb.finish_with_message(format!(
-                        "[{}/{}] Error: '{} {}' exited with {}: '{:?}'",
-                        i + 1,
-                        num_cmds,
-                        program,
-                        args,
-                        output.status,
-                        output
-                    ));
-                    continue;
-                }
+        let ssh_password = ssh_password(&config, cli.non_interactive)?;
 
-                pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-                pb.tick();
-                pb.finish_with_message(format!(
-                    "[{}/{}] Done: '{} {}'",
-                    i + 1,
-                    num_cmds,
-                    program,
-                    args
-                ));
-            }
+        if let Some(ref commands) = config.before_commands {
+            run_before_commands(commands, &config, &directory, &runtime)?;
         }
 
-        let pb = ProgressBar::new_spinner();
+        let pb = spinner();
         pb.set_message(format!("Connecting to '{}' via SSH", config.host));
         pb.enable_steady_tick(Duration::from_millis(20));
 
         // Connect to SSH:
-        let ssh_session = match runtime.block_on(ssh_session_builder.connect(&config.host)) {
+        debug!(port = ?config.port, user = ?config.username, "SSH connecting to '{}'", config.host);
+        let ssh_session = match runtime.block_on(connect(&config, ssh_password.as_deref())) {
             Ok(ssh_session) => ssh_session,
-            Err(error) => panic!("Couldn't establish SSH connection: {:?}", error),
+            Err(err) => {
+                pb.finish_and_clear();
+                // It's asked for again next time, in case the remembered one is outdated:
+                if config.keyring == Some(true) && config.password_auth == Some(true) {
+                    let _ = secrets::forget(SSH_PASSWORD_SECRET);
+                }
+                return Err(Error::Ssh(format!(
+                    "Couldn't establish SSH connection to '{}': {}",
+                    config.host, err
+                )));
+            }
         };
 
         pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
         pb.tick();
         pb.finish_with_message(format!("Connected to '{}' via SSH", config.host));
+        debug!("SSH connected to '{}'", config.host);
+        logging::emit("ssh_connected", json!({ "host": config.host }));
+
+        // Remembered once it worked, unless it came from the environment:
+        if let (Some(true), Some(password), Err(_)) = (
+            config.keyring,
+            &ssh_password,
+            var(askpass::PASSWORD_VARIABLE),
+        ) {
+            if let Err(err) = secrets::store(SSH_PASSWORD_SECRET, password) {
+                warn!("Could not store the SSH password in the keyring: {}", err);
+            }
+        }
 
         if let Some(ref commands) = config.after_commands {
-            let num_cmds = commands.len();
-            println!(
-                "ℹ Running {} command(s) on the newly establishing SSH connection",
-                num_cmds
-            );
-
-            for (i, (program, args)) in commands.iter().enumerate() {
-                let ac_pb = ProgressBar::new_spinner();
-                ac_pb.set_message(format!(
-                    "[{}/{}] Running '{} {}'",
-                    i + 1,
-                    num_cmds,
-                    program,
-                    args
-                ));
-                ac_pb.enable_steady_tick(Duration::from_millis(20));
-
-                let mut remote_cmd = ssh_session.command(program);
-                for arg in args.split(' ') {
-                    remote_cmd.arg(arg);
-                }
-
-                let output = match runtime.block_on(remote_cmd.output()) {
-                    Ok(output) => output,
-                    Err(err) => {
-                        ac_pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                        ac_pb.tick();
-                        ac_pb.finish_with_message(format!(
-                            "[{}/{}] Error: '{} {}' produced an Error: {}",
-                            i + 1,
-                            num_cmds,
-                            program,
-                            args,
-                            err
-                        ));
-                        continue;
-                    }
-                };
-
-                if !output.status.success() {
-                    ac_pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                    ac_pb.tick();
-                    ac_pb.finish_with_message(format!(
-                        "[{}/{}] Error: '{} {}' exited with {}: '{:?}'",
-                        i + 1,
-                        num_cmds,
-                        program,
-                        args,
-                        output.status,
-                        output
-                    ));
-                    continue;
-                }
-
-                ac_pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-                ac_pb.tick();
-                ac_pb.finish_with_message(format!(
-                    "[{}/{}] Done: '{} {}': o: {}",
-                    i + 1,
-                    num_cmds,
-                    program,
-                    args,
-                    std::str::from_utf8(&output.stdout).unwrap(),
-                ));
-            }
+            run_after_commands(commands, &config, &directory, &ssh_session, &runtime);
         }
 
-        App {
+        Ok(App {
             cli,
             config,
             directory,
             runtime,
-            ssh_session,
+            ssh_session: Arc::new(ssh_session),
+            ssh_password,
             miniserve_handle: None,
+            server: None,
+            paused: None,
+            download_limit: None,
+            stdin_buffer: None,
+            scratch_directory: None,
+            site_build: None,
+            hybrid: None,
+            provisioned_proxy: None,
+            dns_record: None,
+            live_reload: None,
+            schedule: None,
+            banner: None,
+            watermark: None,
+            public: None,
+            redirects: Redirects::default(),
+            challenge: None,
+            referrers: None,
+            sessions: None,
+            stats: Arc::new(AccessStats::new()),
+            relay: None,
+            notifier: None,
+            started: None,
             should_end: end,
+            end_notify,
+        })
+    }
+
+    pub fn run(&mut self) -> Result<()> {
+        self.check_share_flags()?;
+        let new_credentials = self.set_up_credentials()?;
+        self.prepare_content()?;
+        let duration = self.duration()?;
+        self.prepare_serving()?;
+        let idle_pause = self.idle_pause()?;
+        self.check_running_shares()?;
+        self.set_up_remote()?;
+
+        // Some clipboards lose the content with its owner, so it lives as long as the share:
+        let clipboard = if self.cli.share.copy {
+            self.copy_share_url(&new_credentials)
+        } else {
+            None
+        };
+
+        self.banner = self.fill_banner(duration);
+
+        let sharing = self.start_sharing(duration, idle_pause, clipboard)?;
+        self.share_until_end(sharing)
+    }
+
+    // Flags of the share that don't go together:
+    fn check_share_flags(&self) -> Result<()> {
+        if self.cli.share.tui && (logging::json() || !std::io::stdout().is_terminal()) {
+            return Err(Error::Config(String::from(
+                "--tui needs a terminal and can't be combined with --output json.",
+            )));
+        }
+
+        if self.cli.share.secure
+            && !self.cli.share.secure_auto
+            && self.cli.non_interactive
+            && self.config.users.is_empty()
+        {
+            return Err(Error::Config(String::from(
+                "Secure sharing selected, but no User(s) set in config. Add one with `livetunnel users add`.",
+            )));
         }
+
+        Ok(())
     }
 
-    pub fn run(&mut self) {
-        if self.cli.secure {
+    // Users for secure sharing, returns the passwords entered or generated for this run:
+    fn set_up_credentials(&mut self) -> Result<Vec<(String, String)>> {
+        let mut new_credentials = vec![];
+        if self.cli.share.secure_auto {
+            // Replaces the stored users for this run, nothing of it is written to the config:
+            let credentials = generate_credentials()?;
+            // Shown even with -q:
+            logging::show(&format!(
+                "ℹ Generated a login for this share: User '{}', Password '{}'",
+                credentials.0, credentials.1
+            ));
+            self.cli.share.secure = true;
+            self.config.users = hash_users(std::slice::from_ref(&credentials));
+            new_credentials.push(credentials);
+        } else if self.cli.share.secure && !self.cli.non_interactive {
             if self.config.users.is_empty() {
-                println!(
-                    "ℹ Secure sharing selected, but no User(s) set in config. Please add one now:"
-                );
-                self.config.users = App::add_users();
+                info!("Secure sharing selected, but no User(s) set in config. Please add one now:");
+                new_credentials = App::add_users()?;
             } else {
                 let add_users =
                     Confirm::new("ℹ Secure sharing selected. Do you want to add new users?")
                         .with_default(false)
-                        .prompt()
-                        .unwrap();
+                        .prompt()?;
 
                 if add_users {
-                    let mut new_users = App::add_users();
-                    self.config.users.append(&mut new_users);
+                    new_credentials = App::add_users()?;
                 }
             }
+            self.config.users.extend(hash_users(&new_credentials));
         }
 
-        let pb = ProgressBar::new_spinner();
-        pb.set_message(format!(
-            "Starting port-forward from local Port {} to remote Port {} via SSH",
-            self.config.local_port, self.config.remote_port
-        ));
-        pb.enable_steady_tick(Duration::from_millis(20));
-
-        let local_socket = TcpSocket(SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            self.config.local_port,
-        ));
-        let remote_socket = TcpSocket(SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-            self.config.remote_port,
-        ));
+        Ok(new_credentials)
+    }
 
-        self.runtime
-            .block_on(self.ssh_session.request_port_forward(
-                openssh::ForwardType::Remote,
-                remote_socket,
-                local_socket,
-            ))
-            .unwrap();
+    // What is shared, if it isn't the directory as it is (--stdin, --build):
+    fn prepare_content(&mut self) -> Result<()> {
+        if self.cli.share.stdin {
+            let (buffer, path) = buffer_stdin(self.cli.share.name.as_deref().unwrap_or("stdin"))?;
+            self.stdin_buffer = Some(buffer);
+            self.directory = path;
+        }
+        if self.cli.share.build || self.config.build.unwrap_or(false) {
+            self.build_site()?;
+        }
 
-        pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-        pb.tick();
-        pb.finish_with_message(format!(
-            "Started port-forward from local Port {} to remote Port {} via SSH",
-            self.config.local_port, self.config.remote_port
-        ));
+        Ok(())
+    }
 
-        let mp = MultiProgress::new();
-        let pb_forward = mp.add(ProgressBar::new_spinner());
-        pb_forward.set_message(format!(
-            "Forwarding local Port {} to remote Port {} via SSH",
-            self.config.local_port, self.config.remote_port
-        ));
-        pb_forward.enable_steady_tick(Duration::from_millis(20));
+    // Checks the settings for the server and prepares what the builtin server needs for them:
+    fn prepare_serving(&mut self) -> Result<()> {
+        for (setting, set) in self.builtin_settings() {
+            if set {
+                self.require_builtin(setting)?;
+            }
+        }
 
-        let pb_serve = mp.add(ProgressBar::new_spinner());
-        pb_serve.set_message(format!(
-            "Starting miniserve to serve content from '{}' on local Port '{}'",
-            self.directory.display(),
-            self.config.local_port
-        ));
-        pb_serve.enable_steady_tick(Duration::from_millis(20));
+        if let Some(max) = self.cli.share.max_downloads {
+            self.download_limit = Some(Arc::new(DownloadLimit::new(max)));
+        }
+        if self.cli.share.receive {
+            self.prepare_uploads()?;
+        }
+        if self.server_command().is_none()
+            && self
+                .config
+                .watch
+                .as_ref()
+                .is_some_and(|watch| watch.live_reload != Some(false))
+        {
+            self.live_reload = Some(LiveReload::new());
+        }
+        let serve = self.config.serve.clone().unwrap_or_default();
+        if let Some(schedule) = &serve.schedule {
+            self.schedule = Some(
+                schedule
+                    .parse()
+                    .map_err(|err| Error::Config(format!("[serve.schedule]: {}.", err)))?,
+            );
+        }
+        for (setting, icon) in [
+            ("favicon", self.serve_icon(|serve| serve.favicon.as_deref())),
+            (
+                "apple_touch_icon",
+                self.serve_icon(|serve| serve.apple_touch_icon.as_deref()),
+            ),
+        ] {
+            if let Some(icon) = icon.filter(|icon| !icon.is_file()) {
+                return Err(Error::Config(format!(
+                    "[serve] {} '{}' doesn't exist.",
+                    setting,
+                    icon.display()
+                )));
+            }
+        }
+        if let Some(watermark) = &serve.watermark {
+            // Tells which share a leaked file came from:
+            let session = random_string(8)?;
+            info!("Watermarking images and PDFs with session {}", session);
+            let font = watermark.font.as_deref().map(expand_home);
+            self.watermark = Some(Arc::new(
+                Watermark::new(
+                    &watermark.text.replace("{session}", &session),
+                    font.as_deref(),
+                )
+                .map_err(|err| Error::Config(format!("[serve.watermark]: {}.", err)))?,
+            ));
+        }
+        if let Some(paths) = serve
+            .public_paths
+            .as_ref()
+            .filter(|paths| !paths.is_empty())
+        {
+            self.public = Some(public_paths(paths).map_err(Error::Config)?);
+        }
+        if let Some(bits) = serve.challenge {
+            if !CHALLENGE_BITS.contains(&bits) {
+                return Err(Error::Config(format!(
+                    "[serve] challenge is {} bits, use {} to {}.",
+                    bits,
+                    CHALLENGE_BITS.start(),
+                    CHALLENGE_BITS.end()
+                )));
+            }
+            self.challenge = Some(Arc::new(
+                Challenge::new(bits).map_err(|err| Error::Io(std::io::Error::other(err)))?,
+            ));
+        }
+        if let Some(referrers) = serve
+            .allowed_referrers
+            .as_ref()
+            .filter(|referrers| !referrers.is_empty())
+        {
+            self.referrers = Some(referrer_patterns(referrers).map_err(Error::Config)?);
+        }
+        if let Some(problem) = serve.secret_header.as_ref().and_then(secret_header_problem) {
+            return Err(Error::Config(problem));
+        }
+        if let Some(rules) = serve.redirects.as_ref().filter(|rules| !rules.is_empty()) {
+            self.redirects = redirect_rules(rules).map_err(Error::Config)?;
+        }
+        if serve
+            .folder_users
+            .as_ref()
+            .is_some_and(|folders| !folders.is_empty())
+        {
+            // Without auth nobody is kept out of them:
+            if !self.cli.share.secure {
+                return Err(Error::Config(String::from(
+                    "[serve.folder_users] needs secure sharing, share with -s.",
+                )));
+            }
+            for (folder, users) in self.folder_users() {
+                if !folder
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)))
+                {
+                    return Err(Error::Config(format!(
+                        "[serve.folder_users] '{}' has to be a relative path inside the shared directory.",
+                        folder.display()
+                    )));
+                }
+                if let Some(user) = users
+                    .iter()
+                    .find(|user| !self.config.users.iter().any(|(name, _)| name == *user))
+                {
+                    warn!(
+                        "[serve.folder_users] '{}' lists '{}', who isn't a user",
+                        folder.display(),
+                        user
+                    );
+                }
+            }
+        }
+        if serve.login_page == Some(true) {
+            // Without users there's nobody to log in:
+            if !self.cli.share.secure {
+                return Err(Error::Config(String::from(
+                    "[serve] login_page needs secure sharing, share with -s.",
+                )));
+            }
+            let lifetime = match serve.login_session.as_deref() {
+                Some(session) => parse_duration(session).map_err(|err| {
+                    Error::Config(format!(
+                        "Invalid login_session '{}' in [serve]: {}",
+                        session, err
+                    ))
+                })?,
+                None => LOGIN_SESSION,
+            };
+            self.sessions = Some(Arc::new(
+                Sessions::new(lifetime).map_err(|err| Error::Io(std::io::Error::other(err)))?,
+            ));
+        }
+        if self.config.local_socket.is_some() {
+            if !cfg!(unix) {
+                return Err(Error::Config(String::from(
+                    "local_socket needs livetunnel to run on Unix.",
+                )));
+            }
+        } else {
+            self.pick_local_port()?;
+        }
 
-        let mut miniserve = Command::new("miniserve");
+        Ok(())
+    }
 
-        // We don't care about miniserve's in-/output:
-        miniserve.stdin(std::process::Stdio::null());
-        miniserve.stdout(std::process::Stdio::null());
-        miniserve.stderr(std::process::Stdio::null());
+    // Settings only the builtin server has, with whether they are set:
+    fn builtin_settings(&self) -> Vec<(&'static str, bool)> {
+        let serve = self.config.serve.clone().unwrap_or_default();
+        fn listed<T>(list: &Option<Vec<T>>) -> bool {
+            list.as_ref().is_some_and(|list| !list.is_empty())
+        }
+        vec![
+            ("--max-downloads", self.cli.share.max_downloads.is_some()),
+            ("[serve.schedule]", serve.schedule.is_some()),
+            ("[serve] favicon", serve.favicon.is_some()),
+            ("[serve] apple_touch_icon", serve.apple_touch_icon.is_some()),
+            ("[serve] analytics", serve.analytics.is_some()),
+            ("[serve] banner", serve.banner.is_some()),
+            ("[serve.watermark]", serve.watermark.is_some()),
+            ("[serve] hashed_assets", serve.hashed_assets == Some(true)),
+            ("[serve] pretty_urls", serve.pretty_urls == Some(true)),
+            ("[serve] redirects_file", serve.redirects_file == Some(true)),
+            ("[serve] public_paths", listed(&serve.public_paths)),
+            ("[serve] challenge", serve.challenge.is_some()),
+            (
+                "[serve] allowed_referrers",
+                listed(&serve.allowed_referrers),
+            ),
+            ("[serve.secret_header]", serve.secret_header.is_some()),
+            ("[[serve.redirects]]", listed(&serve.redirects)),
+            (
+                "[serve.folder_users]",
+                serve
+                    .folder_users
+                    .as_ref()
+                    .is_some_and(|folders| !folders.is_empty()),
+            ),
+            ("[serve] idle_pause", serve.idle_pause.is_some()),
+            ("[serve] login_page", serve.login_page == Some(true)),
+            ("local_socket", self.config.local_socket.is_some()),
+        ]
+    }
 
-        // -H = show hidden files
-        // -i = which network interface to use
-        // -p port
-        miniserve.args([
-            "-H",
-            "-i",
-            "127.0.0.1",
-            "-p",
-            &self.config.local_port.to_string(),
-        ]);
+    // Fails for a setting `server_command` or miniserve can't do:
+    fn require_builtin(&self, setting: &str) -> Result<()> {
+        match self.server_command() {
+            Some(_) => Err(Error::Config(format!(
+                "{} only works with the builtin server, not {}.",
+                setting,
+                self.server_name()
+            ))),
+            None => Ok(()),
+        }
+    }
 
-        if self.cli.secure {
-            for (user, pw) in &self.config.users {
-                miniserve.args(["-a", &format!("{}:sha512:{}", user, pw)]);
+    fn check_running_shares(&self) -> Result<()> {
+        // Other shares in the background need other ports:
+        if self.cli.share.daemonized {
+            let ports = (self.config.local_port, self.config.remote_port);
+            if let Some(other) = running_shares().iter().find(|other| {
+                (self.config.local_socket.is_none() && other.local_port == ports.0)
+                    || other.remote_port == ports.1
+            }) {
+                return Err(Error::Config(format!(
+                    "'{}' (PID {}) already uses local Port {} and remote Port {}. Pass --local-port/--remote-port or set them in a .livetunnel.toml.",
+                    other.directory.display(),
+                    other.pid,
+                    other.local_port,
+                    other.remote_port
+                )));
             }
         }
 
-        miniserve.arg(&self.directory);
+        Ok(())
+    }
 
-        self.miniserve_handle = match miniserve.spawn() {
-            Ok(handle) => Some(handle),
-            Err(err) => {
-                pb_serve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                pb_serve.tick();
-                pb_serve.finish_with_message(format!(
-                    "Could not start miniserve. Is it installed? Error: {}",
-                    err
-                ));
-                sleep(Duration::from_secs(1));
-                None
-            }
-        };
+    // Everything on the server: the synced copy, DNS, the forward and the reverse proxy:
+    fn set_up_remote(&mut self) -> Result<()> {
+        // The server keeps a copy, for the reverse proxy to fall back to while we are offline:
+        if self.cli.share.hybrid || self.config.hybrid.unwrap_or(false) {
+            let options = self.deploy_options(None, false, vec![])?;
+            let remote_path = self.deploy_path(None)?;
+            self.sync(&remote_path, &options)?;
+            self.hybrid = Some((remote_path, options));
+        }
 
-        pb_serve.set_message(format!(
-            "miniserve successfully started. Serving content from '{}' on local Port '{}'",
-            self.directory.display(),
-            self.config.local_port
-        ));
+        if let Some(subdomains) = &self.config.subdomains {
+            // Only for this run, the stored config keeps its domain:
+            self.config.domain = Some(format!("{}.{}", random_string(6)?, subdomains.base_domain));
+        }
 
-        let pb_exit_info = mp.add(ProgressBar::new(42));
-        pb_exit_info.set_style(INFO_TEMPLATE.get().unwrap().clone());
-        pb_exit_info.set_message("Press CTRL+C to exit");
+        if let Some(dns) = self.config.dns.clone() {
+            self.create_dns_record(&dns)?;
+        }
 
-        loop {
-            if self.runtime.block_on(self.ssh_session.check()).is_err() {
-                pb_forward.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                pb_forward.tick();
-                pb_forward.finish_with_message("SSH Forward died! Closing livetunnel.");
-                self.should_end.store(true, Ordering::SeqCst);
-                // TODO: Give option to reconnect
-            };
+        self.request_forward()?;
 
-            if let Some(miniserve_handle) = &mut self.miniserve_handle {
-                match miniserve_handle.try_wait() {
-                    Ok(status) => {
-                        if let Some(status) = status {
-                            if !status.success() {
-                                pb_serve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                                pb_serve.tick();
-                                pb_serve.finish_with_message(format!(
-                                    "miniserve exited unexpectantly {:?}",
-                                    status
-                                ));
-                                // TODO: Give user option to restart/close
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        pb_serve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                        pb_serve.tick();
-                        pb_serve.finish_with_message(format!("miniserve died: {err}"));
-                        // TODO: Give user option to restart/close
-                    }
-                }
+        match (&self.config.subdomains, self.config.provision_proxy) {
+            (Some(subdomains), _) => {
+                let (proxy, template) = (subdomains.proxy, subdomains.template.clone());
+                self.provision_proxy(proxy, template.as_deref())?;
             }
+            (None, Some(proxy)) => self.provision_proxy(proxy, None)?,
+            (None, None) => {}
+        }
 
-            if self.should_end.load(Ordering::SeqCst) {
-                pb_forward.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-                pb_forward.tick();
-                pb_forward.finish();
+        if self.cli.share.verify && !self.verify_proxy()? {
+            warn!("Reverse proxy verification failed, serving anyway.");
+        }
 
-                pb_serve.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-                pb_serve.tick();
-                pb_serve.finish();
+        if self.cli.share.qr || self.config.qr_code.unwrap_or(false) {
+            self.show_qr_code();
+        }
 
-                pb_exit_info.finish_and_clear();
+        Ok(())
+    }
 
-                return;
-            }
+    /// Changes a setting in the running and the stored config, without persisting anything
+    /// that only came from a project config.
+    fn update_config(&mut self, change: impl Fn(&mut Config)) -> Result<()> {
+        change(&mut self.config);
 
-            sleep(Duration::from_secs(1));
-        }
+        let mut stored = load_config()?;
+        change(&mut stored);
+        save_config(&stored)
     }
 
-    pub fn close(mut self) {
-        let mp = MultiProgress::new();
-        let pb_close = mp.add(ProgressBar::new_spinner());
-        pb_close.set_message("Closing livetunnel");
-        pb_close.enable_steady_tick(Duration::from_millis(20));
-        sleep(Duration::from_secs(1));
+    // Runs a no-op on the remote, the traffic keeps NAT and firewall state alive while idle.
+    // Returns the round trip if it worked:
+    fn send_keepalive(&self, interval: Duration) -> Option<Duration> {
+        let started = Instant::now();
+        let status = self.runtime.block_on(timeout(
+            interval.min(Duration::from_secs(10)),
+            self.ssh_session.command("true").status(),
+        ));
+        matches!(status, Ok(Ok(status)) if status.success()).then(|| started.elapsed())
+    }
 
-        let steps = 2;
+    fn duration(&self) -> Result<Option<Duration>> {
+        match (self.cli.share.duration, &self.config.duration) {
+            (Some(duration), _) => Ok(Some(duration)),
+            (None, Some(duration)) => parse_duration(duration).map(Some).map_err(|err| {
+                Error::Config(format!(
+                    "Invalid duration '{}' in the config: {}",
+                    duration, err
+                ))
+            }),
+            (None, None) => Ok(None),
+        }
+    }
 
-        let pb_ssh = mp.add(ProgressBar::new_spinner());
-        pb_ssh.set_message(format!("[{}/{}] Closing SSH connection", 1, steps));
-        pb_ssh.enable_steady_tick(Duration::from_millis(20));
+    // One of the icons of [serve], with ~ expanded:
+    fn serve_icon(&self, setting: fn(&ServeConfig) -> Option<&Path>) -> Option<PathBuf> {
+        self.config
+            .serve
+            .as_ref()
+            .and_then(setting)
+            .map(expand_home)
+    }
 
-        self.runtime.block_on(self.ssh_session.close()).unwrap();
+    // The [serve] banner for a share of `duration`:
+    fn fill_banner(&self, duration: Option<Duration>) -> Option<String> {
+        let template = self.config.serve.as_ref()?.banner.as_ref()?;
+        let expires = duration
+            .and_then(|duration| Zoned::now().checked_add(duration).ok())
+            .map(|end| end.strftime("%Y-%m-%d %H:%M %Z").to_string())
+            .unwrap_or_else(|| String::from("on close"));
+        let name = self
+            .directory
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Some(
+            template
+                .replace("{url}", &self.public_url().unwrap_or_default())
+                .replace("{name}", &name)
+                .replace("{expires}", &expires),
+        )
+    }
 
-        pb_ssh.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-        pb_ssh.tick();
-        pb_ssh.finish_with_message(format!("[{}/{}] Closed SSH connection", 1, steps));
+    // After how long without requests the builtin server is paused ([serve] idle_pause):
+    fn idle_pause(&self) -> Result<Option<Duration>> {
+        let Some(pause) = self
+            .config
+            .serve
+            .as_ref()
+            .and_then(|serve| serve.idle_pause.as_ref())
+        else {
+            return Ok(None);
+        };
+        parse_duration(pause).map(Some).map_err(|err| {
+            Error::Config(format!(
+                "Invalid idle_pause '{}' in [serve]: {}",
+                pause, err
+            ))
+        })
+    }
 
-        if let Some(miniserve_handle) = &mut self.miniserve_handle {
-            let pb_miniserve = mp.add(ProgressBar::new_spinner());
-            pb_miniserve.set_message(format!("[{}/{}] Closing miniserve", 2, steps));
-            pb_miniserve.enable_steady_tick(Duration::from_millis(20));
+    fn transfer_cap(&self) -> Option<u64> {
+        self.cli.share.transfer_cap.or(self.config.transfer_cap)
+    }
 
-            if miniserve_handle.kill().is_ok() {
-                // miniserve should already be killed by CTRL-C:
-                // https://unix.stackexchange.com/questions/149741/why-is-sigint-not-propagated-to-child-process-when-sent-to-its-parent-process/149756#149756
-                // TODO: Logging?
-            }
+    fn request_forward(&mut self) -> Result<()> {
+        let pb = spinner();
+        pb.set_message(format!(
+            "Starting port-forward from {} to {} via SSH",
+            self.local_end(),
+            self.remote_end()
+        ));
+        pb.enable_steady_tick(Duration::from_millis(20));
 
-            if let Err(err) = miniserve_handle.wait() {
-                pb_miniserve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
-                pb_miniserve.tick();
-                pb_miniserve.finish_with_message(format!("Could not close miniserve: {err}"));
-            } else {
-                pb_miniserve.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-                pb_miniserve.tick();
-                pb_miniserve.finish_with_message(format!(
-                    "[{}/{}] Successfully exited miniserve",
-                    2, steps
+        // Route the forward through the relay so we can account for the transferred data:
+        self.relay = match Relay::start(self.local_end(), self.transfer_cap()) {
+            Ok(relay) => Some(relay),
+            Err(err) => {
+                pb.println(format!(
+                    "❗ Could not start transfer accounting, forwarding directly: {}",
+                    err
                 ));
+                None
+            }
+        };
+
+        if self.config.remote_socket.is_none() {
+            if let Err(err) = self.pick_remote_port(&pb) {
+                pb.finish_and_clear();
+                return Err(err);
             }
         }
 
-        sleep(Duration::from_secs(1));
-        pb_close.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
-        pb_close.tick();
-        pb_close.finish_with_message("Successfully closed livetunnel");
-    }
+        if let Err(err) = self.forward_ports() {
+            pb.finish_and_clear();
+            return Err(Error::Ssh(err));
+        }
 
-    fn build_config() -> Config {
-        let optional_features = vec![
-            OptionalFeatures::CmdBefore,
-            OptionalFeatures::CmdAfter,
-            OptionalFeatures::JumpHosts,
-        ];
+        pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+        pb.tick();
+        pb.finish_with_message(format!(
+            "Started port-forward from {} to {} via SSH{}",
+            self.local_end(),
+            self.remote_end(),
+            self.describe_extra_forwards()
+        ));
+        debug!(
+            forwards = ?self.config.forwards,
+            "SSH forwarding {} to {}",
+            self.local_end(),
+            self.remote_end()
+        );
+        self.emit_forward();
+        Ok(())
+    }
 
-        let selection = MultiSelect::new(
-            "Select which optional Features you'd like to use:",
-            optional_features,
+    fn show_qr_code(&self) {
+        let url = match self.public_url() {
+            Some(url) => url,
+            None => {
+                warn!(
+                    "No public URL to show as QR code, set a domain or public_url in the config."
+                );
+                return;
+            }
+        };
+
+        match QrCode::new(url.as_bytes()) {
+            Ok(code) => {
+                // Inverted, most terminals are dark and scanners expect dark modules on light:
+                let rendered = code
+                    .render::<Dense1x2>()
+                    .dark_color(Dense1x2::Light)
+                    .light_color(Dense1x2::Dark)
+                    .build();
+                logging::show(&rendered);
+                if self.cli.share.secure {
+                    let users: Vec<&str> = self
+                        .config
+                        .users
+                        .iter()
+                        .map(|(user, _)| user.as_str())
+                        .collect();
+                    info!("Log in as {} to open {}", users.join(" or "), url);
+                }
+            }
+            Err(err) => warn!("Could not render a QR code for {}: {}", url, err),
+        }
+    }
+
+    fn copy_share_url(&self, credentials: &[(String, String)]) -> Option<Clipboard> {
+        let url = match self.public_url() {
+            Some(url) => url,
+            None => {
+                warn!("No public URL to copy, set a domain or public_url in the config.");
+                return None;
+            }
+        };
+
+        let mut text = url.clone();
+        for (user, password) in credentials {
+            text.push_str(&format!("\nUser: {}\nPassword: {}", user, password));
+        }
+
+        match Clipboard::new().and_then(|mut clipboard| {
+            clipboard.set_text(text)?;
+            Ok(clipboard)
+        }) {
+            Ok(clipboard) => {
+                info!(
+                    success = true,
+                    "Copied {}{} to the clipboard",
+                    url,
+                    if credentials.is_empty() {
+                        ""
+                    } else {
+                        " and the new credentials"
+                    }
+                );
+                Some(clipboard)
+            }
+            Err(err) => {
+                warn!("Could not copy the URL to the clipboard: {}", err);
+                None
+            }
+        }
+    }
+
+    /// The URL the share is reachable on, from `public_url` or the reverse proxy's domain.
+    fn public_url(&self) -> Option<String> {
+        match (&self.config.public_url, &self.config.domain) {
+            (Some(template), domain) => Some(
+                template
+                    .replace("{domain}", domain.as_deref().unwrap_or_default())
+                    .replace("{port}", &self.config.remote_port.to_string()),
+            ),
+            (None, Some(domain)) => Some(format!("https://{}/", domain)),
+            (None, None) => None,
+        }
+    }
+
+    // The command line template of the external server, if one is used:
+    fn server_command(&self) -> Option<&str> {
+        match &self.config.server_command {
+            Some(command) => Some(command),
+            None if self.config.external_server.unwrap_or(false) => Some(MINISERVE_COMMAND),
+            None => None,
+        }
+    }
+
+    fn server_name(&self) -> &str {
+        self.server_command()
+            .and_then(|command| command.split_whitespace().next())
+            .unwrap_or("the builtin server")
+    }
+
+    fn start_server(&mut self) -> std::io::Result<()> {
+        if let Some(command) = self.server_command() {
+            self.miniserve_handle = Some(self.spawn_server_command(command)?);
+        } else {
+            let users = if self.cli.share.secure {
+                self.config.users.clone()
+            } else {
+                vec![]
+            };
+            self.server = Some(Server::start(
+                &self.runtime,
+                &self.local_end(),
+                &self.directory,
+                users,
+                ServeOptions {
+                    downloads: self.download_limit.clone(),
+                    uploads: self.uploads(),
+                    folder_users: self.folder_users(),
+                    public: self.public.clone().filter(|_| self.cli.share.secure),
+                    live_reload: self.live_reload.clone(),
+                    stats: self.stats.clone(),
+                    max_body: self.max_request_body(),
+                    header_timeout: self.serve_timeout(|serve| serve.header_timeout, 30),
+                    idle_timeout: self.serve_timeout(|serve| serve.idle_timeout, 60),
+                    max_per_client: self
+                        .config
+                        .serve
+                        .as_ref()
+                        .and_then(|serve| serve.max_connections_per_client),
+                    schedule: self.schedule.clone(),
+                    favicon: self.serve_icon(|serve| serve.favicon.as_deref()),
+                    touch_icon: self.serve_icon(|serve| serve.apple_touch_icon.as_deref()),
+                    analytics: self
+                        .config
+                        .serve
+                        .as_ref()
+                        .and_then(|serve| serve.analytics.clone())
+                        .filter(|analytics| !analytics.trim().is_empty()),
+                    banner: self.banner.clone(),
+                    watermark: self.watermark.clone(),
+                    hashed_assets: self.serve_flag(|serve| serve.hashed_assets),
+                    pretty_urls: self.serve_flag(|serve| serve.pretty_urls),
+                    redirects_file: self.serve_flag(|serve| serve.redirects_file),
+                    redirects: self.redirects.clone(),
+                    challenge: self.challenge.clone(),
+                    referrers: self.referrers.clone(),
+                    sessions: self.sessions.clone(),
+                    secret_header: self
+                        .config
+                        .serve
+                        .as_ref()
+                        .and_then(|serve| serve.secret_header.as_ref())
+                        .map(|secret| (secret.name.clone(), hash_password(&secret.token))),
+                },
+            )?);
+            // Idle from here on ([serve] idle_pause):
+            self.stats.active();
+        }
+
+        debug!(
+            directory = %self.directory.display(),
+            "{} started on {}",
+            self.server_name(),
+            self.local_end()
+        );
+        logging::emit(
+            "server_started",
+            json!({
+                "server": if self.server.is_some() { "builtin" } else { self.server_name() },
+                "local_port": self.config.local_port,
+                "directory": self.directory,
+            }),
+        );
+        Ok(())
+    }
+
+    fn spawn_server_command(&self, command: &str) -> std::io::Result<ExternalServer> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "server command is empty")
+        })?;
+        let mut server = tokio::process::Command::new(program);
+
+        for part in parts {
+            match part {
+                "{auth}" => {
+                    if self.cli.share.secure {
+                        for (user, pw) in &self.config.users {
+                            server.args(["-a", &format!("{}:sha512:{}", user, pw)]);
+                        }
+                    }
+                }
+                "{dir}" => {
+                    server.arg(&self.directory);
+                }
+                "{upload}" => {
+                    if self.cli.share.receive {
+                        match &self.config.upload_folders {
+                            Some(folders) if !folders.is_empty() => {
+                                for folder in folders {
+                                    server.arg("-u").arg(folder);
+                                }
+                            }
+                            _ => {
+                                server.arg("-u");
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    server.arg(
+                        part.replace("{port}", &self.config.local_port.to_string())
+                            .replace("{dir}", &self.directory.to_string_lossy()),
+                    );
+                }
+            }
+        }
+
+        // tokio reaps the child and reads its output in the background, which needs the runtime:
+        let _runtime = self.runtime.enter();
+        ExternalServer::spawn(
+            server,
+            self.config.server_access_log.as_deref(),
+            self.stats.clone(),
         )
-        .with_vim_mode(true)
-        .prompt()
-        .unwrap();
-
-        let host = Text::new("SSH Host:")
-            .with_validator(ValueRequiredValidator::default())
-            .prompt()
-            .unwrap();
-
-        let port = if Confirm::new("Set Port?")
-            .with_default(false)
-            .prompt()
-            .unwrap()
+    }
+
+    // Only directories take uploads, the allowed folders are created if needed:
+    fn prepare_uploads(&self) -> Result<()> {
+        if !self.directory.is_dir() {
+            return Err(Error::Config(String::from(
+                "--receive needs a directory to store the uploads in.",
+            )));
+        }
+
+        for folder in self.config.upload_folders.iter().flatten() {
+            if !folder
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                return Err(Error::Config(format!(
+                    "Upload folder '{}' has to be a relative path inside the shared directory.",
+                    folder.display()
+                )));
+            }
+            create_dir_all(self.directory.join(folder))?;
+        }
+        Ok(())
+    }
+
+    fn max_request_body(&self) -> Option<u64> {
+        match self
+            .config
+            .serve
+            .as_ref()
+            .and_then(|serve| serve.max_request_body)
+        {
+            Some(max) => Some(max),
+            None if self.cli.share.receive => None,
+            None => Some(DEFAULT_MAX_REQUEST_BODY),
+        }
+    }
+
+    // A timeout of [serve] in seconds, 0 turns it off:
+    fn serve_timeout(
+        &self,
+        setting: fn(&ServeConfig) -> Option<u64>,
+        default: u64,
+    ) -> Option<Duration> {
+        match self
+            .config
+            .serve
+            .as_ref()
+            .and_then(setting)
+            .unwrap_or(default)
         {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    fn serve_flag(&self, flag: fn(&ServeConfig) -> Option<bool>) -> bool {
+        self.config.serve.as_ref().and_then(flag).unwrap_or(false)
+    }
+
+    fn folder_users(&self) -> Vec<(PathBuf, Vec<String>)> {
+        self.config
+            .serve
+            .as_ref()
+            .and_then(|serve| serve.folder_users.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(folder, users)| (PathBuf::from(folder.trim_matches('/')), users))
+            .collect()
+    }
+
+    fn uploads(&self) -> Option<Uploads> {
+        self.cli.share.receive.then(|| Uploads {
+            max_size: self.config.upload_max_size,
+            folders: self.config.upload_folders.clone().unwrap_or_default(),
+        })
+    }
+
+    // Forwards the shared port through the relay and every extra port directly:
+    fn forward_ports(&self) -> std::result::Result<(), String> {
+        let local_socket = |port| TcpSocket(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+        let remote_socket =
+            |port| TcpSocket(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+
+        let shared_local = match &self.relay {
+            Some(relay) => relay.endpoint().clone(),
+            None => self.local_end(),
+        };
+        let shared_remote = match &self.config.remote_socket {
+            Some(path) => self.remote_unix_socket(path)?,
+            None => remote_socket(self.config.remote_port),
+        };
+
+        self.runtime
+            .block_on(self.ssh_session.request_port_forward(
+                openssh::ForwardType::Remote,
+                shared_remote,
+                match &shared_local {
+                    Endpoint::Port(port) => local_socket(*port),
+                    #[cfg(unix)]
+                    Endpoint::Socket(path) => Socket::UnixSocket {
+                        path: path.as_path().into(),
+                    },
+                },
+            ))
+            .map_err(|err| {
+                format!(
+                    "Could not forward {} to {}: {}",
+                    self.remote_end(),
+                    self.local_end(),
+                    err
+                )
+            })?;
+
+        // sshd creates it for the SSH user only, the reverse proxy runs as another one:
+        if let Some(path) = &self.config.remote_socket {
+            self.run_remote_script(&format!("chmod 666 {}", quote(path)))
+                .map_err(|err| {
+                    format!("Could not open up the remote socket '{}': {}", path, err)
+                })?;
+        }
+
+        for &(local_port, remote_port) in self.extra_forwards() {
+            self.runtime
+                .block_on(self.ssh_session.request_port_forward(
+                    openssh::ForwardType::Remote,
+                    remote_socket(remote_port),
+                    local_socket(local_port),
+                ))
+                .map_err(|err| {
+                    format!(
+                        "Could not forward remote Port {} to local Port {}: {}",
+                        remote_port, local_port, err
+                    )
+                })?;
+        }
+
+        Ok(())
+    }
+
+    // Where the builtin server listens and the shared forward leads:
+    fn local_end(&self) -> Endpoint {
+        match &self.config.local_socket {
+            #[cfg(unix)]
+            Some(path) => Endpoint::Socket(expand_home(path)),
+            _ => Endpoint::Port(self.config.local_port),
+        }
+    }
+
+    /// Makes sure the local Port is free, an external server would only fail silently on it.
+    /// With --any-local-port a free one is picked for this run instead.
+    fn pick_local_port(&mut self) -> Result<()> {
+        let taken = self.config.local_port;
+        if TcpListener::bind((Ipv4Addr::LOCALHOST, taken)).is_ok() {
+            return Ok(());
+        }
+
+        if !self.cli.share.any_local_port {
+            return Err(Error::Server(format!(
+                "Local Port {} is already in use. Pick another one with --local-port or pass --any-local-port.",
+                taken
+            )));
+        }
+
+        let free = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .and_then(|listener| listener.local_addr())
+            .map_err(|err| Error::Server(format!("Could not find a free local Port: {}", err)))?
+            .port();
+        self.config.local_port = free;
+        info!(
+            "Local Port {} is already in use, serving on local Port {} instead",
+            taken, free
+        );
+        Ok(())
+    }
+
+    /// Makes sure nothing listens on the remote Port yet, else moves to a free one of
+    /// `remote_port_range` for this run. Without `ss` or `netstat` on the remote it just tries.
+    fn pick_remote_port(&mut self, pb: &ProgressBar) -> Result<()> {
+        let in_use = match self.remote_ports_in_use() {
+            Some(in_use) => in_use,
+            None => return Ok(()),
+        };
+        let taken = self.config.remote_port;
+        if !in_use.contains(&taken) {
+            return Ok(());
+        }
+
+        let (first, last) = self.config.remote_port_range.ok_or_else(|| {
+            Error::Ssh(format!(
+                "Remote Port {} is already in use on '{}'. Pick another one with --remote-port or set remote_port_range in the config.",
+                taken, self.config.host
+            ))
+        })?;
+        let free = (first..=last)
+            .find(|port| !in_use.contains(port))
+            .ok_or_else(|| {
+                Error::Ssh(format!(
+                    "Remote Port {} and all of {}-{} are already in use on '{}'",
+                    taken, first, last, self.config.host
+                ))
+            })?;
+
+        self.config.remote_port = free;
+        pb.println(format!(
+            "ℹ Remote Port {} is already in use, forwarding to remote Port {} instead",
+            taken, free
+        ));
+        Ok(())
+    }
+
+    // Ports with a TCP listener on the remote, from `ss -tln` (or `netstat -tln` on older
+    // systems). Both show the local address in the 4th column:
+    fn remote_ports_in_use(&self) -> Option<Vec<u16>> {
+        self.runtime.block_on(ports_in_use(&self.ssh_session))
+    }
+
+    // sshd doesn't replace an existing socket file, which one of an earlier run would be:
+    #[cfg(unix)]
+    fn remote_unix_socket<'a>(&self, path: &'a str) -> std::result::Result<Socket<'a>, String> {
+        self.run_remote_script(&format!(
+            "mkdir -p \"$(dirname {path})\" && {{ [ ! -S {path} ] || rm -f {path}; }}",
+            path = quote(path)
+        ))
+        .map_err(|err| format!("Could not prepare the remote socket '{}': {}", path, err))?;
+
+        Ok(Socket::UnixSocket {
+            path: Path::new(path).into(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn remote_unix_socket<'a>(&self, _: &'a str) -> std::result::Result<Socket<'a>, String> {
+        Err(String::from(
+            "Forwarding to a remote Unix socket needs livetunnel to run on Unix",
+        ))
+    }
+
+    fn create_dns_record(&mut self, dns: &DnsConfig) -> Result<()> {
+        let Some(domain) = self.config.domain.clone() else {
+            return Err(Error::Config(String::from(
+                "A [dns] section needs the domain to create the record for, set domain in the config.",
+            )));
+        };
+        let address = match dns.address {
+            Some(address) => address,
+            None => self.server_address().ok_or_else(|| {
+                Error::Config(format!(
+                    "Could not resolve the address of '{}' for the DNS record, set address in the [dns] section.",
+                    self.config.host
+                ))
+            })?,
+        };
+
+        let pb = spinner();
+        pb.set_message(format!("Creating DNS record '{}' for {}", domain, address));
+        pb.enable_steady_tick(Duration::from_millis(20));
+
+        match DnsRecord::create(dns, &domain, address) {
+            Ok(record) => {
+                pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+                pb.tick();
+                pb.finish_with_message(format!("Created DNS record '{}' for {}", domain, address));
+                self.dns_record = Some(record);
+                Ok(())
+            }
+            Err(err) => {
+                pb.finish_and_clear();
+                Err(Error::Config(format!(
+                    "Could not create the DNS record '{}': {}",
+                    domain, err
+                )))
+            }
+        }
+    }
+
+    fn remove_dns_record(&mut self) {
+        let Some(record) = self.dns_record.take() else {
+            return;
+        };
+
+        let name = record.name().to_string();
+        match record.remove() {
+            Ok(()) => info!(success = true, "Removed the DNS record '{}'", name),
+            Err(err) => warn!("Could not remove the DNS record '{}': {}", name, err),
+        }
+    }
+
+    // The address the SSH host resolves to, following host aliases:
+    fn server_address(&self) -> Option<IpAddr> {
+        let host = ssh_config::resolve(&self.config.host)
+            .and_then(|alias| alias.host_name)
+            .unwrap_or_else(|| self.config.host.clone());
+        (host.as_str(), 22)
+            .to_socket_addrs()
+            .ok()?
+            .next()
+            .map(|address| address.ip())
+    }
+
+    fn run_remote_script(&self, script: &str) -> std::result::Result<(), String> {
+        match self.runtime.block_on(
+            self.ssh_session
+                .command("sh")
+                .arg("-c")
+                .arg(script)
+                .output(),
+        ) {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    // Where the shared forward ends on the remote, for messages:
+    fn remote_end(&self) -> String {
+        match &self.config.remote_socket {
+            Some(path) => format!("remote socket '{}'", path),
+            None => format!("remote Port {}", self.config.remote_port),
+        }
+    }
+
+    // curl arguments that fetch `path` from the shared forward on the remote:
+    fn remote_curl_target(&self, path: &str) -> String {
+        match &self.config.remote_socket {
+            Some(socket) => format!("--unix-socket {} http://localhost/{}", quote(socket), path),
+            None => format!("http://127.0.0.1:{}/{}", self.config.remote_port, path),
+        }
+    }
+
+    fn extra_forwards(&self) -> &[(u16, u16)] {
+        self.config.forwards.as_deref().unwrap_or_default()
+    }
+
+    fn emit_forward(&self) {
+        logging::emit(
+            "forward_established",
+            json!({
+                "local_port": self.config.local_port,
+                "local_socket": self.config.local_socket,
+                "remote_port": self.config.remote_socket.is_none().then_some(self.config.remote_port),
+                "remote_socket": self.config.remote_socket,
+                "forwards": self.config.forwards,
+            }),
+        );
+    }
+
+    // Tells the webhooks of [notifications] about `event`, once sharing started:
+    fn notify(&self, event: &'static str, message: String, fields: serde_json::Value) {
+        if let Some(notifier) = &self.notifier {
+            notifier.send(event, message, fields);
+        }
+    }
+
+    fn notify_desktop(&self, summary: &str, body: &str) {
+        if self.cli.share.notify || self.config.desktop_notifications.unwrap_or(false) {
+            desktop::notify(summary, body);
+        }
+    }
+
+    // e.g. " (and local Port 8080 to remote Port 9080)" for the extra forwards:
+    fn describe_extra_forwards(&self) -> String {
+        if self.extra_forwards().is_empty() {
+            return String::new();
+        }
+
+        let forwards: Vec<String> = self
+            .extra_forwards()
+            .iter()
+            .map(|(local_port, remote_port)| {
+                format!("local Port {} to remote Port {}", local_port, remote_port)
+            })
+            .collect();
+        format!(" (and {})", forwards.join(", "))
+    }
+
+    // e.g. "2 visitor(s) connected, 1 download(s) running", downloads only show for the
+    // builtin server, external ones just log finished requests:
+    fn describe_visitors(&self) -> String {
+        let (visitors, downloads) = self.stats.live();
+        if self.server_command().is_some() {
+            format!("{} visitor(s) connected", visitors)
+        } else {
+            format!(
+                "{} visitor(s) connected, {} download(s) running",
+                visitors, downloads
+            )
+        }
+    }
+
+    /// Rebuilds the SSH session and the forward with exponential backoff, leaving the local
+    /// server untouched. Returns false once all attempts failed or the user quit meanwhile.
+    fn reconnect(&mut self, pb: &ProgressBar) -> bool {
+        let max_attempts = self.config.reconnect_attempts.unwrap_or(5);
+
+        for attempt in 1..=max_attempts {
+            pb.set_message(format!(
+                "SSH connection lost, reconnecting to '{}' (attempt {}/{})",
+                self.config.host, attempt, max_attempts
+            ));
+
+            let session = self
+                .runtime
+                .block_on(connect(&self.config, self.ssh_password.as_deref()));
+
+            match session {
+                Ok(session) => {
+                    self.ssh_session = Arc::new(session);
+                    match self.forward_ports() {
+                        Ok(()) => {
+                            debug!("SSH reconnected to '{}'", self.config.host);
+                            logging::emit(
+                                "ssh_connected",
+                                json!({ "host": self.config.host, "reconnect": true }),
+                            );
+                            self.emit_forward();
+                            self.notify(
+                                "reconnected",
+                                format!(
+                                    "Reconnected to '{}' after the SSH connection dropped, sharing '{}' again",
+                                    self.config.host,
+                                    self.directory.display()
+                                ),
+                                json!({ "host": self.config.host, "attempt": attempt }),
+                            );
+                            return true;
+                        }
+                        Err(err) => debug!(attempt, "SSH forward failed: {}", err),
+                    }
+                }
+                Err(err) => debug!(attempt, "SSH reconnecting failed: {}", err),
+            }
+
+            if attempt == max_attempts {
+                break;
+            }
+
+            let delay = Duration::from_secs(2u64.saturating_pow(attempt - 1).min(60));
+            pb.set_message(format!(
+                "Reconnecting to '{}' failed (attempt {}/{}), retrying in {}s",
+                self.config.host,
+                attempt,
+                max_attempts,
+                delay.as_secs()
+            ));
+
+            let retry_at = Instant::now() + delay;
+            while Instant::now() < retry_at {
+                if self.should_end.load(Ordering::SeqCst) {
+                    return false;
+                }
+                sleep(Duration::from_millis(100));
+            }
+        }
+
+        false
+    }
+
+    /// Reconnects until it works or the user (or `on_disconnect` without a terminal) gives up.
+    /// The local server keeps serving all along.
+    fn recover_connection(&mut self, mp: &MultiProgress, pb: &ProgressBar) -> bool {
+        if self.config.reconnect_attempts == Some(0) {
+            return false;
+        }
+
+        loop {
+            if self.reconnect(pb) {
+                return true;
+            }
+            if self.should_end.load(Ordering::SeqCst) {
+                return false;
+            }
+
+            let again = if self.cli.non_interactive {
+                self.config.on_disconnect == Some(DisconnectPolicy::Retry)
+            } else {
+                pb.set_message(format!("Could not reconnect to '{}'", self.config.host));
+                let action = mp.suspend(|| {
+                    Select::new(
+                        "The SSH connection is gone, what do you want to do?",
+                        vec![DisconnectAction::Reconnect, DisconnectAction::Quit],
+                    )
+                    .with_vim_mode(true)
+                    .prompt()
+                });
+                matches!(action, Ok(DisconnectAction::Reconnect))
+            };
+
+            if !again {
+                return false;
+            }
+        }
+    }
+
+    pub fn close(mut self) {
+        debug!("Closing");
+        if self.cli.share.daemonized {
+            remove_state(std::process::id());
+        }
+
+        // Leaves the final state behind, the tunnel is gone from now on:
+        if let Some((remote_path, options)) = &self.hybrid {
+            if let Err(err) = self.sync(remote_path, options) {
+                warn!("Could not sync the final state: {}", err);
+            }
+        }
+        // A hybrid share's config stays, it serves the synced copy from now on. Random subdomains
+        // are never used again though:
+        if self.hybrid.is_none() || self.config.subdomains.is_some() {
+            self.remove_proxy();
+            self.remove_dns_record();
+        }
+
+        let mp = multi_progress();
+        let pb_close = mp.add(spinner());
+        pb_close.set_message("Closing livetunnel");
+        pb_close.enable_steady_tick(Duration::from_millis(20));
+        sleep(Duration::from_secs(1));
+
+        let steps = 2;
+
+        let pb_ssh = mp.add(spinner());
+        pb_ssh.set_message(format!("[{}/{}] Closing SSH connection", 1, steps));
+        pb_ssh.enable_steady_tick(Duration::from_millis(20));
+
+        // Nothing checks it anymore, otherwise it's closed once that lets go of it:
+        let closed = match Arc::try_unwrap(self.ssh_session) {
+            Ok(session) => self.runtime.block_on(session.close()),
+            Err(_) => Ok(()),
+        };
+        if let Err(err) = closed {
+            pb_ssh.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+            pb_ssh.tick();
+            pb_ssh.finish_with_message(format!(
+                "[{}/{}] Could not close the SSH connection cleanly: {}",
+                1, steps, err
+            ));
+        } else {
+            pb_ssh.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+            pb_ssh.tick();
+            pb_ssh.finish_with_message(format!("[{}/{}] Closed SSH connection", 1, steps));
+        }
+        debug!("SSH connection to '{}' closed", self.config.host);
+        askpass::cleanup();
+        if let Some(buffer) = self.stdin_buffer.take() {
+            let _ = buffer.close();
+        }
+        if let Some(directory) = self.scratch_directory.take() {
+            let path = directory.path().to_path_buf();
+            match directory.close() {
+                Ok(()) => info!(success = true, "Deleted the scratch directory"),
+                Err(err) => warn!(
+                    "Could not delete the scratch directory '{}': {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+
+        if let Some(miniserve_handle) = self.miniserve_handle.take() {
+            let pb_miniserve = mp.add(spinner());
+            pb_miniserve.set_message(format!("[{}/{}] Closing miniserve", 2, steps));
+            pb_miniserve.enable_steady_tick(Duration::from_millis(20));
+
+            if let Err(err) = miniserve_handle.stop(&self.runtime) {
+                pb_miniserve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb_miniserve.tick();
+                pb_miniserve.finish_with_message(format!("Could not close miniserve: {err}"));
+            } else {
+                pb_miniserve.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+                pb_miniserve.tick();
+                pb_miniserve.finish_with_message(format!(
+                    "[{}/{}] Successfully exited miniserve",
+                    2, steps
+                ));
+            }
+        }
+
+        if let Some(waker) = self.paused.take() {
+            waker.stop(&self.runtime);
+        }
+        if let Some(server) = self.server.take() {
+            let pb_server = mp.add(spinner());
+            pb_server.set_message(format!("[{}/{}] Stopping the builtin server", 2, steps));
+            pb_server.enable_steady_tick(Duration::from_millis(20));
+
+            server.stop(&self.runtime);
+
+            pb_server.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+            pb_server.tick();
+            pb_server.finish_with_message(format!(
+                "[{}/{}] Successfully stopped the builtin server",
+                2, steps
+            ));
+        }
+
+        sleep(Duration::from_secs(1));
+        pb_close.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+        pb_close.tick();
+
+        let transferred = self.relay.take().map(|relay| {
+            let transferred = relay.transferred();
+            relay.stop();
+            transferred
+        });
+        match transferred {
+            Some(transferred) => pb_close.finish_with_message(format!(
+                "Successfully closed livetunnel ({} transferred through the tunnel)",
+                DecimalBytes(transferred)
+            )),
+            None => pb_close.finish_with_message("Successfully closed livetunnel"),
+        }
+
+        let (requests, clients, sent) = self.stats.totals();
+        let timeouts = self.stats.timeouts();
+        if requests > 0 {
+            info!(
+                "Served {} request(s) to {} client(s), {} sent{}:\n{}",
+                requests,
+                clients,
+                DecimalBytes(sent),
+                describe_timeouts(timeouts),
+                self.stats.table()
+            );
+        }
+        logging::emit(
+            "shutdown",
+            json!({
+                "transferred": transferred,
+                "requests": requests,
+                "clients": clients,
+                "sent": sent,
+                "timeouts": timeouts,
+            }),
+        );
+        if let Some(notifier) = &self.notifier {
+            notifier.send(
+                "shutdown",
+                format!(
+                    "Stopped sharing '{}' after {} request(s) from {} client(s), {} sent",
+                    self.directory.display(),
+                    requests,
+                    clients,
+                    DecimalBytes(sent)
+                ),
+                json!({
+                    "transferred": transferred,
+                    "requests": requests,
+                    "clients": clients,
+                    "sent": sent,
+                }),
+            );
+            notifier.finish();
+        }
+    }
+
+    /// Shares the scratch directory made by [`create_scratch_directory`], after opening it in
+    /// the file manager if asked to (`livetunnel scratch`). It's deleted with [`App::close`].
+    pub fn scratch(&mut self, directory: TempDir, open: bool) -> Result<()> {
+        self.scratch_directory = Some(directory);
+        info!(
+            "Sharing the scratch directory '{}', it is deleted when livetunnel closes",
+            self.directory.display()
+        );
+
+        if open {
+            #[cfg(target_os = "macos")]
+            let opener = "open";
+            #[cfg(target_os = "windows")]
+            let opener = "explorer";
+            #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+            let opener = "xdg-open";
+
+            if let Err(err) = Command::new(opener)
+                .arg(&self.directory)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+            {
+                warn!("Could not open the file manager ({}): {}", opener, err);
+            }
+        }
+
+        self.run()
+    }
+
+    /// Runs the setup assistant on the stored config (`livetunnel config setup`).
+    pub fn setup_config() -> Result<()> {
+        info!("Starting setup assistant:");
+        let previous = load_config().unwrap_or_default();
+        Self::build_config(&previous)?;
+        Ok(())
+    }
+
+    fn build_config(previous: &Config) -> Result<Config> {
+        // Only what is picked is asked for again, the rest is kept:
+        let mut section = if previous.host.is_empty() {
+            ConfigSection::Everything
+        } else {
+            Self::ask_section()?
+        };
+
+        let mut config = previous.clone();
+        loop {
+            if matches!(section, ConfigSection::Everything | ConfigSection::Features) {
+                Self::ask_features(&mut config)?;
+            }
+            if matches!(
+                section,
+                ConfigSection::Everything | ConfigSection::Connection
+            ) {
+                Self::ask_connection(&mut config)?;
+            }
+            if matches!(section, ConfigSection::Everything | ConfigSection::Ports) {
+                Self::ask_ports(&mut config)?;
+            }
+            if matches!(section, ConfigSection::Everything | ConfigSection::Users) {
+                let user_choice = Confirm::new("Do you want to add Users for secure sharing now? (You can always add users later when using the -s option)")
+                    .with_default(false)
+                    .prompt()?;
+
+                if user_choice {
+                    config.users.extend(hash_users(&Self::add_users()?));
+                }
+            }
+
+            // Mistakes show up now instead of on the next share:
+            if !Confirm::new("Test this configuration now?")
+                .with_default(true)
+                .prompt()?
+            {
+                break;
+            }
+            let failures = validate::test_config(&config)?;
+            if failures.is_empty() {
+                info!(success = true, "The configuration works");
+                break;
+            }
+
+            for failure in &failures {
+                warn!("{}", failure);
+            }
+            if !Confirm::new("Change it before it's saved?")
+                .with_default(true)
+                .prompt()?
+            {
+                break;
+            }
+            section = Self::ask_section()?;
+        }
+
+        save_config(&config)?;
+
+        Ok(config)
+    }
+
+    fn ask_section() -> Result<ConfigSection> {
+        Ok(Select::new(
+            "Which section do you want to change?",
+            vec![
+                ConfigSection::Everything,
+                ConfigSection::Connection,
+                ConfigSection::Ports,
+                ConfigSection::Users,
+                ConfigSection::Features,
+            ],
+        )
+        .with_vim_mode(true)
+        .prompt()?)
+    }
+
+    fn ask_connection(config: &mut Config) -> Result<()> {
+        let host = match Self::pick_host(&config.host)? {
+            Some(host) => host,
+            None => Text::new("SSH Host:")
+                .with_validator(|input: &str| match parse_host(input) {
+                    Ok(_) => Ok(Validation::Valid),
+                    Err(err) => Ok(Validation::Invalid(err.into())),
+                })
+                .with_placeholder("[user@]host[:port]")
+                .prompt()?,
+        };
+
+        // Already validated by the prompt:
+        let host_spec = parse_host(&host).unwrap();
+        config.host = host_spec.host;
+
+        // ssh applies what its config sets for an alias, no need to ask for it again:
+        let alias = ssh_config::resolve(&config.host).unwrap_or_default();
+        if !alias.is_empty() {
+            info!(
+                "Using '{}' from ~/.ssh/config ({})",
+                config.host,
+                describe_alias(&alias)
+            );
+        }
+
+        config.port = if host_spec.port.is_some() || alias.port.is_some() {
+            host_spec.port
+        } else if Confirm::new("Set Port?").with_default(false).prompt()? {
             Some(
                 CustomType::<u16>::new("SSH Port:")
                     .with_default(22)
                     .with_error_message("Not a valid Port Number")
-                    .prompt()
-                    .unwrap(),
+                    .prompt()?,
             )
         } else {
             None
         };
 
-        let username = if Confirm::new("Set Username?")
-            .with_default(false)
-            .prompt()
-            .unwrap()
-        {
+        config.username = if host_spec.user.is_some() || alias.user.is_some() {
+            host_spec.user
+        } else if Confirm::new("Set Username?").with_default(false).prompt()? {
             Some(
                 Text::new("SSH user:")
                     .with_validator(ValueRequiredValidator::default())
                     .with_default("root")
-                    .prompt()
-                    .unwrap(),
+                    .prompt()?,
             )
         } else {
             None
         };
 
-        let keyfile = if Confirm::new("Set Keyfile?")
-            .with_default(false)
-            .prompt()
-            .unwrap()
-        {
+        config.keyfile = if alias.identity_file.is_some() {
+            None
+        } else if Confirm::new("Set Keyfile?").with_default(false).prompt()? {
             Some(
                 Text::new("SSH Keyfile:")
                     .with_validator(|input: &str| {
@@ -587,156 +2383,1139 @@ impl App {
                         }
                     })
                     .with_placeholder("~/.ssh/id_rsa")
-                    .prompt()
-                    .unwrap()
+                    .prompt()?
                     .into(),
             )
         } else {
             None
         };
 
-        let remote_port = CustomType::<u16>::new("Remote Port to forward to:")
-            .with_error_message("Not a valid Port Number")
-            .prompt()
-            .unwrap();
+        Ok(())
+    }
 
-        let local_port = CustomType::<u16>::new("Local Port to host on / forward:")
-            .with_default(3000)
-            .with_error_message("Not a valid Port Number")
-            .prompt()
-            .unwrap();
+    // One of the hosts in ~/.ssh/config and known_hosts, None to type another one. Starts at the
+    // current host:
+    fn pick_host(current: &str) -> Result<Option<String>> {
+        let mut hosts: Vec<String> = ssh_config::known_hosts()
+            .into_iter()
+            .filter(|host| parse_host(host).is_ok())
+            .collect();
+        if hosts.is_empty() {
+            return Ok(None);
+        }
 
-        let user_choice = Confirm::new("Do you want to add Users for secure sharing now? (You can always add users later when using the -s option)")
-            .with_default(false)
-            .prompt()
-            .unwrap();
+        let cursor = hosts
+            .iter()
+            .position(|host| parse_host(host).is_ok_and(|spec| spec.host == current))
+            .unwrap_or_default();
+        hosts.push(String::from(OTHER_HOST));
+        let host = Select::new("SSH Host (type to filter):", hosts)
+            .with_starting_cursor(cursor)
+            .prompt()?;
 
-        let mut users = Vec::new();
-        if user_choice {
-            users = Self::add_users();
+        Ok((host != OTHER_HOST).then_some(host))
+    }
+
+    // Prefilled with the Ports of the config being edited:
+    fn ask_ports(config: &mut Config) -> Result<()> {
+        // Forwarded to remote_socket instead:
+        if config.remote_socket.is_none() {
+            let mut prompt = CustomType::<u16>::new("Remote Port to forward to:")
+                .with_error_message("Not a valid Port Number");
+            if config.remote_port != 0 {
+                prompt = prompt.with_default(config.remote_port);
+            }
+            config.remote_port = prompt.prompt()?;
         }
 
-        let mut before_cmd: Vec<(String, String)> = vec![];
-        let mut after_cmd: Vec<(String, String)> = vec![];
+        let local_port = match config.local_port {
+            0 => 3000,
+            port => port,
+        };
+        config.local_port = CustomType::<u16>::new("Local Port to host on / forward:")
+            .with_default(local_port)
+            .with_error_message("Not a valid Port Number")
+            .prompt()?;
+
+        Ok(())
+    }
+
+    // The optional features and their settings, the ones not selected are removed:
+    fn ask_features(config: &mut Config) -> Result<()> {
+        let (editor, editor_args) = editor_command(config.editor.as_deref());
+        let editor_args: Vec<&OsStr> = editor_args.iter().map(OsString::as_os_str).collect();
+
+        let optional_features = vec![
+            OptionalFeatures::CmdBefore,
+            OptionalFeatures::CmdAfter,
+            OptionalFeatures::JumpHosts,
+            OptionalFeatures::PasswordAuth,
+            OptionalFeatures::RemoteSocket,
+            OptionalFeatures::Heartbeat,
+            OptionalFeatures::TransferCap,
+            OptionalFeatures::ServerCommand,
+        ];
+
+        // Preselect what is already in use when reconfiguring:
+        let enabled_features: Vec<usize> = optional_features
+            .iter()
+            .enumerate()
+            .filter(|(_, feature)| match feature {
+                OptionalFeatures::CmdBefore => config.before_commands.is_some(),
+                OptionalFeatures::CmdAfter => config.after_commands.is_some(),
+                OptionalFeatures::JumpHosts => config.jump_hosts.is_some(),
+                OptionalFeatures::PasswordAuth => config.password_auth.unwrap_or(false),
+                OptionalFeatures::RemoteSocket => config.remote_socket.is_some(),
+                OptionalFeatures::Heartbeat => config.heartbeat_url.is_some(),
+                OptionalFeatures::TransferCap => config.transfer_cap.is_some(),
+                OptionalFeatures::ServerCommand => config.server_command.is_some(),
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        let selection = MultiSelect::new(
+            "Select which optional Features you'd like to use:",
+            optional_features,
+        )
+        .with_default(&enabled_features)
+        .with_vim_mode(true)
+        .prompt()?;
+
+        let mut before_cmd: Vec<ConnectCommand> = vec![];
+        let mut after_cmd: Vec<ConnectCommand> = vec![];
         let mut jump_h: Vec<String> = vec![];
+        let mut heartbeat_url = None;
+        let mut heartbeat_interval = None;
+        let mut transfer_cap = None;
+        let mut server_command = None;
+        let mut password_auth = None;
+        let mut remote_socket = None;
 
         for entry in selection {
             match entry {
                 OptionalFeatures::CmdBefore => {
-                    let cmd = Editor::new("Which commands should be run before making the SSH connection (One per line):")
+                    if let Some(commands) = config.before_commands.clone() {
+                        before_cmd = Self::edit_commands(
+                            "Commands run (locally) before making the SSH connection ({remote_port}, {local_port}, {host} and {dir} are replaced)",
+                            commands,
+                            true,
+                        )?;
+                        continue;
+                    }
+
+                    let cmd = Editor::new("Which commands should be run before making the SSH connection (One per line, {remote_port}, {local_port}, {host} and {dir} are replaced):")
                         .with_validator(ValueRequiredValidator::default())
-                        .with_editor_command(std::ffi::OsStr::new("vim"))
+                        .with_editor_command(&editor)
+                        .with_args(&editor_args)
                         .prompt();
 
-                    if cmd.is_err() {
-                        continue;
-                    }
+                    let cmd = match cmd {
+                        Ok(cmd) => cmd,
+                        Err(InquireError::OperationInterrupted) => return Err(Error::Aborted),
+                        // Skipped or the editor didn't start, leave the feature out:
+                        Err(_) => continue,
+                    };
 
-                    for line in cmd.unwrap().lines() {
-                        let command = line.split_once(' ');
-                        match command {
-                            // (program) (Arguments)
-                            Some(x) => before_cmd.push((String::from(x.0), String::from(x.1))),
-                            None => before_cmd.push((String::from(line), String::new())),
-                        }
+                    before_cmd.extend(parse_commands(&cmd));
+                    if Confirm::new("Set what happens when one of them fails or hangs? (Otherwise a failing one is only reported)")
+                        .with_default(false)
+                        .prompt()?
+                    {
+                        before_cmd = Self::edit_commands(
+                            "Commands run (locally) before making the SSH connection",
+                            before_cmd,
+                            true,
+                        )?;
                     }
                 }
 
                 OptionalFeatures::CmdAfter => {
-                    let cmd = Editor::new("Which commands should be run (remotly) after making the SSH connection (One per line):")
+                    if let Some(commands) = config.after_commands.clone() {
+                        after_cmd = Self::edit_commands(
+                            "Commands run (remotely) after making the SSH connection ({remote_port}, {local_port}, {host} and {dir} are replaced)",
+                            commands,
+                            false,
+                        )?;
+                        continue;
+                    }
+
+                    let cmd = Editor::new("Which commands should be run (remotly) after making the SSH connection (One per line, {remote_port}, {local_port}, {host} and {dir} are replaced):")
                         .with_validator(ValueRequiredValidator::default())
-                        .with_editor_command(std::ffi::OsStr::new("vim"))
+                        .with_editor_command(&editor)
+                        .with_args(&editor_args)
                         .prompt();
 
-                    if cmd.is_err() {
-                        continue;
-                    }
+                    let cmd = match cmd {
+                        Ok(cmd) => cmd,
+                        Err(InquireError::OperationInterrupted) => return Err(Error::Aborted),
+                        // Skipped or the editor didn't start, leave the feature out:
+                        Err(_) => continue,
+                    };
 
-                    for line in cmd.unwrap().lines() {
-                        let command = line.split_once(' ');
-                        match command {
-                            // (program) (Arguments)
-                            Some(x) => after_cmd.push((String::from(x.0), String::from(x.1))),
-                            None => after_cmd.push((String::from(line), String::new())),
-                        }
-                    }
+                    after_cmd.extend(parse_commands(&cmd));
                 }
 
                 OptionalFeatures::JumpHosts => {
-                    let cmd = Editor::new("Please specify your List of Jump-Hosts (one per line):")
+                    let cmd = Editor::new("Please specify your List of Jump-Hosts (one per line, [user@]host[:port]):")
                         .with_validator(ValueRequiredValidator::default())
-                        .with_editor_command(std::ffi::OsStr::new("vim"))
+                        .with_validator(|input: &str| {
+                            for (i, line) in input.lines().enumerate() {
+                                if line.trim().is_empty() {
+                                    continue;
+                                }
+
+                                if let Err(err) = parse_host(line) {
+                                    return Ok(Validation::Invalid(
+                                        format!("Line {}: {}", i + 1, err).into(),
+                                    ));
+                                }
+                            }
+
+                            Ok(Validation::Valid)
+                        })
+                        .with_editor_command(&editor)
+                        .with_args(&editor_args)
                         .prompt();
 
-                    if cmd.is_err() {
-                        continue;
+                    let cmd = match cmd {
+                        Ok(cmd) => cmd,
+                        Err(InquireError::OperationInterrupted) => return Err(Error::Aborted),
+                        // Skipped or the editor didn't start, leave the feature out:
+                        Err(_) => continue,
+                    };
+
+                    for line in cmd.lines() {
+                        if !line.trim().is_empty() {
+                            jump_h.push(String::from(line.trim()));
+                        }
+                    }
+                }
+
+                OptionalFeatures::PasswordAuth => password_auth = Some(true),
+
+                OptionalFeatures::RemoteSocket => {
+                    let mut prompt = Text::new("Unix socket on the remote to forward to:")
+                        .with_validator(|input: &str| {
+                            if input.starts_with('/') {
+                                Ok(Validation::Valid)
+                            } else {
+                                Ok(Validation::Invalid("Needs an absolute path".into()))
+                            }
+                        })
+                        .with_placeholder("/run/livetunnel/site.sock");
+                    if let Some(path) = &config.remote_socket {
+                        prompt = prompt.with_default(path);
                     }
 
-                    for line in cmd.unwrap().lines() {
-                        jump_h.push(String::from(line));
+                    remote_socket = Some(prompt.prompt()?);
+                }
+
+                OptionalFeatures::Heartbeat => {
+                    heartbeat_url = Some(
+                        Text::new("Heartbeat URL (pinged while healthy, '<URL>/fail' on failure):")
+                            .with_validator(ValueRequiredValidator::default())
+                            .with_placeholder("https://hc-ping.com/<uuid>")
+                            .prompt()?,
+                    );
+
+                    heartbeat_interval = Some(
+                        CustomType::<u64>::new("Heartbeat interval in seconds:")
+                            .with_default(60)
+                            .with_error_message("Not a valid number of seconds")
+                            .prompt()?,
+                    );
+                }
+
+                OptionalFeatures::TransferCap => {
+                    let cap = Text::new("Maximum data per session:")
+                        .with_validator(|input: &str| match parse_size(input) {
+                            Ok(_) => Ok(Validation::Valid),
+                            Err(err) => Ok(Validation::Invalid(err.into())),
+                        })
+                        .with_placeholder("10G")
+                        .prompt()?;
+
+                    transfer_cap = parse_size(&cap).ok();
+                }
+
+                OptionalFeatures::ServerCommand => {
+                    let mut prompt = Text::new("Server command:")
+                        .with_validator(ValueRequiredValidator::default())
+                        .with_placeholder(
+                            "python3 -m http.server {port} --bind 127.0.0.1 --directory {dir}",
+                        )
+                        .with_help_message(
+                            "{port} = local Port, {dir} = shared directory, {auth} = miniserve style '-a user:sha512:hash' for every user, {upload} = miniserve style '-u' with --receive",
+                        );
+                    if let Some(command) = &config.server_command {
+                        prompt = prompt.with_default(command);
                     }
+
+                    server_command = Some(prompt.prompt()?);
                 }
             }
         }
 
-        let config = Config {
-            before_commands: if before_cmd.is_empty() {
-                None
-            } else {
-                Some(before_cmd)
-            },
-            after_commands: if after_cmd.is_empty() {
-                None
-            } else {
-                Some(after_cmd)
-            },
-            host,
-            port,
-            username,
-            keyfile,
-            jump_hosts: if jump_h.is_empty() {
-                None
-            } else {
-                Some(jump_h)
-            },
-            local_port,
-            remote_port,
-            users,
+        config.before_commands = if before_cmd.is_empty() {
+            None
+        } else {
+            Some(before_cmd)
+        };
+        config.after_commands = if after_cmd.is_empty() {
+            None
+        } else {
+            Some(after_cmd)
+        };
+        config.jump_hosts = if jump_h.is_empty() {
+            None
+        } else {
+            Some(jump_h)
         };
+        config.password_auth = password_auth;
+        config.remote_socket = remote_socket;
+        config.heartbeat_url = heartbeat_url;
+        config.heartbeat_interval = heartbeat_interval;
+        config.transfer_cap = transfer_cap;
+        config.server_command = server_command;
+
+        Ok(())
+    }
+
+    /// Lets the user change single entries of an existing command list instead of retyping it,
+    /// with `settings` also what happens when one fails (before commands only).
+    fn edit_commands(
+        title: &str,
+        mut commands: Vec<ConnectCommand>,
+        settings: bool,
+    ) -> Result<Vec<ConnectCommand>> {
+        loop {
+            info!("{}:", title);
+            for (i, command) in commands.iter().enumerate() {
+                println!("  {}. {}", i + 1, command.summary());
+            }
+
+            let mut actions = vec![ListAction::Add];
+            if !commands.is_empty() {
+                actions.extend([ListAction::Edit, ListAction::Remove]);
+            }
+            if commands.len() > 1 {
+                actions.extend([ListAction::MoveUp, ListAction::MoveDown]);
+            }
+            if settings && !commands.is_empty() {
+                actions.push(ListAction::Settings);
+            }
+            actions.push(ListAction::Done);
+
+            let action = Select::new("What do you want to change?", actions)
+                .with_vim_mode(true)
+                .prompt()?;
+
+            match action {
+                ListAction::Add => {
+                    let line = Text::new("New command:")
+                        .with_validator(ValueRequiredValidator::default())
+                        .prompt()?;
+                    commands.push(ConnectCommand::new(&line));
+                }
+                ListAction::Edit => {
+                    let i = Self::pick_command("Which command do you want to edit?", &commands)?;
+                    // Only the command line, its dir and env stay:
+                    let line = Text::new("Command:")
+                        .with_initial_value(&commands[i].command)
+                        .with_validator(ValueRequiredValidator::default())
+                        .prompt()?;
+                    commands[i].command = String::from(line.trim());
+                }
+                ListAction::Remove => {
+                    let i = Self::pick_command("Which command do you want to remove?", &commands)?;
+                    commands.remove(i);
+                }
+                ListAction::MoveUp => {
+                    let i = Self::pick_command("Which command do you want to move up?", &commands)?;
+                    if i > 0 {
+                        commands.swap(i, i - 1);
+                    }
+                }
+                ListAction::MoveDown => {
+                    let i =
+                        Self::pick_command("Which command do you want to move down?", &commands)?;
+                    if i + 1 < commands.len() {
+                        commands.swap(i, i + 1);
+                    }
+                }
+                ListAction::Settings => {
+                    let i = Self::pick_command("Which command do you want to set up?", &commands)?;
+                    Self::ask_command_settings(&mut commands[i])?;
+                }
+                ListAction::Done => return Ok(commands),
+            }
+        }
+    }
 
-        store("livetunnel", "livetunnel", &config).unwrap();
+    fn ask_command_settings(command: &mut ConnectCommand) -> Result<()> {
+        let on_failure = Select::new(
+            &format!("When '{}' fails:", command.command),
+            vec![FailurePolicy::Continue, FailurePolicy::Abort],
+        )
+        .with_starting_cursor(usize::from(
+            command.on_failure == Some(FailurePolicy::Abort),
+        ))
+        .with_vim_mode(true)
+        .prompt()?;
+        command.on_failure = (on_failure == FailurePolicy::Abort).then_some(on_failure);
+
+        let timeout = Text::new("Stop it after (empty for no limit):")
+            .with_initial_value(command.timeout.as_deref().unwrap_or_default())
+            .with_placeholder("5m")
+            .with_validator(|input: &str| match input.trim() {
+                "" => Ok(Validation::Valid),
+                input => match parse_duration(input) {
+                    Ok(_) => Ok(Validation::Valid),
+                    Err(err) => Ok(Validation::Invalid(err.into())),
+                },
+            })
+            .prompt()?;
+        command.timeout = Some(String::from(timeout.trim())).filter(|timeout| !timeout.is_empty());
+
+        let retries = CustomType::<u32>::new("How often to try it again when it fails:")
+            .with_default(command.retries.unwrap_or(0))
+            .with_error_message("Not a valid number")
+            .prompt()?;
+        command.retries = (retries > 0).then_some(retries);
+
+        Ok(())
+    }
+
+    fn pick_command(message: &str, commands: &[ConnectCommand]) -> Result<usize> {
+        let options: Vec<String> = commands.iter().map(ConnectCommand::summary).collect();
 
-        config
+        Ok(Select::new(message, options)
+            .with_vim_mode(true)
+            .raw_prompt()?
+            .index)
     }
 
-    fn add_users() -> Vec<(String, String)> {
-        let mut hasher = Sha512::new();
+    // Asks for users and their passwords, in plain text:
+    fn add_users() -> Result<Vec<(String, String)>> {
         let mut users = Vec::new();
 
         loop {
             let user = Text::new("Username:")
                 .with_validator(ValueRequiredValidator::default())
-                .prompt()
-                .unwrap();
+                .prompt()?;
 
             let password = Password::new("Password:")
                 .with_validator(ValueRequiredValidator::default())
-                .prompt()
-                .unwrap();
+                .prompt()?;
 
-            hasher.update(password);
-            users.push((user, format!("{:x}", hasher.finalize_reset())));
+            users.push((user, password));
 
             let stop = Confirm::new("Do you want to add another User?")
                 .with_default(false)
-                .prompt()
-                .unwrap();
+                .prompt()?;
 
             if !stop {
                 break;
             }
         }
 
-        users
+        Ok(users)
+    }
+}
+
+fn key_action(key: char) -> Option<Action> {
+    match key {
+        'r' => Some(Action::RestartServer),
+        'u' => Some(Action::AddUser),
+        'c' => Some(Action::CopyUrl),
+        'q' => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+fn spinner() -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    if logging::quiet() || logging::json() {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    } else if *PLAIN_OUTPUT.get().unwrap_or(&false) {
+        pb.set_style(INFO_TEMPLATE.get().unwrap().clone());
+        pb.set_draw_target(ProgressDrawTarget::term_like(Box::new(LineLog::default())));
+    }
+    pb
+}
+
+fn multi_progress() -> MultiProgress {
+    MultiProgress::with_draw_target(draw_target())
+}
+
+fn draw_target() -> ProgressDrawTarget {
+    if logging::quiet() || logging::json() {
+        ProgressDrawTarget::hidden()
+    } else if *PLAIN_OUTPUT.get().unwrap_or(&false) {
+        ProgressDrawTarget::term_like(Box::new(LineLog::default()))
+    } else {
+        ProgressDrawTarget::stderr()
+    }
+}
+
+// See `App::remote_ports_in_use`:
+async fn ports_in_use(session: &Session) -> Option<Vec<u16>> {
+    let output = session
+        .command("sh")
+        .arg("-c")
+        .arg("ss -tln 2>/dev/null || netstat -tln 2>/dev/null")
+        .output()
+        .await
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(3))
+            .filter_map(|address| address.rsplit_once(':'))
+            .filter_map(|(_, port)| port.parse().ok())
+            .collect(),
+    )
+}
+
+// Settings of livetunnel's config win over the ones ~/.ssh/config has for the host. Password
+// logins need a master connection of our own, see `askpass::connect`:
+async fn connect(config: &Config, password: Option<&str>) -> std::result::Result<Session, String> {
+    let alias = ssh_config::resolve(&config.host).unwrap_or_default();
+    let port = config.port.or(alias.port);
+    let user = config.username.clone().or(alias.user);
+    let keyfile = config.keyfile.clone().or(alias.identity_file);
+    let jump_hosts = config.jump_hosts.clone().or(alias.proxy_jump);
+    // Lets ssh notice a dead connection on its own as well:
+    let keepalive = keepalive_interval(config);
+    let keepalive_count_max = config.keepalive_count_max.unwrap_or(3).max(1);
+
+    if let Some(password) = password {
+        let mut arguments: Vec<OsString> = vec![];
+        if let Some(port) = port {
+            arguments.extend(["-p".into(), port.to_string().into()]);
+        }
+        if let Some(user) = user {
+            arguments.extend(["-l".into(), user.into()]);
+        }
+        if let Some(keyfile) = keyfile {
+            arguments.extend(["-i".into(), keyfile.into()]);
+        }
+        if let Some(jump_hosts) = jump_hosts {
+            arguments.extend(["-J".into(), jump_hosts.join(",").into()]);
+        }
+        if let Some(interval) = keepalive {
+            arguments.extend([
+                "-o".into(),
+                format!("ServerAliveInterval={}", interval.as_secs()).into(),
+                "-o".into(),
+                format!("ServerAliveCountMax={}", keepalive_count_max).into(),
+            ]);
+        }
+
+        return askpass::connect(&config.host, arguments, password).await;
+    }
+
+    let mut ssh_session_builder = SessionBuilder::default();
+    if let Some(port) = port {
+        ssh_session_builder.port(port);
+    }
+
+    if let Some(user) = user {
+        ssh_session_builder.user(user);
+    }
+
+    if let Some(keyfile) = keyfile {
+        ssh_session_builder.keyfile(keyfile);
+    }
+
+    if let Some(jump_hosts) = jump_hosts {
+        ssh_session_builder.jump_hosts(jump_hosts);
+    }
+
+    // The builder has no option for ServerAliveCountMax, ssh gets it from a config of its own:
+    let mut ssh_config_file = None;
+    if let Some(interval) = keepalive {
+        ssh_session_builder.server_alive_interval(interval);
+        if config.keepalive_count_max.is_some() {
+            let path = keepalive_config_file(keepalive_count_max).map_err(|err| err.to_string())?;
+            ssh_session_builder.config_file(&path);
+            ssh_config_file = Some(path);
+        }
+    }
+
+    let session = ssh_session_builder
+        .connect(&config.host)
+        .await
+        .map_err(|err| err.to_string());
+    // ssh has read it once the master connection is up:
+    if let Some(path) = ssh_config_file {
+        let _ = remove_file(path);
+    }
+    session
+}
+
+// An ssh config that sets ServerAliveCountMax and includes the usual ones for everything else.
+// `-F` replaces them, the first value found wins:
+fn keepalive_config_file(count_max: u32) -> std::io::Result<PathBuf> {
+    let path = temp_dir().join(format!("livetunnel-ssh-config-{}", std::process::id()));
+    std::fs::write(
+        &path,
+        format!(
+            "ServerAliveCountMax {}\nInclude ~/.ssh/config\nInclude /etc/ssh/ssh_config\n",
+            count_max
+        ),
+    )?;
+    Ok(path)
+}
+
+// The password for password logins, from the environment or asked for once per run:
+fn ssh_password(config: &Config, non_interactive: bool) -> Result<Option<String>> {
+    if let Ok(password) = var(askpass::PASSWORD_VARIABLE) {
+        return Ok(Some(password));
+    }
+
+    if !config.password_auth.unwrap_or(false) {
+        return Ok(None);
+    }
+
+    if let Some(password) = &config.ssh_password {
+        return Ok(Some(password.clone()));
+    }
+
+    if config.keyring == Some(true) {
+        match secrets::get(SSH_PASSWORD_SECRET) {
+            Ok(Some(password)) => return Ok(Some(password)),
+            Ok(None) => {}
+            Err(err) => warn!("Could not read the SSH password from the keyring: {}", err),
+        }
+    }
+
+    if non_interactive {
+        return Err(Error::Config(format!(
+            "Password authentication is set up, but --non-interactive can't ask for the password. Set {}.",
+            askpass::PASSWORD_VARIABLE
+        )));
+    }
+
+    let password = Password::new(&format!("SSH password for '{}':", config.host))
+        .without_confirmation()
+        .prompt()?;
+    Ok(Some(password))
+}
+
+fn keepalive_interval(config: &Config) -> Option<Duration> {
+    match config.keepalive_interval.unwrap_or(30) {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds)),
+    }
+}
+
+// e.g. ", 2 stalled connection(s) cut" when clients were too slow for the builtin server:
+fn describe_timeouts(timeouts: u64) -> String {
+    match timeouts {
+        0 => String::new(),
+        timeouts => format!(", {} stalled connection(s) cut", timeouts),
+    }
+}
+
+fn describe_alias(alias: &ssh_config::SshHost) -> String {
+    let mut settings = vec![];
+    if let Some(host_name) = &alias.host_name {
+        settings.push(format!("HostName {}", host_name));
+    }
+    if let Some(user) = &alias.user {
+        settings.push(format!("User {}", user));
+    }
+    if let Some(port) = alias.port {
+        settings.push(format!("Port {}", port));
+    }
+    if let Some(identity_file) = &alias.identity_file {
+        settings.push(format!("IdentityFile {}", identity_file.display()));
+    }
+    if let Some(proxy_jump) = &alias.proxy_jump {
+        settings.push(format!("ProxyJump {}", proxy_jump.join(",")));
+    }
+    settings.join(", ")
+}
+
+// In whole minutes, so plain output only logs the countdown once a minute:
+fn describe_time_left(left: Duration) -> String {
+    match left.as_secs().div_ceil(60) {
+        1 => String::from("less than a minute"),
+        minutes => format_duration(Duration::from_secs(minutes * 60)).to_string(),
+    }
+}
+
+/// Creates an empty directory for `livetunnel scratch` only the current user can open, deleted
+/// when the returned guard is dropped (see [`App::scratch`]).
+pub fn create_scratch_directory() -> Result<TempDir> {
+    Ok(tempfile::Builder::new()
+        .prefix("livetunnel-scratch-")
+        .tempdir()?)
+}
+
+/// Reads everything piped in into a file called `name` in a fresh temporary directory only the
+/// current user can open, so it can be shared (and downloaded more than once) like any other file.
+/// The directory is deleted with the returned guard.
+fn buffer_stdin(name: &str) -> Result<(TempDir, PathBuf)> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(Error::Config(format!(
+            "--name has to be a plain file name, not '{}'.",
+            name
+        )));
+    }
+    if stdin().is_terminal() {
+        return Err(Error::Config(String::from(
+            "--stdin needs something piped in, e.g. `tar cz . | livetunnel --stdin --name project.tar.gz`.",
+        )));
+    }
+
+    let directory = tempfile::Builder::new()
+        .prefix("livetunnel-stdin-")
+        .tempdir()?;
+    let path = directory.path().join(name);
+
+    let pb = spinner();
+    pb.set_message(format!("Reading '{}' from stdin", name));
+    pb.enable_steady_tick(Duration::from_millis(20));
+    let read = match File::create(&path).and_then(|mut file| copy(&mut stdin().lock(), &mut file)) {
+        Ok(read) => read,
+        Err(err) => {
+            pb.finish_and_clear();
+            return Err(err.into());
+        }
+    };
+    pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+    pb.tick();
+    pb.finish_with_message(format!(
+        "Read {} from stdin into '{}'",
+        DecimalBytes(read),
+        name
+    ));
+
+    Ok((directory, path))
+}
+
+// A random user like "guest-k7q2" with a 16 character password:
+fn generate_credentials() -> Result<(String, String)> {
+    let user = format!("guest-{}", random_string(4)?);
+    Ok((user, random_string(16)?))
+}
+
+// Lowercase letters and digits without look-alike characters, fit for DNS labels as well:
+fn random_string(length: usize) -> Result<String> {
+    const ALPHABET: &[u8; 32] = b"abcdefghjkmnpqrstuvwxyz123456789";
+
+    let mut bytes = vec![0u8; length];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| Error::Io(std::io::Error::other(err.to_string())))?;
+    Ok(bytes
+        .iter()
+        .map(|byte| ALPHABET[(byte % 32) as usize] as char)
+        .collect())
+}
+
+// Users are stored with the hex encoded sha512 of their password, as miniserve expects it:
+fn hash_password(password: &str) -> String {
+    format!("{:x}", Sha512::digest(password))
+}
+
+fn hash_users(users: &[(String, String)]) -> Vec<(String, String)> {
+    users
+        .iter()
+        .map(|(user, password)| (user.clone(), hash_password(password)))
+        .collect()
+}
+
+// [serve] public_paths as one set, `*` doesn't match across folders:
+fn public_paths(paths: &[String]) -> std::result::Result<GlobSet, String> {
+    let mut set = GlobSetBuilder::new();
+    for path in paths {
+        let glob = GlobBuilder::new(path)
+            .literal_separator(true)
+            .build()
+            .map_err(|err| format!("[serve] public_paths '{}' is invalid: {}.", path, err))?;
+        set.add(glob);
+    }
+    set.build()
+        .map_err(|err| format!("[serve] public_paths: {}.", err))
+}
+
+// [serve] allowed_referrers as one set, `*` matches across the slashes of URLs:
+fn referrer_patterns(referrers: &[String]) -> std::result::Result<GlobSet, String> {
+    let mut set = GlobSetBuilder::new();
+    for referrer in referrers {
+        let glob = GlobBuilder::new(referrer)
+            .case_insensitive(true)
+            .build()
+            .map_err(|err| {
+                format!(
+                    "[serve] allowed_referrers '{}' is invalid: {}.",
+                    referrer, err
+                )
+            })?;
+        set.add(glob);
+    }
+    set.build()
+        .map_err(|err| format!("[serve] allowed_referrers: {}.", err))
+}
+
+fn secret_header_problem(secret: &SecretHeaderConfig) -> Option<String> {
+    if secret.name.is_empty()
+        || !secret
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Some(format!(
+            "[serve.secret_header] name '{}' is no header name, use e.g. \"X-Share-Key\".",
+            secret.name
+        ));
+    }
+    // Anyone could guess a short one:
+    if secret.token.chars().count() < 16 {
+        return Some(String::from(
+            "[serve.secret_header] token is shorter than 16 characters. Generate one with `openssl rand -hex 32`.",
+        ));
+    }
+    None
+}
+
+// [[serve.redirects]] in order, each needs either from or regex:
+fn redirect_rules(rules: &[RedirectConfig]) -> std::result::Result<Redirects, String> {
+    let mut redirects = Redirects::default();
+    for rule in rules {
+        let pattern = match (&rule.from, &rule.regex) {
+            (Some(glob), None) => Pattern::Glob(glob.clone()),
+            (None, Some(regex)) => Pattern::Regex(regex.clone()),
+            _ => {
+                return Err(format!(
+                    "[[serve.redirects]] to '{}' needs either from or regex.",
+                    rule.to
+                ))
+            }
+        };
+        redirects
+            .add(
+                &pattern,
+                &rule.to,
+                rule.status.unwrap_or(301),
+                rule.force.unwrap_or(false),
+            )
+            .map_err(|err| format!("[[serve.redirects]]: {}.", err))?;
+    }
+    Ok(redirects)
+}
+
+/// Reads and writes the config at `path` instead of confy's default location (`--config`).
+pub fn use_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+/// The config file in use, see [`use_config_path`].
+pub fn config_path() -> std::result::Result<PathBuf, ConfyError> {
+    match CONFIG_PATH.get() {
+        Some(path) => Ok(path.clone()),
+        None => get_configuration_file_path("livetunnel", "livetunnel"),
+    }
+}
+
+fn load_config() -> Result<Config> {
+    let path = config_path()?;
+    // confy creates the default one:
+    if !path.exists() {
+        return Ok(load_path(path)?);
+    }
+
+    let content = read_config_file(&path)?;
+    let (mut config, changed) = parse_value(&content)
+        .and_then(|(mut config, changed)| {
+            resolve_references(&mut config)?;
+            let config: Config = config
+                .try_into()
+                .map_err(|err: toml::de::Error| err.to_string())?;
+            Ok((config, changed))
+        })
+        .map_err(|err| {
+            Error::Config(format!(
+                "Could not read the config '{}': {}.",
+                path.display(),
+                err
+            ))
+        })?;
+
+    let mut plaintext = 0;
+    for (name, value) in secrets_mut(&mut config) {
+        if secrets::is_reference(value) {
+            *value = secrets::resolve(value).map_err(|err| {
+                Error::Config(format!("Could not read {} from the keyring: {}", name, err))
+            })?;
+        } else {
+            plaintext += 1;
+        }
+    }
+
+    if changed {
+        save_config(&config)?;
+        info!(
+            "Upgraded the config '{}' to version {}, the previous one is backed up",
+            path.display(),
+            CONFIG_VERSION
+        );
+    } else if config.keyring == Some(true) && plaintext > 0 {
+        // They still work from the config if the keyring can't take them:
+        match save_config(&config) {
+            Ok(()) => info!(
+                success = true,
+                "Moved {} secret(s) from the config into the keyring", plaintext
+            ),
+            Err(err) => warn!("{}", err),
+        }
+    }
+    Ok(config)
+}
+
+// Backs up the previous config before overwriting it, always as the current version:
+fn save_config(config: &Config) -> Result<()> {
+    if let Err(err) = backup_config() {
+        warn!("Could not back up the previous config: {}", err);
+    }
+
+    let mut config = Config {
+        version: Some(CONFIG_VERSION),
+        ..config.clone()
+    };
+    if config.keyring == Some(true) {
+        for (name, value) in secrets_mut(&mut config) {
+            // Ones in a password manager stay there:
+            let elsewhere = vault::is_reference(value) || vault::was_resolved(value);
+            if !secrets::is_reference(value) && !elsewhere {
+                *value = secrets::store(&name, value).map_err(|err| {
+                    Error::Config(format!(
+                        "Could not store {} in the keyring: {}. Set keyring = false to keep it in the config.",
+                        name, err
+                    ))
+                })?;
+            }
+        }
+    }
+
+    let content = toml::Value::try_from(&config)
+        .and_then(|mut config| {
+            vault::restore(&mut config);
+            toml::to_string_pretty(&config)
+        })
+        .map_err(|err| Error::Config(format!("Could not write the config: {}", err)))?;
+    write_config_file(&config_path()?, &content)
+}
+
+// Reads what's kept in a password manager, user passwords are hashed like the stored ones:
+fn resolve_references(config: &mut toml::Value) -> std::result::Result<(), String> {
+    let users = config.get_mut("users").and_then(toml::Value::as_array_mut);
+    for password in users
+        .into_iter()
+        .flatten()
+        .filter_map(|user| user.get_mut(1))
+    {
+        let Some(reference) = password.as_str().filter(|value| vault::is_reference(value)) else {
+            continue;
+        };
+        let reference = String::from(reference);
+        let hash = match vault::lookup(&reference) {
+            Some(hash) => hash,
+            None => {
+                let hash = hash_password(
+                    &vault::read(&reference).map_err(|err| format!("'{}': {}", reference, err))?,
+                );
+                vault::remember(&reference, &hash);
+                hash
+            }
+        };
+        *password = toml::Value::from(hash);
+    }
+
+    vault::resolve_all(config)
+}
+
+/// The content of the config file at `path`, decrypted if it's encrypted (see
+/// `livetunnel config encrypt`).
+fn read_config_file(path: &Path) -> Result<String> {
+    let content = std::fs::read(path)?;
+    if encryption::is_encrypted(&content) {
+        return encryption::decrypt(&content);
+    }
+
+    String::from_utf8(content)
+        .map_err(|err| Error::Config(format!("'{}' is no text: {}", path.display(), err)))
+}
+
+// Encrypted again if it was:
+fn write_config_file(path: &Path, content: &str) -> Result<()> {
+    let encrypted = std::fs::read(path).is_ok_and(|current| encryption::is_encrypted(&current));
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+
+    if encrypted {
+        std::fs::write(path, encryption::encrypt(content)?)?;
+    } else {
+        std::fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+/// The passphrase of the config if it's encrypted, checked by opening it. Asks for it if
+/// needed, e.g. for a share in the background that can't.
+pub fn config_passphrase() -> Result<Option<String>> {
+    match std::fs::read(config_path()?) {
+        Ok(content) if encryption::is_encrypted(&content) => {
+            encryption::decrypt(&content)?;
+            Ok(Some(encryption::passphrase()?))
+        }
+        _ => Ok(None),
+    }
+}
+
+// What the keyring takes when it's used, by the name it's stored as there:
+fn secrets_mut(config: &mut Config) -> Vec<(String, &mut String)> {
+    let mut secrets: Vec<(String, &mut String)> = config
+        .users
+        .iter_mut()
+        .map(|(user, hash)| (format!("user/{}", user), hash))
+        .collect();
+    if let Some(dns) = &mut config.dns {
+        secrets.push((String::from("dns/token"), &mut dns.token));
+    }
+    if let Some(password) = &mut config.ssh_password {
+        secrets.push((String::from(SSH_PASSWORD_SECRET), password));
+    }
+    if let Some(secret) = config
+        .serve
+        .as_mut()
+        .and_then(|serve| serve.secret_header.as_mut())
+    {
+        secrets.push((String::from("serve/secret_header"), &mut secret.token));
+    }
+    secrets
+}
+
+// Replaces the placeholders that can be used in before and after commands, `escape` quotes
+// the host and directory for where they end up (e.g. a command line):
+fn fill_placeholders(
+    template: &str,
+    config: &Config,
+    directory: &Path,
+    escape: fn(&str) -> String,
+) -> String {
+    template
+        .replace("{remote_port}", &config.remote_port.to_string())
+        .replace("{local_port}", &config.local_port.to_string())
+        .replace("{host}", &escape(&config.host))
+        .replace("{dir}", &escape(&directory.display().to_string()))
+}
+
+// Single-quotes a string for the remote shell:
+fn quote(input: &str) -> String {
+    format!("'{}'", input.replace('\'', "'\\''"))
+}
+
+// One command per line of the assistant's editor, empty lines are skipped:
+fn parse_commands(lines: &str) -> Vec<ConnectCommand> {
+    lines
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(ConnectCommand::new)
+        .collect()
+}
+
+/// Picks the editor for the multi-line prompts of the setup assistant: the configured one,
+/// then `$VISUAL` and `$EDITOR`, then the first installed platform default.
+fn editor_command(configured: Option<&str>) -> (OsString, Vec<OsString>) {
+    let editor = configured
+        .map(OsString::from)
+        .into_iter()
+        .chain(var_os("VISUAL"))
+        .chain(var_os("EDITOR"))
+        .find(|editor| !editor.is_empty());
+
+    if let Some(editor) = editor {
+        // Editors are commonly configured with arguments, e.g. "code --wait":
+        let editor = editor.to_string_lossy().into_owned();
+        let mut parts = editor.split_whitespace().map(OsString::from);
+        if let Some(program) = parts.next() {
+            return (program, parts.collect());
+        }
+    }
+
+    let fallbacks: &[&str] = if cfg!(windows) {
+        &["notepad"]
+    } else {
+        &["nano", "vi"]
+    };
+
+    let program = fallbacks
+        .iter()
+        .find(|program| is_installed(program))
+        .unwrap_or(&fallbacks[fallbacks.len() - 1]);
+
+    (OsString::from(program), vec![])
+}
+
+fn is_installed(program: &str) -> bool {
+    let program = if cfg!(windows) {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+
+    var_os("PATH")
+        .map(|path| split_paths(&path).any(|dir| dir.join(&program).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_take_the_share_settings() {
+        let config = Config {
+            host: String::from("ops@web-1.example.net"),
+            local_port: 4000,
+            remote_port: 8443,
+            ..Config::default()
+        };
+        let filled = fill_placeholders(
+            "curl -fsS http://localhost:{remote_port}/ && echo {host} {local_port} {dir} {user}",
+            &config,
+            Path::new("/srv/blog"),
+            quote,
+        );
+        assert_eq!(
+            filled,
+            "curl -fsS http://localhost:8443/ && echo 'ops@web-1.example.net' 4000 '/srv/blog' {user}"
+        );
+    }
+
+    // Whatever the directory is called, the remote shell gets it as one argument:
+    #[cfg(unix)]
+    #[test]
+    fn quoted_placeholders_survive_the_shell() {
+        let config = Config::default();
+        for directory in [
+            "/home/sam/it's mine",
+            "/tmp/$(touch pwned)",
+            "/tmp/a;b|c`d`",
+            "/tmp/'quoted'\"twice\"",
+        ] {
+            let script = fill_placeholders("printf %s {dir}", &config, Path::new(directory), quote);
+            let output = std::process::Command::new("sh")
+                .args(["-c", &script])
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), directory);
+        }
     }
 }
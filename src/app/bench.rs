@@ -0,0 +1,246 @@
+use super::{spinner, App, SUCCESS_TEMPLATE, WARNING_TEMPLATE};
+use crate::{
+    error::{Error, Result},
+    logging,
+    probe::Probe,
+};
+
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{copy, sink},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use indicatif::DecimalBytes;
+use serde_json::json;
+use tracing::info;
+
+// How often the SSH round trip is measured:
+const LATENCY_SAMPLES: u32 = 5;
+
+enum Hop {
+    Ssh,
+    Tunnel(String),
+    ReverseProxy(String),
+    Public(String),
+}
+
+impl Display for Hop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Hop::Ssh => write!(f, "SSH round trip"),
+            Hop::Tunnel(remote_end) => {
+                write!(f, "Tunnel (your uplink, {})", remote_end)
+            }
+            Hop::ReverseProxy(domain) => {
+                write!(f, "Reverse proxy for '{}' (on the server)", domain)
+            }
+            Hop::Public(domain) => write!(f, "Public URL 'http://{}' (from here)", domain),
+        }
+    }
+}
+
+impl Hop {
+    // As in the `bench_hop` event of --output json:
+    fn name(&self) -> &'static str {
+        match self {
+            Hop::Ssh => "ssh",
+            Hop::Tunnel(_) => "tunnel",
+            Hop::ReverseProxy(_) => "reverse_proxy",
+            Hop::Public(_) => "public",
+        }
+    }
+}
+
+// Result of a single hop, (latency until the first byte, bytes per second):
+type Measurement = std::result::Result<(Duration, f64), String>;
+
+impl App {
+    /// Measures latency and throughput of every hop between the local machine and the public
+    /// domain by serving a synthetic payload through the tunnel.
+    pub fn bench(&mut self, size: u64) -> Result<()> {
+        self.request_forward()?;
+
+        let payload: Arc<[u8]> = synthetic_payload(size as usize).into();
+        let probe = match Probe::start(&self.local_end(), payload) {
+            Ok(probe) => probe,
+            Err(err) => {
+                return Err(Error::Server(format!(
+                    "Could not listen on {} to serve the benchmark payload: {}",
+                    self.local_end(),
+                    err
+                )));
+            }
+        };
+
+        info!("Benchmarking with a payload of {}", DecimalBytes(size));
+
+        let mut hops = vec![Hop::Ssh, Hop::Tunnel(self.remote_end())];
+        match &self.config.domain {
+            Some(domain) => {
+                hops.push(Hop::ReverseProxy(domain.clone()));
+                hops.push(Hop::Public(domain.clone()));
+            }
+            None => info!(
+                "No domain configured, skipping the reverse proxy. Run `livetunnel setup-remote` or `livetunnel verify` to set one."
+            ),
+        }
+
+        let num_hops = hops.len();
+        for (i, hop) in hops.iter().enumerate() {
+            let pb = spinner();
+            pb.set_message(format!("[{}/{}] Measuring {}", i + 1, num_hops, hop));
+            pb.enable_steady_tick(Duration::from_millis(20));
+
+            let measurement = self.measure(hop);
+            logging::emit(
+                "bench_hop",
+                match &measurement {
+                    Ok((latency, throughput)) => json!({
+                        "hop": hop.name(),
+                        "host": self.config.host,
+                        "latency_ms": latency.as_millis(),
+                        "bytes_per_second": (*throughput > 0.0).then_some(*throughput as u64),
+                    }),
+                    Err(err) => json!({
+                        "hop": hop.name(),
+                        "host": self.config.host,
+                        "error": err,
+                    }),
+                },
+            );
+            match measurement {
+                Ok((latency, throughput)) => {
+                    pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+                    pb.tick();
+                    pb.finish_with_message(format!(
+                        "[{}/{}] {}: {} ms latency{}",
+                        i + 1,
+                        num_hops,
+                        hop,
+                        latency.as_millis(),
+                        if throughput > 0.0 {
+                            format!(", {}/s", DecimalBytes(throughput as u64))
+                        } else {
+                            String::new()
+                        }
+                    ));
+                }
+                Err(err) => {
+                    pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                    pb.tick();
+                    pb.finish_with_message(format!(
+                        "[{}/{}] {}: Error: {}",
+                        i + 1,
+                        num_hops,
+                        hop,
+                        err
+                    ));
+                }
+            }
+        }
+
+        probe.stop();
+        Ok(())
+    }
+
+    fn measure(&self, hop: &Hop) -> Measurement {
+        match hop {
+            Hop::Ssh => self.measure_ssh_latency(),
+            Hop::Tunnel(_) => self.measure_remote_download(&format!(
+                "curl -sS -o /dev/null -w '%{{time_starttransfer}} %{{speed_download}}' {}",
+                self.remote_curl_target(".livetunnel-bench")
+            )),
+            Hop::ReverseProxy(domain) => self.measure_remote_download(&format!(
+                "curl -sSk -L -o /dev/null -w '%{{time_starttransfer}} %{{speed_download}}' --resolve {domain}:80:127.0.0.1 --resolve {domain}:443:127.0.0.1 http://{domain}/.livetunnel-bench",
+                domain = domain
+            )),
+            Hop::Public(domain) => measure_public_download(domain),
+        }
+    }
+
+    fn measure_ssh_latency(&self) -> Measurement {
+        let mut total = Duration::ZERO;
+        for _ in 0..LATENCY_SAMPLES {
+            let start = Instant::now();
+            self.runtime
+                .block_on(self.ssh_session.command("true").status())
+                .map_err(|err| err.to_string())?;
+            total += start.elapsed();
+        }
+
+        Ok((total / LATENCY_SAMPLES, 0.0))
+    }
+
+    // Runs a curl download on the server, printing "<seconds until first byte> <bytes/s>":
+    fn measure_remote_download(&self, script: &str) -> Measurement {
+        let output = self
+            .runtime
+            .block_on(
+                self.ssh_session
+                    .command("sh")
+                    .arg("-c")
+                    .arg(script)
+                    .output(),
+            )
+            .map_err(|err| err.to_string())?;
+
+        match output.status.code() {
+            Some(0) => {}
+            Some(127) => return Err(String::from("curl is not installed on the remote")),
+            _ => {
+                return Err(format!(
+                    "request failed: '{}'",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))
+            }
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut values = stdout.split_whitespace().map(|value| value.parse::<f64>());
+        match (values.next(), values.next()) {
+            (Some(Ok(latency)), Some(Ok(throughput))) => {
+                Ok((Duration::from_secs_f64(latency), throughput))
+            }
+            _ => Err(format!("unexpected curl output: '{}'", stdout.trim())),
+        }
+    }
+}
+
+fn measure_public_download(domain: &str) -> Measurement {
+    let start = Instant::now();
+    let mut response = ureq::get(&format!("http://{}/.livetunnel-bench", domain))
+        .config()
+        .timeout_global(Some(Duration::from_secs(120)))
+        .build()
+        .call()
+        .map_err(|err| err.to_string())?;
+    let latency = start.elapsed();
+
+    let downloaded =
+        copy(&mut response.body_mut().as_reader(), &mut sink()).map_err(|err| err.to_string())?;
+    let transfer_time = (start.elapsed() - latency).as_secs_f64();
+
+    Ok((
+        latency,
+        if transfer_time > 0.0 {
+            downloaded as f64 / transfer_time
+        } else {
+            0.0
+        },
+    ))
+}
+
+// Incompressible, so SSH or proxy compression can't skew the results:
+fn synthetic_payload(size: usize) -> Vec<u8> {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
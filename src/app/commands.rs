@@ -0,0 +1,513 @@
+use super::{fill_placeholders, quote, spinner, Config, SUCCESS_TEMPLATE, WARNING_TEMPLATE};
+use crate::{
+    error::{Error, Result},
+    logging,
+};
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Formatter},
+    path::Path,
+    thread::sleep,
+    time::Duration,
+};
+
+use humantime::{format_duration, parse_duration};
+use indicatif::ProgressBar;
+use openssh::Session;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use tokio::{process::Command, runtime::Runtime, time::timeout};
+use tracing::{debug, info};
+
+// Pause before a failed before command is tried again:
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// One of the before_commands or after_commands: the command line and where it runs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(super) struct ConnectCommand {
+    // Split into arguments like a shell would for before_commands (quotes work, `&&` and pipes
+    // need `sh -c`), run through `sh -c` on the remote for after_commands:
+    pub(super) command: String,
+    // Working directory, relative to the shared one for before_commands and to the login
+    // directory for after_commands (default: where livetunnel was started / the login directory):
+    pub(super) dir: Option<String>,
+    // Extra environment variables:
+    pub(super) env: Option<BTreeMap<String, String>>,
+
+    // Only for before_commands: what happens once it failed (default: "continue"), how long it
+    // may take, e.g. "5m" (default: no limit) and how often it's tried again (default: 0):
+    pub(super) on_failure: Option<FailurePolicy>,
+    pub(super) timeout: Option<String>,
+    pub(super) retries: Option<u32>,
+}
+
+/// What happens when a before command still fails after its retries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum FailurePolicy {
+    /// Warn and connect anyway
+    #[default]
+    Continue,
+    /// Stop livetunnel, e.g. when the share needs what the command builds
+    Abort,
+}
+
+impl Display for FailurePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FailurePolicy::Continue => write!(f, "Warn and connect anyway"),
+            FailurePolicy::Abort => write!(f, "Stop livetunnel"),
+        }
+    }
+}
+
+impl ConnectCommand {
+    pub(super) fn new(command: &str) -> Self {
+        ConnectCommand {
+            command: String::from(command.trim()),
+            ..Default::default()
+        }
+    }
+
+    /// The command line with its failure settings, for the lists of the setup assistant.
+    pub(super) fn summary(&self) -> String {
+        let mut settings = vec![];
+        if self.on_failure == Some(FailurePolicy::Abort) {
+            settings.push(String::from("stops livetunnel on failure"));
+        }
+        if let Some(limit) = &self.timeout {
+            settings.push(format!("timeout {}", limit));
+        }
+        if let Some(retries) = self.retries.filter(|retries| *retries > 0) {
+            settings.push(format!("{} retries", retries));
+        }
+
+        if settings.is_empty() {
+            self.command.clone()
+        } else {
+            format!("{} ({})", self.command, settings.join(", "))
+        }
+    }
+
+    // {dir}, {local_port}, {remote_port} and {host} work in the command, its dir and env. In the
+    // command they're quoted with `quote`, so a directory with spaces stays one argument:
+    fn filled(&self, config: &Config, directory: &Path, quote: fn(&str) -> String) -> Self {
+        let fill =
+            |template: &String| fill_placeholders(template, config, directory, str::to_owned);
+        ConnectCommand {
+            command: fill_placeholders(&self.command, config, directory, quote),
+            dir: self.dir.as_ref().map(fill),
+            env: self.env.as_ref().map(|env| {
+                env.iter()
+                    .map(|(name, value)| (name.clone(), fill(value)))
+                    .collect()
+            }),
+            ..self.clone()
+        }
+    }
+
+    // What the remote shell runs: the command after changing to dir and exporting env:
+    fn remote_script(&self) -> String {
+        let mut script = String::new();
+        if let Some(dir) = &self.dir {
+            script.push_str(&format!("cd {} || exit; ", quote(dir)));
+        }
+        for (name, value) in self.env.iter().flatten() {
+            script.push_str(&format!("export {}={}; ", name, quote(value)));
+        }
+        script + &self.command
+    }
+}
+
+/// Reads a command list of the config, where a command line alone is short for
+/// `{ command = "…" }` (e.g. in a .livetunnel.toml of a project).
+pub(super) fn deserialize_commands<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Vec<ConnectCommand>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Line(String),
+        Table(ConnectCommand),
+    }
+
+    let entries = Option::<Vec<Entry>>::deserialize(deserializer)?;
+    let commands: Option<Vec<ConnectCommand>> = entries.map(|entries| {
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                Entry::Line(line) => ConnectCommand::new(&line),
+                Entry::Table(command) => command,
+            })
+            .collect()
+    });
+
+    // They're exported in the remote command, anything but a name would be run as shell code:
+    for command in commands.iter().flatten() {
+        if let Some(name) = command
+            .env
+            .iter()
+            .flatten()
+            .map(|(name, _)| name)
+            .find(|name| !is_variable_name(name))
+        {
+            return Err(D::Error::custom(format!(
+                "'{}' in the env of '{}' is no valid variable name (letters, digits and _, not starting with a digit)",
+                name, command.command
+            )));
+        }
+    }
+    Ok(commands)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits a local command into its program and arguments like a shell would, so quoted
+/// arguments stay together (`bash -c "npm run build && cp -r dist /tmp"`). It's not run through a
+/// shell though, `&&` and pipes need one like that.
+pub(super) fn split_command(command: &str) -> std::result::Result<(String, Vec<String>), String> {
+    let mut words = shlex::split(command)
+        .ok_or_else(|| String::from("it has an unmatched quote or ends with a backslash"))?
+        .into_iter();
+    let program = words
+        .next()
+        .ok_or_else(|| String::from("it has no program"))?;
+    Ok((program, words.collect()))
+}
+
+// Quotes a value for `split_command`, as it is if it needs no quotes:
+fn quote_local(value: &str) -> String {
+    shlex::try_quote(value).map_or_else(|_| String::from(value), String::from)
+}
+
+/// Runs the before_commands locally, each tried again as often as its retries allow. One that
+/// still fails is reported and the next one runs, unless its on_failure is "abort".
+pub(super) fn run_before_commands(
+    commands: &[ConnectCommand],
+    config: &Config,
+    directory: &Path,
+    runtime: &Runtime,
+) -> Result<()> {
+    let num_cmds = commands.len();
+    info!(
+        "Running {} command(s) before establishing SSH connection",
+        num_cmds
+    );
+
+    for (i, command) in commands.iter().enumerate() {
+        let command = command.filled(config, directory, quote_local);
+        let pb = spinner();
+        pb.set_message(format!(
+            "[{}/{}] Running '{}'",
+            i + 1,
+            num_cmds,
+            command.command
+        ));
+        pb.enable_steady_tick(Duration::from_millis(20));
+
+        let attempts = command.retries.unwrap_or(0) + 1;
+        let mut result = run_local(&command, directory, runtime);
+        for attempt in 2..=attempts {
+            let Err(err) = &result else {
+                break;
+            };
+            debug!("'{}' {}, trying again", command.command, err);
+            pb.set_message(format!(
+                "[{}/{}] Running '{}' (attempt {}/{})",
+                i + 1,
+                num_cmds,
+                command.command,
+                attempt,
+                attempts
+            ));
+            sleep(RETRY_DELAY);
+            result = run_local(&command, directory, runtime);
+        }
+
+        if let Err(err) = result {
+            warn_finish(
+                &pb,
+                format!(
+                    "[{}/{}] Error: '{}' {}",
+                    i + 1,
+                    num_cmds,
+                    command.command,
+                    err
+                ),
+            );
+            if command.on_failure == Some(FailurePolicy::Abort) {
+                return Err(Error::Command(format!(
+                    "Stopped, '{}' failed and its on_failure is \"abort\".",
+                    command.command
+                )));
+            }
+            continue;
+        }
+
+        pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+        pb.tick();
+        pb.finish_with_message(format!(
+            "[{}/{}] Done: '{}'",
+            i + 1,
+            num_cmds,
+            command.command
+        ));
+    }
+
+    Ok(())
+}
+
+// Runs a before command once, the error tells how it failed:
+fn run_local(
+    command: &ConnectCommand,
+    directory: &Path,
+    runtime: &Runtime,
+) -> std::result::Result<(), String> {
+    let (program, args) =
+        split_command(&command.command).map_err(|err| format!("produced an Error: {}", err))?;
+    let limit = match &command.timeout {
+        Some(limit) => Some(
+            parse_duration(limit)
+                .map_err(|err| format!("has an invalid timeout '{}': {}", limit, err))?,
+        ),
+        None => None,
+    };
+
+    let mut process = Command::new(program);
+    // Stopped when it runs out of time:
+    process
+        .args(args)
+        .envs(command.env.iter().flatten())
+        .kill_on_drop(true);
+    if let Some(dir) = &command.dir {
+        let dir = directory.join(dir);
+        if !dir.is_dir() {
+            return Err(format!(
+                "produced an Error: its dir '{}' doesn't exist",
+                dir.display()
+            ));
+        }
+        process.current_dir(dir);
+    }
+
+    let output = runtime.block_on(async {
+        match limit {
+            Some(limit) => timeout(limit, process.output())
+                .await
+                .map_err(|_| format!("timed out after {}", format_duration(limit)))?,
+            None => process.output().await,
+        }
+        .map_err(|err| format!("produced an Error: {}", err))
+    })?;
+
+    logging::command_output(&command.command, &output);
+    if !output.status.success() {
+        return Err(format!("exited with {}: '{:?}'", output.status, output));
+    }
+    Ok(())
+}
+
+/// Runs the after_commands on the remote through the SSH session, like [`run_before_commands`].
+pub(super) fn run_after_commands(
+    commands: &[ConnectCommand],
+    config: &Config,
+    directory: &Path,
+    ssh_session: &Session,
+    runtime: &Runtime,
+) {
+    let num_cmds = commands.len();
+    info!(
+        "Running {} command(s) on the newly establishing SSH connection",
+        num_cmds
+    );
+
+    for (i, command) in commands.iter().enumerate() {
+        let command = command.filled(config, directory, quote);
+
+        let ac_pb = spinner();
+        ac_pb.set_message(format!(
+            "[{}/{}] Running '{}'",
+            i + 1,
+            num_cmds,
+            command.command
+        ));
+        ac_pb.enable_steady_tick(Duration::from_millis(20));
+
+        let mut remote_cmd = ssh_session.command("sh");
+        remote_cmd.arg("-c").arg(command.remote_script());
+
+        let output = match runtime.block_on(remote_cmd.output()) {
+            Ok(output) => output,
+            Err(err) => {
+                warn_finish(
+                    &ac_pb,
+                    format!(
+                        "[{}/{}] Error: '{}' produced an Error: {}",
+                        i + 1,
+                        num_cmds,
+                        command.command,
+                        err
+                    ),
+                );
+                continue;
+            }
+        };
+
+        logging::command_output(&command.command, &output);
+        if !output.status.success() {
+            warn_finish(
+                &ac_pb,
+                format!(
+                    "[{}/{}] Error: '{}' exited with {}: '{:?}'",
+                    i + 1,
+                    num_cmds,
+                    command.command,
+                    output.status,
+                    output
+                ),
+            );
+            continue;
+        }
+
+        ac_pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+        ac_pb.tick();
+        ac_pb.finish_with_message(format!(
+            "[{}/{}] Done: '{}': o: {}",
+            i + 1,
+            num_cmds,
+            command.command,
+            String::from_utf8_lossy(&output.stdout).trim(),
+        ));
+    }
+}
+
+fn warn_finish(pb: &ProgressBar, message: String) {
+    pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+    pb.tick();
+    pb.finish_with_message(message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(command: &str) -> std::result::Result<Vec<String>, String> {
+        let (program, mut arguments) = split_command(command)?;
+        arguments.insert(0, program);
+        Ok(arguments)
+    }
+
+    #[test]
+    fn command_lines_split_like_a_shell_would() {
+        assert_eq!(split("npm   run build").unwrap(), ["npm", "run", "build"]);
+        assert_eq!(
+            split(r#"sh -c "zola build && touch public/.done""#).unwrap(),
+            ["sh", "-c", "zola build && touch public/.done"]
+        );
+        assert_eq!(
+            split(r#"cp 'My Site/index.html' out\ dir/"#).unwrap(),
+            ["cp", "My Site/index.html", "out dir/"]
+        );
+        // No shell runs it, its operators are plain arguments:
+        assert_eq!(
+            split("make | tee log").unwrap(),
+            ["make", "|", "tee", "log"]
+        );
+        assert_eq!(split("echo ''").unwrap(), ["echo", ""]);
+
+        for broken in [
+            "",
+            " \t ",
+            "echo 'never closed",
+            "echo \"half",
+            "echo end\\",
+        ] {
+            assert!(split_command(broken).is_err(), "{:?}", broken);
+        }
+    }
+
+    // The filled in directory stays one argument, however it's named:
+    #[test]
+    fn filled_directories_stay_one_argument() {
+        let config = Config::default();
+        let command = ConnectCommand::new("rsync -a {dir}/ backup:sites/");
+        for directory in [
+            "/home/kim/Sites",
+            "/home/kim/My Sites",
+            "/tmp/it's \"here\"",
+        ] {
+            let filled = command.filled(&config, Path::new(directory), quote_local);
+            assert_eq!(
+                split(&filled.command).unwrap(),
+                ["rsync", "-a", &format!("{}/", directory), "backup:sites/"]
+            );
+        }
+    }
+
+    // What runs on the remote, tried with the local shell:
+    #[cfg(unix)]
+    #[test]
+    fn remote_scripts_change_directory_and_export_first() {
+        let command = ConnectCommand {
+            command: String::from("printf '%s|%s|%s' \"$PWD\" \"$GREETING\" \"$EMPTY\""),
+            dir: Some(String::from("/")),
+            env: Some(BTreeMap::from([
+                (String::from("GREETING"), String::from("it's $HOME; `id`")),
+                (String::from("EMPTY"), String::new()),
+            ])),
+            ..ConnectCommand::default()
+        };
+        let output = std::process::Command::new("sh")
+            .args(["-c", &command.remote_script()])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "/|it's $HOME; `id`|"
+        );
+
+        // Without its directory it doesn't run at all:
+        let missing = ConnectCommand {
+            dir: Some(String::from("/nonexistent/livetunnel")),
+            ..ConnectCommand::new("echo ran")
+        };
+        let output = std::process::Command::new("sh")
+            .args(["-c", &missing.remote_script()])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    fn command_lists_take_lines_and_tables() {
+        #[derive(Deserialize)]
+        struct Commands {
+            #[serde(default, deserialize_with = "deserialize_commands")]
+            before_commands: Option<Vec<ConnectCommand>>,
+        }
+        let parse = |toml: &str| toml::from_str::<Commands>(toml).map(|read| read.before_commands);
+
+        let commands = parse(
+            "before_commands = [\"make\", { command = \"npm ci\", dir = \"web\", env = { NODE_ENV = \"production\" } }]",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(commands[0], ConnectCommand::new("make"));
+        assert_eq!(commands[1].dir.as_deref(), Some("web"));
+        assert_eq!(commands[1].env.as_ref().unwrap()["NODE_ENV"], "production");
+        assert_eq!(parse("").unwrap(), None);
+
+        for name in ["1ST", "A-B", "PATH; rm -rf ~", ""] {
+            let toml = format!(
+                "before_commands = [{{ command = \"true\", env = {{ \"{}\" = \"x\" }} }}]",
+                name
+            );
+            let err = parse(&toml).unwrap_err().to_string();
+            assert!(err.contains("no valid variable name"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use super::{hash_password, load_config, Action, App};
+use crate::{
+    control::{Request, Response},
+    error::{Error, Result},
+    logging,
+    relay::Relay,
+    server::Waker,
+};
+
+use std::sync::atomic::Ordering;
+
+use arboard::Clipboard;
+use serde_json::json;
+use tracing::{info, warn};
+
+// Asks for new users, [`App::add_users`], with the terminal given back for the prompts:
+type AddUsers = fn() -> Result<Vec<(String, String)>>;
+
+impl App {
+    /// Answers a request from the control socket. `healthy` is the state of the last check.
+    pub(super) fn handle_control(&mut self, request: Request, healthy: bool) -> Response {
+        match request {
+            Request::Status => {
+                let (visitors, downloads) = self.stats.live();
+                Ok(json!({
+                    "pid": std::process::id(),
+                    "directory": self.directory,
+                    "host": self.config.host,
+                    "local_port": self.config.local_port,
+                    "local_socket": self.config.local_socket,
+                    "remote_port": self.config.remote_port,
+                    "url": self.public_url(),
+                    "forwards": self.config.forwards.clone().unwrap_or_default(),
+                    "healthy": healthy,
+                    "paused": self.paused.is_some(),
+                    "visitors": visitors,
+                    "downloads": downloads,
+                    "secure": self.cli.share.secure,
+                    "transferred": self.relay.as_ref().map(Relay::transferred).unwrap_or(0),
+                    "users": self.config.users.iter().map(|(user, _)| user).collect::<Vec<_>>(),
+                }))
+            }
+            Request::AddUser { username, password } => {
+                if self.has_user(&username) {
+                    return Err(format!("user '{}' already exists", username));
+                }
+
+                let hash = hash_password(&password);
+                self.update_config(|config| config.users.push((username.clone(), hash.clone())))
+                    .map_err(|err| err.to_string())?;
+                self.apply_users()
+            }
+            Request::SetPassword { username, password } => {
+                if !self.has_user(&username) {
+                    return Err(format!("there is no user '{}'", username));
+                }
+
+                let hash = hash_password(&password);
+                self.update_config(|config| {
+                    for (_, stored) in config
+                        .users
+                        .iter_mut()
+                        .filter(|(user, _)| *user == username)
+                    {
+                        *stored = hash.clone();
+                    }
+                })
+                .map_err(|err| err.to_string())?;
+                self.apply_users()
+            }
+            Request::RemoveUser { username } => {
+                if !self.has_user(&username) {
+                    return Err(format!("there is no user '{}'", username));
+                }
+
+                self.update_config(|config| config.users.retain(|(user, _)| *user != username))
+                    .map_err(|err| err.to_string())?;
+                self.apply_users()
+            }
+            Request::ReloadUsers => {
+                // A throwaway login (--secure-auto) stays the only one:
+                if self.cli.share.secure_auto {
+                    return Ok(json!({ "applied": false }));
+                }
+
+                self.config.users = load_config().map_err(|err| err.to_string())?.users;
+                self.apply_users()
+            }
+            Request::Pause => {
+                if self.server.is_none() {
+                    return Err(String::from("only a running builtin server can be paused"));
+                }
+                Ok(json!({ "paused": self.pause_server() }))
+            }
+            Request::Resume => {
+                if self.paused.is_none() {
+                    return Err(String::from("the server isn't paused"));
+                }
+                Ok(json!({ "resumed": self.resume_server() }))
+            }
+            Request::Shutdown => {
+                self.should_end.store(true, Ordering::SeqCst);
+                Ok(json!({}))
+            }
+        }
+    }
+
+    /// Does what a key pressed while sharing asks for. `prompt` runs `add` with the terminal
+    /// given back, `healthy` and `clipboard` are updated by restarts and copies.
+    pub(super) fn handle_action(
+        &mut self,
+        action: Action,
+        healthy: &mut bool,
+        clipboard: &mut Option<Clipboard>,
+        prompt: impl FnOnce(AddUsers) -> Result<Vec<(String, String)>>,
+    ) {
+        match action {
+            Action::RestartServer => *healthy = self.restart_server(),
+            Action::AddUser => match prompt(App::add_users) {
+                Ok(credentials) => self.add_users_while_sharing(credentials, *healthy),
+                Err(Error::Aborted) => {}
+                Err(err) => warn!("Could not add users: {}", err),
+            },
+            Action::CopyUrl => {
+                // Some clipboards lose the content with its owner:
+                if let Some(copied) = self.copy_share_url(&[]) {
+                    *clipboard = Some(copied);
+                }
+            }
+            Action::Quit => self.should_end.store(true, Ordering::SeqCst),
+        }
+    }
+
+    // Stops the server and starts it again, returns whether it runs:
+    fn restart_server(&mut self) -> bool {
+        if let Some(waker) = self.paused.take() {
+            waker.stop(&self.runtime);
+        }
+        if let Some(process) = self.miniserve_handle.take() {
+            let _ = process.stop(&self.runtime);
+        }
+        if let Some(server) = self.server.take() {
+            server.stop(&self.runtime);
+        }
+
+        match self.start_server() {
+            Ok(()) => {
+                info!(success = true, "Restarted {}", self.server_name());
+                true
+            }
+            Err(err) => {
+                warn!("Could not restart {}: {}", self.server_name(), err);
+                false
+            }
+        }
+    }
+
+    /// Stops the builtin server and holds its port with a [`Waker`] until the next request,
+    /// returns whether it's paused. The server keeps running if the port can't be held.
+    pub(super) fn pause_server(&mut self) -> bool {
+        let Some(server) = self.server.take() else {
+            return false;
+        };
+        server.stop(&self.runtime);
+
+        match Waker::start(&self.runtime, &self.local_end(), self.stats.clone()) {
+            Ok(waker) => {
+                info!("Paused {} until the next request", self.server_name());
+                logging::emit(
+                    "server_paused",
+                    json!({ "local_port": self.config.local_port }),
+                );
+                self.paused = Some(waker);
+                true
+            }
+            Err(err) => {
+                warn!("Could not pause {}: {}", self.server_name(), err);
+                if let Err(err) = self.start_server() {
+                    warn!("Could not start {} again: {}", self.server_name(), err);
+                }
+                false
+            }
+        }
+    }
+
+    /// Starts a paused server again, returns whether it runs.
+    pub(super) fn resume_server(&mut self) -> bool {
+        let Some(waker) = self.paused.take() else {
+            return self.server.is_some();
+        };
+        waker.stop(&self.runtime);
+
+        match self.start_server() {
+            Ok(()) => {
+                info!("Resumed {}", self.server_name());
+                true
+            }
+            Err(err) => {
+                warn!("Could not resume {}: {}", self.server_name(), err);
+                false
+            }
+        }
+    }
+
+    // Hands users entered while sharing to the running server, like `add_user` on the control
+    // socket does:
+    fn add_users_while_sharing(&mut self, credentials: Vec<(String, String)>, healthy: bool) {
+        for (username, password) in credentials {
+            let request = Request::AddUser {
+                username: username.clone(),
+                password,
+            };
+            match self.handle_control(request, healthy) {
+                Ok(response) if response["applied"] == true => {
+                    info!(success = true, "Added user '{}'", username)
+                }
+                Ok(_) => info!(
+                    "Added user '{}' to the config, this share is not secure (--secure)",
+                    username
+                ),
+                Err(err) => warn!("Could not add user '{}': {}", username, err),
+            }
+        }
+    }
+
+    fn has_user(&self, username: &str) -> bool {
+        self.config.users.iter().any(|(user, _)| user == username)
+    }
+
+    // Hands changed users to the running server. External servers only read them on start:
+    fn apply_users(&mut self) -> Response {
+        if !self.cli.share.secure {
+            return Ok(json!({ "applied": false }));
+        }
+
+        if let Some(server) = &self.server {
+            server.set_users(self.config.users.clone());
+        }
+
+        if let Some(process) = self.miniserve_handle.take() {
+            let _ = process.stop(&self.runtime);
+            self.start_server()
+                .map_err(|err| format!("could not restart {}: {}", self.server_name(), err))?;
+        }
+
+        Ok(json!({ "applied": true }))
+    }
+}
@@ -0,0 +1,290 @@
+use super::{describe_timeouts, key_action, Action, App};
+use crate::logging;
+
+use std::{
+    collections::VecDeque,
+    io::{self, stdout, Stdout},
+    time::Duration,
+};
+
+use humantime::format_duration;
+use indicatif::DecimalBytes;
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+    Terminal,
+};
+use tracing::debug;
+
+// Status lines kept for the events pane, the latest last:
+const EVENT_LINES: usize = 6;
+
+/// What the main loop knows beyond the [`App`], handed to every redraw.
+pub(super) struct Status {
+    pub connected: bool,
+    // Round trip of the last keepalive:
+    pub latency: Option<Duration>,
+    pub healthy: bool,
+    // (restarts so far, limit):
+    pub restarts: (u32, u32),
+    pub transfer_cap: Option<u64>,
+    pub time_left: Option<Duration>,
+}
+
+/// The live view of a share (--tui) on the alternate screen. Status lines show up in it instead
+/// of the terminal, the spinners are hidden meanwhile. The terminal is restored on drop.
+pub(super) struct Dashboard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    events: VecDeque<String>,
+    // Requests scrolled back from the latest one:
+    scroll: usize,
+}
+
+impl Dashboard {
+    pub fn start() -> io::Result<Dashboard> {
+        enter()?;
+        let terminal = match Terminal::new(CrosstermBackend::new(stdout())) {
+            Ok(terminal) => terminal,
+            Err(err) => {
+                leave();
+                return Err(err);
+            }
+        };
+        logging::capture(true);
+
+        Ok(Dashboard {
+            terminal,
+            events: VecDeque::with_capacity(EVENT_LINES),
+            scroll: 0,
+        })
+    }
+
+    /// Gives the terminal back for `f`, e.g. a prompt, and shows the dashboard again afterwards.
+    pub fn suspend<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        self.collect_events();
+        logging::capture(false);
+        leave();
+
+        let result = f();
+
+        if let Err(err) = enter() {
+            debug!("Could not show the dashboard again: {}", err);
+        }
+        let _ = self.terminal.clear();
+        logging::capture(true);
+        result
+    }
+
+    /// The keys pressed since the last call. Scrolling is handled right here.
+    pub fn actions(&mut self) -> Vec<Action> {
+        let mut actions = vec![];
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    // Raw mode keeps CTRL+C from sending SIGINT:
+                    actions.push(Action::Quit)
+                }
+                KeyCode::Up | KeyCode::Char('k') => self.scroll += 1,
+                KeyCode::Down | KeyCode::Char('j') => self.scroll = self.scroll.saturating_sub(1),
+                KeyCode::End => self.scroll = 0,
+                KeyCode::Char(key) => actions.extend(key_action(key)),
+                KeyCode::Esc => actions.push(Action::Quit),
+                _ => {}
+            }
+        }
+        actions
+    }
+
+    pub fn draw(&mut self, app: &App, status: &Status) -> io::Result<()> {
+        self.collect_events();
+
+        let requests = app.stats.recent();
+        self.scroll = self.scroll.min(requests.len().saturating_sub(1));
+        let (count, clients, sent) = app.stats.totals();
+
+        let transferred = match &app.relay {
+            Some(relay) => format!(
+                "{} through the tunnel{}, ",
+                DecimalBytes(relay.transferred()),
+                match status.transfer_cap {
+                    Some(cap) => format!(" of {}", DecimalBytes(cap)),
+                    None => String::new(),
+                }
+            ),
+            None => String::new(),
+        };
+        let overview = vec![
+            row(
+                "URL",
+                vec![app
+                    .public_url()
+                    .unwrap_or_else(|| String::from("none, set a domain or public_url"))
+                    .into()],
+            ),
+            row(
+                "Tunnel",
+                vec![
+                    dot(status.connected),
+                    format!(
+                        " {} to {} via '{}'{}",
+                        app.local_end(),
+                        app.remote_end(),
+                        app.config.host,
+                        app.describe_extra_forwards()
+                    )
+                    .into(),
+                ],
+            ),
+            row(
+                "SSH",
+                vec![match status.latency {
+                    Some(latency) => format!("{} ms latency", latency.as_millis()).into(),
+                    None if app.config.keepalive_interval == Some(0) => {
+                        "latency not measured, keepalives are off".into()
+                    }
+                    None => "latency not measured yet, with the next keepalive".into(),
+                }],
+            ),
+            row(
+                "Server",
+                vec![
+                    dot(status.healthy),
+                    format!(
+                        " {} serving '{}' on {}, restarted {}/{}",
+                        app.server_name(),
+                        app.directory.display(),
+                        app.local_end(),
+                        status.restarts.0,
+                        status.restarts.1
+                    )
+                    .into(),
+                ],
+            ),
+            row(
+                "Transfer",
+                vec![format!(
+                    "{}{} request(s) from {} client(s), {} sent{}",
+                    transferred,
+                    count,
+                    clients,
+                    DecimalBytes(sent),
+                    describe_timeouts(app.stats.timeouts())
+                )
+                .into()],
+            ),
+            row("Visitors", vec![app.describe_visitors().into()]),
+            row(
+                "Time left",
+                vec![match status.time_left {
+                    Some(left) => format_duration(Duration::from_secs(left.as_secs()))
+                        .to_string()
+                        .into(),
+                    None => "shares until you quit".into(),
+                }],
+            ),
+        ];
+
+        let scroll = self.scroll;
+        let events: Vec<Line> = self
+            .events
+            .iter()
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        self.terminal.draw(|frame| {
+            let [overview_area, requests_area, events_area, keys_area] = Layout::vertical([
+                Constraint::Length(overview.len() as u16 + 2),
+                Constraint::Min(3),
+                Constraint::Length(EVENT_LINES as u16 + 2),
+                Constraint::Length(1),
+            ])
+            .areas(frame.area());
+
+            frame.render_widget(
+                Paragraph::new(overview).block(Block::bordered().title(" livetunnel ")),
+                overview_area,
+            );
+
+            let end = requests.len() - scroll.min(requests.len());
+            let start = end.saturating_sub(requests_area.height.saturating_sub(2) as usize);
+            let title = if scroll > 0 {
+                format!(" Requests ({} newer, End for the latest) ", scroll)
+            } else {
+                String::from(" Requests ")
+            };
+            frame.render_widget(
+                Paragraph::new(
+                    requests[start..end]
+                        .iter()
+                        .map(|request| Line::from(request.as_str()))
+                        .collect::<Vec<_>>(),
+                )
+                .block(Block::bordered().title(title)),
+                requests_area,
+            );
+
+            frame.render_widget(
+                Paragraph::new(events).block(Block::bordered().title(" Events ")),
+                events_area,
+            );
+            frame.render_widget(
+                Paragraph::new(" r restart server · u add user · c copy URL · ↑/↓ scroll · q quit")
+                    .dim(),
+                keys_area,
+            );
+        })?;
+        Ok(())
+    }
+
+    fn collect_events(&mut self) {
+        for line in logging::captured() {
+            if self.events.len() == EVENT_LINES {
+                self.events.pop_front();
+            }
+            self.events.push_back(line);
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        logging::capture(false);
+        leave();
+    }
+}
+
+fn enter() -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)
+}
+
+fn leave() {
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout(), LeaveAlternateScreen);
+}
+
+// A line of the overview, "Tunnel    ● local Port 8080 to ...":
+fn row(label: &str, value: Vec<Span<'static>>) -> Line<'static> {
+    let mut spans = vec![format!("{:<10}", label).bold()];
+    spans.extend(value);
+    Line::from(spans)
+}
+
+fn dot(ok: bool) -> Span<'static> {
+    if ok {
+        "●".green()
+    } else {
+        "●".red()
+    }
+}
@@ -0,0 +1,503 @@
+use super::{quote, spinner, App, SUCCESS_TEMPLATE};
+use crate::{
+    error::{Error, Result},
+    logging,
+};
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{read_dir, symlink_metadata},
+    io::{self, pipe, PipeWriter, Write},
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    thread::spawn,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use indicatif::{DecimalBytes, ProgressBar};
+use inquire::{validator::ValueRequiredValidator, Text};
+use openssh::Stdio;
+use serde::{Deserialize, Serialize};
+use tar::Builder;
+use tokio::process::Command;
+
+/// How `livetunnel deploy` copies the files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployMethod {
+    /// A tar stream through the SSH session, needs nothing but tar on the server
+    #[default]
+    Tar,
+    /// rsync, only sends what changed and can delete what's gone (needs rsync on both sides)
+    Rsync,
+    /// sftp, for servers that only allow file transfers
+    Sftp,
+}
+
+impl Display for DeployMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DeployMethod::Tar => write!(f, "tar over SSH"),
+            DeployMethod::Rsync => write!(f, "rsync"),
+            DeployMethod::Sftp => write!(f, "sftp"),
+        }
+    }
+}
+
+// Counts the bytes of the archive as they are handed to ssh:
+struct CountingWriter {
+    inner: PipeWriter,
+    written: u64,
+    pb: ProgressBar,
+    message: String,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        self.pb.set_message(format!(
+            "{} ({} sent)",
+            self.message,
+            DecimalBytes(self.written)
+        ));
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// What `livetunnel deploy` and `--hybrid` sync with, from the arguments and the config.
+#[derive(Clone, Debug)]
+pub(super) struct DeployOptions {
+    method: DeployMethod,
+    delete: bool,
+    exclude: Vec<String>,
+}
+
+impl App {
+    /// Copies the shared directory to `remote_path` on the server through the SSH session, as a
+    /// tar stream (so neither side needs rsync or sftp), with rsync or with sftp. Paths matching
+    /// one of the `exclude` patterns stay behind, `delete` (rsync only) removes remote files that
+    /// don't exist locally.
+    pub fn deploy(
+        &mut self,
+        remote_path: Option<String>,
+        method: Option<DeployMethod>,
+        delete: bool,
+        exclude: Vec<String>,
+    ) -> Result<()> {
+        let options = self.deploy_options(method, delete, exclude)?;
+        let remote_path = self.deploy_path(remote_path)?;
+
+        if self.cli.share.build || self.config.build.unwrap_or(false) {
+            self.build_site()?;
+        }
+
+        self.sync(&remote_path, &options)
+    }
+
+    pub(super) fn deploy_options(
+        &self,
+        method: Option<DeployMethod>,
+        delete: bool,
+        exclude: Vec<String>,
+    ) -> Result<DeployOptions> {
+        let method = method.or(self.config.deploy_method).unwrap_or_default();
+        let delete = delete || self.config.deploy_delete.unwrap_or(false);
+        let exclude: Vec<String> = self
+            .config
+            .deploy_exclude
+            .iter()
+            .flatten()
+            .cloned()
+            .chain(exclude)
+            .collect();
+        if delete && method != DeployMethod::Rsync {
+            return Err(Error::Config(format!(
+                "Deleting remote files only works with rsync, not {}.",
+                method
+            )));
+        }
+
+        Ok(DeployOptions {
+            method,
+            delete,
+            exclude,
+        })
+    }
+
+    // The remote directory to copy into, asked for (and stored) if there is none yet:
+    pub(super) fn deploy_path(&mut self, remote_path: Option<String>) -> Result<String> {
+        match remote_path.or_else(|| self.config.deploy_path.clone()) {
+            Some(remote_path) => Ok(remote_path),
+            None if self.cli.non_interactive => Err(Error::Config(String::from(
+                "No remote directory configured, pass one with --remote-path or set deploy_path in the config.",
+            ))),
+            None => {
+                let remote_path = Text::new("Remote directory to deploy to:")
+                    .with_validator(ValueRequiredValidator::default())
+                    .with_placeholder("/var/www/share")
+                    .prompt()?;
+                self.update_config(|config| config.deploy_path = Some(remote_path.clone()))?;
+                Ok(remote_path)
+            }
+        }
+    }
+
+    /// Copies the shared directory to `remote_path`, showing the progress.
+    pub(super) fn sync(&self, remote_path: &str, options: &DeployOptions) -> Result<()> {
+        let DeployOptions {
+            method,
+            delete,
+            exclude,
+        } = options;
+        let message = format!(
+            "Deploying '{}' to '{}:{}' via {}",
+            self.directory.display(),
+            self.config.host,
+            remote_path,
+            method
+        );
+        let pb = spinner();
+        pb.set_message(message.clone());
+        pb.enable_steady_tick(Duration::from_millis(20));
+
+        let result = match method {
+            DeployMethod::Tar => self
+                .stream_tar(remote_path, exclude, pb.clone(), message)
+                .map(|sent| format!(" ({} sent)", DecimalBytes(sent))),
+            DeployMethod::Rsync => self.rsync(remote_path, *delete, exclude).map(|()| {
+                if *delete {
+                    String::from(", removed files that are gone")
+                } else {
+                    String::new()
+                }
+            }),
+            DeployMethod::Sftp => self
+                .sftp(remote_path, exclude)
+                .map(|files| format!(" ({} files)", files)),
+        };
+
+        match result {
+            Ok(summary) => {
+                pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+                pb.tick();
+                pb.finish_with_message(format!(
+                    "Deployed '{}' to '{}:{}' via {}{}",
+                    self.directory.display(),
+                    self.config.host,
+                    remote_path,
+                    method,
+                    summary
+                ));
+                Ok(())
+            }
+            Err(err) => {
+                pb.finish_and_clear();
+                Err(Error::Ssh(format!("Deploy failed: {}", err)))
+            }
+        }
+    }
+
+    fn stream_tar(
+        &self,
+        remote_path: &str,
+        exclude: &[String],
+        pb: ProgressBar,
+        message: String,
+    ) -> std::result::Result<u64, String> {
+        let entries = if self.directory.is_dir() {
+            entries(&self.directory, exclude)
+                .map_err(|err| format!("could not read the directory: {}", err))?
+        } else {
+            vec![]
+        };
+        let (reader, writer) = pipe().map_err(|err| err.to_string())?;
+
+        let directory: PathBuf = self.directory.clone();
+        let archiver = spawn(move || -> io::Result<u64> {
+            let mut builder = Builder::new(CountingWriter {
+                inner: writer,
+                written: 0,
+                pb,
+                message,
+            });
+            builder.follow_symlinks(false);
+            match directory.file_name().filter(|_| directory.is_file()) {
+                Some(name) => builder.append_path_with_name(&directory, name)?,
+                None => {
+                    for relative in entries {
+                        builder.append_path_with_name(directory.join(&relative), &relative)?;
+                    }
+                }
+            }
+
+            // Dropping the writer closes the pipe, which ends the remote tar:
+            let writer = builder.into_inner()?;
+            Ok(writer.written)
+        });
+
+        // The command has to be dropped right after it exits, otherwise our copy of the read
+        // end keeps the archiver blocked if the remote side fails early:
+        let output = {
+            let mut remote_cmd = self.ssh_session.command("sh");
+            remote_cmd
+                .arg("-c")
+                .arg("mkdir -p \"$1\" && tar -x -f - -C \"$1\"")
+                .arg("livetunnel-deploy")
+                .arg(remote_path)
+                .stdin(Stdio::from(OwnedFd::from(reader)));
+
+            self.runtime.block_on(remote_cmd.output())
+        };
+        if let Ok(output) = &output {
+            logging::command_output("tar -x", output);
+        }
+
+        match output {
+            Ok(output) if !output.status.success() => {
+                return Err(format!(
+                    "remote tar exited with {}: '{}'",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            Err(err) => return Err(err.to_string()),
+            Ok(_) => {}
+        }
+
+        match archiver.join() {
+            Ok(Ok(sent)) => Ok(sent),
+            Ok(Err(err)) => Err(format!("could not archive the directory: {}", err)),
+            Err(_) => Err(String::from("archiving the directory panicked")),
+        }
+    }
+
+    fn rsync(
+        &self,
+        remote_path: &str,
+        delete: bool,
+        exclude: &[String],
+    ) -> std::result::Result<(), String> {
+        self.remote_mkdir(remote_path)?;
+
+        let mut rsync = Command::new("rsync");
+        rsync.args(["-a", "-z"]);
+        if delete {
+            // Excluded remote files are kept, they were never ours to delete:
+            rsync.arg("--delete");
+        }
+        for pattern in exclude {
+            rsync.arg(format!("--exclude={}", pattern));
+        }
+
+        // A trailing slash copies the content, not the directory itself:
+        let mut source = self.directory.clone().into_os_string();
+        if self.directory.is_dir() {
+            source.push("/");
+        }
+        let ssh: Vec<String> = self
+            .multiplex_options()
+            .iter()
+            .map(|option| quote(option))
+            .collect();
+        rsync
+            .arg("-e")
+            .arg(format!("ssh {}", ssh.join(" ")))
+            .arg(source)
+            .arg(format!("{}:{}/", self.config.host, remote_path));
+
+        match self
+            .runtime
+            .block_on(rsync.output())
+            .inspect(|output| logging::command_output("rsync", output))
+        {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(format!(
+                "rsync exited with {}: '{}'",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => Err(format!("could not run rsync: {}", err)),
+        }
+    }
+
+    // Returns how many files were uploaded:
+    fn sftp(&self, remote_path: &str, exclude: &[String]) -> std::result::Result<usize, String> {
+        self.remote_mkdir(remote_path)?;
+
+        let (base, entries) = match self
+            .directory
+            .file_name()
+            .filter(|_| self.directory.is_file())
+        {
+            Some(name) => (
+                self.directory
+                    .parent()
+                    .unwrap_or(Path::new(""))
+                    .to_path_buf(),
+                vec![PathBuf::from(name)],
+            ),
+            None => (
+                self.directory.clone(),
+                entries(&self.directory, exclude)
+                    .map_err(|err| format!("could not read the directory: {}", err))?,
+            ),
+        };
+
+        // sftp has no recursive upload that skips files, so every one gets its own command:
+        let mut batch = String::new();
+        let mut files = 0;
+        for relative in entries {
+            let local = base.join(&relative);
+            let remote = format!("{}/{}", remote_path, relative.to_string_lossy());
+            if local.is_dir() {
+                // Leading '-': the directory may exist already
+                batch.push_str(&format!("-mkdir {}\n", sftp_quote(&remote)));
+            } else {
+                batch.push_str(&format!(
+                    "put -p {} {}\n",
+                    sftp_quote(&local.to_string_lossy()),
+                    sftp_quote(&remote)
+                ));
+                files += 1;
+            }
+        }
+
+        let mut sftp = Command::new("sftp");
+        sftp.args(["-q", "-b", "-"])
+            .args(self.multiplex_options())
+            .arg(&self.config.host)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped());
+
+        let output = self.runtime.block_on(async {
+            use tokio::io::AsyncWriteExt;
+
+            let mut child = sftp.spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(batch.as_bytes()).await?;
+            }
+            child.wait_with_output().await
+        });
+        if let Ok(output) = &output {
+            logging::command_output("sftp", output);
+        }
+        match output {
+            Ok(output) if output.status.success() => Ok(files),
+            Ok(output) => Err(format!(
+                "sftp exited with {}: '{}'",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => Err(format!("could not run sftp: {}", err)),
+        }
+    }
+
+    fn remote_mkdir(&self, remote_path: &str) -> std::result::Result<(), String> {
+        let output = self.runtime.block_on(
+            self.ssh_session
+                .command("sh")
+                .arg("-c")
+                .arg(format!("mkdir -p {}", quote(remote_path)))
+                .output(),
+        );
+        match output {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(format!(
+                "could not create '{}': '{}'",
+                remote_path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    // Lets rsync and sftp use the connection that is already up (same login, hooks and
+    // password) instead of logging in again:
+    fn multiplex_options(&self) -> Vec<String> {
+        vec![
+            String::from("-o"),
+            format!(
+                "ControlPath={}",
+                self.ssh_session.control_socket().display()
+            ),
+            String::from("-o"),
+            String::from("ControlMaster=no"),
+        ]
+    }
+}
+
+// Every path below `directory` (relative, directories before their content) that no pattern of
+// `exclude` matches. Symlinks are listed, but not followed:
+fn entries(directory: &Path, exclude: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut entries = vec![];
+    let mut pending = vec![PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let mut children: Vec<PathBuf> = read_dir(directory.join(&relative))?
+            .map(|entry| entry.map(|entry| relative.join(entry.file_name())))
+            .collect::<io::Result<_>>()?;
+        children.sort();
+
+        for child in children {
+            if excluded(&child, exclude) {
+                continue;
+            }
+            if symlink_metadata(directory.join(&child))?.is_dir() {
+                pending.push(child.clone());
+            }
+            entries.push(child);
+        }
+    }
+
+    Ok(entries)
+}
+
+// Like rsync: patterns with a '/' match the whole relative path, others any single name in it.
+// '*' and '?' are wildcards:
+fn excluded(relative: &Path, exclude: &[String]) -> bool {
+    exclude.iter().any(|pattern| {
+        if pattern.contains('/') {
+            let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+            wildcard(pattern, &relative.to_string_lossy())
+        } else {
+            relative
+                .iter()
+                .any(|name| wildcard(pattern, &name.to_string_lossy()))
+        }
+    })
+}
+
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // Where to continue after the last '*' when the rest doesn't match:
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn sftp_quote(input: &str) -> String {
+    format!("\"{}\"", input.replace('\\', "\\\\").replace('"', "\\\""))
+}
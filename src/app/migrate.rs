@@ -0,0 +1,167 @@
+use super::Config;
+
+use toml::{value::Table, Value};
+
+/// Schema of the config files this livetunnel writes, stored as `version` in them.
+pub(super) const CONFIG_VERSION: u32 = 3;
+
+// Upgrades a config from the version at its index to the next one. A renamed or restructured
+// field gets a step here and CONFIG_VERSION goes up, so older files keep their settings instead
+// of losing them to the defaults:
+const MIGRATIONS: [fn(&mut Table); CONFIG_VERSION as usize] = [
+    // 0 → 1: files from before there was a version, the fields are the same:
+    |_| {},
+    // 1 → 2: commands were [program, arguments] pairs, the arguments split at spaces. Now they're
+    // command lines with shell quoting:
+    |config| {
+        for key in ["before_commands", "after_commands"] {
+            let commands = config.get_mut(key).and_then(Value::as_array_mut);
+            for command in commands.into_iter().flatten() {
+                if let Some(pair) = command.as_array() {
+                    let line: Vec<&str> = pair
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .filter(|part| !part.is_empty())
+                        .collect();
+                    *command = Value::from(line.join(" "));
+                }
+            }
+        }
+    },
+    // 2 → 3: commands are tables, to give them a dir and env:
+    |config| {
+        for key in ["before_commands", "after_commands"] {
+            let commands = config.get_mut(key).and_then(Value::as_array_mut);
+            for command in commands.into_iter().flatten() {
+                if let Value::String(line) = command {
+                    let mut table = Table::new();
+                    table.insert(String::from("command"), Value::from(line.as_str()));
+                    *command = Value::Table(table);
+                }
+            }
+        }
+    },
+];
+
+/// Reads a config file of any version, upgraded to CONFIG_VERSION. True if that changed its
+/// settings, the file should be stored again then.
+pub(super) fn parse_config(content: &str) -> Result<(Config, bool), String> {
+    let (config, changed) = parse_value(content)?;
+    let config = config
+        .try_into()
+        .map_err(|err: toml::de::Error| err.to_string())?;
+    Ok((config, changed))
+}
+
+/// Like [`parse_config`], but leaves it as TOML to change before it's read.
+pub(super) fn parse_value(content: &str) -> Result<(Value, bool), String> {
+    let mut config: Value = toml::from_str(content).map_err(|err| err.to_string())?;
+    let changed = migrate(&mut config)?;
+    Ok((config, changed))
+}
+
+/// Upgrades a parsed config file to CONFIG_VERSION in place, true if that changed more than the
+/// version. Fails for one written by a newer livetunnel, its settings would be lost otherwise.
+pub(super) fn migrate(config: &mut Value) -> Result<bool, String> {
+    let table = config
+        .as_table_mut()
+        .ok_or_else(|| String::from("it's not a TOML table"))?;
+    let version = match table.get("version") {
+        None => 0,
+        Some(Value::Integer(version)) => {
+            u32::try_from(*version).map_err(|_| format!("version {} is invalid", version))?
+        }
+        Some(version) => return Err(format!("version {} is not a number", version)),
+    };
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "it's version {} of the config, this livetunnel only knows up to {}. Update livetunnel",
+            version, CONFIG_VERSION
+        ));
+    }
+
+    let previous = table.clone();
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(table);
+    }
+    let changed = *table != previous;
+    table.insert(
+        String::from("version"),
+        Value::Integer(CONFIG_VERSION.into()),
+    );
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::commands::ConnectCommand;
+
+    #[test]
+    fn unversioned_files_keep_their_commands() {
+        let (config, changed) = parse_config(
+            r#"
+host = "files.example.org"
+local_port = 8000
+remote_port = 9000
+users = []
+before_commands = [["hugo", "--minify --baseURL https://files.example.org/"], ["true", ""]]
+after_commands = [["systemctl", "--user reload caddy"]]
+"#,
+        )
+        .unwrap();
+
+        assert!(changed);
+        assert_eq!(config.version, Some(CONFIG_VERSION));
+        assert_eq!(config.host, "files.example.org");
+        assert_eq!(config.remote_port, 9000);
+        let lines = |commands: Option<Vec<ConnectCommand>>| -> Vec<String> {
+            commands
+                .unwrap()
+                .into_iter()
+                .map(|command| command.command)
+                .collect()
+        };
+        assert_eq!(
+            lines(config.before_commands),
+            ["hugo --minify --baseURL https://files.example.org/", "true"]
+        );
+        assert_eq!(
+            lines(config.after_commands),
+            ["systemctl --user reload caddy"]
+        );
+    }
+
+    #[test]
+    fn only_a_changed_setting_asks_to_store_the_file() {
+        // Nothing to upgrade but the number:
+        let (config, changed) = parse_value("local_port = 3000").unwrap();
+        assert!(!changed);
+        assert_eq!(config["version"].as_integer(), Some(CONFIG_VERSION.into()));
+
+        let current = format!(
+            "version = {}\n[[before_commands]]\ncommand = \"make site\"\ndir = \"docs\"\n",
+            CONFIG_VERSION
+        );
+        let (config, changed) = parse_value(&current).unwrap();
+        assert!(!changed);
+        assert_eq!(config["before_commands"][0]["dir"].as_str(), Some("docs"));
+
+        let (config, changed) =
+            parse_value("version = 2\nafter_commands = [\"rm -rf /tmp/x\"]").unwrap();
+        assert!(changed);
+        assert_eq!(
+            config["after_commands"][0]["command"].as_str(),
+            Some("rm -rf /tmp/x")
+        );
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let newer = parse_value(&format!("version = {}", CONFIG_VERSION + 1)).unwrap_err();
+        assert!(newer.contains("Update livetunnel"), "{}", newer);
+        assert!(parse_value("version = -1").is_err());
+        assert!(parse_value("version = \"2\"").is_err());
+        assert!(parse_value("version = 1.5").is_err());
+    }
+}
@@ -0,0 +1,716 @@
+use super::{quote, spinner, App, INFO_TEMPLATE, SUCCESS_TEMPLATE, WARNING_TEMPLATE};
+use crate::{
+    endpoint::Endpoint,
+    error::{Error, Result},
+    logging,
+    probe::Probe,
+};
+
+use std::{
+    fmt::{self, Display, Formatter},
+    process::id,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use indicatif::{MultiProgress, ProgressBar};
+use inquire::{validator::ValueRequiredValidator, Confirm, Select, Text};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use tracing::{info, warn};
+
+const NGINX_TEMPLATE: &str = include_str!("templates/nginx.conf");
+const CADDY_TEMPLATE: &str = include_str!("templates/Caddyfile");
+const INSTALL_TEMPLATE: &str = include_str!("templates/install.sh");
+const FIREWALL_SCRIPT: &str = include_str!("templates/firewall.sh");
+
+const CADDY_IMPORT: &str = "import /etc/caddy/livetunnel/*.caddy";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum ReverseProxy {
+    Nginx,
+    Caddy,
+}
+
+// How a hop of the share is requested by `check_share`:
+enum Request {
+    // From here:
+    Local(String),
+    // With curl on the remote, the arguments before the URL:
+    Remote(String),
+}
+
+impl Display for ReverseProxy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReverseProxy::Nginx => write!(f, "nginx"),
+            ReverseProxy::Caddy => write!(f, "Caddy (automatic HTTPS)"),
+        }
+    }
+}
+
+impl App {
+    pub fn setup_remote(&mut self) -> Result<()> {
+        if self.cli.non_interactive {
+            return Err(Error::Config(String::from(
+                "setup-remote asks for its settings and can't run with --non-interactive.",
+            )));
+        }
+
+        let mut domain_prompt = Text::new("Domain livetunnel should be reachable on:")
+            .with_validator(ValueRequiredValidator::default())
+            .with_placeholder("share.example.com");
+
+        if let Some(domain) = &self.config.domain {
+            domain_prompt = domain_prompt.with_default(domain);
+        }
+
+        let domain = domain_prompt.prompt()?;
+
+        let proxy = Select::new(
+            "Which reverse proxy should be set up?",
+            vec![ReverseProxy::Nginx, ReverseProxy::Caddy],
+        )
+        .with_vim_mode(true)
+        .prompt()?;
+
+        let firewall = Confirm::new("Open ports 80 and 443 in the firewall (ufw/firewalld)?")
+            .with_default(true)
+            .prompt()?;
+
+        let certbot_email = if matches!(proxy, ReverseProxy::Nginx)
+            && Confirm::new("Request a Let's Encrypt certificate via certbot?")
+                .with_default(true)
+                .prompt()?
+        {
+            let mut email_prompt = Text::new("E-Mail address for Let's Encrypt:")
+                .with_validator(ValueRequiredValidator::default());
+            if let Some(email) = &self.config.certbot_email {
+                email_prompt = email_prompt.with_default(email);
+            }
+            Some(email_prompt.prompt()?)
+        } else {
+            None
+        };
+
+        self.update_config(|config| {
+            config.domain = Some(domain.clone());
+            if certbot_email.is_some() {
+                config.certbot_email = certbot_email.clone();
+            }
+        })?;
+
+        let mut steps = self.proxy_steps(&proxy, &domain, None);
+
+        if firewall {
+            steps.push((
+                String::from("Opening ports 80 and 443 in the firewall"),
+                String::from(FIREWALL_SCRIPT),
+            ));
+        }
+
+        if let Some(email) = &certbot_email {
+            steps.extend(certbot_steps(email, &domain));
+        }
+
+        let use_sudo = !self.remote_is_root();
+        if use_sudo {
+            info!("Remote user is not root, running setup steps via 'sudo -n'");
+        }
+
+        if !self.run_remote_steps(&steps, use_sudo) {
+            return Err(Error::Ssh(String::from("Remote setup aborted.")));
+        }
+
+        let pb_done = ProgressBar::new(42);
+        pb_done.set_style(INFO_TEMPLATE.get().unwrap().clone());
+        pb_done.finish_with_message(format!(
+            "Remote setup complete. Shares will be reachable on '{}{}'",
+            if certbot_email.is_some() || matches!(proxy, ReverseProxy::Caddy) {
+                "https://"
+            } else {
+                "http://"
+            },
+            domain
+        ));
+        Ok(())
+    }
+
+    pub fn verify(&mut self) -> Result<()> {
+        self.request_forward()?;
+        if !self.verify_proxy()? {
+            return Err(Error::Ssh(String::from(
+                "Reverse proxy verification failed.",
+            )));
+        }
+
+        info!(
+            success = true,
+            "Reverse proxy is correctly wired to the forwarded port."
+        );
+        Ok(())
+    }
+
+    /// Checks that the public domain is routed to our forward by the remote reverse proxy.
+    ///
+    /// Serves a nonce on the local port and fetches it on the remote, first directly from the
+    /// forwarded port and then through the reverse proxy. Needs an established forward and a
+    /// free local port, so it has to run before the local server is started.
+    pub(super) fn verify_proxy(&mut self) -> Result<bool> {
+        let domain = match &self.config.domain {
+            Some(domain) => domain.clone(),
+            None if self.cli.non_interactive => {
+                warn!("No domain configured, run `livetunnel setup-remote` or `livetunnel verify` once interactively.");
+                return Ok(false);
+            }
+            None => {
+                let domain = Text::new("Domain your reverse proxy serves livetunnel on:")
+                    .with_validator(ValueRequiredValidator::default())
+                    .with_placeholder("share.example.com")
+                    .prompt()?;
+                self.update_config(|config| config.domain = Some(domain.clone()))?;
+                domain
+            }
+        };
+
+        let nonce = nonce();
+        let probe = match Probe::start(&self.local_end(), nonce.as_bytes().into()) {
+            Ok(probe) => probe,
+            Err(err) => {
+                warn!(
+                    "Could not listen on {} to verify the reverse proxy: {}",
+                    self.local_end(),
+                    err
+                );
+                return Ok(false);
+            }
+        };
+
+        let checks = [
+            (
+                format!("forward on {}", self.remote_end()),
+                format!(
+                    "curl -sS --max-time 10 {}",
+                    self.remote_curl_target(".livetunnel-verify")
+                ),
+            ),
+            (
+                format!("reverse proxy for '{}'", domain),
+                format!(
+                    "curl -sSk -L --max-time 10 --resolve {domain}:80:127.0.0.1 --resolve {domain}:443:127.0.0.1 http://{domain}/.livetunnel-verify",
+                    domain = quote(&domain)
+                ),
+            ),
+        ];
+
+        let num_checks = checks.len();
+        let mut success = true;
+        for (i, (hop, script)) in checks.iter().enumerate() {
+            let pb = spinner();
+            pb.set_message(format!("[{}/{}] Checking {}", i + 1, num_checks, hop));
+            pb.enable_steady_tick(Duration::from_millis(20));
+
+            let error = match self.runtime.block_on(
+                self.ssh_session
+                    .command("sh")
+                    .arg("-c")
+                    .arg(script)
+                    .output(),
+            ) {
+                Ok(output) if output.status.code() == Some(127) => {
+                    Some(String::from("curl is not installed on the remote"))
+                }
+                Ok(output) if !output.status.success() => Some(format!(
+                    "request failed: '{}'",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )),
+                Ok(output) if output.stdout != nonce.as_bytes() => Some(format!(
+                    "got an answer, but not from livetunnel: '{}'",
+                    String::from_utf8_lossy(&output.stdout)
+                        .chars()
+                        .take(80)
+                        .collect::<String>()
+                        .trim()
+                )),
+                Ok(_) => None,
+                Err(err) => Some(format!("produced an Error: {}", err)),
+            };
+
+            if let Some(error) = error {
+                pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb.tick();
+                pb.finish_with_message(format!(
+                    "[{}/{}] Error: {} {}",
+                    i + 1,
+                    num_checks,
+                    hop,
+                    error
+                ));
+                success = false;
+                break;
+            }
+
+            pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+            pb.tick();
+            pb.finish_with_message(format!("[{}/{}] Working: {}", i + 1, num_checks, hop));
+        }
+
+        probe.stop();
+        Ok(success)
+    }
+
+    /// Writes a vhost for the domain to the reverse proxy for this share and reloads it, unless
+    /// one exists already (e.g. from `livetunnel setup-remote`). With a `certbot_email`, nginx
+    /// gets a Let's Encrypt certificate, which is only renewed when it is close to expiring.
+    /// [`App::remove_proxy`] takes it down again.
+    pub(super) fn provision_proxy(
+        &mut self,
+        proxy: ReverseProxy,
+        template: Option<&str>,
+    ) -> Result<()> {
+        let Some(domain) = self.config.domain.clone() else {
+            return Err(Error::Config(String::from(
+                "provision_proxy needs the domain to serve the share on, set domain in the config.",
+            )));
+        };
+
+        let use_sudo = !self.remote_is_root();
+        let (file, reload) = proxy_files(&proxy, &domain);
+        if self
+            .run_privileged(&format!("test -e {}", quote(&file)), use_sudo)
+            .is_ok()
+        {
+            info!(
+                "Using the existing {} config for '{}' ('{}')",
+                proxy, domain, file
+            );
+            return Ok(());
+        }
+
+        if !self.run_remote_steps(&self.proxy_steps(&proxy, &domain, template), use_sudo) {
+            // A config that didn't validate mustn't stay for the next reload:
+            let _ = self.run_privileged(&format!("rm -f {}", quote(&file)), use_sudo);
+            return Err(Error::Ssh(format!(
+                "Could not set up {} for '{}'.",
+                proxy, domain
+            )));
+        }
+
+        self.provisioned_proxy = Some((format!("rm -f {} && {}", quote(&file), reload), use_sudo));
+
+        // Caddy gets its certificates on its own:
+        if let (ReverseProxy::Nginx, Some(email)) = (proxy, &self.config.certbot_email) {
+            if !self.run_remote_steps(&certbot_steps(email, &domain), use_sudo) {
+                warn!("No certificate for '{}', serving it via HTTP only. Does it resolve to the server?", domain);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the vhost written by [`App::provision_proxy`] and reloads the reverse proxy.
+    pub(super) fn remove_proxy(&mut self) {
+        let Some((script, use_sudo)) = self.provisioned_proxy.take() else {
+            return;
+        };
+
+        match self.run_privileged(&script, use_sudo) {
+            Ok(()) => info!(
+                success = true,
+                "Removed the reverse proxy config of this share"
+            ),
+            Err(err) => warn!(
+                "Could not remove the reverse proxy config of this share: {}",
+                err
+            ),
+        }
+    }
+
+    // Installs the reverse proxy if needed and writes (and activates) its config for `domain`,
+    // from `template` instead of the builtin one if given:
+    fn proxy_steps(
+        &self,
+        proxy: &ReverseProxy,
+        domain: &str,
+        template: Option<&str>,
+    ) -> Vec<(String, String)> {
+        // Where the reverse proxy passes requests to, in the syntax of each. Caddy uses HTTP/2
+        // with the builtin server if it's turned on:
+        let h2c = self.server_command().is_none()
+            && self
+                .config
+                .serve
+                .as_ref()
+                .and_then(|serve| serve.http2)
+                .unwrap_or(false);
+        let (nginx_upstream, caddy_upstream) = match (&self.config.remote_socket, h2c) {
+            (Some(path), false) => (format!("unix:{}:", path), format!("unix/{}", path)),
+            (Some(path), true) => (format!("unix:{}:", path), format!("unix+h2c/{}", path)),
+            (None, false) => {
+                let address = format!("127.0.0.1:{}", self.config.remote_port);
+                (address.clone(), address)
+            }
+            (None, true) => {
+                let address = format!("127.0.0.1:{}", self.config.remote_port);
+                (address.clone(), format!("h2c://{}", address))
+            }
+        };
+        let (file, reload) = proxy_files(proxy, domain);
+        let mut steps: Vec<(String, String)> = vec![];
+
+        match proxy {
+            ReverseProxy::Nginx => {
+                steps.push((
+                    String::from("Installing nginx"),
+                    install_script("nginx", "nginx"),
+                ));
+
+                let (fallback, synced) = match &self.config.deploy_path {
+                    Some(path) => (
+                        String::from(
+                            "\n        # The copy synced by --hybrid while the tunnel is down:\n        error_page 502 504 = @synced;\n",
+                        ),
+                        format!(
+                            "\n    location @synced {{\n        root {};\n        autoindex on;\n    }}\n",
+                            proxy_quote(path)
+                        ),
+                    ),
+                    None => (String::new(), String::new()),
+                };
+                let vhost = template
+                    .unwrap_or(NGINX_TEMPLATE)
+                    .replace("{domain}", domain)
+                    .replace("{upstream}", &nginx_upstream)
+                    .replace("{fallback}", &fallback)
+                    .replace("{synced}", &synced);
+                steps.push((
+                    format!("Writing nginx vhost for '{}'", domain),
+                    format!(
+                        "{} && nginx -t && {}",
+                        write_file_script(&file, &vhost),
+                        reload
+                    ),
+                ));
+            }
+            ReverseProxy::Caddy => {
+                steps.push((
+                    String::from("Installing Caddy"),
+                    install_script("caddy", "caddy"),
+                ));
+
+                let fallback = match &self.config.deploy_path {
+                    Some(path) => format!(
+                        "\n    # The copy synced by --hybrid while the tunnel is down:\n    handle_errors {{\n        root * {}\n        file_server browse {{\n            status 200\n        }}\n    }}\n",
+                        proxy_quote(path)
+                    ),
+                    None => String::new(),
+                };
+                let site = template
+                    .unwrap_or(CADDY_TEMPLATE)
+                    .replace("{domain}", domain)
+                    .replace("{upstream}", &caddy_upstream)
+                    .replace("{fallback}", &fallback);
+                steps.push((
+                    format!("Writing Caddy site for '{}'", domain),
+                    format!(
+                        "mkdir -p /etc/caddy/livetunnel && {} && (grep -qxF '{import}' /etc/caddy/Caddyfile || echo '{import}' >> /etc/caddy/Caddyfile) && caddy validate --adapter caddyfile --config /etc/caddy/Caddyfile && {}",
+                        write_file_script(&file, &site),
+                        reload,
+                        import = CADDY_IMPORT,
+                    ),
+                ));
+            }
+        }
+
+        steps
+    }
+
+    // Runs the (description, script) steps one after another with a spinner each, stops at the
+    // first that fails. Returns whether all of them succeeded:
+    fn run_remote_steps(&self, steps: &[(String, String)], use_sudo: bool) -> bool {
+        let num_steps = steps.len();
+        for (i, (description, script)) in steps.iter().enumerate() {
+            let pb = spinner();
+            pb.set_message(format!("[{}/{}] {}", i + 1, num_steps, description));
+            pb.enable_steady_tick(Duration::from_millis(20));
+
+            if let Err(error) = self.run_privileged(script, use_sudo) {
+                pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb.tick();
+                pb.finish_with_message(format!(
+                    "[{}/{}] Error: {} {}",
+                    i + 1,
+                    num_steps,
+                    description,
+                    error
+                ));
+                return false;
+            }
+
+            pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+            pb.tick();
+            pb.finish_with_message(format!("[{}/{}] Done: {}", i + 1, num_steps, description));
+        }
+
+        true
+    }
+
+    fn run_privileged(&self, script: &str, use_sudo: bool) -> std::result::Result<(), String> {
+        let mut remote_cmd = if use_sudo {
+            let mut cmd = self.ssh_session.command("sudo");
+            cmd.args(["-n", "sh"]);
+            cmd
+        } else {
+            self.ssh_session.command("sh")
+        };
+        remote_cmd.arg("-c").arg(script);
+
+        // Scripts are logged by their first line, the rest is mostly file content:
+        let label = script.lines().next().unwrap_or_default();
+        match self
+            .runtime
+            .block_on(remote_cmd.output())
+            .inspect(|output| logging::command_output(label, output))
+        {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(format!(
+                "exited with {}: '{}'",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Err(err) => Err(format!("produced an Error: {}", err)),
+        }
+    }
+
+    /// Requests the share through every hop once it is served: the local server, the forward on
+    /// the remote, the reverse proxy (on the remote, with the domain resolved to itself) and the
+    /// public URL from here. HEAD requests, so nothing counts as a download. Stops at the first
+    /// hop that doesn't answer with 200 (401 for secure shares) and returns whether all did.
+    pub(super) fn check_share(&self, mp: &MultiProgress) -> bool {
+        // External servers may still be starting up:
+        let started = Instant::now();
+        while self.local_end().connect().is_err() && started.elapsed() < Duration::from_secs(5) {
+            sleep(Duration::from_millis(200));
+        }
+
+        // A local socket is only checked through the forward, there's no URL for it:
+        let mut hops = vec![];
+        if let Endpoint::Port(port) = self.local_end() {
+            hops.push((
+                format!("local server on Port {}", port),
+                Request::Local(format!("http://127.0.0.1:{}/", port)),
+            ));
+        }
+        hops.push((
+            format!("forward on {}", self.remote_end()),
+            Request::Remote(self.remote_curl_target("")),
+        ));
+        if let Some(url) = self.public_url() {
+            if let Some((host, port)) = url_host(&url) {
+                let resolve = match port {
+                    Some(port) => format!(
+                        "--resolve {}",
+                        quote(&format!("{}:{}:127.0.0.1", host, port))
+                    ),
+                    None => format!(
+                        "--resolve {} --resolve {}",
+                        quote(&format!("{}:80:127.0.0.1", host)),
+                        quote(&format!("{}:443:127.0.0.1", host))
+                    ),
+                };
+                hops.push((
+                    format!("reverse proxy for '{}'", host),
+                    Request::Remote(format!("-k -L {} {}", resolve, quote(&url))),
+                ));
+            }
+            hops.push((format!("public URL {}", url), Request::Local(url)));
+        } else {
+            let pb = mp.add(ProgressBar::new(42));
+            pb.set_style(INFO_TEMPLATE.get().unwrap().clone());
+            pb.finish_with_message(
+                "No domain configured, only checking the local server and the forward",
+            );
+        }
+
+        let num_hops = hops.len();
+        for (i, (hop, request)) in hops.into_iter().enumerate() {
+            let pb = mp.add(spinner());
+            pb.set_message(format!("[{}/{}] Checking {}", i + 1, num_hops, hop));
+            pb.enable_steady_tick(Duration::from_millis(20));
+
+            let status = match request {
+                Request::Local(url) => local_status(&url),
+                Request::Remote(target) => self.remote_status(&target),
+            };
+            let result = match status {
+                Ok(200) => Ok(String::new()),
+                Ok(401) if self.cli.share.secure => Ok(String::from(" (asks for the login)")),
+                Ok(status) => Err(format!("answered with HTTP {}", status)),
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(note) => {
+                    pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+                    pb.tick();
+                    pb.finish_with_message(format!(
+                        "[{}/{}] Working: {}{}",
+                        i + 1,
+                        num_hops,
+                        hop,
+                        note
+                    ));
+                }
+                Err(err) => {
+                    pb.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                    pb.tick();
+                    pb.finish_with_message(format!(
+                        "[{}/{}] Failing: {} {}",
+                        i + 1,
+                        num_hops,
+                        hop,
+                        err
+                    ));
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    // The HTTP status of a HEAD request with curl on the remote:
+    fn remote_status(&self, target: &str) -> std::result::Result<u16, String> {
+        let script = format!(
+            "curl -sS -I -o /dev/null -w '%{{http_code}}' --max-time 10 {}",
+            target
+        );
+        match self.runtime.block_on(
+            self.ssh_session
+                .command("sh")
+                .arg("-c")
+                .arg(script)
+                .output(),
+        ) {
+            Ok(output) if output.status.code() == Some(127) => {
+                Err(String::from("(curl is not installed on the remote)"))
+            }
+            Ok(output) if !output.status.success() => Err(format!(
+                "didn't answer: '{}'",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .parse()
+                .map_err(|_| String::from("didn't answer with HTTP")),
+            Err(err) => Err(format!("produced an Error: {}", err)),
+        }
+    }
+
+    fn remote_is_root(&self) -> bool {
+        match self
+            .runtime
+            .block_on(self.ssh_session.command("id").arg("-u").output())
+        {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).trim() == "0",
+            Err(_) => false,
+        }
+    }
+}
+
+// The HTTP status of a HEAD request from here:
+fn local_status(url: &str) -> std::result::Result<u16, String> {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(10)))
+        .http_status_as_error(false)
+        .build()
+        .new_agent()
+        .head(url)
+        .call()
+        .map(|response| response.status().as_u16())
+        .map_err(|err| format!("didn't answer: {}", err))
+}
+
+// Host and explicit port of a URL like "https://share.example.com:8443/files/":
+fn url_host(url: &str) -> Option<(String, Option<u16>)> {
+    let authority = url.split_once("://")?.1.split(['/', '?', '#']).next()?;
+    let (host, port) = match authority.rsplit_once(':') {
+        // Not the end of an IPv6 address like "[::1]":
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()),
+        _ => (authority, None),
+    };
+    (!host.is_empty()).then(|| (host.to_string(), port))
+}
+
+fn nonce() -> String {
+    let mut hasher = Sha512::new();
+    hasher.update(format!(
+        "{}-{}",
+        id(),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+    ));
+    format!("{:x}", hasher.finalize())[..32].to_string()
+}
+
+// The config file livetunnel manages for `domain` and the script that reloads the proxy:
+fn proxy_files(proxy: &ReverseProxy, domain: &str) -> (String, String) {
+    match proxy {
+        ReverseProxy::Nginx => (
+            format!("/etc/nginx/conf.d/livetunnel-{}.conf", domain),
+            reload_script("nginx", "nginx -s reload || nginx"),
+        ),
+        ReverseProxy::Caddy => (
+            format!("/etc/caddy/livetunnel/{}.caddy", domain),
+            reload_script(
+                "caddy",
+                "caddy reload --config /etc/caddy/Caddyfile || caddy start --config /etc/caddy/Caddyfile",
+            ),
+        ),
+    }
+}
+
+// Installs certbot and gets a certificate for `domain` into its nginx vhost, a valid one is
+// reused instead of requesting a new one:
+fn certbot_steps(email: &str, domain: &str) -> Vec<(String, String)> {
+    vec![
+        (
+            String::from("Installing certbot"),
+            install_script("certbot", "certbot python3-certbot-nginx"),
+        ),
+        (
+            format!("Requesting certificate for '{}'", domain),
+            format!(
+                "certbot --nginx --non-interactive --agree-tos --keep-until-expiring --redirect -m {} -d {}",
+                quote(email),
+                quote(domain)
+            ),
+        ),
+    ]
+}
+
+fn install_script(program: &str, packages: &str) -> String {
+    INSTALL_TEMPLATE
+        .replace("{program}", program)
+        .replace("{packages}", packages)
+}
+
+fn write_file_script(path: &str, content: &str) -> String {
+    format!("printf '%s' {} > {}", quote(content), quote(path))
+}
+
+// Double quotes, understood by nginx and Caddy alike:
+fn proxy_quote(input: &str) -> String {
+    format!("\"{}\"", input.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn reload_script(service: &str, fallback: &str) -> String {
+    format!(
+        "if command -v systemctl >/dev/null 2>&1; then systemctl enable --now {service} && systemctl reload {service}; else {fallback}; fi",
+        service = service,
+        fallback = fallback
+    )
+}
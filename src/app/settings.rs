@@ -0,0 +1,353 @@
+use super::{
+    config_path, editor_command, load_config, migrate, read_config_file, save_config,
+    users::reload_running_shares, validate, write_config_file, App, Config,
+};
+use crate::{
+    backup::{backup_config, backup_files},
+    encryption,
+    error::{Error, Result},
+    secrets, vault,
+};
+
+use std::{
+    env::var,
+    fs::{read_to_string, remove_file, write},
+    path::Path,
+    process::Command,
+};
+
+use inquire::{validator::ValueRequiredValidator, Confirm, Password};
+use toml::Value;
+use tracing::{info, warn};
+
+// Put in place of passwords, tokens and the secret part of URLs by `livetunnel config show`:
+const REDACTED: &str = "<redacted>";
+
+impl App {
+    /// Prints where the config file lives and its content, without passwords and tokens
+    /// unless `secrets` is set (`livetunnel config show`).
+    pub fn show_config(secrets: bool) -> Result<()> {
+        let path = config_path()?;
+        let content = read_existing(&path)?;
+
+        let content = if secrets {
+            content
+        } else {
+            let mut config: Value = toml::from_str(&content).map_err(|err| {
+                Error::Config(format!(
+                    "'{}' can't be read: {}. Run `livetunnel config validate` for details.",
+                    path.display(),
+                    err
+                ))
+            })?;
+            redact(&mut config);
+            toml::to_string(&config).map_err(|err| Error::Config(err.to_string()))?
+        };
+
+        info!("Config file '{}':", path.display());
+        println!();
+        print!("{}", content);
+        Ok(())
+    }
+
+    /// Prints only where the config file lives, e.g. for scripts (`livetunnel config path`).
+    pub fn print_config_path() -> Result<()> {
+        println!("{}", config_path()?.display());
+        Ok(())
+    }
+
+    /// Opens the config file in the editor and stores it once it can be read, after telling
+    /// what's wrong with it (`livetunnel config edit`).
+    pub fn edit_config() -> Result<()> {
+        let path = config_path()?;
+        let original = read_existing(&path)?;
+        let previous = load_config().unwrap_or_default();
+
+        // Edited in a copy, so the config stays intact until the changes are checked (in plain
+        // text for the editor, also for an encrypted config, removed right after):
+        let draft = path.with_extension("edit.toml");
+        write(&draft, &original)?;
+        let result = edit_draft(&draft, previous.editor.as_deref());
+        let _ = remove_file(&draft);
+
+        let content = match result? {
+            Some(content) if content != original => content,
+            _ => {
+                info!("The config is unchanged.");
+                return Ok(());
+            }
+        };
+
+        if let Err(err) = backup_config() {
+            warn!("Could not back up the previous config: {}", err);
+        }
+        write_config_file(&path, &content)?;
+        info!(success = true, "Saved the config to '{}'", path.display());
+
+        if load_config().is_ok_and(|config| config.users != previous.users) {
+            reload_running_shares();
+        }
+        Ok(())
+    }
+
+    /// Encrypts the config file and its backups with a new passphrase, asked for every time it's
+    /// read from then on (`livetunnel config encrypt`).
+    pub fn encrypt_config() -> Result<()> {
+        let path = config_path()?;
+        let content = read_existing(&path)?;
+        if encryption::is_encrypted(&std::fs::read(&path)?) {
+            info!("'{}' is encrypted already.", path.display());
+            return Ok(());
+        }
+
+        let passphrase = match var(encryption::PASSPHRASE_VARIABLE) {
+            Ok(passphrase) => passphrase,
+            Err(_) => Password::new("New passphrase of the config:")
+                .with_validator(ValueRequiredValidator::default())
+                .prompt()?,
+        };
+        encryption::set_passphrase(passphrase);
+
+        if let Err(err) = backup_config() {
+            warn!("Could not back up the previous config: {}", err);
+        }
+        write(&path, encryption::encrypt(&content)?)?;
+        info!(success = true, "Encrypted '{}'", path.display());
+
+        // The plain ones would give it away:
+        let backups: Vec<_> = backup_files()?
+            .into_iter()
+            .filter(|backup| {
+                std::fs::read(backup).is_ok_and(|content| !encryption::is_encrypted(&content))
+            })
+            .collect();
+        if !backups.is_empty() {
+            info!("Encrypting {} backup(s)", backups.len());
+        }
+        for backup in backups {
+            write(&backup, encryption::encrypt(&read_to_string(&backup)?)?)?;
+        }
+
+        info!(
+            "Type the passphrase whenever livetunnel reads the config, or set {}.",
+            encryption::PASSPHRASE_VARIABLE
+        );
+        Ok(())
+    }
+
+    /// Stores the config file in plain text again (`livetunnel config decrypt`).
+    pub fn decrypt_config() -> Result<()> {
+        let path = config_path()?;
+        let content = read_existing(&path)?;
+        if !encryption::is_encrypted(&std::fs::read(&path)?) {
+            info!("'{}' is not encrypted.", path.display());
+            return Ok(());
+        }
+
+        write(&path, content)?;
+        info!(
+            success = true,
+            "Decrypted '{}', its backups stay encrypted",
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Changes a single setting without the assistant (`livetunnel config set`), e.g.
+    /// `remote_port 8081` or `watch.command "npm run build"` for one in a section.
+    pub fn set_config(key: &str, value: &str) -> Result<()> {
+        let path = config_path()?;
+        let content = read_existing(&path)?;
+        // Set on the current version of the settings:
+        let stored = toml::from_str::<Value>(&content)
+            .map_err(|err| err.to_string())
+            .and_then(|mut stored| migrate::migrate(&mut stored).map(|_| stored))
+            .map_err(|err| {
+                Error::Config(format!(
+                    "'{}' can't be read: {}. Fix it with `livetunnel config edit`.",
+                    path.display(),
+                    err
+                ))
+            })?;
+        let previous: Config = stored.clone().try_into().unwrap_or_default();
+
+        // Numbers, booleans and lists as in the config file, anything else is taken as text:
+        let config = match toml::from_str::<Value>(&format!("value = {}", value))
+            .ok()
+            .and_then(|parsed| parsed.get("value").cloned())
+            .and_then(|parsed| with_setting(&stored, key, parsed).ok())
+        {
+            Some(config) => config,
+            None => with_setting(&stored, key, Value::from(value)).map_err(|err| {
+                Error::Config(format!("{} can't be set to '{}': {}.", key, value, err))
+            })?,
+        };
+
+        // Settings livetunnel doesn't know are dropped when it's read:
+        let known = Value::try_from(&config)
+            .ok()
+            .is_some_and(|config| setting(&config, key).is_some());
+        if !known {
+            return Err(Error::Config(format!(
+                "There is no setting '{}'. The README lists them, `livetunnel config show` the ones in use.",
+                key
+            )));
+        }
+
+        save_config(&config)?;
+        info!(success = true, "Set {} to {}", key, value);
+
+        for problem in validate::problems(&config) {
+            warn!("{}", problem);
+        }
+        if config.users != previous.users {
+            reload_running_shares();
+        }
+        Ok(())
+    }
+}
+
+// The stored config with `key` (sections separated by dots) set to `value`:
+fn with_setting(stored: &Value, key: &str, value: Value) -> std::result::Result<Config, String> {
+    let mut config = stored.clone();
+    let mut keys = key.split('.').peekable();
+    let mut table = &mut config;
+    while let Some(key) = keys.next() {
+        let entries = table
+            .as_table_mut()
+            .ok_or_else(|| String::from("it's not in a section"))?;
+        if keys.peek().is_none() {
+            entries.insert(String::from(key), value);
+            break;
+        }
+        table = entries
+            .entry(key)
+            .or_insert_with(|| Value::Table(Default::default()));
+    }
+
+    config
+        .try_into()
+        .map_err(|err: toml::de::Error| err.to_string())
+}
+
+fn setting<'a>(config: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(config, |table, key| table.get(key))
+}
+
+// Runs the editor until the draft can be read and has no problems, or it's kept anyway. None
+// discards the changes:
+fn edit_draft(draft: &Path, editor: Option<&str>) -> Result<Option<String>> {
+    let (editor, editor_args) = editor_command(editor);
+
+    loop {
+        let status = Command::new(&editor)
+            .args(&editor_args)
+            .arg(draft)
+            .status()
+            .map_err(|err| {
+                Error::Config(format!(
+                    "Could not start the editor {:?}: {}. Set one with $EDITOR.",
+                    editor, err
+                ))
+            })?;
+        if !status.success() {
+            warn!(
+                "The editor exited with {}, the changes are discarded.",
+                status
+            );
+            return Ok(None);
+        }
+
+        let content = read_to_string(draft)?;
+        match migrate::parse_config(&content) {
+            Ok((config, _)) => {
+                let problems = validate::problems(&config);
+                if problems.is_empty() {
+                    return Ok(Some(content));
+                }
+
+                for problem in &problems {
+                    warn!("{}", problem);
+                }
+                if !Confirm::new("Edit it again? Otherwise it's saved as it is.")
+                    .with_default(true)
+                    .prompt()?
+                {
+                    return Ok(Some(content));
+                }
+            }
+            Err(err) => {
+                warn!("The config can't be read: {}", err);
+                if !Confirm::new("Edit it again? Otherwise the changes are discarded.")
+                    .with_default(true)
+                    .prompt()?
+                {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+// Passwords (hashes of them), the DNS token and the URLs that work as secrets. What's in the
+// keyring or a password manager is only referred to and stays:
+fn redact(config: &mut Value) {
+    if let Some(users) = config.get_mut("users").and_then(Value::as_array_mut) {
+        for user in users {
+            if let Some(password) = user.as_array_mut().and_then(|user| user.get_mut(1)) {
+                redact_secret(password);
+            }
+        }
+    }
+    if let Some(token) = config.get_mut("dns").and_then(|dns| dns.get_mut("token")) {
+        redact_secret(token);
+    }
+    if let Some(password) = config.get_mut("ssh_password") {
+        redact_secret(password);
+    }
+    if let Some(url) = config.get_mut("heartbeat_url") {
+        redact_url(url);
+    }
+    let webhooks = config
+        .get_mut("notifications")
+        .and_then(|notifications| notifications.get_mut("webhooks"))
+        .and_then(Value::as_array_mut);
+    for url in webhooks.into_iter().flatten() {
+        redact_url(url);
+    }
+}
+
+fn redact_secret(secret: &mut Value) {
+    if !secret
+        .as_str()
+        .is_some_and(|secret| secrets::is_reference(secret) || vault::is_reference(secret))
+    {
+        *secret = Value::from(REDACTED);
+    }
+}
+
+// Keeps where a URL goes, e.g. "https://hc-ping.com/<redacted>":
+fn redact_url(url: &mut Value) {
+    if let Some(text) = url.as_str().filter(|url| !vault::is_reference(url)) {
+        let (scheme, rest) = text.split_once("://").unwrap_or(("https", text));
+        let authority = rest.split('/').next().unwrap_or_default();
+        // Without a login in it:
+        let host = authority.rsplit('@').next().unwrap_or_default();
+        *url = Value::from(format!("{}://{}/{}", scheme, host, REDACTED));
+    }
+}
+
+// The config file decrypted, or how to create one:
+fn read_existing(path: &Path) -> Result<String> {
+    if !path.exists() {
+        return Err(no_config(path));
+    }
+    read_config_file(path)
+}
+
+fn no_config(path: &Path) -> Error {
+    Error::Config(format!(
+        "There is no config at '{}' yet. Run `livetunnel config setup` to create one.",
+        path.display()
+    ))
+}
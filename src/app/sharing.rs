@@ -0,0 +1,831 @@
+use super::{
+    dashboard::{Dashboard, Status},
+    describe_time_left, draw_target, keepalive_interval, key_action, multi_progress, spinner, App,
+    DURATION_WARNING, INFO_TEMPLATE, SUCCESS_TEMPLATE, WARNING_TEMPLATE,
+};
+use crate::{
+    control::{Control, Request, Response},
+    daemon::{control_socket_path, write_state, State},
+    error::{Error, Result},
+    external::ExternalServer,
+    heartbeat::Heartbeat,
+    keys::{Keys, Typing},
+    logging,
+    server::{Server, Waker},
+    watch::Watcher,
+    webhooks::Notifier,
+};
+
+use std::{
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{atomic::Ordering, mpsc::Sender, Arc},
+    time::{Duration, Instant},
+};
+
+use arboard::Clipboard;
+use humantime::format_duration;
+use indicatif::{DecimalBytes, MultiProgress, ProgressBar, ProgressDrawTarget};
+use openssh::Session;
+use serde_json::json;
+use tokio::{
+    runtime::{Handle, Runtime},
+    select,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time::{interval, Interval, MissedTickBehavior},
+};
+use tracing::{debug, warn};
+
+// How often the SSH connection is checked while sharing:
+const CONNECTION_CHECK: Duration = Duration::from_secs(1);
+
+// What the main loop reacts to:
+enum Event {
+    End,
+    ServerExited(String),
+    // A request came in for the paused server:
+    Wake,
+    Control(Request, Sender<Response>),
+    // The SSH connection is gone, with why:
+    ConnectionLost(String),
+    Tick,
+    // Something was typed, keys or for the dashboard (--tui):
+    Input,
+}
+
+/// Checks the SSH connection in a task of its own, so the main loop only hears from it once the
+/// connection is lost. The task ends with it, a new session needs a new check.
+struct ConnectionCheck {
+    lost: mpsc::Receiver<String>,
+    task: JoinHandle<()>,
+    runtime: Handle,
+}
+
+impl ConnectionCheck {
+    fn start(runtime: &Runtime, session: Arc<Session>) -> ConnectionCheck {
+        let (report, lost) = mpsc::channel(1);
+        let task = runtime.spawn(async move {
+            let mut checks = interval(CONNECTION_CHECK);
+            checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                checks.tick().await;
+                if let Err(err) = session.check().await {
+                    let _ = report.send(err.to_string()).await;
+                    return;
+                }
+            }
+        });
+        ConnectionCheck {
+            lost,
+            task,
+            runtime: runtime.handle().clone(),
+        }
+    }
+
+    // Returns why once the connection is lost, never again after that:
+    async fn lost(&mut self) -> String {
+        match self.lost.recv().await {
+            Some(reason) => reason,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for ConnectionCheck {
+    // Waits for the task to let go of the session, which closes once nothing else holds it:
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = self.runtime.block_on(&mut self.task);
+    }
+}
+
+/// Everything the main loop keeps track of while sharing, besides the [`App`] itself.
+pub(super) struct Sharing {
+    mp: MultiProgress,
+    pb_forward: ProgressBar,
+    pb_serve: ProgressBar,
+    pb_visitors: ProgressBar,
+    pb_exit_info: ProgressBar,
+    exit_hint: &'static str,
+    keys: Option<Keys>,
+    dashboard: Option<Dashboard>,
+    input: Option<Typing>,
+    interval: Interval,
+    control: Option<Control>,
+    connection: Option<ConnectionCheck>,
+    // The state file of a share in the background:
+    state: Option<State>,
+    heartbeat: Option<Heartbeat>,
+    // Some clipboards lose the content with its owner, so it lives as long as the share:
+    clipboard: Option<Clipboard>,
+    _watcher: Option<Watcher>,
+    idle_pause: Option<Duration>,
+    ends_at: Option<(Instant, Duration)>,
+    warned_of_end: bool,
+    transfer_cap: Option<u64>,
+    restarts: u32,
+    restart_limit: u32,
+    keepalive_interval: Option<Duration>,
+    keepalive_count_max: u32,
+    last_keepalive: Instant,
+    missed_keepalives: u32,
+    latency: Option<Duration>,
+    connected: bool,
+    healthy: bool,
+}
+
+impl App {
+    /// Starts the server and everything around it once the forward is up, with the spinners
+    /// (or the dashboard) that show it.
+    pub(super) fn start_sharing(
+        &mut self,
+        duration: Option<Duration>,
+        idle_pause: Option<Duration>,
+        clipboard: Option<Clipboard>,
+    ) -> Result<Sharing> {
+        let mp = multi_progress();
+        let pb_forward = mp.add(spinner());
+        pb_forward.set_message(format!(
+            "Forwarding {} to {} via SSH{}",
+            self.local_end(),
+            self.remote_end(),
+            self.describe_extra_forwards()
+        ));
+        pb_forward.enable_steady_tick(Duration::from_millis(20));
+
+        let pb_serve = mp.add(spinner());
+        pb_serve.set_message(format!(
+            "Starting {} to serve content from '{}' on {}",
+            self.server_name(),
+            self.directory.display(),
+            self.local_end()
+        ));
+        pb_serve.enable_steady_tick(Duration::from_millis(20));
+
+        if let Err(err) = self.start_server() {
+            let hint = if self.server_command().is_some() {
+                " Is it installed?"
+            } else {
+                ""
+            };
+            pb_forward.finish_and_clear();
+            pb_serve.finish_and_clear();
+            return Err(Error::Server(format!(
+                "Could not start {}.{} Error: {}",
+                self.server_name(),
+                hint,
+                err
+            )));
+        }
+
+        pb_serve.set_message(format!(
+            "{} successfully started. Serving content from '{}' on {}",
+            self.server_name(),
+            self.directory.display(),
+            self.local_end()
+        ));
+
+        if let Some(url) = self.public_url() {
+            let pb_url = mp.insert_before(&pb_forward, ProgressBar::new(42));
+            pb_url.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+            pb_url.finish_with_message(format!("Your share is live at {}", url));
+            // All -q prints of a share, e.g. for scripts:
+            if logging::quiet() {
+                logging::show(&url);
+            }
+            logging::emit("url", json!({ "url": url }));
+        }
+
+        self.announce_start();
+
+        if (self.cli.share.check || self.config.check_share.unwrap_or(false))
+            && !self.check_share(&mp)
+        {
+            let pb_check = mp.add(ProgressBar::new(42));
+            pb_check.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+            pb_check.finish_with_message("The share is not reachable from outside, serving anyway");
+        }
+
+        let watcher = self.start_watcher(&mp);
+
+        let state = self.cli.share.daemonized.then(|| {
+            let state = State::new(
+                self.directory.clone(),
+                self.config.host.clone(),
+                self.config.local_port,
+                self.config.remote_port,
+            );
+            if let Err(err) = write_state(&state) {
+                warn!("Could not write the state file: {}", err);
+            }
+            state
+        });
+
+        let control = match control_socket_path(std::process::id()).and_then(Control::start) {
+            Ok(control) => Some(control),
+            Err(err) if err.kind() == ErrorKind::Unsupported => None,
+            Err(err) => {
+                let _ = mp.println(format!("❗Could not open the control socket: {}", err));
+                None
+            }
+        };
+
+        let pb_visitors = mp.add(ProgressBar::new(42));
+        pb_visitors.set_style(INFO_TEMPLATE.get().unwrap().clone());
+        pb_visitors.set_message(self.describe_visitors());
+        let pb_exit_info = mp.add(ProgressBar::new(42));
+        pb_exit_info.set_style(INFO_TEMPLATE.get().unwrap().clone());
+        // Keys work in the default view as well, unless there is no one to press them:
+        let keys = if self.cli.share.tui || self.cli.non_interactive {
+            None
+        } else {
+            match Keys::start() {
+                Ok(keys) => Some(keys),
+                Err(err) if err.kind() == ErrorKind::Unsupported => None,
+                Err(err) => {
+                    debug!("Could not read keys from the terminal: {}", err);
+                    None
+                }
+            }
+        };
+        let exit_hint = if keys.is_some() {
+            "Press r to restart the server, u to add a user, c to copy the URL, q or CTRL+C to exit"
+        } else {
+            "Press CTRL+C to exit"
+        };
+        pb_exit_info.set_message(exit_hint);
+
+        let mut dashboard = None;
+        if self.cli.share.tui {
+            // The dashboard shows all of it, the spinners come back once it's gone:
+            let _ = mp.clear();
+            mp.set_draw_target(ProgressDrawTarget::hidden());
+            dashboard = Some(Dashboard::start()?);
+        }
+
+        let _runtime = self.runtime.enter();
+        let input = (dashboard.is_some() || keys.is_some()).then(Typing::start);
+        let mut interval = interval(Duration::from_secs(1));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        Ok(Sharing {
+            exit_hint,
+            keys,
+            dashboard,
+            input,
+            interval,
+            control,
+            connection: Some(ConnectionCheck::start(
+                &self.runtime,
+                self.ssh_session.clone(),
+            )),
+            state,
+            heartbeat: self.config.heartbeat_url.clone().map(|url| {
+                Heartbeat::new(
+                    url,
+                    Duration::from_secs(self.config.heartbeat_interval.unwrap_or(60)),
+                )
+            }),
+            clipboard,
+            _watcher: watcher,
+            idle_pause,
+            ends_at: duration.map(|duration| (Instant::now() + duration, duration)),
+            warned_of_end: false,
+            transfer_cap: self.transfer_cap(),
+            restarts: 0,
+            restart_limit: if self.cli.share.no_restart {
+                0
+            } else {
+                self.config.miniserve_restarts.unwrap_or(3)
+            },
+            keepalive_interval: keepalive_interval(&self.config),
+            keepalive_count_max: self.config.keepalive_count_max.unwrap_or(3).max(1),
+            last_keepalive: Instant::now(),
+            missed_keepalives: 0,
+            latency: None,
+            connected: true,
+            healthy: self.miniserve_handle.is_some() || self.server.is_some(),
+            mp,
+            pb_forward,
+            pb_serve,
+            pb_visitors,
+            pb_exit_info,
+        })
+    }
+
+    /// The main loop: reacts to whatever happens until the share ends.
+    pub(super) fn share_until_end(&mut self, mut sharing: Sharing) -> Result<()> {
+        loop {
+            let event = self.runtime.block_on(next_event(
+                &self.end_notify,
+                &mut sharing,
+                &mut self.miniserve_handle,
+                &mut self.server,
+                self.paused.as_ref(),
+            ));
+
+            match event {
+                Event::End => {}
+                Event::Wake => {
+                    if self.resume_server() {
+                        sharing.pb_serve.set_message(format!(
+                            "Resumed {}. Serving content from '{}' on {}",
+                            self.server_name(),
+                            self.directory.display(),
+                            self.local_end()
+                        ));
+                    }
+                }
+                Event::Control(request, reply) => {
+                    let _ = reply.send(self.handle_control(request, sharing.healthy));
+                }
+                Event::ServerExited(reason) => self.on_server_exited(&mut sharing, &reason),
+                Event::ConnectionLost(reason) => {
+                    debug!("SSH connection check failed: {}", reason);
+                    self.on_connection_lost(&mut sharing);
+                }
+                Event::Tick => self.on_tick(&mut sharing),
+                Event::Input => self.on_input(&mut sharing),
+            }
+
+            // After every event, which is at least once a second:
+            self.draw_dashboard(&mut sharing);
+
+            if self.should_end.load(Ordering::SeqCst) {
+                finish(sharing);
+                return Ok(());
+            }
+        }
+    }
+
+    // Restarts the server, up to the limit, unless livetunnel is closing anyway:
+    fn on_server_exited(&mut self, sharing: &mut Sharing, reason: &str) {
+        debug!("{} exited: {}", self.server_name(), reason);
+        sharing.healthy = false;
+        let pb_serve = &sharing.pb_serve;
+        if self.should_end.load(Ordering::SeqCst) {
+            // miniserve got CTRL-C as well, nothing to restart
+        } else if sharing.restarts < sharing.restart_limit {
+            sharing.restarts += 1;
+            match self.start_server() {
+                Ok(()) => {
+                    pb_serve.set_message(format!(
+                        "{} exited ({}), restarted it ({}/{}). Serving content from '{}' on {}",
+                        self.server_name(),
+                        reason,
+                        sharing.restarts,
+                        sharing.restart_limit,
+                        self.directory.display(),
+                        self.local_end()
+                    ));
+                }
+                Err(err) => {
+                    pb_serve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                    pb_serve.tick();
+                    pb_serve.finish_with_message(format!(
+                        "{} exited ({}) and could not be restarted: {}",
+                        self.server_name(),
+                        reason,
+                        err
+                    ));
+                }
+            }
+        } else {
+            pb_serve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+            pb_serve.tick();
+            pb_serve.finish_with_message(format!(
+                "{} exited unexpectantly {}",
+                self.server_name(),
+                reason
+            ));
+        }
+
+        if !self.should_end.load(Ordering::SeqCst) {
+            let restarted = self.miniserve_handle.is_some() || self.server.is_some();
+            self.notify(
+                "server_crashed",
+                format!(
+                    "{} exited ({}) while sharing '{}', {}",
+                    self.server_name(),
+                    reason,
+                    self.directory.display(),
+                    if restarted {
+                        "restarted it"
+                    } else {
+                        "it's down now"
+                    }
+                ),
+                json!({
+                    "server": self.server_name(),
+                    "reason": reason,
+                    "restarted": restarted,
+                }),
+            );
+        }
+    }
+
+    // Once a second: pauses an idle server, sends keepalives and checks the limits of the share:
+    fn on_tick(&mut self, sharing: &mut Sharing) {
+        if let Some(pause) = sharing.idle_pause {
+            let idle = self.stats.idle_for();
+            if self.server.is_some()
+                && idle >= pause
+                && !self.should_end.load(Ordering::SeqCst)
+                && self.pause_server()
+            {
+                sharing.pb_serve.set_message(format!(
+                    "Paused {} after {} without requests, the next one starts it again",
+                    self.server_name(),
+                    format_duration(Duration::from_secs(idle.as_secs()))
+                ));
+            }
+        }
+        sharing.healthy =
+            self.miniserve_handle.is_some() || self.server.is_some() || self.paused.is_some();
+        // Only when it changed, plain output prints every message as a line:
+        let visitors = self.describe_visitors();
+        if sharing.pb_visitors.message() != visitors {
+            sharing.pb_visitors.set_message(visitors);
+        }
+
+        if let Some(every) = sharing.keepalive_interval {
+            if sharing.last_keepalive.elapsed() >= every {
+                sharing.last_keepalive = Instant::now();
+                match self.send_keepalive(every) {
+                    Some(round_trip) => {
+                        sharing.latency = Some(round_trip);
+                        sharing.missed_keepalives = 0;
+                    }
+                    None => {
+                        sharing.missed_keepalives += 1;
+                        if sharing.missed_keepalives >= sharing.keepalive_count_max {
+                            self.on_connection_lost(sharing);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.check_limits(sharing);
+
+        if let Some(heartbeat) = &mut sharing.heartbeat {
+            heartbeat.update(sharing.healthy);
+        }
+
+        if let Some(state) = &mut sharing.state {
+            if state.healthy != sharing.healthy {
+                state.healthy = sharing.healthy;
+                let _ = write_state(state);
+            }
+        }
+    }
+
+    // Reconnects, or ends the share if that fails:
+    fn on_connection_lost(&mut self, sharing: &mut Sharing) {
+        sharing.connected = false;
+        sharing.connection = None;
+        debug!("SSH connection to '{}' lost", self.config.host);
+        self.notify_desktop(
+            "SSH connection lost",
+            &format!("Reconnecting to '{}'", self.config.host),
+        );
+        sharing.missed_keepalives = 0;
+        if let Some(heartbeat) = &mut sharing.heartbeat {
+            heartbeat.update(false);
+        }
+
+        let (mp, pb_forward) = (&sharing.mp, &sharing.pb_forward);
+        let recovered = match &mut sharing.dashboard {
+            // Reconnecting blocks the loop and may ask what to do, so that happens on the
+            // terminal:
+            Some(dashboard) => dashboard.suspend(|| {
+                mp.set_draw_target(draw_target());
+                let recovered = self.recover_connection(mp, pb_forward);
+                let _ = mp.clear();
+                mp.set_draw_target(ProgressDrawTarget::hidden());
+                recovered
+            }),
+            None => self.recover_connection(mp, pb_forward),
+        };
+        if recovered {
+            sharing.connected = true;
+            sharing.connection = Some(ConnectionCheck::start(
+                &self.runtime,
+                self.ssh_session.clone(),
+            ));
+            self.notify_desktop(
+                "SSH connection is back",
+                &format!("Reconnected to '{}'", self.config.host),
+            );
+            pb_forward.set_message(format!(
+                "Reconnected. Forwarding {} to {} via SSH",
+                self.local_end(),
+                self.remote_end()
+            ));
+        } else {
+            sharing.healthy = false;
+            pb_forward.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+            pb_forward.tick();
+            pb_forward.finish_with_message("SSH Forward died! Closing livetunnel.");
+            self.notify_desktop(
+                "SSH Forward died",
+                &format!(
+                    "Could not reconnect to '{}', closing livetunnel",
+                    self.config.host
+                ),
+            );
+            self.should_end.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // Ends the share once the transfer cap, the download limit or --duration is reached:
+    fn check_limits(&mut self, sharing: &mut Sharing) {
+        let (pb_forward, pb_serve) = (&sharing.pb_forward, &sharing.pb_serve);
+        if let Some(relay) = &self.relay {
+            let transferred = relay.transferred();
+            if !pb_forward.is_finished() {
+                pb_forward.set_message(format!(
+                    "Forwarding {} to {} via SSH ({} transferred{}){}",
+                    self.local_end(),
+                    self.remote_end(),
+                    DecimalBytes(transferred),
+                    match sharing.transfer_cap {
+                        Some(cap) => format!(" of {}", DecimalBytes(cap)),
+                        None => String::new(),
+                    },
+                    self.describe_extra_forwards()
+                ));
+            }
+
+            if let Some(cap) = sharing.transfer_cap.filter(|cap| transferred >= *cap) {
+                sharing.healthy = false;
+                pb_forward.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb_forward.tick();
+                pb_forward.finish_with_message(format!(
+                    "Transfer cap of {} reached! Closing livetunnel.",
+                    DecimalBytes(cap)
+                ));
+                self.should_end.store(true, Ordering::SeqCst);
+            }
+        }
+
+        if let Some(limit) = &self.download_limit {
+            if limit.used_up() {
+                pb_serve.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb_serve.tick();
+                pb_serve.finish_with_message(format!(
+                    "Download limit of {} reached! Closing livetunnel.",
+                    limit.max()
+                ));
+                self.should_end.store(true, Ordering::SeqCst);
+            }
+        }
+
+        if let Some((ends_at, duration)) = sharing.ends_at {
+            let left = ends_at.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                pb_forward.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb_forward.tick();
+                pb_forward.finish_with_message(format!(
+                    "Sharing for {} is over! Closing livetunnel.",
+                    format_duration(duration)
+                ));
+                self.should_end.store(true, Ordering::SeqCst);
+            } else {
+                sharing.pb_exit_info.set_message(format!(
+                    "{}, stopping in {}",
+                    sharing.exit_hint,
+                    describe_time_left(left)
+                ));
+
+                if !sharing.warned_of_end && left <= DURATION_WARNING && duration > DURATION_WARNING
+                {
+                    sharing.warned_of_end = true;
+                    self.notify_desktop(
+                        "Sharing ends soon",
+                        &format!(
+                            "'{}' stops being shared in {}",
+                            self.directory.display(),
+                            describe_time_left(left)
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    // Acts on the keys pressed, in the default view or on the dashboard:
+    fn on_input(&mut self, sharing: &mut Sharing) {
+        if let Some(keys) = &sharing.keys {
+            for action in keys.pressed().into_iter().filter_map(key_action) {
+                // Status lines and prompts go between the spinners:
+                sharing.mp.suspend(|| {
+                    self.handle_action(
+                        action,
+                        &mut sharing.healthy,
+                        &mut sharing.clipboard,
+                        |add| add(),
+                    )
+                });
+            }
+        }
+
+        if let Some(dashboard) = &mut sharing.dashboard {
+            for action in dashboard.actions() {
+                self.handle_action(
+                    action,
+                    &mut sharing.healthy,
+                    &mut sharing.clipboard,
+                    |add| dashboard.suspend(add),
+                );
+            }
+        }
+    }
+
+    fn draw_dashboard(&self, sharing: &mut Sharing) {
+        let Some(dashboard) = &mut sharing.dashboard else {
+            return;
+        };
+        let status = Status {
+            connected: sharing.connected,
+            latency: sharing.latency,
+            healthy: sharing.healthy,
+            restarts: (sharing.restarts, sharing.restart_limit),
+            transfer_cap: sharing.transfer_cap,
+            time_left: sharing
+                .ends_at
+                .map(|(ends_at, _)| ends_at.saturating_duration_since(Instant::now())),
+        };
+        if let Err(err) = dashboard.draw(self, &status) {
+            debug!("Could not draw the dashboard: {}", err);
+        }
+    }
+
+    // Tells everyone who wants to know that the share is up:
+    fn announce_start(&mut self) {
+        self.notifier = self.config.notifications.clone().map(Notifier::new);
+        self.notify(
+            "started",
+            match self.public_url() {
+                Some(url) => format!("Sharing '{}' at {}", self.directory.display(), url),
+                None => format!(
+                    "Sharing '{}' on remote Port {} of '{}'",
+                    self.directory.display(),
+                    self.config.remote_port,
+                    self.config.host
+                ),
+            },
+            json!({
+                "url": self.public_url(),
+                "directory": self.directory,
+                "host": self.config.host,
+            }),
+        );
+        if let Some(started) = self.started.take() {
+            let _ = started.send(self.public_url());
+        }
+        self.notify_desktop(
+            "The tunnel is up",
+            &self
+                .public_url()
+                .unwrap_or_else(|| format!("Sharing '{}'", self.directory.display())),
+        );
+    }
+
+    // Watches the shared directory ([watch]) for changes, to run a command and reload pages:
+    fn start_watcher(&self, mp: &MultiProgress) -> Option<Watcher> {
+        let watch = self.config.watch.as_ref()?;
+        let pb_watch = mp.add(ProgressBar::new(42));
+        pb_watch.set_style(INFO_TEMPLATE.get().unwrap().clone());
+        // A built site is watched in its source and rebuilt the same way by default:
+        let (directory, command) = match &self.site_build {
+            Some(build) => (
+                &build.source,
+                watch
+                    .command
+                    .clone()
+                    .or_else(|| Some(build.command.clone())),
+            ),
+            None => (&self.directory, watch.command.clone()),
+        };
+        pb_watch.set_message(match (&command, &self.live_reload) {
+            (Some(command), Some(_)) => {
+                format!("Watching for changes to run '{}' and reload pages", command)
+            }
+            (Some(command), None) => format!("Watching for changes to run '{}'", command),
+            (None, _) => String::from("Watching for changes to reload pages"),
+        });
+
+        let paths = watch
+            .paths
+            .clone()
+            .unwrap_or_else(|| vec![PathBuf::from(".")]);
+        match Watcher::start(
+            directory,
+            &paths,
+            Duration::from_millis(watch.debounce.unwrap_or(500)),
+            command,
+            pb_watch.clone(),
+            {
+                let live_reload = self.live_reload.clone();
+                move || {
+                    if let Some(live_reload) = &live_reload {
+                        live_reload.reload();
+                    }
+                }
+            },
+        ) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                pb_watch.set_style(WARNING_TEMPLATE.get().unwrap().clone());
+                pb_watch.finish_with_message(format!("Could not watch for changes: {}", err));
+                None
+            }
+        }
+    }
+}
+
+// Brings the spinners back and finishes them, the share is over:
+fn finish(mut sharing: Sharing) {
+    if sharing.dashboard.take().is_some() {
+        sharing.mp.set_draw_target(draw_target());
+    }
+
+    sharing
+        .pb_forward
+        .set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+    sharing.pb_forward.tick();
+    sharing.pb_forward.finish();
+
+    sharing
+        .pb_serve
+        .set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+    sharing.pb_serve.tick();
+    sharing.pb_serve.finish();
+
+    sharing.pb_visitors.finish_and_clear();
+    sharing.pb_exit_info.finish_and_clear();
+}
+
+// Waits for whatever the main loop has to handle next:
+async fn next_event(
+    end: &Notify,
+    sharing: &mut Sharing,
+    miniserve_handle: &mut Option<ExternalServer>,
+    server: &mut Option<Server>,
+    paused: Option<&Waker>,
+) -> Event {
+    let Sharing {
+        interval,
+        input,
+        control,
+        connection,
+        ..
+    } = sharing;
+    select! {
+        _ = end.notified() => Event::End,
+        _ = async {
+            match paused {
+                Some(waker) => waker.woken().await,
+                None => std::future::pending().await,
+            }
+        } => Event::Wake,
+        reason = server_exit(miniserve_handle, server) => Event::ServerExited(reason),
+        Some((request, reply)) = async {
+            match control.as_mut() {
+                Some(control) => control.recv().await,
+                None => std::future::pending().await,
+            }
+        } => Event::Control(request, reply),
+        reason = async {
+            match connection.as_mut() {
+                Some(connection) => connection.lost().await,
+                None => std::future::pending().await,
+            }
+        } => Event::ConnectionLost(reason),
+        _ = interval.tick() => Event::Tick,
+        _ = async {
+            match input.as_mut() {
+                Some(input) => input.typed().await,
+                None => std::future::pending().await,
+            }
+        } => Event::Input,
+    }
+}
+
+// Returns why the server stopped once it does, never if there is none:
+async fn server_exit(
+    miniserve_handle: &mut Option<ExternalServer>,
+    server: &mut Option<Server>,
+) -> String {
+    let reason = match (miniserve_handle.as_mut(), server.as_mut()) {
+        (Some(child), _) => child.wait().await,
+        (None, Some(server)) => server.wait().await,
+        (None, None) => std::future::pending().await,
+    };
+
+    *miniserve_handle = None;
+    *server = None;
+    reason
+}
@@ -0,0 +1,185 @@
+use super::{quote, spinner, App, SUCCESS_TEMPLATE};
+use crate::{
+    error::{Error, Result},
+    logging,
+    watch::shell,
+};
+
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::read_to_string,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// A static site generator livetunnel knows how to build with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Generator {
+    Hugo,
+    Jekyll,
+    Zola,
+    MdBook,
+}
+
+impl Display for Generator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Generator::Hugo => write!(f, "Hugo"),
+            Generator::Jekyll => write!(f, "Jekyll"),
+            Generator::Zola => write!(f, "Zola"),
+            Generator::MdBook => write!(f, "mdBook"),
+        }
+    }
+}
+
+impl Generator {
+    // Tells the generators apart by their config files:
+    fn detect(directory: &Path) -> Option<Generator> {
+        let has = |name: &str| directory.join(name).is_file();
+
+        if has("book.toml") {
+            Some(Generator::MdBook)
+        } else if has("_config.yml") || has("_config.yaml") {
+            Some(Generator::Jekyll)
+        } else if has("hugo.toml") || has("hugo.yaml") || has("hugo.json") {
+            Some(Generator::Hugo)
+        } else if read_to_string(directory.join("config.toml"))
+            .is_ok_and(|config| config.contains("base_url"))
+        {
+            Some(Generator::Zola)
+        } else if has("config.toml") || has("config.yaml") || has("config.json") {
+            // Hugo's name for its config before v0.110:
+            Some(Generator::Hugo)
+        } else {
+            None
+        }
+    }
+
+    // Links in the output point to the share instead of the configured site, if its URL is known:
+    fn command(&self, directory: &Path, url: Option<&str>) -> String {
+        match (self, url) {
+            (Generator::Hugo, Some(url)) => format!("hugo --baseURL {}", quote(url)),
+            (Generator::Hugo, None) => String::from("hugo"),
+            (Generator::Jekyll, _) if directory.join("Gemfile").is_file() => {
+                String::from("bundle exec jekyll build")
+            }
+            (Generator::Jekyll, _) => String::from("jekyll build"),
+            (Generator::Zola, Some(url)) => format!("zola build --base-url {}", quote(url)),
+            (Generator::Zola, None) => String::from("zola build"),
+            (Generator::MdBook, _) => String::from("mdbook build"),
+        }
+    }
+
+    fn output(&self) -> &'static str {
+        match self {
+            Generator::Hugo | Generator::Zola => "public",
+            Generator::Jekyll => "_site",
+            Generator::MdBook => "book",
+        }
+    }
+}
+
+/// How the shared site was built, the watcher rebuilds it the same way.
+#[derive(Clone, Debug)]
+pub(super) struct SiteBuild {
+    /// The source tree, shared is the output below it.
+    pub source: PathBuf,
+    pub command: String,
+}
+
+impl App {
+    /// Builds the shared directory with its static site generator (detected, or `build_command`
+    /// from the config) and shares the output directory from then on.
+    pub(super) fn build_site(&mut self) -> Result<()> {
+        let source = self.directory.clone();
+        if !source.is_dir() {
+            return Err(Error::Config(format!(
+                "Only a directory can be built, '{}' isn't one.",
+                source.display()
+            )));
+        }
+
+        let generator = Generator::detect(&source);
+        let (command, output) = match (
+            &self.config.build_command,
+            &self.config.build_output,
+            generator,
+        ) {
+            (Some(command), Some(output), _) => (command.clone(), output.clone()),
+            (Some(command), None, Some(generator)) => {
+                (command.clone(), PathBuf::from(generator.output()))
+            }
+            (None, output, Some(generator)) => (
+                generator.command(&source, self.public_url().as_deref()),
+                output
+                    .clone()
+                    .unwrap_or_else(|| PathBuf::from(generator.output())),
+            ),
+            (_, _, None) => {
+                return Err(Error::Config(format!(
+                    "No Hugo, Jekyll, Zola or mdBook site found in '{}'. Set build_command and build_output in the config to build it anyway.",
+                    source.display()
+                )));
+            }
+        };
+        let name = generator.map_or_else(|| command.clone(), |generator| generator.to_string());
+
+        let pb = spinner();
+        pb.set_message(format!("Building '{}' with {}", source.display(), name));
+        pb.enable_steady_tick(Duration::from_millis(20));
+
+        let started = Instant::now();
+        let result = shell(&command).current_dir(&source).output();
+        if let Ok(output) = &result {
+            logging::command_output(&command, output);
+        }
+        match result {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => {
+                pb.finish_and_clear();
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(Error::Server(format!(
+                    "Building with '{}' failed ({}){}",
+                    command,
+                    output.status,
+                    stderr
+                        .lines()
+                        .rfind(|line| !line.trim().is_empty())
+                        .map(|line| format!(": {}", line.trim()))
+                        .unwrap_or_default()
+                )));
+            }
+            Err(err) => {
+                pb.finish_and_clear();
+                return Err(Error::Server(format!(
+                    "Could not run '{}': {}. Is {} installed?",
+                    command, err, name
+                )));
+            }
+        }
+
+        let output = source.join(output);
+        if !output.is_dir() {
+            pb.finish_and_clear();
+            return Err(Error::Config(format!(
+                "'{}' ran, but there is no output in '{}'. Set build_output in the config.",
+                command,
+                output.display()
+            )));
+        }
+
+        pb.set_style(SUCCESS_TEMPLATE.get().unwrap().clone());
+        pb.tick();
+        pb.finish_with_message(format!(
+            "Built '{}' with {} in {:.1}s, sharing '{}'",
+            source.display(),
+            name,
+            started.elapsed().as_secs_f64(),
+            output.display()
+        ));
+
+        self.directory = output;
+        self.site_build = Some(SiteBuild { source, command });
+        Ok(())
+    }
+}
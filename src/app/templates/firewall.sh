@@ -0,0 +1,9 @@
+if command -v ufw >/dev/null 2>&1; then
+    ufw allow 80/tcp && ufw allow 443/tcp
+elif command -v firewall-cmd >/dev/null 2>&1; then
+    firewall-cmd --permanent --add-service=http \
+        && firewall-cmd --permanent --add-service=https \
+        && firewall-cmd --reload
+else
+    echo "Neither ufw nor firewalld found, skipping" >&2
+fi
@@ -0,0 +1,18 @@
+if command -v {program} >/dev/null 2>&1; then
+    exit 0
+fi
+
+if command -v apt-get >/dev/null 2>&1; then
+    apt-get update -qq && DEBIAN_FRONTEND=noninteractive apt-get install -y -qq {packages}
+elif command -v dnf >/dev/null 2>&1; then
+    dnf install -y -q {packages}
+elif command -v yum >/dev/null 2>&1; then
+    yum install -y -q {packages}
+elif command -v pacman >/dev/null 2>&1; then
+    pacman -Sy --noconfirm {packages}
+elif command -v apk >/dev/null 2>&1; then
+    apk add -q {packages}
+else
+    echo "No supported package manager found" >&2
+    exit 1
+fi
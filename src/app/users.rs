@@ -0,0 +1,96 @@
+use super::{hash_password, load_config, save_config, App, Config};
+use crate::{control::request, daemon::share_sockets, error::Result, UsersCommands};
+
+use inquire::{validator::ValueRequiredValidator, Password};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+impl App {
+    /// Manages the users of secure shares without starting one (`livetunnel users`).
+    pub fn manage_users(command: &UsersCommands) -> Result<()> {
+        let mut config = load_config()?;
+
+        match command {
+            UsersCommands::Add { username } => {
+                if find_user(&config, username).is_some() {
+                    warn!(
+                        "User '{}' already exists. Use `livetunnel users passwd {}` to change the password.",
+                        username, username
+                    );
+                    return Ok(());
+                }
+
+                let password = prompt_password(&format!("Password for '{}':", username))?;
+                config
+                    .users
+                    .push((username.clone(), hash_password(&password)));
+                save_config(&config)?;
+                info!(success = true, "Added user '{}'", username);
+                reload_running_shares();
+            }
+            UsersCommands::Remove { username } => match find_user(&config, username) {
+                Some(i) => {
+                    config.users.remove(i);
+                    save_config(&config)?;
+                    info!(success = true, "Removed user '{}'", username);
+                    reload_running_shares();
+                }
+                None => warn!("There is no user '{}'", username),
+            },
+            UsersCommands::List => list_users(&config),
+            UsersCommands::Passwd { username } => match find_user(&config, username) {
+                Some(i) => {
+                    let password = prompt_password(&format!("New password for '{}':", username))?;
+                    config.users[i].1 = hash_password(&password);
+                    save_config(&config)?;
+                    info!(success = true, "Changed the password of '{}'", username);
+                    reload_running_shares();
+                }
+                None => warn!("There is no user '{}'", username),
+            },
+        }
+
+        Ok(())
+    }
+}
+
+fn find_user(config: &Config, username: &str) -> Option<usize> {
+    config.users.iter().position(|(user, _)| user == username)
+}
+
+// Running shares read the users on start, so they are asked to read them again:
+pub(super) fn reload_running_shares() {
+    let applied = share_sockets()
+        .iter()
+        .filter(
+            |path| match request(path, &json!({ "command": "reload_users" })) {
+                Ok(response) => response["applied"] == true,
+                Err(err) => {
+                    debug!("Could not reach the share at '{}': {}", path.display(), err);
+                    false
+                }
+            },
+        )
+        .count();
+    if applied > 0 {
+        info!(success = true, "Applied it to {} running share(s)", applied);
+    }
+}
+
+fn prompt_password(message: &str) -> Result<String> {
+    Ok(Password::new(message)
+        .with_validator(ValueRequiredValidator::default())
+        .prompt()?)
+}
+
+fn list_users(config: &Config) {
+    if config.users.is_empty() {
+        println!("ℹ No users configured. Secure shares (-s) ask for one when started.");
+        return;
+    }
+
+    println!("ℹ {} user(s) can access secure shares:", config.users.len());
+    for (user, _) in &config.users {
+        println!("  {}", user);
+    }
+}
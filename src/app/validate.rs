@@ -0,0 +1,441 @@
+use super::{
+    commands::{split_command, ConnectCommand},
+    config_path, connect, is_installed,
+    migrate::parse_config,
+    ports_in_use, public_paths, read_config_file, redirect_rules, referrer_patterns,
+    secret_header_problem, spinner, ssh_password, App, Config, CHALLENGE_BITS, MINISERVE_COMMAND,
+};
+use crate::{
+    error::{Error, Result},
+    host::parse_host,
+};
+
+use std::{
+    collections::HashSet,
+    env::var_os,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use humantime::parse_duration;
+use tokio::runtime::Runtime;
+use tracing::{debug, info, warn};
+
+impl App {
+    /// Checks the stored config for what would break a share, and tells how to fix it
+    /// (`livetunnel config validate`). Fails with the number of problems found.
+    pub fn validate_config() -> Result<()> {
+        let path = config_path()?;
+        if !path.exists() {
+            return Err(Error::Config(format!(
+                "There is no config at '{}' yet. Run `livetunnel config setup` to create one.",
+                path.display()
+            )));
+        }
+        let content = read_config_file(&path)?;
+        let (config, _) = parse_config(&content).map_err(|err| {
+            Error::Config(format!(
+                "'{}' can't be read: {}. Fix it, or run `livetunnel config restore` to go back to a backup.",
+                path.display(),
+                err
+            ))
+        })?;
+
+        let problems = problems(&config);
+        if problems.is_empty() {
+            info!(success = true, "No problems found in '{}'", path.display());
+            return Ok(());
+        }
+
+        for problem in &problems {
+            warn!("{}", problem);
+        }
+        Err(Error::Config(format!(
+            "Found {} problem(s) in '{}'.",
+            problems.len(),
+            path.display()
+        )))
+    }
+}
+
+/// Tries a config of the setup assistant before it's saved: the SSH connection, whether the
+/// remote Port is free and whether the server is installed. Returns what failed.
+pub(super) fn test_config(config: &Config) -> Result<Vec<String>> {
+    let mut failures = vec![];
+
+    let server = match &config.server_command {
+        Some(command) => command.split_whitespace().next(),
+        None if config.external_server == Some(true) => MINISERVE_COMMAND.split(' ').next(),
+        None => None,
+    };
+    if let Some(program) = server.filter(|program| !is_installed(program)) {
+        failures.push(format!(
+            "The server '{}' is not installed or not in the PATH.",
+            program
+        ));
+    }
+
+    let password = ssh_password(config, false)?;
+    let runtime = Runtime::new()?;
+    let pb = spinner();
+    pb.set_message(format!("Connecting to '{}' via SSH", config.host));
+    pb.enable_steady_tick(Duration::from_millis(20));
+    let session = runtime.block_on(connect(config, password.as_deref()));
+    pb.finish_and_clear();
+
+    let session = match session {
+        Ok(session) => session,
+        Err(err) => {
+            failures.push(format!(
+                "Couldn't establish SSH connection to '{}': {}{}",
+                config.host,
+                err,
+                if config.before_commands.is_some() {
+                    " (the before_commands don't run for the test)"
+                } else {
+                    ""
+                }
+            ));
+            return Ok(failures);
+        }
+    };
+
+    // A socket needs no free Port:
+    if config.remote_socket.is_none() {
+        match runtime.block_on(ports_in_use(&session)) {
+            Some(in_use) if in_use.contains(&config.remote_port) => {
+                if config.remote_port_range.is_none() {
+                    failures.push(format!(
+                        "Remote Port {} is already in use on '{}'. Pick another one, or set a remote_port_range to move to a free one.",
+                        config.remote_port, config.host
+                    ));
+                }
+            }
+            Some(_) => {}
+            None => debug!("Can't tell which remote Ports are in use without ss or netstat"),
+        }
+    }
+    let _ = runtime.block_on(session.close());
+
+    Ok(failures)
+}
+
+pub(super) fn problems(config: &Config) -> Vec<String> {
+    let mut problems = vec![];
+
+    if config.host.trim().is_empty() {
+        problems.push(String::from(
+            "host is empty. Set the SSH host with `livetunnel config set host <host>`.",
+        ));
+    } else if let Err(err) = parse_host(&config.host) {
+        problems.push(format!("host '{}' is invalid: {}.", config.host, err));
+    }
+    if let Some(keyfile) = &config.keyfile {
+        if !expand_home(keyfile).is_file() {
+            problems.push(format!(
+                "keyfile '{}' doesn't exist. Point it at your private key, or remove it to use the keys of ssh-agent and ~/.ssh/config.",
+                keyfile.display()
+            ));
+        }
+    }
+    for jump_host in config.jump_hosts.iter().flatten() {
+        if let Err(err) = parse_host(jump_host) {
+            problems.push(format!("jump host '{}' is invalid: {}.", jump_host, err));
+        }
+    }
+
+    problems.extend(port_problems(config));
+
+    for (kind, commands) in [
+        ("before_commands", &config.before_commands),
+        ("after_commands", &config.after_commands),
+    ] {
+        for command in commands.iter().flatten() {
+            problems.extend(command_problem(kind, command));
+        }
+    }
+    if let (Some(_), Some(_)) = (&config.local_socket, &config.server_command) {
+        problems.push(String::from(
+            "local_socket only works with the builtin server, remove it or server_command.",
+        ));
+    }
+    if let Some(command) = &config.server_command {
+        if command.trim().is_empty() {
+            problems.push(String::from(
+                "server_command is empty. Remove it to use the builtin server.",
+            ));
+        } else if !command.contains("{port}") {
+            problems.push(format!(
+                "server_command '{}' has no {{port}}, the server won't listen on local_port.",
+                command
+            ));
+        }
+    }
+    if config.server_command.is_none()
+        && config.external_server == Some(true)
+        && !is_installed(MINISERVE_COMMAND.split(' ').next().unwrap_or_default())
+    {
+        problems.push(String::from(
+            "external_server is set, but miniserve is not installed. Install it (`cargo install miniserve`) or remove external_server.",
+        ));
+    }
+
+    let mut seen = HashSet::new();
+    for (user, _) in &config.users {
+        if user.trim().is_empty() {
+            problems.push(String::from(
+                "A user has an empty name. Remove it from the config.",
+            ));
+        } else if !seen.insert(user) {
+            problems.push(format!(
+                "User '{}' is there more than once. Remove the duplicates from the config.",
+                user
+            ));
+        }
+    }
+
+    if let Some(duration) = &config.duration {
+        if let Err(err) = parse_duration(duration) {
+            problems.push(format!(
+                "duration '{}' is invalid: {}. Use e.g. \"8h\" or \"30m\".",
+                duration, err
+            ));
+        }
+    }
+    if let Some(watch) = &config.watch {
+        if watch
+            .command
+            .as_ref()
+            .is_some_and(|command| command.trim().is_empty())
+        {
+            problems.push(String::from(
+                "[watch] command is empty. Remove it to only reload pages.",
+            ));
+        }
+    }
+    if let Some(serve) = &config.serve {
+        if serve.max_connections_per_client == Some(0) {
+            problems.push(String::from(
+                "[serve] max_connections_per_client is 0, every request would be refused. Remove it for no limit.",
+            ));
+        }
+        if let Some(Err(err)) = serve.idle_pause.as_deref().map(parse_duration) {
+            problems.push(format!(
+                "[serve] idle_pause is invalid: {}. Use e.g. \"30m\" or \"2h\".",
+                err
+            ));
+        }
+        if let Some(Err(err)) = serve.login_session.as_deref().map(parse_duration) {
+            problems.push(format!(
+                "[serve] login_session is invalid: {}. Use e.g. \"8h\" or \"7days\".",
+                err
+            ));
+        }
+        for (setting, icon) in [
+            ("favicon", &serve.favicon),
+            ("apple_touch_icon", &serve.apple_touch_icon),
+        ] {
+            if let Some(icon) = icon.as_ref().filter(|icon| !expand_home(icon).is_file()) {
+                problems.push(format!(
+                    "[serve] {} '{}' doesn't exist. Point it at an image file.",
+                    setting,
+                    icon.display()
+                ));
+            }
+        }
+        if let Some(analytics) = serve
+            .analytics
+            .as_ref()
+            .filter(|analytics| !analytics.trim_start().starts_with('<'))
+        {
+            problems.push(format!(
+                "[serve] analytics '{}' is no HTML. Use the whole snippet of the service, e.g. <script defer data-domain=\"...\" src=\"...\"></script>.",
+                analytics
+            ));
+        }
+        if let Some(Err(err)) = serve.schedule.as_ref().map(|schedule| schedule.parse()) {
+            problems.push(format!("[serve.schedule]: {}.", err));
+        }
+        if let Some(watermark) = &serve.watermark {
+            if watermark.text.trim().is_empty() {
+                problems.push(String::from(
+                    "[serve.watermark] text is empty, nothing would be stamped. Use e.g. \"{user} {client} {date}\".",
+                ));
+            }
+            if let Some(font) = watermark
+                .font
+                .as_ref()
+                .filter(|font| !expand_home(font).is_file())
+            {
+                problems.push(format!(
+                    "[serve.watermark] font '{}' doesn't exist. Point it at a TrueType or OpenType file.",
+                    font.display()
+                ));
+            }
+        }
+        if let Some(Err(err)) = serve.public_paths.as_deref().map(public_paths) {
+            problems.push(err);
+        }
+        if let Some(Err(err)) = serve.allowed_referrers.as_deref().map(referrer_patterns) {
+            problems.push(err);
+        }
+        if let Some(Err(err)) = serve.redirects.as_deref().map(redirect_rules) {
+            problems.push(err);
+        }
+        if let Some(bits) = serve
+            .challenge
+            .filter(|bits| !CHALLENGE_BITS.contains(bits))
+        {
+            problems.push(format!(
+                "[serve] challenge is {} bits, use {} to {}.",
+                bits,
+                CHALLENGE_BITS.start(),
+                CHALLENGE_BITS.end()
+            ));
+        }
+        if let Some(problem) = serve.secret_header.as_ref().and_then(secret_header_problem) {
+            problems.push(problem);
+        }
+        for (folder, users) in serve.folder_users.iter().flatten() {
+            for user in users
+                .iter()
+                .filter(|user| !config.users.iter().any(|(name, _)| name == *user))
+            {
+                problems.push(format!(
+                    "[serve.folder_users] '{}' lists '{}', who isn't a user. Add them with `livetunnel users add`.",
+                    folder, user
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+fn port_problems(config: &Config) -> Vec<String> {
+    let mut problems = vec![];
+
+    if config.local_port == 0 {
+        problems.push(String::from(
+            "local_port is 0. Set the Port to serve on with `livetunnel config set local_port <Port>`.",
+        ));
+    }
+    if config.remote_port == 0 && config.remote_socket.is_none() {
+        problems.push(String::from(
+            "remote_port is 0. Set the Port your reverse proxy forwards to with `livetunnel config set remote_port <Port>`.",
+        ));
+    }
+
+    let mut local_ports = vec![config.local_port];
+    let mut remote_ports = vec![];
+    if config.remote_socket.is_none() {
+        remote_ports.push(config.remote_port);
+    }
+    for (local, remote) in config.forwards.iter().flatten() {
+        if local_ports.contains(local) {
+            problems.push(format!(
+                "forwards: local Port {} is used twice. Every forward needs a local Port of its own.",
+                local
+            ));
+        }
+        if remote_ports.contains(remote) {
+            problems.push(format!(
+                "forwards: remote Port {} is used twice. Every forward needs a remote Port of its own.",
+                remote
+            ));
+        }
+        local_ports.push(*local);
+        remote_ports.push(*remote);
+    }
+
+    if let Some((first, last)) = config.remote_port_range {
+        if first > last {
+            problems.push(format!(
+                "remote_port_range [{}, {}] is empty. Put the lower Port first.",
+                first, last
+            ));
+        }
+    }
+
+    problems
+}
+
+fn command_problem(kind: &str, command: &ConnectCommand) -> Option<String> {
+    if command.command.trim().is_empty() {
+        return Some(format!(
+            "{}: a command is empty. Remove it with `livetunnel config edit`.",
+            kind
+        ));
+    }
+    if let Some(Err(err)) = command.timeout.as_deref().map(parse_duration) {
+        return Some(format!(
+            "{}: the timeout of '{}' is invalid: {}. Use e.g. \"30s\" or \"5m\".",
+            kind, command.command, err
+        ));
+    }
+
+    // Only local commands can be checked, the others run on the remote:
+    if kind == "before_commands" {
+        before_command_problem(command)
+    } else if command.on_failure.is_some() || command.timeout.is_some() || command.retries.is_some()
+    {
+        Some(format!(
+            "{}: '{}' has on_failure, timeout or retries, but only before_commands use them.",
+            kind, command.command
+        ))
+    } else {
+        None
+    }
+}
+
+// Before commands are split like a shell would, but don't run in one:
+fn before_command_problem(command: &ConnectCommand) -> Option<String> {
+    let (program, args) = match split_command(&command.command) {
+        Ok(words) => words,
+        Err(err) => {
+            return Some(format!(
+                "before_commands: '{}' can't be run, {}.",
+                command.command, err
+            ))
+        }
+    };
+
+    // Placeholders are only known once it runs:
+    let missing_dir = command
+        .dir
+        .as_deref()
+        .filter(|dir| !dir.contains('{'))
+        .map(Path::new)
+        .filter(|dir| dir.is_absolute() && !dir.is_dir());
+    // Paths like "./build.sh" aren't looked up in the PATH:
+    if !program.contains(['/', '\\']) && !is_installed(&program) {
+        Some(format!(
+            "before_commands: '{}' is not installed or not in the PATH.",
+            program
+        ))
+    } else if args
+        .iter()
+        .any(|arg| ["&&", "||", "|", ";", ">", "<"].contains(&arg.as_str()))
+    {
+        Some(format!(
+            "before_commands: '{}' needs a shell for its operators. Run it as `sh -c \"…\"`.",
+            command.command
+        ))
+    } else {
+        missing_dir.map(|dir| {
+            format!(
+                "before_commands: the dir '{}' of '{}' doesn't exist.",
+                dir.display(),
+                command.command
+            )
+        })
+    }
+}
+
+// ssh takes "~/.ssh/id_ed25519" as it is:
+pub(super) fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    env::{current_exe, temp_dir, var, var_os},
+    ffi::OsString,
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all},
+    io,
+    path::PathBuf,
+    process::{exit, Stdio},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use openssh::Session;
+use tokio::process::Command;
+
+/// Password for SSH logins, used instead of asking for it.
+pub const PASSWORD_VARIABLE: &str = "LIVETUNNEL_SSH_PASSWORD";
+// Set when ssh runs livetunnel as its SSH_ASKPASS program:
+const ASKPASS_VARIABLE: &str = "LIVETUNNEL_ASKPASS";
+
+/// Answers a password or keyboard-interactive prompt of ssh and exits, if livetunnel was started
+/// as its askpass program. Has to run before the arguments are parsed: ssh passes the prompt.
+pub fn answer() {
+    if var_os(ASKPASS_VARIABLE).is_some() {
+        println!("{}", var(PASSWORD_VARIABLE).unwrap_or_default());
+        exit(0);
+    }
+}
+
+/// Starts an SSH master connection to `host` that may log in with `password`.
+///
+/// openssh always runs ssh with BatchMode, which rules out passwords, so the master is started
+/// here with livetunnel itself as SSH_ASKPASS and then taken over like one started by openssh.
+/// `arguments` are passed to ssh before the host.
+pub async fn connect(
+    host: &str,
+    arguments: Vec<OsString>,
+    password: &str,
+) -> Result<Session, String> {
+    let directory = control_directory().map_err(|err| err.to_string())?;
+    let control = directory.join("master");
+    let log = directory.join("log");
+
+    let status = Command::new("ssh")
+        .arg("-E")
+        .arg(&log)
+        .arg("-S")
+        .arg(&control)
+        .args(["-M", "-f", "-N"])
+        .args(["-o", "ControlPersist=yes"])
+        .args(["-o", "BatchMode=no"])
+        // A host key prompt would get the password as answer:
+        .args(["-o", "StrictHostKeyChecking=accept-new"])
+        .args(["-o", "NumberOfPasswordPrompts=1"])
+        .args(arguments)
+        .arg("--")
+        .arg(host)
+        .env("SSH_ASKPASS", current_exe().map_err(|err| err.to_string())?)
+        .env("SSH_ASKPASS_REQUIRE", "force")
+        // Older ssh versions only use SSH_ASKPASS with a display and without a terminal:
+        .env(
+            "DISPLAY",
+            var_os("DISPLAY").unwrap_or_else(|| OsString::from(":0")),
+        )
+        .env(ASKPASS_VARIABLE, "1")
+        .env(PASSWORD_VARIABLE, password)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .map_err(|err| format!("could not run ssh: {}", err))?;
+
+    if !status.success() {
+        let reason = read_to_string(&log)
+            .ok()
+            .and_then(|log| log.lines().last().map(String::from))
+            .unwrap_or_else(|| format!("ssh exited with {}", status));
+        let _ = remove_dir_all(&directory);
+        return Err(reason);
+    }
+
+    Ok(Session::resume(
+        control.into_boxed_path(),
+        Some(log.into_boxed_path()),
+    ))
+}
+
+/// Removes the control directories of this process' master connections once they are closed.
+pub fn cleanup() {
+    let prefix = format!("livetunnel-{}-", std::process::id());
+    if let Ok(entries) = read_dir(temp_dir()) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = remove_dir_all(entry.path());
+            }
+        }
+    }
+}
+
+// Only for our user, it holds the socket that runs commands on the server:
+fn control_directory() -> io::Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let directory = temp_dir().join(format!("livetunnel-{}-{}", std::process::id(), nanos));
+    create_dir_all(&directory)?;
+
+    #[cfg(unix)]
+    {
+        use std::{
+            fs::{set_permissions, Permissions},
+            os::unix::fs::PermissionsExt,
+        };
+        set_permissions(&directory, Permissions::from_mode(0o700))?;
+    }
+
+    Ok(directory)
+}
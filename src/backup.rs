@@ -0,0 +1,147 @@
+use crate::{
+    app,
+    error::{Error, Result},
+};
+
+use std::{
+    cmp::Reverse,
+    fmt::{self, Display, Formatter},
+    fs::{copy, create_dir_all, read, read_dir, remove_file},
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use humantime::format_rfc3339_seconds;
+use inquire::{Confirm, Select};
+use tracing::info;
+
+// How many backups of the config file are kept around:
+const BACKUPS_TO_KEEP: usize = 10;
+
+struct Backup {
+    path: PathBuf,
+    created: SystemTime,
+}
+
+impl Display for Backup {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_rfc3339_seconds(self.created))
+    }
+}
+
+/// Copies the current config file into the backup directory before it gets overwritten.
+///
+/// Does nothing if there is no config yet or the newest backup already has the same content.
+pub fn backup_config() -> io::Result<()> {
+    let config_path = config_path()?;
+    if !config_path.exists() {
+        return Ok(());
+    }
+
+    let current = read(&config_path)?;
+    let backups = list_backups()?;
+    if let Some(newest) = backups.first() {
+        if read(&newest.path)? == current {
+            return Ok(());
+        }
+    }
+
+    let directory = backup_directory()?;
+    create_dir_all(&directory)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    copy(
+        &config_path,
+        directory.join(format!("livetunnel-{}.toml", timestamp)),
+    )?;
+
+    for old_backup in list_backups()?.iter().skip(BACKUPS_TO_KEEP) {
+        remove_file(&old_backup.path)?;
+    }
+
+    Ok(())
+}
+
+/// Interactively restores one of the config backups (`livetunnel config restore`).
+pub fn restore_config() -> Result<()> {
+    let backups = list_backups().unwrap_or_default();
+    if backups.is_empty() {
+        info!("There are no config backups to restore.");
+        return Ok(());
+    }
+
+    let backup = Select::new("Which backup do you want to restore?", backups)
+        .with_vim_mode(true)
+        .prompt()?;
+
+    let restore = Confirm::new(&format!(
+        "Restore the config from {}? The current config is backed up first.",
+        backup
+    ))
+    .with_default(true)
+    .prompt()?;
+
+    if !restore {
+        return Ok(());
+    }
+
+    config_path()
+        .and_then(|config_path| backup_config().and_then(|_| copy(&backup.path, &config_path)))
+        .map_err(|err| Error::Config(format!("Could not restore the config: {}", err)))?;
+
+    info!(success = true, "Restored the config from {}", backup);
+    Ok(())
+}
+
+/// The files of the config backups, newest first.
+pub fn backup_files() -> io::Result<Vec<PathBuf>> {
+    Ok(list_backups()?
+        .into_iter()
+        .map(|backup| backup.path)
+        .collect())
+}
+
+// Newest backup first:
+fn list_backups() -> io::Result<Vec<Backup>> {
+    let directory = backup_directory()?;
+    if !directory.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut backups: Vec<Backup> = read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let timestamp = path
+                .file_stem()?
+                .to_str()?
+                .strip_prefix("livetunnel-")?
+                .parse()
+                .ok()?;
+
+            Some(Backup {
+                path,
+                created: UNIX_EPOCH + Duration::from_secs(timestamp),
+            })
+        })
+        .collect();
+
+    backups.sort_by_key(|backup| Reverse(backup.created));
+    Ok(backups)
+}
+
+fn config_path() -> io::Result<PathBuf> {
+    app::config_path().map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))
+}
+
+fn backup_directory() -> io::Result<PathBuf> {
+    let config_path = config_path()?;
+    Ok(config_path
+        .parent()
+        .map(|parent| parent.join("backups"))
+        .unwrap_or_else(|| PathBuf::from("backups")))
+}
@@ -0,0 +1,79 @@
+use crate::signed::{now, unexpired, Signer};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+
+// Seconds a challenge may take to be solved and a solved one lets a visitor in:
+const SOLVE_TIME: u64 = 5 * 60;
+/// Seconds the cookie of a solved challenge lasts.
+pub const PASS_TIME: u64 = 12 * 60 * 60;
+
+/// A proof of work a visitor's browser does (a second or so) before it gets any file, against
+/// scrapers that bulk-download a share whose URL they found: a nonce that gives the SHA-256 of
+/// `<challenge>:<nonce>` enough leading zero bits. Challenges and the passes for solved ones are
+/// signed with a key of the share, so nothing is stored per visitor.
+#[derive(Debug)]
+pub struct Challenge {
+    signer: Signer,
+    bits: u32,
+}
+
+impl Challenge {
+    /// `bits` leading zero bits a solution needs, every one doubles the work.
+    pub fn new(bits: u32) -> Result<Challenge, String> {
+        Ok(Challenge {
+            signer: Signer::new()?,
+            bits,
+        })
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// A new challenge, to be solved within a few minutes.
+    pub fn issue(&self) -> String {
+        let mut random = [0; 16];
+        let _ = getrandom::getrandom(&mut random);
+        self.signer.sign(&format!(
+            "c.{}.{}",
+            now() + SOLVE_TIME,
+            URL_SAFE_NO_PAD.encode(random)
+        ))
+    }
+
+    /// Whether `nonce` solves `challenge`, one of [`Challenge::issue`] that didn't expire yet.
+    pub fn solved(&self, challenge: &str, nonce: &str) -> bool {
+        if !self.valid(challenge, "c") {
+            return false;
+        }
+        let hash = Sha256::digest(format!("{}:{}", challenge, nonce));
+        let mut zeros = 0;
+        for byte in hash {
+            zeros += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+        zeros >= self.bits
+    }
+
+    /// The cookie value for a solved challenge, good for [`PASS_TIME`].
+    pub fn pass(&self) -> String {
+        self.signer.sign(&format!("p.{}", now() + PASS_TIME))
+    }
+
+    /// Whether `pass` is one of [`Challenge::pass`] that didn't expire yet.
+    pub fn admits(&self, pass: &str) -> bool {
+        self.valid(pass, "p")
+    }
+
+    // Signed by this, of `kind` and not expired:
+    fn valid(&self, token: &str, kind: &str) -> bool {
+        let Some(payload) = self.signer.verify(token) else {
+            return false;
+        };
+        let mut parts = payload.split('.');
+        parts.next() == Some(kind) && parts.next().is_some_and(unexpired)
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Commands accepted on the control socket, one JSON object per line, e.g.
+/// `{"command": "add_user", "username": "alice", "password": "secret"}`.
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    AddUser { username: String, password: String },
+    SetPassword { username: String, password: String },
+    RemoveUser { username: String },
+    // Reads the users from the config again, after `livetunnel users` changed them:
+    ReloadUsers,
+    // Stops the builtin server until `resume` or the next request, like `[serve] idle_pause`:
+    Pause,
+    Resume,
+    Shutdown,
+}
+
+pub type Response = Result<Value, String>;
+
+/// Unix socket a running share answers [`Request`]s on, so other tools (and `livetunnel status`
+/// or `stop`) can talk to it without signals.
+///
+/// Requests are handed to the share's main loop, which answers them through the enclosed sender.
+pub struct Control {
+    path: PathBuf,
+    requests: UnboundedReceiver<(Request, Sender<Response>)>,
+}
+
+impl Control {
+    #[cfg(unix)]
+    pub fn start(path: PathBuf) -> io::Result<Control> {
+        use std::{
+            fs::{remove_file, set_permissions, Permissions},
+            os::unix::{fs::PermissionsExt, net::UnixListener},
+            thread::spawn,
+        };
+
+        // Left over by a share that didn't close properly:
+        let _ = remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        // It can add users and stop the share, so it's for our user only:
+        set_permissions(&path, Permissions::from_mode(0o600))?;
+
+        let (sender, requests) = unbounded_channel();
+        spawn(move || {
+            for stream in listener.incoming().filter_map(|stream| stream.ok()) {
+                let sender = sender.clone();
+                spawn(move || {
+                    let reader = match stream.try_clone() {
+                        Ok(reader) => BufReader::new(reader),
+                        Err(_) => return,
+                    };
+                    let _ = handle_connection(reader, stream, &sender);
+                });
+            }
+        });
+
+        Ok(Control { path, requests })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start(_: PathBuf) -> io::Result<Control> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "control sockets need Unix",
+        ))
+    }
+
+    /// Waits for the next request to answer.
+    pub async fn recv(&mut self) -> Option<(Request, Sender<Response>)> {
+        self.requests.recv().await
+    }
+}
+
+impl Drop for Control {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Sends a single request to the share listening on `path` and returns its answer.
+#[cfg(unix)]
+pub fn request(path: &Path, request: &Value) -> io::Result<Value> {
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(not(unix))]
+pub fn request(_: &Path, _: &Value) -> io::Result<Value> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "control sockets need Unix",
+    ))
+}
+
+fn handle_connection(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    sender: &UnboundedSender<(Request, Sender<Response>)>,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let (reply, response) = channel();
+                match sender.send((request, reply)) {
+                    Ok(()) => response
+                        .recv()
+                        .unwrap_or_else(|_| Err(String::from("livetunnel is closing"))),
+                    Err(_) => Err(String::from("livetunnel is closing")),
+                }
+            }
+            Err(err) => Err(format!("invalid request: {}", err)),
+        };
+
+        let response = match response {
+            Ok(Value::Object(mut fields)) => {
+                fields.insert(String::from("ok"), Value::Bool(true));
+                Value::Object(fields)
+            }
+            Ok(value) => json!({ "ok": true, "result": value }),
+            Err(err) => json!({ "ok": false, "error": err }),
+        };
+        writeln!(writer, "{}", response)?;
+    }
+
+    Ok(())
+}
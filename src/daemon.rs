@@ -0,0 +1,409 @@
+use crate::{
+    app,
+    control::request,
+    encryption::PASSPHRASE_VARIABLE,
+    error::{Error, Result},
+};
+
+use std::{
+    env::{args_os, current_exe},
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, write, File},
+    io,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use confy::get_configuration_file_path;
+use humantime::format_duration;
+use indicatif::DecimalBytes;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, warn};
+
+// How long `--detach` waits for the background process to be up:
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+// How long `livetunnel stop` waits for it to close:
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a detached livetunnel shares, written once the tunnel and server are up.
+///
+/// Every share runs in a background process of its own with its own state file, so several
+/// directories can be shared at once on different ports.
+#[derive(Serialize, Deserialize)]
+pub struct State {
+    pub pid: u32,
+    pub directory: PathBuf,
+    pub host: String,
+    pub local_port: u16,
+    pub remote_port: u16,
+    pub started: u64,
+    // False while the forward or the local server is down:
+    pub healthy: bool,
+}
+
+impl State {
+    pub fn new(directory: PathBuf, host: String, local_port: u16, remote_port: u16) -> State {
+        State {
+            pid: std::process::id(),
+            directory,
+            host,
+            local_port,
+            remote_port,
+            started: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            healthy: true,
+        }
+    }
+
+    fn uptime(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Duration::from_secs(now.saturating_sub(self.started))
+    }
+}
+
+/// Starts livetunnel again in the background with the same arguments and returns once it is
+/// sharing (`--detach`). The background process can't ask anything, so it runs non-interactive.
+pub fn detach() -> Result<()> {
+    let (mut child, log_path) = spawn_daemon().map_err(|err| {
+        Error::Io(io::Error::new(
+            err.kind(),
+            format!("Could not start livetunnel in the background: {}", err),
+        ))
+    })?;
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if running_shares().iter().any(|state| state.pid == child.id()) {
+            info!(
+                success = true,
+                "livetunnel is running in the background (PID {}), logging to '{}'. Stop it with `livetunnel stop`.",
+                child.id(),
+                log_path.display()
+            );
+            return Ok(());
+        }
+
+        if let Ok(Some(status)) = child.try_wait() {
+            print!("{}", read_to_string(&log_path).unwrap_or_default());
+            return Err(Error::Io(io::Error::other(format!(
+                "livetunnel exited before it was up ({}), see '{}' above.",
+                status,
+                log_path.display()
+            ))));
+        }
+
+        if Instant::now() > deadline {
+            return Err(Error::Io(io::Error::other(format!(
+                "livetunnel isn't up after {}, see '{}'",
+                format_duration(STARTUP_TIMEOUT),
+                log_path.display()
+            ))));
+        }
+
+        sleep(Duration::from_millis(200));
+    }
+}
+
+/// Prints one line per share running in the background (`livetunnel list`).
+pub fn list() {
+    let shares = running_shares();
+    if shares.is_empty() {
+        println!("ℹ livetunnel isn't running in the background.");
+        return;
+    }
+
+    println!("ℹ {} share(s) running in the background:", shares.len());
+    for state in shares {
+        println!(
+            "  PID {}: '{}' on local Port {} -> remote Port {}, up {}, {}",
+            state.pid,
+            state.directory.display(),
+            state.local_port,
+            state.remote_port,
+            format_duration(state.uptime()),
+            if state.healthy { "healthy" } else { "degraded" }
+        );
+    }
+}
+
+/// Prints what the background processes are sharing (`livetunnel status`).
+pub fn status() {
+    let shares = running_shares();
+    if shares.is_empty() {
+        println!("ℹ livetunnel isn't running in the background.");
+        return;
+    }
+
+    for state in shares {
+        println!(
+            "{} livetunnel is running in the background (PID {}){}",
+            if state.healthy { "✓" } else { "❗" },
+            state.pid,
+            if state.healthy {
+                ""
+            } else {
+                ", but the forward or the local server is down"
+            }
+        );
+        println!("  Directory: {}", state.directory.display());
+        println!(
+            "  Forwarding local Port {} to remote Port {} on '{}'",
+            state.local_port, state.remote_port, state.host
+        );
+        println!("  Up for {}", format_duration(state.uptime()));
+
+        let live = control_socket_path(state.pid)
+            .and_then(|path| request(&path, &json!({ "command": "status" })))
+            .ok();
+        if let Some(transferred) = live.as_ref().and_then(|live| live["transferred"].as_u64()) {
+            println!(
+                "  {} transferred through the tunnel",
+                DecimalBytes(transferred)
+            );
+        }
+        if let Some(visitors) = live.as_ref().and_then(|live| live["visitors"].as_u64()) {
+            println!(
+                "  {} visitor(s) connected, {} download(s) running",
+                visitors,
+                live.as_ref()
+                    .and_then(|live| live["downloads"].as_u64())
+                    .unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Closes background shares like CTRL+C would (`livetunnel stop`).
+///
+/// Without `pid` the only running share is stopped, `all` stops every share.
+pub fn stop(pid: Option<u32>, all: bool) -> Result<()> {
+    let shares = running_shares();
+    let to_stop: Vec<State> = match pid {
+        Some(pid) => shares
+            .into_iter()
+            .filter(|state| state.pid == pid)
+            .collect(),
+        None if all || shares.len() <= 1 => shares,
+        None => {
+            return Err(Error::Io(io::Error::other(format!(
+                "{} shares are running in the background. Pass the PID of one (see `livetunnel list`) or --all.",
+                shares.len()
+            ))));
+        }
+    };
+
+    if to_stop.is_empty() {
+        match pid {
+            Some(pid) => warn!("There is no share with PID {} in the background.", pid),
+            None => info!("livetunnel isn't running in the background."),
+        }
+        return Ok(());
+    }
+
+    for state in to_stop {
+        stop_share(&state)?;
+    }
+    Ok(())
+}
+
+fn stop_share(state: &State) -> Result<()> {
+    // Politely through the control socket first, shares of older versions only get a signal:
+    let asked = control_socket_path(state.pid)
+        .and_then(|path| request(&path, &json!({ "command": "shutdown" })))
+        .is_ok_and(|response| response["ok"] == true);
+
+    if asked {
+        // Closing on its own now
+    } else if let Err(err) = terminate(state.pid) {
+        return Err(Error::Io(io::Error::new(
+            err.kind(),
+            format!("Could not stop livetunnel (PID {}): {}", state.pid, err),
+        )));
+    }
+
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while is_running(state.pid) {
+        if Instant::now() > deadline {
+            return Err(Error::Io(io::Error::other(format!(
+                "livetunnel (PID {}) didn't close within {}",
+                state.pid,
+                format_duration(STOP_TIMEOUT)
+            ))));
+        }
+        sleep(Duration::from_millis(200));
+    }
+
+    remove_state(state.pid);
+    info!(
+        success = true,
+        "Stopped sharing '{}' (PID {})",
+        state.directory.display(),
+        state.pid
+    );
+    Ok(())
+}
+
+/// Every share running in the background, oldest first.
+///
+/// Cleans up after background processes that didn't get to do it themselves.
+pub fn running_shares() -> Vec<State> {
+    let Ok(entries) = run_directory().and_then(read_dir) else {
+        return vec![];
+    };
+
+    let mut shares: Vec<State> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("share-") && name.ends_with(".toml"))
+        })
+        .filter_map(|path| toml::from_str::<State>(&read_to_string(path).ok()?).ok())
+        .filter(|state| {
+            let running = is_running(state.pid);
+            if !running {
+                remove_state(state.pid);
+            }
+            running
+        })
+        .collect();
+
+    shares.sort_by_key(|state| state.started);
+    shares
+}
+
+pub fn write_state(state: &State) -> io::Result<()> {
+    let directory = run_directory()?;
+    create_dir_all(&directory)?;
+
+    let content = toml::to_string(state).map_err(|err| io::Error::other(err.to_string()))?;
+    write(
+        directory.join(format!("share-{}.pid", state.pid)),
+        format!("{}\n", state.pid),
+    )?;
+    write(directory.join(format!("share-{}.toml", state.pid)), content)
+}
+
+pub fn remove_state(pid: u32) {
+    if let Ok(directory) = run_directory() {
+        let _ = remove_file(directory.join(format!("share-{}.toml", pid)));
+        let _ = remove_file(directory.join(format!("share-{}.pid", pid)));
+        let _ = remove_file(directory.join(format!("share-{}.sock", pid)));
+    }
+}
+
+// Every share logs to its own file, named after its PID once it is known:
+fn spawn_daemon() -> io::Result<(Child, PathBuf)> {
+    let directory = run_directory()?;
+    create_dir_all(&directory)?;
+
+    let log_path = directory.join(format!("starting-{}.log", std::process::id()));
+    let log = File::create(&log_path)?;
+
+    let mut command = Command::new(current_exe()?);
+    // It can't ask for the passphrase of an encrypted config:
+    if let Some(passphrase) = app::config_passphrase().map_err(io::Error::other)? {
+        command.env(PASSPHRASE_VARIABLE, passphrase);
+    }
+    command
+        .args(args_os().skip(1).filter(|arg| arg != "--detach"))
+        .args(["--daemonized", "--non-interactive"])
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+
+    // A session of its own, so closing the terminal doesn't hang it up:
+    #[cfg(unix)]
+    unsafe {
+        use std::os::unix::process::CommandExt;
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+
+    let child = command.spawn()?;
+
+    let share_log_path = directory.join(format!("share-{}.log", child.id()));
+    match rename(&log_path, &share_log_path) {
+        Ok(()) => Ok((child, share_log_path)),
+        Err(_) => Ok((child, log_path)),
+    }
+}
+
+/// Control sockets of the shares running right now, in the background or not.
+pub fn share_sockets() -> Vec<PathBuf> {
+    let Ok(entries) = run_directory().and_then(read_dir) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("share-")?.strip_suffix(".sock"))
+                .and_then(|pid| pid.parse().ok())
+                .is_some_and(is_running)
+        })
+        .collect()
+}
+
+/// Where the share with `pid` listens for control requests.
+pub fn control_socket_path(pid: u32) -> io::Result<PathBuf> {
+    let directory = run_directory()?;
+    create_dir_all(&directory)?;
+    Ok(directory.join(format!("share-{}.sock", pid)))
+}
+
+fn run_directory() -> io::Result<PathBuf> {
+    let config_path = get_configuration_file_path("livetunnel", "livetunnel")
+        .map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+    Ok(config_path
+        .parent()
+        .map(|parent| parent.join("run"))
+        .unwrap_or_else(|| PathBuf::from("run")))
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_running(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> io::Result<()> {
+    match unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(pid: u32) -> io::Result<()> {
+    let status = Command::new("taskkill")
+        .args(["/PID", &pid.to_string()])
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("taskkill exited with {}", status)))
+    }
+}
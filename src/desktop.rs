@@ -0,0 +1,75 @@
+use std::{
+    process::{Command, Stdio},
+    thread::spawn,
+};
+
+use tracing::debug;
+
+/// Shows a desktop notification with the notifier of the platform: notify-send (libnotify),
+/// osascript on macOS, a balloon tip through PowerShell on Windows. Doesn't wait for it and
+/// only logs (`debug!`) when there is none.
+pub fn notify(summary: &str, body: &str) {
+    let mut command = notifier(summary, body);
+    match command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(mut child) => {
+            spawn(move || child.wait());
+        }
+        Err(err) => debug!(
+            "Could not show a desktop notification ({:?}): {}",
+            command.get_program(),
+            err
+        ),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn notifier(summary: &str, body: &str) -> Command {
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title \"livetunnel\" subtitle {}",
+        apple_script_string(body),
+        apple_script_string(summary)
+    ));
+    command
+}
+
+#[cfg(target_os = "windows")]
+fn notifier(summary: &str, body: &str) -> Command {
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command"])
+        .arg(format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+         $icon = New-Object System.Windows.Forms.NotifyIcon; \
+         $icon.Icon = [System.Drawing.SystemIcons]::Information; \
+         $icon.Visible = $true; \
+         $icon.ShowBalloonTip(10000, {}, {}, 'Info'); \
+         Start-Sleep -Seconds 10; \
+         $icon.Dispose()",
+            power_shell_string(summary),
+            power_shell_string(body)
+        ));
+    command
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn notifier(summary: &str, body: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name=livetunnel", summary, body]);
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn apple_script_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(target_os = "windows")]
+fn power_shell_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
@@ -0,0 +1,248 @@
+use std::{
+    io::Write,
+    net::IpAddr,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com/client/v4";
+const DESEC_API: &str = "https://desec.io/api/v1";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsProvider {
+    Cloudflare,
+    Desec,
+    Rfc2136,
+}
+
+/// Where the DNS record of a share is created, the `[dns]` section of the config.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DnsConfig {
+    // "cloudflare", "desec" or "rfc2136" (sent with nsupdate):
+    pub provider: DnsProvider,
+    // API token (Cloudflare, deSEC) or TSIG key as "hmac-sha256:name:secret" (RFC2136):
+    pub token: String,
+    // Cloudflare: the zone ID, deSEC: the domain registered there (e.g. "example.com"),
+    // RFC2136: the zone to update (default: found by nsupdate):
+    pub zone: Option<String>,
+    // RFC2136: the name server taking the update:
+    pub server: Option<String>,
+    // What the record points at (default: the address of the SSH host):
+    pub address: Option<IpAddr>,
+    // Seconds (default: 60, deSEC allows no less than 3600):
+    pub ttl: Option<u32>,
+}
+
+/// A record created for a share, [`DnsRecord::remove`] deletes it again.
+#[derive(Debug)]
+pub struct DnsRecord {
+    config: DnsConfig,
+    name: String,
+    kind: &'static str,
+    // Cloudflare's ID of the record:
+    id: Option<String>,
+}
+
+impl DnsRecord {
+    /// Creates an A (or AAAA) record for `name` pointing at `address`.
+    pub fn create(config: &DnsConfig, name: &str, address: IpAddr) -> Result<DnsRecord, String> {
+        let kind = if address.is_ipv4() { "A" } else { "AAAA" };
+        let mut record = DnsRecord {
+            config: config.clone(),
+            name: name.trim_end_matches('.').to_string(),
+            kind,
+            id: None,
+        };
+
+        match config.provider {
+            DnsProvider::Cloudflare => {
+                let response = post(
+                    &format!("{}/zones/{}/dns_records", CLOUDFLARE_API, record.zone()?),
+                    format!("Bearer {}", config.token),
+                    json!({
+                        "type": kind,
+                        "name": record.name,
+                        "content": address.to_string(),
+                        "ttl": config.ttl.unwrap_or(60),
+                        "proxied": false,
+                    }),
+                )?;
+                record.id = response["result"]["id"].as_str().map(String::from);
+                if record.id.is_none() {
+                    return Err(String::from(
+                        "Cloudflare didn't return the ID of the record",
+                    ));
+                }
+            }
+            DnsProvider::Desec => {
+                post(
+                    &format!("{}/domains/{}/rrsets/", DESEC_API, record.zone()?),
+                    format!("Token {}", config.token),
+                    json!({
+                        "subname": record.subname()?,
+                        "type": kind,
+                        "ttl": config.ttl.unwrap_or(3600),
+                        "records": [address.to_string()],
+                    }),
+                )?;
+            }
+            DnsProvider::Rfc2136 => {
+                record.nsupdate(&format!(
+                    "update add {}. {} {} {}",
+                    record.name,
+                    config.ttl.unwrap_or(60),
+                    kind,
+                    address
+                ))?;
+            }
+        }
+
+        Ok(record)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn remove(self) -> Result<(), String> {
+        match self.config.provider {
+            DnsProvider::Cloudflare => delete(
+                &format!(
+                    "{}/zones/{}/dns_records/{}",
+                    CLOUDFLARE_API,
+                    self.zone()?,
+                    self.id.as_deref().unwrap_or_default()
+                ),
+                format!("Bearer {}", self.config.token),
+            )
+            .map(|_| ()),
+            DnsProvider::Desec => delete(
+                &format!(
+                    "{}/domains/{}/rrsets/{}/{}/",
+                    DESEC_API,
+                    self.zone()?,
+                    self.subname()?,
+                    self.kind
+                ),
+                format!("Token {}", self.config.token),
+            )
+            .map(|_| ()),
+            DnsProvider::Rfc2136 => {
+                self.nsupdate(&format!("update delete {}. {}", self.name, self.kind))
+            }
+        }
+    }
+
+    fn zone(&self) -> Result<&str, String> {
+        self.config
+            .zone
+            .as_deref()
+            .ok_or_else(|| String::from("no zone set in the [dns] section of the config"))
+    }
+
+    // The name relative to the deSEC domain, e.g. "k7q2mx.share" in "example.com":
+    fn subname(&self) -> Result<String, String> {
+        let zone = self.zone()?;
+        match self.name.strip_suffix(zone) {
+            Some(subname) if subname.ends_with('.') => {
+                Ok(subname.trim_end_matches('.').to_string())
+            }
+            _ => Err(format!("'{}' is not below '{}'", self.name, zone)),
+        }
+    }
+
+    fn nsupdate(&self, update: &str) -> Result<(), String> {
+        let server =
+            self.config.server.as_deref().ok_or_else(|| {
+                String::from("no name server set in the [dns] section of the config")
+            })?;
+        // "hmac-sha256:name:secret" becomes "key hmac-sha256:name secret", it goes in through
+        // stdin as well, so it doesn't show up in the process list:
+        let Some((key, secret)) = self.config.token.rsplit_once(':') else {
+            return Err(String::from(
+                "the TSIG key needs to look like 'hmac-sha256:name:secret'",
+            ));
+        };
+        let mut script = format!("key {} {}\nserver {}\n", key, secret, server);
+        if let Some(zone) = &self.config.zone {
+            script.push_str(&format!("zone {}\n", zone));
+        }
+        script.push_str(&format!("{}\nsend\n", update));
+
+        let mut nsupdate = Command::new("nsupdate")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| format!("could not run nsupdate: {}", err))?;
+        if let Some(mut stdin) = nsupdate.stdin.take() {
+            stdin
+                .write_all(script.as_bytes())
+                .map_err(|err| err.to_string())?;
+        }
+
+        let output = nsupdate.wait_with_output().map_err(|err| err.to_string())?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "nsupdate exited with {}: '{}'",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+fn agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(20)))
+        .http_status_as_error(false)
+        .build()
+        .new_agent()
+}
+
+fn post(url: &str, authorization: String, body: Value) -> Result<Value, String> {
+    answer(
+        agent()
+            .post(url)
+            .header("Authorization", &authorization)
+            .content_type("application/json")
+            .send(body.to_string()),
+    )
+}
+
+fn delete(url: &str, authorization: String) -> Result<Value, String> {
+    answer(
+        agent()
+            .delete(url)
+            .header("Authorization", &authorization)
+            .call(),
+    )
+}
+
+// The JSON answer of a DNS API, or its error message:
+fn answer(
+    response: Result<ureq::http::Response<ureq::Body>, ureq::Error>,
+) -> Result<Value, String> {
+    let mut response = response.map_err(|err| err.to_string())?;
+
+    let status = response.status();
+    let text = response.body_mut().read_to_string().unwrap_or_default();
+    let answer: Value = serde_json::from_str(&text).unwrap_or(Value::Null);
+    if status.is_success() {
+        return Ok(answer);
+    }
+
+    // Cloudflare: {"errors": [{"message": ...}]}, deSEC: {"detail": ...} or field errors:
+    let message = answer["errors"][0]["message"]
+        .as_str()
+        .or(answer["detail"].as_str())
+        .map(String::from)
+        .unwrap_or(text);
+    Err(format!("{} ({})", message.trim(), status))
+}
@@ -0,0 +1,123 @@
+use crate::error::{Error, Result};
+
+use std::{
+    env::var,
+    io::{stdin, IsTerminal, Write},
+    iter,
+    sync::Mutex,
+};
+
+use age::{
+    armor::{ArmoredWriter, Format},
+    scrypt, DecryptError, Encryptor,
+};
+use inquire::{validator::ValueRequiredValidator, Password};
+use tracing::warn;
+
+/// Passphrase of an encrypted config, used instead of asking for it.
+pub const PASSPHRASE_VARIABLE: &str = "LIVETUNNEL_CONFIG_PASSPHRASE";
+// Encrypted configs are ASCII armored age files, `age -d` opens them as well:
+const ARMOR_BEGIN: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+// Wrong passphrases typed before giving up:
+const ATTEMPTS: usize = 3;
+
+// Asked for once per run:
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+// The last file decrypted or encrypted with its plaintext, scrypt takes about a second each time:
+static LAST: Mutex<Option<(Vec<u8>, String)>> = Mutex::new(None);
+
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.trim_ascii_start().starts_with(ARMOR_BEGIN)
+}
+
+/// Opens an encrypted config with the passphrase, asking for it (again, if it's wrong) unless
+/// it's known already or set in `LIVETUNNEL_CONFIG_PASSPHRASE`.
+pub fn decrypt(content: &[u8]) -> Result<String> {
+    if let Some((encrypted, plaintext)) = &*LAST.lock().unwrap() {
+        if encrypted == content {
+            return Ok(plaintext.clone());
+        }
+    }
+
+    for attempt in 1..=ATTEMPTS {
+        let passphrase = passphrase()?;
+        let identity = scrypt::Identity::new(passphrase.into());
+        match age::decrypt(&identity, content) {
+            Ok(plaintext) => {
+                let plaintext = String::from_utf8(plaintext)
+                    .map_err(|err| Error::Config(format!("The config is no text: {}", err)))?;
+                *LAST.lock().unwrap() = Some((content.to_vec(), plaintext.clone()));
+                return Ok(plaintext);
+            }
+            Err(DecryptError::DecryptionFailed | DecryptError::NoMatchingKeys) => {
+                *PASSPHRASE.lock().unwrap() = None;
+                if attempt == ATTEMPTS || var(PASSPHRASE_VARIABLE).is_ok() || !can_ask() {
+                    break;
+                }
+                warn!("Wrong passphrase, try again.");
+            }
+            Err(err) => {
+                return Err(Error::Config(format!(
+                    "Could not decrypt the config: {}",
+                    err
+                )))
+            }
+        }
+    }
+
+    Err(Error::Config(String::from(
+        "Wrong passphrase for the encrypted config.",
+    )))
+}
+
+/// Encrypts a config with the passphrase it was opened with, see [`decrypt`].
+pub fn encrypt(plaintext: &str) -> Result<String> {
+    let recipient = scrypt::Recipient::new(passphrase()?.into());
+    let encryptor = Encryptor::with_recipients(iter::once(&recipient as _))
+        .map_err(|err| Error::Config(format!("Could not encrypt the config: {}", err)))?;
+
+    let mut encrypted = vec![];
+    let mut writer = encryptor.wrap_output(ArmoredWriter::wrap_output(
+        &mut encrypted,
+        Format::AsciiArmor,
+    )?)?;
+    writer.write_all(plaintext.as_bytes())?;
+    writer.finish()?.finish()?;
+
+    *LAST.lock().unwrap() = Some((encrypted.clone(), String::from(plaintext)));
+    Ok(String::from_utf8_lossy(&encrypted).into_owned())
+}
+
+/// Uses `passphrase` from now on, e.g. a new one for `livetunnel config encrypt`.
+pub fn set_passphrase(passphrase: String) {
+    *PASSPHRASE.lock().unwrap() = Some(passphrase);
+}
+
+/// The passphrase of the config: the one known already, `LIVETUNNEL_CONFIG_PASSPHRASE` or asked
+/// for.
+pub fn passphrase() -> Result<String> {
+    let mut known = PASSPHRASE.lock().unwrap();
+    if let Some(passphrase) = &*known {
+        return Ok(passphrase.clone());
+    }
+
+    let passphrase = match var(PASSPHRASE_VARIABLE) {
+        Ok(passphrase) => passphrase,
+        Err(_) if can_ask() => Password::new("Passphrase of the config:")
+            .with_validator(ValueRequiredValidator::default())
+            .without_confirmation()
+            .prompt()?,
+        Err(_) => {
+            return Err(Error::Config(format!(
+            "The config is encrypted and there is no terminal to ask for the passphrase. Set {}.",
+            PASSPHRASE_VARIABLE
+        )))
+        }
+    };
+    *known = Some(passphrase.clone());
+    Ok(passphrase)
+}
+
+fn can_ask() -> bool {
+    stdin().is_terminal()
+}
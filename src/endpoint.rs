@@ -0,0 +1,194 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, TcpListener, TcpStream},
+    path::PathBuf,
+    time::Duration,
+};
+#[cfg(unix)]
+use std::{
+    fs::{self, Permissions},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+};
+
+/// Where the local server listens and the SSH forward leads: a port of `127.0.0.1`, or a Unix
+/// socket only the current user may connect to (`local_socket`), which other users of the
+/// machine can't reach and which takes no port.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Port(u16),
+    #[cfg(unix)]
+    Socket(PathBuf),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Port(port) => write!(f, "local Port {}", port),
+            #[cfg(unix)]
+            Endpoint::Socket(path) => write!(f, "local socket '{}'", path.display()),
+        }
+    }
+}
+
+impl Endpoint {
+    /// Listens on it, a free port for `Port(0)`.
+    pub fn listen(&self) -> io::Result<Listener> {
+        match self {
+            Endpoint::Port(port) => Ok(Listener::Tcp(TcpListener::bind((
+                Ipv4Addr::LOCALHOST,
+                *port,
+            ))?)),
+            #[cfg(unix)]
+            Endpoint::Socket(path) => Ok(Listener::Unix(
+                bind_socket(path, |path| UnixListener::bind(path))?,
+                path.clone(),
+            )),
+        }
+    }
+
+    pub fn connect(&self) -> io::Result<Stream> {
+        match self {
+            Endpoint::Port(port) => Ok(Stream::Tcp(TcpStream::connect((
+                Ipv4Addr::LOCALHOST,
+                *port,
+            ))?)),
+            #[cfg(unix)]
+            Endpoint::Socket(path) => Ok(Stream::Unix(UnixStream::connect(path)?)),
+        }
+    }
+}
+
+/// Binds the socket at `path` with `bind` (of std or tokio) for the current user only. A stale
+/// one of an earlier run is replaced, one that still takes connections is in use.
+#[cfg(unix)]
+pub fn bind_socket<L>(path: &Path, bind: impl FnOnce(&Path) -> io::Result<L>) -> io::Result<L> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("'{}' is in use", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    let listener = bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Takes connections of an [`Endpoint`], a socket file is removed with it.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Where it listens, with the port that was picked for `Port(0)`.
+    pub fn endpoint(&self) -> io::Result<Endpoint> {
+        match self {
+            Listener::Tcp(listener) => Ok(Endpoint::Port(listener.local_addr()?.port())),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => Ok(Endpoint::Socket(path.clone())),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => Ok(Stream::Tcp(listener.accept()?.0)),
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => Ok(Stream::Unix(listener.accept()?.0)),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A connection to or from an [`Endpoint`].
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(stream) => Ok(Stream::Tcp(stream.try_clone()?)),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Ok(Stream::Unix(stream.try_clone()?)),
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(how),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
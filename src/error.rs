@@ -0,0 +1,85 @@
+use crate::logging;
+
+use std::{io, process::exit};
+
+use confy::ConfyError;
+use inquire::InquireError;
+use serde_json::json;
+use thiserror::Error;
+use tracing::error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that makes livetunnel give up, grouped by what went wrong so scripts can tell the
+/// cases apart by the exit code.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The config is missing, invalid or could not be stored (exit code 2).
+    #[error("{0}")]
+    Config(String),
+    /// The SSH connection, the forward or a remote command failed (exit code 3).
+    #[error("{0}")]
+    Ssh(String),
+    /// The local server could not be started (exit code 4).
+    #[error("{0}")]
+    Server(String),
+    /// A before command with `on_failure = "abort"` failed (exit code 5).
+    #[error("{0}")]
+    Command(String),
+    /// A prompt was cancelled with ESC or CTRL+C (exit code 130).
+    #[error("Aborted.")]
+    Aborted,
+    /// Anything else, mostly reading or writing local files or the terminal (exit code 1).
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl Error {
+    /// The code livetunnel exits with for it.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io(_) => 1,
+            Error::Config(_) => 2,
+            Error::Ssh(_) => 3,
+            Error::Server(_) => 4,
+            Error::Command(_) => 5,
+            // Like a shell reports a process ended by SIGINT:
+            Error::Aborted => 130,
+        }
+    }
+
+    /// Prints the error without a backtrace and exits with its code.
+    pub fn exit(&self) -> ! {
+        error!("{}", self);
+        logging::emit(
+            "error",
+            json!({ "message": self.to_string(), "code": self.exit_code() }),
+        );
+        exit(self.exit_code());
+    }
+}
+
+impl From<ConfyError> for Error {
+    fn from(err: ConfyError) -> Self {
+        Error::Config(format!("Could not read or write the config: {}", err))
+    }
+}
+
+impl From<openssh::Error> for Error {
+    fn from(err: openssh::Error) -> Self {
+        Error::Ssh(err.to_string())
+    }
+}
+
+impl From<InquireError> for Error {
+    fn from(err: InquireError) -> Self {
+        match err {
+            InquireError::OperationCanceled | InquireError::OperationInterrupted => Error::Aborted,
+            InquireError::NotTTY => Error::Config(String::from(
+                "Can't ask for input without a terminal, pass --non-interactive.",
+            )),
+            InquireError::IO(err) => Error::Io(err),
+            err => Error::Io(io::Error::other(err.to_string())),
+        }
+    }
+}
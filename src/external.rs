@@ -0,0 +1,146 @@
+use crate::stats::{parse_access_line, AccessStats};
+
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    runtime::Runtime,
+    task::JoinHandle,
+    time::timeout,
+};
+use tracing::debug;
+
+// How much of stderr explains why the server exited:
+const STDERR_TAIL: usize = 5;
+
+/// A server livetunnel runs as child process, miniserve or `server_command`. Everything it
+/// prints is logged (`debug!`). Lines that look like requests of an access log count in the
+/// [`AccessStats`] and are optionally appended to an access log file, the last other lines of
+/// stderr tell why it exited.
+pub struct ExternalServer {
+    child: Child,
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl ExternalServer {
+    /// Spawns `command` with its output piped to us, inside a tokio runtime.
+    pub fn spawn(
+        mut command: Command,
+        access_log: Option<&Path>,
+        stats: Arc<AccessStats>,
+    ) -> io::Result<ExternalServer> {
+        let requests = Arc::new(Requests {
+            access_log: access_log
+                .map(|path| OpenOptions::new().create(true).append(true).open(path))
+                .transpose()?
+                .map(Mutex::new),
+            stats,
+        });
+
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::with_capacity(STDERR_TAIL)));
+        let mut readers = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(tokio::spawn(read_lines(
+                stdout,
+                "stdout",
+                requests.clone(),
+                None,
+            )));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(tokio::spawn(read_lines(
+                stderr,
+                "stderr",
+                requests,
+                Some(stderr_tail.clone()),
+            )));
+        }
+
+        Ok(ExternalServer {
+            child,
+            stderr_tail,
+            readers,
+        })
+    }
+
+    /// Waits until the server exits and returns why, with its last words on stderr if it failed.
+    pub async fn wait(&mut self) -> String {
+        let status = match self.child.wait().await {
+            Ok(status) => status,
+            Err(err) => return err.to_string(),
+        };
+
+        // What it printed last may still be in the pipes:
+        for reader in self.readers.drain(..) {
+            let _ = timeout(Duration::from_secs(1), reader).await;
+        }
+
+        let tail: Vec<String> = self.stderr_tail.lock().unwrap().drain(..).collect();
+        if status.success() || tail.is_empty() {
+            status.to_string()
+        } else {
+            format!("{}: '{}'", status, tail.join(" / "))
+        }
+    }
+
+    /// Kills the server, if CTRL+C didn't already, and waits for it to exit.
+    pub fn stop(mut self, runtime: &Runtime) -> io::Result<()> {
+        // The child gets CTRL+C as well, unless it's in another process group:
+        // https://unix.stackexchange.com/questions/149741/why-is-sigint-not-propagated-to-child-process-when-sent-to-its-parent-process/149756#149756
+        let _ = self.child.start_kill();
+        let status = runtime.block_on(self.child.wait())?;
+        debug!("Server exited on close: {}", status);
+        Ok(())
+    }
+}
+
+// Where the requests a server logs go, servers differ in which stream they use for them:
+struct Requests {
+    access_log: Option<Mutex<File>>,
+    stats: Arc<AccessStats>,
+}
+
+async fn read_lines(
+    output: impl AsyncRead + Unpin,
+    stream: &'static str,
+    requests: Arc<Requests>,
+    tail: Option<Arc<Mutex<VecDeque<String>>>>,
+) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        debug!(stream, "{}", line);
+
+        if let Some(logged) = parse_access_line(&line) {
+            requests.stats.request(&logged.client, &logged.request);
+            requests
+                .stats
+                .sent(&logged.client, logged.bytes.unwrap_or_default());
+            if let Some(file) = &requests.access_log {
+                let _ = writeln!(file.lock().unwrap(), "{}", line);
+            }
+        } else if let Some(tail) = &tail {
+            let mut tail = tail.lock().unwrap();
+            if tail.len() == STDERR_TAIL {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    thread::spawn,
+    time::{Duration, Instant},
+};
+
+/// Pings a healthchecks.io-style URL while the tunnel is healthy, and `<url>/fail` once it
+/// degrades, so external uptime monitoring notices a share that silently died.
+pub struct Heartbeat {
+    url: String,
+    interval: Duration,
+    last_ping: Option<Instant>,
+    failing: bool,
+}
+
+impl Heartbeat {
+    pub fn new(url: String, interval: Duration) -> Self {
+        Heartbeat {
+            url: url.trim_end_matches('/').to_string(),
+            interval,
+            last_ping: None,
+            failing: false,
+        }
+    }
+
+    /// Called on every iteration of the main loop with the current state of the tunnel.
+    pub fn update(&mut self, healthy: bool) {
+        if !healthy {
+            if !self.failing {
+                self.failing = true;
+                ping(format!("{}/fail", self.url));
+            }
+            return;
+        }
+
+        let due = match self.last_ping {
+            Some(last_ping) => last_ping.elapsed() >= self.interval,
+            None => true,
+        };
+
+        // Recovering from a failure is reported right away:
+        if due || self.failing {
+            self.failing = false;
+            self.last_ping = Some(Instant::now());
+            ping(self.url.clone());
+        }
+    }
+}
+
+fn ping(url: String) {
+    // Don't block the main loop on a slow monitoring endpoint:
+    spawn(move || {
+        let _ = ureq::get(&url)
+            .config()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .build()
+            .call();
+    });
+}
@@ -0,0 +1,85 @@
+use std::net::Ipv6Addr;
+
+/// A `[user@]host[:port]` string as accepted by the host and jump-host prompts.
+///
+/// IPv6 addresses need brackets when a port is given (`[::1]:2222`).
+#[derive(Debug, Clone)]
+pub struct HostSpec {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+pub fn parse_host(input: &str) -> Result<HostSpec, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(String::from("Host must not be empty"));
+    }
+
+    if input.contains(char::is_whitespace) {
+        return Err(String::from("Host must not contain whitespace"));
+    }
+
+    let (user, rest) = match input.rsplit_once('@') {
+        Some(("", _)) => return Err(String::from("Username before '@' must not be empty")),
+        Some((user, rest)) => (Some(user.to_string()), rest),
+        None => (None, input),
+    };
+
+    let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+        let (address, after) = bracketed
+            .split_once(']')
+            .ok_or_else(|| String::from("Missing ']' after IPv6 address"))?;
+
+        address
+            .parse::<Ipv6Addr>()
+            .map_err(|_| format!("'{}' is not a valid IPv6 address", address))?;
+
+        let port = match after {
+            "" => None,
+            _ => Some(
+                after
+                    .strip_prefix(':')
+                    .ok_or_else(|| format!("Unexpected '{}' after IPv6 address", after))?,
+            ),
+        };
+
+        (address, port)
+    } else if rest.matches(':').count() > 1 {
+        // Unbracketed IPv6 address, can't carry a port:
+        rest.parse::<Ipv6Addr>().map_err(|_| {
+            format!(
+                "'{}' is not a valid IPv6 address (use [address]:port to add a port)",
+                rest
+            )
+        })?;
+        (rest, None)
+    } else {
+        match rest.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (rest, None),
+        }
+    };
+
+    if host.is_empty() {
+        return Err(String::from("Hostname must not be empty"));
+    }
+
+    if host.contains(['/', '[', ']']) {
+        return Err(format!("'{}' is not a valid hostname", host));
+    }
+
+    let port = match port {
+        Some(port) => match port.parse::<u16>() {
+            Ok(0) | Err(_) => return Err(format!("'{}' is not a valid Port Number", port)),
+            Ok(port) => Some(port),
+        },
+        None => None,
+    };
+
+    Ok(HostSpec {
+        user,
+        host: host.to_string(),
+        port,
+    })
+}
@@ -0,0 +1,132 @@
+use std::{io, time::Duration};
+
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+
+#[cfg(unix)]
+use tokio::io::unix::AsyncFd;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+#[cfg(unix)]
+use tracing::debug;
+
+// How often the terminal is checked for input where it can't be waited for:
+const INPUT_CHECK: Duration = Duration::from_millis(100);
+
+/// Reads single keys from the terminal while sharing, without waiting for Enter and without
+/// echoing them. Unlike a raw terminal, output and CTRL+C keep working as usual, so spinners and
+/// status lines are unaffected. The terminal is restored on drop.
+pub struct Keys {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl Keys {
+    #[cfg(unix)]
+    pub fn start() -> io::Result<Keys> {
+        use std::io::{stdin, IsTerminal};
+
+        if !stdin().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stdin is not a terminal",
+            ));
+        }
+
+        let mut original = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut keys = original;
+        keys.c_lflag &= !(libc::ICANON | libc::ECHO);
+        // Reads return right away, with whatever was typed so far. They only happen once
+        // [`Typing`] saw stdin become readable:
+        keys.c_cc[libc::VMIN] = 0;
+        keys.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &keys) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Keys { original })
+    }
+
+    #[cfg(not(unix))]
+    pub fn start() -> io::Result<Keys> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "reading keys needs Unix",
+        ))
+    }
+
+    /// The keys pressed since the last call, never blocks.
+    #[cfg(unix)]
+    pub fn pressed(&self) -> Vec<char> {
+        let mut typed = vec![];
+        let mut buffer = [0u8; 64];
+        loop {
+            let read =
+                unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+            if read <= 0 {
+                break;
+            }
+            typed.extend_from_slice(&buffer[..read as usize]);
+        }
+        String::from_utf8_lossy(&typed).chars().collect()
+    }
+
+    #[cfg(not(unix))]
+    pub fn pressed(&self) -> Vec<char> {
+        vec![]
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Keys {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Tells when something was typed, for [`Keys`] or the dashboard to read it: waits for stdin to
+/// become readable rather than checking it over and over. Where that doesn't work (Windows, or
+/// stdin isn't a terminal) it's checked every 100ms instead.
+pub enum Typing {
+    #[cfg(unix)]
+    Stdin(AsyncFd<RawFd>),
+    Checked(Interval),
+}
+
+impl Typing {
+    /// Needs to be called within a Tokio runtime.
+    pub fn start() -> Typing {
+        #[cfg(unix)]
+        {
+            use std::io::{stdin, IsTerminal};
+
+            if stdin().is_terminal() {
+                match AsyncFd::new(libc::STDIN_FILENO) {
+                    Ok(stdin) => return Typing::Stdin(stdin),
+                    Err(err) => debug!("Could not wait for stdin: {}", err),
+                }
+            }
+        }
+        let mut checks = interval(INPUT_CHECK);
+        checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Typing::Checked(checks)
+    }
+
+    /// Returns once there may be something to read. Whatever it is has to be read before the
+    /// next call, only new input wakes that one up.
+    pub async fn typed(&mut self) {
+        match self {
+            #[cfg(unix)]
+            Typing::Stdin(stdin) => match stdin.readable().await {
+                Ok(mut ready) => ready.clear_ready(),
+                Err(_) => std::future::pending().await,
+            },
+            Typing::Checked(checks) => {
+                checks.tick().await;
+            }
+        }
+    }
+}
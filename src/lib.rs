@@ -0,0 +1,517 @@
+//! livetunnel shares a local directory through an SSH tunnel to your own web server.
+//!
+//! Besides the `livetunnel` command ([`run_cli`]), shares can be started from code with
+//! [`Tunnel`], using the stored config (`livetunnel config setup`) for everything not set there:
+//!
+//! ```no_run
+//! # fn main() -> livetunnel::Result<()> {
+//! let tunnel = livetunnel::Tunnel::builder()
+//!     .host("deploy@example.com")
+//!     .directory("public")
+//!     .spawn()?;
+//! println!("Sharing at {}", tunnel.url().unwrap_or("the configured domain"));
+//! tunnel.stop()
+//! # }
+//! ```
+
+mod app;
+mod askpass;
+mod backup;
+mod challenge;
+mod control;
+mod daemon;
+mod desktop;
+mod dns;
+mod encryption;
+mod endpoint;
+mod error;
+mod external;
+mod heartbeat;
+mod host;
+mod keys;
+mod linelog;
+mod logging;
+mod login;
+mod probe;
+mod project;
+mod redirects;
+mod relay;
+mod schedule;
+mod secrets;
+mod server;
+mod signed;
+mod ssh_config;
+mod stats;
+mod tunnel;
+mod vault;
+mod watch;
+mod watermark;
+mod webhooks;
+
+/// Programs sharing through [`Tunnel`] with password logins (`password_auth`) call it first thing
+/// in `main`, ssh runs the current executable to ask for the password.
+pub use askpass::answer as answer_askpass;
+pub use error::{Error, Result};
+pub use tunnel::{Tunnel, TunnelBuilder};
+
+use crate::{
+    app::{App, DeployMethod},
+    backup::restore_config,
+    host::{parse_host, HostSpec},
+    logging::OutputFormat,
+    relay::parse_size,
+};
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+use humantime::parse_duration;
+use tokio::sync::Notify;
+use tracing::warn;
+
+#[derive(Parser, Debug, Default)]
+#[command(
+    version,
+    about,
+    long_about = "Tunnel your local files to your own Webserver"
+)]
+pub(crate) struct Cli {
+    /// Never prompt: fail instead of asking and log plain lines instead of spinners (for CI/cron)
+    #[arg(long, visible_alias = "yes", global = true)]
+    non_interactive: bool,
+
+    /// Config file to use instead of the default location
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Print what happens in detail as plain lines (-vv for everything)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Only print warnings, errors and the public URL
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// What to print on stdout: spinners for humans, or one JSON event per line for scripts
+    #[arg(long, value_enum, default_value_t, global = true)]
+    output: OutputFormat,
+
+    /// Log SSH events, command output and the server to this file as well, rotated at 10 MB
+    /// (default: log_file from config)
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    #[command(flatten)]
+    share: ShareArgs,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+// Override the stored config for a single run, for every command that connects:
+#[derive(Args, Debug, Clone, Default)]
+pub(crate) struct ConnectionArgs {
+    /// SSH Host to connect to, [user@]host[:port] (default: from config)
+    #[arg(long, global = true, value_parser = parse_host)]
+    host: Option<HostSpec>,
+
+    /// SSH user (default: from config or --host)
+    #[arg(long, global = true)]
+    user: Option<String>,
+
+    /// SSH Keyfile (default: from config)
+    #[arg(long, global = true)]
+    keyfile: Option<PathBuf>,
+
+    /// Local Port to serve on (default: from config)
+    #[arg(long, global = true)]
+    local_port: Option<u16>,
+
+    /// Remote Port to forward to (default: from config)
+    #[arg(long, global = true)]
+    remote_port: Option<u16>,
+}
+
+// Sharing is the default, so these work with and without `livetunnel share`:
+#[derive(Args, Debug, Clone, Default)]
+pub(crate) struct ShareArgs {
+    /// Reconfigure the app via the config assistant
+    #[arg(long)]
+    reconfigure: bool,
+
+    /// Require credentials to access the hosted site
+    #[arg(short, long)]
+    secure: bool,
+
+    /// Require a generated username and password for this run only
+    #[arg(long)]
+    secure_auto: bool,
+
+    /// Verify the remote reverse proxy routes to the forward before serving
+    #[arg(long)]
+    verify: bool,
+
+    /// Request the public URL through every hop once serving, and tell which one fails
+    #[arg(long)]
+    check: bool,
+
+    /// Stop sharing once this much data went through the tunnel (e.g. 10G)
+    #[arg(long, value_parser = parse_size)]
+    transfer_cap: Option<u64>,
+
+    /// Stop sharing after this long (e.g. 2h or 30m)
+    #[arg(long, value_parser = parse_duration)]
+    duration: Option<Duration>,
+
+    /// Let visitors upload files into the shared directory
+    #[arg(long)]
+    receive: bool,
+
+    /// Stop sharing after this many complete downloads of a file (builtin server only)
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_downloads: Option<u64>,
+
+    /// Don't restart the local server when it exits
+    #[arg(long)]
+    no_restart: bool,
+
+    /// Serve on a free local Port if the configured one is already taken
+    #[arg(long)]
+    any_local_port: bool,
+
+    /// Show a QR code of the public URL once the tunnel is up
+    #[arg(long)]
+    qr: bool,
+
+    /// Copy the public URL (and passwords entered for this run) to the clipboard
+    #[arg(long)]
+    copy: bool,
+
+    /// Show desktop notifications when the tunnel is up, when the SSH connection drops or
+    /// reconnects and before --duration ends (default: desktop_notifications from config)
+    #[arg(long)]
+    notify: bool,
+
+    /// Keep sharing in the background once the tunnel is up
+    #[arg(long)]
+    detach: bool,
+
+    /// Show a live dashboard instead of spinners: tunnel, server, requests and time left, with
+    /// keys to restart the server, add users, copy the URL and quit
+    #[arg(long, conflicts_with_all = ["detach", "non_interactive"])]
+    tui: bool,
+
+    // Set for the background process started by --detach, global because it's appended after a
+    // subcommand as well:
+    #[arg(long, hide = true, global = true)]
+    daemonized: bool,
+
+    /// Build the site with its generator (Hugo, Jekyll, Zola, mdBook) and share the output
+    #[arg(long, conflicts_with = "stdin")]
+    build: bool,
+
+    /// Sync the directory to deploy_path first and again on close, the reverse proxy serves that
+    /// copy while the tunnel is down (set up by `livetunnel setup-remote`)
+    #[arg(long)]
+    hybrid: bool,
+
+    /// Share what is piped in as a single file, e.g. `tar cz . | livetunnel --stdin`
+    #[arg(long, conflicts_with_all = ["directory", "detach"])]
+    stdin: bool,
+
+    /// File name for the content shared with --stdin
+    #[arg(long, requires = "stdin")]
+    name: Option<String>,
+
+    /// Which directory, or single file, to host (default: cwd)
+    directory: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum Commands {
+    /// Share the directory through the tunnel (the default)
+    Share(ShareArgs),
+    /// Install and configure a reverse proxy (nginx/Caddy) for livetunnel on the remote host
+    SetupRemote,
+    /// Check that the remote reverse proxy routes the domain to the forwarded port
+    Verify,
+    /// Copy the directory to the server over SSH (tar, rsync or sftp) instead of tunneling
+    Deploy {
+        /// Remote directory to copy into (default: from config)
+        #[arg(long)]
+        remote_path: Option<String>,
+        /// How to copy the files (default: from config, or tar)
+        #[arg(long, value_enum)]
+        method: Option<DeployMethod>,
+        /// Delete remote files that don't exist locally (rsync only)
+        #[arg(long)]
+        delete: bool,
+        /// Leave out files and directories matching this pattern, e.g. '*.log' (repeatable)
+        #[arg(long)]
+        exclude: Vec<String>,
+    },
+    /// Share a new temporary directory, deleted again when livetunnel closes
+    Scratch {
+        /// Open the directory in the file manager
+        #[arg(long)]
+        open: bool,
+    },
+    /// Measure latency and throughput of every hop between here and the public domain
+    Bench {
+        /// Size of the synthetic payload (e.g. 10M)
+        #[arg(long, default_value = "10M", value_parser = parse_size)]
+        size: u64,
+    },
+    /// Manage the stored configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Show what livetunnel shares in the background
+    Status,
+    /// List the shares running in the background, one per line
+    List,
+    /// Stop livetunnel running in the background
+    Stop {
+        /// PID of the share to stop (see `livetunnel list`)
+        pid: Option<u32>,
+        /// Stop every share running in the background
+        #[arg(long)]
+        all: bool,
+    },
+    /// Manage the users allowed to access secure shares
+    Users {
+        #[command(subcommand)]
+        command: UsersCommands,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum ConfigCommands {
+    /// Run the setup assistant without sharing afterwards
+    Setup,
+    /// Open the config file in the editor, it's checked before it's stored
+    Edit,
+    /// Print the location and content of the config file, without passwords and tokens
+    Show {
+        /// Print passwords, tokens and webhook URLs as well
+        #[arg(long)]
+        secrets: bool,
+    },
+    /// Print only the location of the config file
+    Path,
+    /// Change a single setting, e.g. `remote_port 8081` or `watch.command "npm run build"`
+    Set {
+        /// Name of the setting as in the config file, `section.name` for one in a section
+        key: String,
+        /// Numbers, `true`/`false` and `[...]` lists as in the config file, anything else as text
+        value: String,
+    },
+    /// Restore the config from one of the automatic backups
+    Restore,
+    /// Check the config for problems that would break a share, and how to fix them
+    Validate,
+    /// Encrypt the config and its backups with a passphrase, asked for whenever it's read
+    Encrypt,
+    /// Store the config in plain text again
+    Decrypt,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub(crate) enum UsersCommands {
+    /// Add a user, asking for the password
+    Add { username: String },
+    /// Remove a user
+    Remove { username: String },
+    /// List the configured users
+    List,
+    /// Change the password of a user
+    Passwd { username: String },
+}
+
+/// The `livetunnel` command: parses the arguments of the process and does what they ask for,
+/// exits with the code of the error if something fails.
+pub fn run_cli() {
+    // ssh runs us to answer its password prompt:
+    askpass::answer();
+
+    let mut cli = Cli::parse();
+
+    logging::init(
+        if cli.quiet {
+            -1
+        } else {
+            cli.verbose.min(2) as i8
+        },
+        cli.output,
+    );
+    if let Some(path) = &cli.log_file {
+        if let Err(err) = logging::log_to(path) {
+            Error::Config(format!(
+                "Could not open the log file '{}': {}",
+                path.display(),
+                err
+            ))
+            .exit();
+        }
+    }
+
+    if let Some(path) = &cli.config {
+        app::use_config_path(path.clone());
+    }
+
+    if cli.non_interactive {
+        let interactive = match &cli.command {
+            Some(Commands::Config { command }) => {
+                matches!(
+                    command,
+                    ConfigCommands::Setup | ConfigCommands::Edit | ConfigCommands::Restore
+                )
+            }
+            Some(Commands::Users { command }) => {
+                matches!(
+                    command,
+                    UsersCommands::Add { .. } | UsersCommands::Passwd { .. }
+                )
+            }
+            _ => false,
+        };
+
+        if interactive {
+            Error::Config(String::from(
+                "This command asks for input and can't run with --non-interactive.",
+            ))
+            .exit();
+        }
+    }
+
+    // Managing the config doesn't need an SSH connection:
+    match &cli.command {
+        Some(Commands::Config { command }) => {
+            let result = match command {
+                ConfigCommands::Setup => App::setup_config(),
+                ConfigCommands::Edit => App::edit_config(),
+                ConfigCommands::Show { secrets } => App::show_config(*secrets),
+                ConfigCommands::Path => App::print_config_path(),
+                ConfigCommands::Set { key, value } => App::set_config(key, value),
+                ConfigCommands::Restore => restore_config(),
+                ConfigCommands::Validate => App::validate_config(),
+                ConfigCommands::Encrypt => App::encrypt_config(),
+                ConfigCommands::Decrypt => App::decrypt_config(),
+            };
+            if let Err(err) = result {
+                err.exit();
+            }
+            return;
+        }
+        Some(Commands::Users { command }) => {
+            if let Err(err) = App::manage_users(command) {
+                err.exit();
+            }
+            return;
+        }
+        Some(Commands::Status) => return daemon::status(),
+        Some(Commands::List) => return daemon::list(),
+        Some(Commands::Stop { pid, all }) => {
+            if let Err(err) = daemon::stop(*pid, *all) {
+                err.exit();
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    if let Some(Commands::Share(share)) = &cli.command {
+        cli.share = share.clone();
+    }
+
+    let scratch_directory = match &cli.command {
+        Some(Commands::Scratch { .. }) => match app::create_scratch_directory() {
+            Ok(directory) => {
+                cli.share.directory = Some(directory.path().to_path_buf());
+                Some(directory)
+            }
+            Err(err) => err.exit(),
+        },
+        _ => None,
+    };
+
+    if cli.share.detach
+        && matches!(
+            cli.command,
+            Some(Commands::Share(_)) | Some(Commands::Scratch { .. }) | None
+        )
+    {
+        let result = daemon::detach();
+        // The background process created a scratch directory of its own:
+        drop(scratch_directory);
+        if let Err(err) = result {
+            err.exit();
+        }
+        return;
+    }
+
+    let end: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+    let end_app = end.clone();
+    let end_notify = Arc::new(Notify::new());
+    let end_notify_app = end_notify.clone();
+
+    if let Err(err) = ctrlc::set_handler(move || {
+        end.store(true, Ordering::Relaxed);
+        end_notify.notify_one();
+    }) {
+        warn!(
+            "Could not handle CTRL+C, livetunnel won't close cleanly: {}",
+            err
+        );
+    }
+
+    let command = cli.command.clone();
+    let mut app = match App::new(cli, end_app, end_notify_app) {
+        Ok(app) => app,
+        Err(err) => {
+            // Still empty, nothing to lose:
+            drop(scratch_directory);
+            err.exit()
+        }
+    };
+
+    let result = match command {
+        Some(Commands::SetupRemote) => app.setup_remote(),
+        Some(Commands::Verify) => app.verify(),
+        Some(Commands::Deploy {
+            remote_path,
+            method,
+            delete,
+            exclude,
+        }) => app.deploy(remote_path, method, delete, exclude),
+        Some(Commands::Bench { size }) => app.bench(size),
+        Some(Commands::Scratch { open }) => match scratch_directory {
+            Some(directory) => app.scratch(directory, open),
+            None => unreachable!("created for the scratch command"),
+        },
+        Some(Commands::Config { .. })
+        | Some(Commands::Users { .. })
+        | Some(Commands::Status)
+        | Some(Commands::List)
+        | Some(Commands::Stop { .. }) => {
+            unreachable!("handled before connecting")
+        }
+        Some(Commands::Share(_)) | None => app.run(),
+    };
+    app.close();
+
+    if let Err(err) = result {
+        err.exit();
+    }
+}
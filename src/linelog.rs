@@ -0,0 +1,82 @@
+use std::{io, sync::Mutex};
+
+use indicatif::TermLike;
+
+/// Draw target for progress bars that prints every changed line once instead of redrawing it,
+/// for logs of CI pipelines and cron jobs.
+#[derive(Debug, Default)]
+pub struct LineLog {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Lines of the last drawn frame, to only print what changed since:
+    printed: Vec<String>,
+    frame: Vec<String>,
+    pending: String,
+}
+
+impl TermLike for LineLog {
+    fn width(&self) -> u16 {
+        u16::MAX
+    }
+
+    fn move_cursor_up(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_down(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_right(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn move_cursor_left(&self, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write_line(&self, s: &str) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let line = format!("{}{}", state.pending, s);
+        state.pending.clear();
+        state.frame.push(line);
+        Ok(())
+    }
+
+    fn write_str(&self, s: &str) -> io::Result<()> {
+        self.state.lock().unwrap().pending.push_str(s);
+        Ok(())
+    }
+
+    fn clear_line(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // indicatif flushes once per frame:
+    fn flush(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let pending = std::mem::take(&mut state.pending);
+        if !pending.trim().is_empty() {
+            state.frame.push(pending);
+        }
+
+        let frame = std::mem::take(&mut state.frame);
+        for (i, line) in frame.iter().enumerate() {
+            let line = line.trim_end();
+            if !line.is_empty()
+                && state.printed.get(i).map(|printed| printed.trim_end()) != Some(line)
+            {
+                println!("{}", line);
+            }
+        }
+
+        // Nothing drawn (e.g. a cleared bar) shouldn't print everything again next time:
+        if !frame.is_empty() {
+            state.printed = frame;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    ffi::OsString,
+    fmt::{Debug, Write as _},
+    fs::{rename, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::SystemTime,
+};
+
+use clap::ValueEnum;
+use humantime::format_rfc3339_millis;
+use serde_json::{json, Value};
+use tracing::{
+    debug,
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, subscriber, Event, Level, Metadata, Subscriber,
+};
+
+// A log file is rotated once it grows past this, and this many old ones are kept next to it
+// (".1" is the newest):
+const LOG_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const LOG_KEEP: u32 = 3;
+
+// -q = -1, default = 0, -v = 1, -vv = 2:
+static VERBOSITY: OnceLock<i8> = OnceLock::new();
+static OUTPUT: OnceLock<OutputFormat> = OnceLock::new();
+static LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+// Used as library (Tunnel), the application decides what is printed:
+static EMBEDDED: AtomicBool = AtomicBool::new(false);
+// Status lines collected instead of printed while the dashboard owns the terminal (--tui):
+static CAPTURED: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// What goes to stdout (--output).
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Spinners and status lines
+    #[default]
+    Human,
+    /// One JSON object per line for scripts, status lines go to stderr
+    Json,
+}
+
+/// Prints livetunnel's events for humans: `info!` as "ℹ", with `success = true` as "✓" and
+/// warnings and errors as "❗", like the spinners do. `debug!` and `trace!` only show up with
+/// -v/-vv (on stderr) and in the log file, with all their fields.
+struct Logger {
+    human: LevelFilter,
+    // stdout is kept for JSON events (--output json):
+    stderr_only: bool,
+}
+
+/// Installs the logger for the whole process, before anything is printed.
+pub fn init(verbosity: i8, output: OutputFormat) {
+    let _ = VERBOSITY.set(verbosity);
+    let _ = OUTPUT.set(output);
+    let human = match verbosity {
+        ..=-1 => LevelFilter::WARN,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let _ = subscriber::set_global_default(Logger {
+        human,
+        stderr_only: output == OutputFormat::Json,
+    });
+}
+
+/// Prints nothing from now on, for shares started through [`crate::Tunnel`]: no spinners and
+/// nothing shown, events only reach the `tracing` subscriber of the application, if it has one.
+pub fn embed() {
+    let _ = VERBOSITY.set(-1);
+    EMBEDDED.store(true, Ordering::Relaxed);
+}
+
+/// -q: only warnings, errors and what was asked for are printed, no spinners.
+pub fn quiet() -> bool {
+    VERBOSITY.get().is_some_and(|verbosity| *verbosity < 0)
+}
+
+/// -v or -vv: debug events are printed between plain lines instead of spinners.
+pub fn verbose() -> bool {
+    VERBOSITY.get().is_some_and(|verbosity| *verbosity > 0)
+}
+
+/// --output json: stdout only carries JSON events.
+pub fn json() -> bool {
+    OUTPUT.get() == Some(&OutputFormat::Json)
+}
+
+/// Prints `{"event": name, ...fields}` as one line on stdout with --output json, e.g.
+/// `emit("url", json!({ "url": url }))`. Events: config_loaded, ssh_connected,
+/// forward_established, server_started, server_paused, url, error and shutdown.
+pub fn emit(name: &str, fields: Value) {
+    if !json() {
+        return;
+    }
+    let mut event = json!({ "event": name });
+    if let (Some(event), Value::Object(fields)) = (event.as_object_mut(), fields) {
+        event.extend(fields);
+    }
+    println!("{}", event);
+}
+
+/// Prints what was asked for (a QR code, a generated login) on stdout, on stderr with --output
+/// json. Never logged.
+pub fn show(text: &str) {
+    if EMBEDDED.load(Ordering::Relaxed) {
+        return;
+    }
+    if json() {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+/// Collects status lines instead of printing them (`on`), until turned off again.
+pub fn capture(on: bool) {
+    *CAPTURED.lock().unwrap() = on.then(Vec::new);
+}
+
+/// The status lines collected since the last call.
+pub fn captured() -> Vec<String> {
+    CAPTURED
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map(std::mem::take)
+        .unwrap_or_default()
+}
+
+/// Writes every event from now on to `path` as well, down to `debug!` (`trace!` with -vv).
+pub fn log_to(path: &Path) -> io::Result<()> {
+    let file = LogFile::open(path.to_path_buf())?;
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Logs what a command printed, line by line, the spinners only tell whether it worked.
+pub fn command_output(command: &str, output: &Output) {
+    debug!("'{}' exited with {}", command, output.status);
+    for (stream, bytes) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        for line in String::from_utf8_lossy(bytes).lines() {
+            debug!(stream, "{}", line);
+        }
+    }
+}
+
+impl Logger {
+    fn file_level(&self) -> LevelFilter {
+        self.human.max(LevelFilter::DEBUG)
+    }
+
+    // Dependencies (axum, hyper) log on their own, that's not for livetunnel's users:
+    fn ours(metadata: &Metadata<'_>) -> bool {
+        metadata.is_event() && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+}
+
+impl Subscriber for Logger {
+    // Asked on every event, a log file can be opened after the first ones:
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> subscriber::Interest {
+        if Self::ours(metadata) {
+            subscriber::Interest::sometimes()
+        } else {
+            subscriber::Interest::never()
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if !Self::ours(metadata) {
+            return false;
+        }
+        let level = metadata.level();
+        *level <= self.human || (*level <= self.file_level() && LOG_FILE.lock().unwrap().is_some())
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(self.file_level())
+    }
+
+    // livetunnel only logs events, spans are never enabled:
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let metadata = event.metadata();
+        let level = *metadata.level();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        if level <= self.human {
+            let line = match level {
+                Level::ERROR | Level::WARN => format!("❗{}", fields.message),
+                Level::INFO if fields.success => format!("✓ {}", fields.message),
+                Level::INFO => format!("ℹ {}", fields.message),
+                _ => format!("  {}{}", fields.message, fields.rest),
+            };
+            if let Some(captured) = CAPTURED.lock().unwrap().as_mut() {
+                captured.push(line);
+            } else if self.stderr_only || level > Level::INFO {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+
+        if level <= self.file_level() {
+            if let Some(file) = LOG_FILE.lock().unwrap().as_mut() {
+                file.write(&format!(
+                    "{} {:<5} {}: {}{}\n",
+                    format_rfc3339_millis(SystemTime::now()),
+                    level,
+                    metadata.target(),
+                    fields.message,
+                    fields.rest
+                ));
+            }
+        }
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+// The message of an event and its other fields as " name=value":
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+    success: bool,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == "success" {
+            self.success = value;
+        } else {
+            let _ = write!(self.rest, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path, file, size })
+    }
+
+    // Losing a line is better than stopping a share over its log:
+    fn write(&mut self, line: &str) {
+        if self.size + line.len() as u64 > LOG_MAX_SIZE && self.size > 0 {
+            if let Ok(rotated) = self.rotate() {
+                *self = rotated;
+            }
+        }
+        if self.file.write_all(line.as_bytes()).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    // livetunnel.log.2 -> livetunnel.log.3, livetunnel.log.1 -> livetunnel.log.2,
+    // livetunnel.log -> livetunnel.log.1, the oldest one is replaced:
+    fn rotate(&self) -> io::Result<LogFile> {
+        for number in (1..LOG_KEEP).rev() {
+            let _ = rename(
+                numbered(&self.path, number),
+                numbered(&self.path, number + 1),
+            );
+        }
+        rename(&self.path, numbered(&self.path, 1))?;
+        LogFile::open(self.path.clone())
+    }
+}
+
+fn numbered(path: &Path, number: u32) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(format!(".{}", number));
+    PathBuf::from(name)
+}
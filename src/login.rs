@@ -0,0 +1,129 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::signed::{now, unexpired, Signer};
+
+/// Logins of the builtin server's login page: cookies that name the user, expire and are signed
+/// with a key of the share, so nothing is stored per login but the ones that logged out, until
+/// they would have expired anyway.
+#[derive(Debug)]
+pub struct Sessions {
+    signer: Signer,
+    lifetime: Duration,
+    // Ids of ended sessions, with when they expire:
+    ended: Mutex<HashMap<String, u64>>,
+}
+
+impl Sessions {
+    pub fn new(lifetime: Duration) -> Result<Sessions, String> {
+        Ok(Sessions {
+            signer: Signer::new()?,
+            lifetime,
+            ended: Mutex::default(),
+        })
+    }
+
+    /// How long a login lasts.
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    /// The cookie value of a new login of `user`.
+    pub fn start(&self, user: &str) -> String {
+        let mut id = [0; 16];
+        let _ = getrandom::getrandom(&mut id);
+        self.signer.sign(&format!(
+            "s.{}.{}.{}",
+            now() + self.lifetime.as_secs(),
+            URL_SAFE_NO_PAD.encode(id),
+            URL_SAFE_NO_PAD.encode(user)
+        ))
+    }
+
+    /// The user of a cookie of [`Sessions::start`] that neither expired nor ended.
+    pub fn user(&self, cookie: &str) -> Option<String> {
+        let (expires, id, user) = self.parts(cookie)?;
+        if self.ended.lock().unwrap().contains_key(id) {
+            return None;
+        }
+        let user = String::from_utf8(URL_SAFE_NO_PAD.decode(user).ok()?).ok()?;
+        unexpired(expires).then_some(user)
+    }
+
+    /// Logs the session of a cookie out, it doesn't let anybody in anymore.
+    pub fn end(&self, cookie: &str) {
+        let Some((expires, id, _)) = self.parts(cookie) else {
+            return;
+        };
+        let Ok(expires) = expires.parse::<u64>() else {
+            return;
+        };
+        let mut ended = self.ended.lock().unwrap();
+        let now = now();
+        ended.retain(|_, expires| *expires > now);
+        ended.insert(id.to_string(), expires);
+    }
+
+    // (expires, id, user) of a cookie signed by this:
+    fn parts<'a>(&self, cookie: &'a str) -> Option<(&'a str, &'a str, &'a str)> {
+        let mut parts = self.signer.verify(cookie)?.split('.');
+        if parts.next() != Some("s") {
+            return None;
+        }
+        Some((parts.next()?, parts.next()?, parts.next()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn for_an_hour() -> Sessions {
+        Sessions::new(Duration::from_secs(3600)).unwrap()
+    }
+
+    #[test]
+    fn ending_a_session_leaves_the_others() {
+        let sessions = for_an_hour();
+        let laptop = sessions.start("zoë");
+        let phone = sessions.start("zoë");
+        assert_ne!(laptop, phone);
+        assert_eq!(sessions.user(&laptop).as_deref(), Some("zoë"));
+
+        sessions.end(&laptop);
+        assert_eq!(sessions.user(&laptop), None);
+        assert_eq!(sessions.user(&phone).as_deref(), Some("zoë"));
+    }
+
+    #[test]
+    fn only_own_unchanged_logins_count() {
+        let sessions = for_an_hour();
+        let cookie = sessions.start("mallory");
+        assert_eq!(for_an_hour().user(&cookie), None);
+
+        // A later expiry or another user breaks the signature:
+        let (expires, _) = cookie[2..].split_once('.').unwrap();
+        let later = cookie.replacen(expires, &(now() + 10_000_000).to_string(), 1);
+        assert_eq!(sessions.user(&later), None);
+        let admin = cookie.replacen(
+            &URL_SAFE_NO_PAD.encode("mallory"),
+            &URL_SAFE_NO_PAD.encode("admin"),
+            1,
+        );
+        assert_eq!(sessions.user(&admin), None);
+
+        // Other tokens of the same key (a challenge pass) aren't logins:
+        let pass = sessions.signer.sign(&format!("p.{}", now() + 60));
+        assert_eq!(sessions.user(&pass), None);
+        sessions.end(&pass);
+        sessions.end("not a cookie");
+        assert_eq!(sessions.user(""), None);
+    }
+
+    #[test]
+    fn sessions_end_with_their_lifetime() {
+        let sessions = Sessions::new(Duration::ZERO).unwrap();
+        assert_eq!(sessions.user(&sessions.start("walter")), None);
+    }
+}
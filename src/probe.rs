@@ -0,0 +1,79 @@
+use crate::endpoint::{Endpoint, Stream};
+
+use std::{
+    io::{ErrorKind, Read, Result, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{sleep, spawn, JoinHandle},
+    time::Duration,
+};
+
+/// Minimal HTTP responder that answers every request on the local endpoint with a fixed body.
+///
+/// Used to check the whole chain (reverse proxy -> SSH forward -> local port) without
+/// exposing any of the shared files.
+pub struct Probe {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Probe {
+    pub fn start(endpoint: &Endpoint, body: Arc<[u8]>) -> Result<Self> {
+        let listener = endpoint.listen()?;
+        listener.set_nonblocking(true)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_thread = stop.clone();
+
+        let handle = spawn(move || {
+            while !stop_thread.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok(stream) => {
+                        let body = body.clone();
+                        spawn(move || {
+                            // The client hanging up early is not our problem:
+                            let _ = respond(stream, &body);
+                        });
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        sleep(Duration::from_millis(50));
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(Probe { stop, handle })
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+}
+
+fn respond(mut stream: Stream, body: &[u8]) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    // Read until the end of the request headers, we don't care about their content:
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
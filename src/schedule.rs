@@ -0,0 +1,118 @@
+use jiff::{
+    civil::{Time, Weekday},
+    tz::TimeZone,
+    Zoned,
+};
+
+/// When a share is served, e.g. weekdays from 08:00 to 20:00 in a given time zone. Outside of
+/// it the tunnel stays up, but visitors only get a page that tells when it's back.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    days: Vec<Weekday>,
+    from: Time,
+    // End of the day without:
+    until: Option<Time>,
+    timezone: TimeZone,
+}
+
+impl Schedule {
+    /// `days` as names ("mon", "tuesday") or ranges ("mon-fri"), every day if empty. `from` and
+    /// `until` as "HH:MM", an `until` before `from` ends the next day. `timezone` as IANA name
+    /// ("Europe/Berlin"), the system's one without.
+    pub fn parse(
+        days: &[String],
+        from: Option<&str>,
+        until: Option<&str>,
+        timezone: Option<&str>,
+    ) -> Result<Schedule, String> {
+        let mut weekdays = vec![];
+        for entry in days {
+            match entry.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (weekday(first)?, weekday(last)?);
+                    weekdays.extend(first.cycle_forward().take(first.until(last) as usize + 1));
+                }
+                None => weekdays.push(weekday(entry)?),
+            }
+        }
+        if weekdays.is_empty() {
+            weekdays.extend(Weekday::Monday.cycle_forward().take(7));
+        }
+
+        let time = |time: &str| {
+            time.trim()
+                .parse::<Time>()
+                .map_err(|_| format!("'{}' is no time of day, use e.g. \"08:00\"", time))
+        };
+        let timezone = match timezone {
+            Some(name) => TimeZone::get(name).map_err(|_| {
+                format!(
+                    "'{}' is no known time zone, use e.g. \"Europe/Berlin\"",
+                    name
+                )
+            })?,
+            None => TimeZone::system(),
+        };
+
+        Ok(Schedule {
+            days: weekdays,
+            from: from.map(time).transpose()?.unwrap_or(Time::midnight()),
+            until: until.map(time).transpose()?,
+            timezone,
+        })
+    }
+
+    /// None while the share is served, otherwise when it's served again.
+    pub fn closed_until(&self, now: &Zoned) -> Option<Zoned> {
+        let now = now.with_time_zone(self.timezone.clone());
+        if self.is_open(&now) {
+            return None;
+        }
+
+        let mut date = now.date();
+        for _ in 0..8 {
+            if self.days.contains(&date.weekday()) {
+                if let Ok(opening) = date.to_datetime(self.from).to_zoned(self.timezone.clone()) {
+                    if opening > now {
+                        return Some(opening);
+                    }
+                }
+            }
+            date = date.tomorrow().ok()?;
+        }
+        None
+    }
+
+    fn is_open(&self, now: &Zoned) -> bool {
+        let (day, time) = (now.weekday(), now.time());
+        match self.until {
+            // Over midnight, e.g. 22:00 to 06:00:
+            Some(until) if until <= self.from => {
+                (self.days.contains(&day) && time >= self.from)
+                    || (self.days.contains(&day.previous()) && time < until)
+            }
+            Some(until) => self.days.contains(&day) && time >= self.from && time < until,
+            None => self.days.contains(&day) && time >= self.from,
+        }
+    }
+}
+
+fn weekday(name: &str) -> Result<Weekday, String> {
+    let name = name.trim().to_ascii_lowercase();
+    let day = match name.get(..3).unwrap_or_default() {
+        "mon" => Weekday::Monday,
+        "tue" => Weekday::Tuesday,
+        "wed" => Weekday::Wednesday,
+        "thu" => Weekday::Thursday,
+        "fri" => Weekday::Friday,
+        "sat" => Weekday::Saturday,
+        "sun" => Weekday::Sunday,
+        _ => {
+            return Err(format!(
+                "'{}' is no day of the week, use e.g. \"mon\"",
+                name
+            ))
+        }
+    };
+    Ok(day)
+}
@@ -0,0 +1,52 @@
+use crate::app;
+
+use keyring::Entry;
+
+/// Written to the config in place of a secret kept in the OS keyring, e.g. "keyring:user/alice".
+pub const PREFIX: &str = "keyring:";
+const SERVICE: &str = "livetunnel";
+
+/// Whether a config value refers to the keyring instead of being the secret itself.
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+/// The secret behind a config value: what the keyring has for a reference, anything else as it
+/// is.
+pub fn resolve(value: &str) -> Result<String, String> {
+    match value.strip_prefix(PREFIX) {
+        Some(name) => entry(name)?.get_password().map_err(|err| err.to_string()),
+        None => Ok(String::from(value)),
+    }
+}
+
+/// Puts `secret` into the keyring as `name` and returns the reference to store instead.
+pub fn store(name: &str, secret: &str) -> Result<String, String> {
+    entry(name)?
+        .set_password(secret)
+        .map_err(|err| err.to_string())?;
+    Ok(format!("{}{}", PREFIX, name))
+}
+
+/// What the keyring has for `name`, None if it has nothing.
+pub fn get(name: &str) -> Result<Option<String>, String> {
+    match entry(name)?.get_password() {
+        Ok(secret) => Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Removes `name` from the keyring, if it's there.
+pub fn forget(name: &str) -> Result<(), String> {
+    match entry(name)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+// Scoped to the config file, so files used with --config don't share their secrets:
+fn entry(name: &str) -> Result<Entry, String> {
+    let config = app::config_path().map_err(|err| err.to_string())?;
+    Entry::new(SERVICE, &format!("{} ({})", name, config.display())).map_err(|err| err.to_string())
+}
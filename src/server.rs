@@ -0,0 +1,3010 @@
+use crate::{
+    challenge::{Challenge, PASS_TIME},
+    endpoint::Endpoint,
+    login::Sessions,
+    redirects::Redirects,
+    schedule::Schedule,
+    stats::AccessStats,
+    watermark::{Kind, Watermark},
+};
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    future::Future,
+    io::{self, BufWriter, IoSlice, Write},
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes, HttpBody},
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Form, FromRequest, FromRequestParts, Multipart, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{Html, IntoResponse, Redirect, Response},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{write::GzEncoder, Compression};
+use globset::GlobSet;
+use http_body::{Frame, SizeHint};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto::Builder as ConnectionBuilder,
+    service::TowerToHyperService,
+};
+use jiff::Zoned;
+use percent_encoding::{
+    percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS, NON_ALPHANUMERIC,
+};
+use sha2::{Digest, Sha512};
+use tar::Builder;
+use tokio::{
+    fs::{metadata, read_dir, remove_file, OpenOptions},
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpListener,
+    runtime::Runtime,
+    sync::{
+        broadcast, mpsc,
+        oneshot::{channel, Receiver, Sender},
+        watch, Notify,
+    },
+    task::{spawn_blocking, JoinHandle},
+    time::{interval, sleep, Instant, Sleep},
+};
+use tower_http::services::{ServeDir, ServeFile};
+use tracing::{debug, warn};
+
+#[cfg(unix)]
+use crate::endpoint::bind_socket;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+// Characters escaped in the links of a directory listing:
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+// Where pages with live reload open their websocket, never a file of the share:
+const RELOAD_PATH: &str = "/.livetunnel/reload";
+// Put into HTML pages with live reload, reloads once the server says so and reconnects when the
+// connection drops (e.g. while the tunnel reconnects):
+const RELOAD_SCRIPT: &str = r#"<script>(function () {
+  var scheme = location.protocol === "https:" ? "wss://" : "ws://";
+  function connect() {
+    var socket = new WebSocket(scheme + location.host + "/.livetunnel/reload");
+    socket.onmessage = function () { location.reload(); };
+    socket.onclose = function () { setTimeout(connect, 2000); };
+  }
+  connect();
+})();</script>"#;
+// How often the open reload sockets are pinged, less than any sensible idle timeout:
+const RELOAD_PING: Duration = Duration::from_secs(20);
+// Header and idle timeout of the [`Waker`], it only answers with a short page:
+const WAKER_TIMEOUT: Duration = Duration::from_secs(10);
+// HTML files up to this size get the snippets, larger ones are sent as they are:
+const SNIPPETS_MAX_SIZE: usize = 16 * 1024 * 1024;
+// Where the configured icons are served, besides the paths browsers ask for without a link:
+const ICON_PATH: &str = "/.livetunnel/favicon";
+const TOUCH_ICON_PATH: &str = "/.livetunnel/apple-touch-icon";
+// Where the page of a challenge sends the solution, never a file of the share:
+const CHALLENGE_PATH: &str = "/.livetunnel/challenge";
+// The cookie of a solved challenge:
+const PASS_COOKIE: &str = "livetunnel_pass";
+// The login page sends the credentials to the first, the other ends the session of the request:
+const LOGIN_PATH: &str = "/.livetunnel/login";
+const LOGOUT_PATH: &str = "/.livetunnel/logout";
+// The cookie of a login:
+const SESSION_COOKIE: &str = "livetunnel_session";
+// Bytes the form of the login page or of a challenge may have:
+const FORM_LIMIT: usize = 16 * 1024;
+const LOGIN_PAGE: &str = r#"<!DOCTYPE html><html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>Log in</title></head><body><h1>Log in</h1>{problem}<form method="post" action="/.livetunnel/login"><input type="hidden" name="back" value="{back}"><p><label>User <input name="user" autocomplete="username" autocapitalize="none" required autofocus></label></p><p><label>Password <input type="password" name="password" autocomplete="current-password" required></label></p><p><button>Log in</button></p></form></body></html>"#;
+// Tries nonces until the SHA-256 has {bits} leading zero bits. crypto.subtle only exists on https
+// (and localhost):
+const CHALLENGE_PAGE: &str = r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>Checking your browser</title></head><body><h1>Checking your browser</h1><p id="status">This takes a moment.</p><noscript><p>It needs JavaScript.</p></noscript><form method="post" action="/.livetunnel/challenge"><input type="hidden" name="challenge" value="{challenge}"><input type="hidden" name="nonce"><input type="hidden" name="back" value="{back}"></form><script>(async function () {
+  var form = document.forms[0];
+  if (!window.crypto || !crypto.subtle) {
+    document.getElementById("status").textContent = "It needs a secure (https) connection.";
+    return;
+  }
+  var encoder = new TextEncoder();
+  for (var nonce = 0; ; nonce++) {
+    var data = encoder.encode(form.challenge.value + ":" + nonce);
+    var hash = new Uint8Array(await crypto.subtle.digest("SHA-256", data));
+    var zeros = 0;
+    for (var i = 0; i < hash.length; i++) {
+      zeros += hash[i] ? Math.clz32(hash[i]) - 24 : 8;
+      if (hash[i]) break;
+    }
+    if (zeros >= {bits}) break;
+  }
+  form.nonce.value = nonce;
+  form.submit();
+})();</script></body></html>"#;
+// Larger images and PDFs are refused with a watermark set, rather than sent without one:
+const WATERMARK_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+// Characters escaped in the file name of a download (RFC 5987):
+const FILENAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'.')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'~');
+
+struct Shared {
+    root: PathBuf,
+    // Name of the only file in `root` that is shared, when a single file is:
+    file: Option<PathBuf>,
+    // (username, hex encoded sha512 of the password), same as `Config::users`:
+    users: RwLock<Vec<(String, String)>>,
+    downloads: Option<Arc<DownloadLimit>>,
+    uploads: Option<Uploads>,
+    folder_users: Vec<(PathBuf, Vec<String>)>,
+    public: Option<GlobSet>,
+    secret_header: Option<(String, String)>,
+    sessions: Option<Arc<Sessions>>,
+    challenge: Option<Arc<Challenge>>,
+    referrers: Option<GlobSet>,
+    live_reload: Option<LiveReload>,
+    favicon: Option<PathBuf>,
+    touch_icon: Option<PathBuf>,
+    snippets: Snippets,
+    watermark: Option<Arc<Watermark>>,
+    asset_hashes: Option<AssetHashes>,
+    pretty_urls: bool,
+    rules: Redirects,
+    redirects: Option<RedirectsFile>,
+    stats: Arc<AccessStats>,
+    max_body: Option<u64>,
+    header_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    client_limit: Option<Arc<ClientLimit>>,
+    schedule: Option<Schedule>,
+}
+
+/// What a share serves besides files, the same for every (re)start of the server.
+#[derive(Clone, Debug, Default)]
+pub struct ServeOptions {
+    /// Full downloads count against it (`--max-downloads`).
+    pub downloads: Option<Arc<DownloadLimit>>,
+    /// Directories take uploads (`--receive`).
+    pub uploads: Option<Uploads>,
+    /// Directories (relative to the shared one) only the listed users may open, including their
+    /// subdirectories. The deepest one decides, all others are open to every user.
+    pub folder_users: Vec<(PathBuf, Vec<String>)>,
+    /// URL paths (decoded) that open without credentials, e.g. assets the protected pages need.
+    pub public: Option<GlobSet>,
+    /// (name, hex encoded sha512 of the token) of a header that opens the share like a login,
+    /// for scripts. Required with it, even without users.
+    pub secret_header: Option<(String, String)>,
+    /// Users log in on a page, which sets a cookie, rather than with basic auth (which still
+    /// works, e.g. for scripts).
+    pub sessions: Option<Arc<Sessions>>,
+    /// Visitors solve it before they get anything, requests with the secret header skip it.
+    pub challenge: Option<Arc<Challenge>>,
+    /// Referrers (or origins, with a `/` appended) requests need to come from, besides the share
+    /// itself, e.g. the site it's embedded in. Requests with the secret header don't.
+    pub referrers: Option<GlobSet>,
+    /// HTML pages reload on [`LiveReload::reload`].
+    pub live_reload: Option<LiveReload>,
+    /// Served as the icon of every page, instead of the share's own (`favicon.ico` or a link).
+    pub favicon: Option<PathBuf>,
+    /// Served as `apple-touch-icon.png`, the icon of a page on home screens.
+    pub touch_icon: Option<PathBuf>,
+    /// HTML put into the head of every page, e.g. the script tag of an analytics service.
+    pub analytics: Option<String>,
+    /// Text of a dismissible banner on top of every page, e.g. that the preview expires.
+    pub banner: Option<String>,
+    /// Stamped on every image and PDF sent, with the user and client of the request.
+    pub watermark: Option<Arc<Watermark>>,
+    /// Pages link files with the hash of their content, which are then cached for good.
+    pub hashed_assets: bool,
+    /// `/about` serves `about.html` and directories their `index.html`, like static hosts do.
+    pub pretty_urls: bool,
+    /// The rules of the share's `_redirects` file apply, see [`Redirects`].
+    pub redirects_file: bool,
+    /// Rules of the config, which come before the ones of `_redirects`.
+    pub redirects: Redirects,
+    /// Counts requests and bytes per client.
+    pub stats: Arc<AccessStats>,
+    /// Bytes a request body may have, larger ones are refused with 413. Unlimited without.
+    pub max_body: Option<u64>,
+    /// How long a client may take to send the headers of a request.
+    pub header_timeout: Option<Duration>,
+    /// How long a connection may go without data moving in either direction.
+    pub idle_timeout: Option<Duration>,
+    /// Requests a single client may have open at once, more are refused with 429.
+    pub max_per_client: Option<u32>,
+    /// Outside of it, every request gets a page that tells when the share is back.
+    pub schedule: Option<Schedule>,
+}
+
+/// Where files may be uploaded to a share (`--receive`) and how large they may be.
+#[derive(Clone, Debug, Default)]
+pub struct Uploads {
+    /// Bytes per file, unlimited without.
+    pub max_size: Option<u64>,
+    /// Directories (relative to the shared one) that take uploads, including their
+    /// subdirectories. All of them do if empty.
+    pub folders: Vec<PathBuf>,
+}
+
+impl Uploads {
+    fn allowed(&self, relative: &Path) -> bool {
+        self.folders.is_empty()
+            || self
+                .folders
+                .iter()
+                .any(|folder| relative.starts_with(folder))
+    }
+}
+
+/// How many files may be downloaded from a share (`--max-downloads`). Kept outside the server,
+/// so a restarted one doesn't start counting from zero again.
+#[derive(Debug)]
+pub struct DownloadLimit {
+    max: u64,
+    // Started downloads, minus the ones that were cut off:
+    count: AtomicU64,
+    // Downloads still being sent:
+    active: AtomicU64,
+}
+
+impl DownloadLimit {
+    pub fn new(max: u64) -> DownloadLimit {
+        DownloadLimit {
+            max,
+            count: AtomicU64::new(0),
+            active: AtomicU64::new(0),
+        }
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Every allowed download went out completely.
+    pub fn used_up(&self) -> bool {
+        self.count.load(Ordering::SeqCst) >= self.max && self.active.load(Ordering::SeqCst) == 0
+    }
+
+    // Takes one download before serving, so parallel requests can't go over the limit:
+    fn take(&self) -> bool {
+        if self.count.fetch_add(1, Ordering::SeqCst) < self.max {
+            self.active.fetch_add(1, Ordering::SeqCst);
+            true
+        } else {
+            self.count.fetch_sub(1, Ordering::SeqCst);
+            false
+        }
+    }
+
+    fn finish(&self, completed: bool) {
+        if !completed {
+            self.count.fetch_sub(1, Ordering::SeqCst);
+        }
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// How many requests each client has open, until their responses are sent completely:
+struct ClientLimit {
+    max: u32,
+    open: Mutex<HashMap<String, u32>>,
+}
+
+impl ClientLimit {
+    fn take(limit: &Arc<ClientLimit>, client: &str) -> Option<ClientSlot> {
+        let mut open = limit.open.lock().unwrap();
+        let count = open.entry(client.to_string()).or_default();
+        if *count >= limit.max {
+            return None;
+        }
+        *count += 1;
+        Some(ClientSlot {
+            limit: limit.clone(),
+            client: client.to_string(),
+        })
+    }
+}
+
+// One open request of a client, given back when dropped:
+struct ClientSlot {
+    limit: Arc<ClientLimit>,
+    client: String,
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        let mut open = self.limit.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.client) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.client);
+            }
+        }
+    }
+}
+
+/// Tells the browsers showing HTML pages of a share to reload them (live reload while the
+/// watcher rebuilds). Kept outside the server, so pages stay connected across restarts.
+#[derive(Clone, Debug)]
+pub struct LiveReload {
+    sender: broadcast::Sender<()>,
+}
+
+impl LiveReload {
+    pub fn new() -> LiveReload {
+        LiveReload {
+            sender: broadcast::channel(1).0,
+        }
+    }
+
+    /// Reloads every connected page.
+    pub fn reload(&self) {
+        let _ = self.sender.send(());
+    }
+}
+
+// Response body of a counted download, it only counts once it was sent to the end:
+struct Download {
+    body: Body,
+    limit: Arc<DownloadLimit>,
+    // Bytes still to send, hyper stops polling once it has the announced length:
+    remaining: Option<u64>,
+    completed: bool,
+}
+
+impl Download {
+    fn new(body: Body, length: Option<u64>, limit: Arc<DownloadLimit>) -> Download {
+        let remaining = length.or(body.size_hint().exact());
+        Download {
+            body,
+            limit,
+            remaining,
+            completed: false,
+        }
+    }
+}
+
+impl HttpBody for Download {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let download = self.get_mut();
+        let frame = Pin::new(&mut download.body).poll_frame(cx);
+        match &frame {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let (Some(remaining), Some(data)) = (&mut download.remaining, frame.data_ref()) {
+                    *remaining = remaining.saturating_sub(data.len() as u64);
+                    download.completed |= *remaining == 0;
+                }
+            }
+            Poll::Ready(None) => download.completed = true,
+            _ => {}
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        self.limit.finish(self.completed);
+    }
+}
+
+// Response body that adds what was sent of it to the client's stats, and keeps the request
+// open for the client's limit until then:
+struct Counted {
+    body: Body,
+    stats: Arc<AccessStats>,
+    client: String,
+    sent: u64,
+    _slot: Option<ClientSlot>,
+}
+
+impl HttpBody for Counted {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let counted = self.get_mut();
+        let frame = Pin::new(&mut counted.body).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &frame {
+            counted.sent += frame.data_ref().map_or(0, |data| data.len() as u64);
+        }
+        frame
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        self.stats.sent(&self.client, self.sent);
+        self.stats.finished(&self.client);
+    }
+}
+
+/// Builtin static file server, so sharing works without miniserve installed.
+///
+/// Speaks HTTP/1.1 and HTTP/2 without TLS (h2c, with prior knowledge) on the same port, which
+/// one is told by the first bytes of a connection. TLS is up to the reverse proxy.
+///
+/// Serves `root` on `127.0.0.1:port` with directory listings (including hidden files) and
+/// optional basic auth against the sha512 hashed users from the config, some folders only for
+/// some of them and some paths for everyone, or a secret header. With [`Sessions`], users log in
+/// on a page instead, which sets a cookie. Visitors may have to solve a
+/// [`Challenge`] first, or come from certain sites. If `root` is a file,
+/// only that one is served. Full downloads of files (not listings or ranges) count against an
+/// optional [`DownloadLimit`], files carry an ETag and Last-Modified for conditional requests.
+/// Every directory can be downloaded as .tar.gz with `?archive`.
+/// With [`Uploads`], directories take files POSTed as `multipart/form-data` and their listings
+/// show a form for it. With [`LiveReload`], HTML pages get a script that reloads them on
+/// [`LiveReload::reload`]. A configured favicon and apple-touch-icon replace the share's own ones,
+/// pages get links to them, the analytics snippet and the banner if they are set. With a
+/// [`Watermark`], images and PDFs are stamped for each request and archives are off. With
+/// hashed assets, pages link the files of the share with `?v=<hash of the content>`, which are
+/// cached as immutable, while the pages themselves are always revalidated. Pretty URLs and the
+/// share's `_redirects` work like on Netlify, if enabled. Requests and the bytes sent are counted per client in
+/// [`AccessStats`], by the address the reverse proxy tells. Outside of a [`Schedule`] it only
+/// answers with a page that tells when it's back. Clients that take too long for the
+/// headers of a request, or let a connection stall, are cut off and counted there as well.
+pub struct Server {
+    shared: Arc<Shared>,
+    shutdown: Sender<()>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Server {
+    pub fn start(
+        runtime: &Runtime,
+        endpoint: &Endpoint,
+        root: &Path,
+        users: Vec<(String, String)>,
+        options: ServeOptions,
+    ) -> io::Result<Server> {
+        let root = root.canonicalize()?;
+        // A file is served alone, at `/<name>` and as download at `/`:
+        let (root, file) = match (root.is_file(), root.parent(), root.file_name()) {
+            (true, Some(parent), Some(name)) => (parent.to_path_buf(), Some(PathBuf::from(name))),
+            _ => (root, None),
+        };
+        let snippets = Snippets::new(&options);
+        let redirects = options.redirects_file.then(|| RedirectsFile {
+            path: root.join("_redirects"),
+            cached: Mutex::new(None),
+        });
+        let shared = Arc::new(Shared {
+            root,
+            file,
+            users: RwLock::new(users),
+            downloads: options.downloads,
+            uploads: options.uploads,
+            folder_users: options.folder_users,
+            public: options.public,
+            secret_header: options.secret_header,
+            sessions: options.sessions,
+            challenge: options.challenge,
+            referrers: options.referrers,
+            live_reload: options.live_reload,
+            favicon: options.favicon,
+            touch_icon: options.touch_icon,
+            snippets,
+            watermark: options.watermark,
+            asset_hashes: options.hashed_assets.then(AssetHashes::default),
+            pretty_urls: options.pretty_urls,
+            rules: options.redirects,
+            redirects,
+            stats: options.stats,
+            max_body: options.max_body,
+            header_timeout: options.header_timeout,
+            idle_timeout: options.idle_timeout,
+            client_limit: options.max_per_client.map(|max| {
+                Arc::new(ClientLimit {
+                    max,
+                    open: Mutex::new(HashMap::new()),
+                })
+            }),
+            schedule: options.schedule,
+        });
+        let listener = Incoming::bind(runtime, endpoint)?;
+
+        // For bodies without a Content-Length (the Content-Length of others is checked in `serve`).
+        // The forms of the login page and challenges have a fixed limit, see `read_form`:
+        let body_limit = match options.max_body {
+            Some(max) => DefaultBodyLimit::max(usize::try_from(max).unwrap_or(usize::MAX)),
+            None => DefaultBodyLimit::disable(),
+        };
+        let router = Router::new()
+            .fallback(serve)
+            .layer(body_limit)
+            .with_state(shared.clone());
+        let (shutdown, shutdown_received) = channel::<()>();
+        let handle = runtime.spawn(accept(
+            listener,
+            router,
+            (shared.header_timeout, shared.idle_timeout),
+            shared.stats.clone(),
+            shutdown_received,
+        ));
+
+        Ok(Server {
+            shared,
+            shutdown,
+            handle,
+        })
+    }
+
+    /// Replaces who may access the share, effective with the next request.
+    pub fn set_users(&self, users: Vec<(String, String)>) {
+        *self.shared.users.write().unwrap() = users;
+    }
+
+    /// Waits until the server stops on its own and returns why.
+    pub async fn wait(&mut self) -> String {
+        match (&mut self.handle).await {
+            Ok(Ok(())) => String::from("server stopped"),
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    pub fn stop(self, runtime: &Runtime) {
+        let _ = self.shutdown.send(());
+        let _ = runtime.block_on(self.handle);
+    }
+}
+
+/// Stands in for a paused server (`[serve] idle_pause`) on its endpoint. Every request gets a page
+/// that reloads in a moment, and wakes up [`Waker::woken`] to start the server again.
+pub struct Waker {
+    woken: Arc<Notify>,
+    shutdown: Sender<()>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl Waker {
+    pub fn start(
+        runtime: &Runtime,
+        endpoint: &Endpoint,
+        stats: Arc<AccessStats>,
+    ) -> io::Result<Waker> {
+        let listener = Incoming::bind(runtime, endpoint)?;
+        let woken = Arc::new(Notify::new());
+        let router = Router::new().fallback({
+            let woken = woken.clone();
+            move || async move {
+                woken.notify_one();
+                waking()
+            }
+        });
+        let (shutdown, shutdown_received) = channel::<()>();
+        let handle = runtime.spawn(accept(
+            listener,
+            router,
+            (Some(WAKER_TIMEOUT), Some(WAKER_TIMEOUT)),
+            stats,
+            shutdown_received,
+        ));
+
+        Ok(Waker {
+            woken,
+            shutdown,
+            handle,
+        })
+    }
+
+    /// Returns once a request came in.
+    pub async fn woken(&self) {
+        self.woken.notified().await
+    }
+
+    /// Frees the endpoint, for the server to take it again.
+    pub fn stop(self, runtime: &Runtime) {
+        let _ = self.shutdown.send(());
+        let _ = runtime.block_on(self.handle);
+    }
+}
+
+// The server is started again meanwhile, browsers try once more after Retry-After:
+fn waking() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, HeaderValue::from_static("2"))],
+        Html("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"2\"><title>Waking up</title></head><body><h1>This share is waking up</h1><p>It reloads in a moment.</p></body></html>"),
+    )
+        .into_response()
+}
+
+// What the server (or the waker) takes connections from, a socket file is removed with it:
+enum Incoming {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+// A connection of either, for hyper:
+trait Connection: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Connection for T {}
+
+impl Incoming {
+    fn bind(runtime: &Runtime, endpoint: &Endpoint) -> io::Result<Incoming> {
+        match endpoint {
+            Endpoint::Port(port) => Ok(Incoming::Tcp(
+                runtime.block_on(TcpListener::bind(("127.0.0.1", *port)))?,
+            )),
+            #[cfg(unix)]
+            Endpoint::Socket(path) => {
+                let _runtime = runtime.enter();
+                Ok(Incoming::Unix(
+                    bind_socket(path, |path| UnixListener::bind(path))?,
+                    path.clone(),
+                ))
+            }
+        }
+    }
+
+    async fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        match self {
+            Incoming::Tcp(listener) => Ok(Box::new(listener.accept().await?.0)),
+            #[cfg(unix)]
+            Incoming::Unix(listener, _) => Ok(Box::new(listener.accept().await?.0)),
+        }
+    }
+}
+
+impl Drop for Incoming {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Incoming::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// Serves the connections until `shutdown`, then lets the open ones finish their requests.
+// Connections are cut after (header, idle) timeouts, counted in `stats`:
+async fn accept(
+    listener: Incoming,
+    router: Router,
+    (header_timeout, idle_timeout): (Option<Duration>, Option<Duration>),
+    stats: Arc<AccessStats>,
+    shutdown: Receiver<()>,
+) -> io::Result<()> {
+    let mut builder = ConnectionBuilder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(header_timeout);
+    // Websockets (live reload) through HTTP/2:
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .enable_connect_protocol();
+
+    // Connections hold a receiver each, so the sender knows when all of them are closed:
+    let (stopping, stop) = watch::channel(());
+    tokio::pin!(shutdown);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(stream) => stream,
+                // e.g. out of file descriptors for a moment:
+                Err(_) => {
+                    sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let io = TokioIo::new(Stalled::new(stream, idle_timeout, stats.clone()));
+        let service = TowerToHyperService::new(router.clone());
+        let builder = builder.clone();
+        let stats = stats.clone();
+        let mut stop = stop.clone();
+        tokio::spawn(async move {
+            let connection = builder.serve_connection_with_upgrades(io, service);
+            tokio::pin!(connection);
+            loop {
+                tokio::select! {
+                    result = connection.as_mut() => {
+                        let header_timeout = result.is_err_and(|err| {
+                            err.downcast_ref::<hyper::Error>()
+                                .is_some_and(hyper::Error::is_timeout)
+                        });
+                        if header_timeout {
+                            stats.timed_out();
+                        }
+                        break;
+                    }
+                    _ = stop.changed() => connection.as_mut().graceful_shutdown(),
+                }
+            }
+        });
+    }
+
+    drop(stop);
+    let _ = stopping.send(());
+    stopping.closed().await;
+    Ok(())
+}
+
+// A connection that fails once no data moved in either direction for `timeout`, so stalled
+// clients (slowloris, or ones that stop reading) don't hold on to it:
+struct Stalled {
+    stream: Box<dyn Connection>,
+    timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    stats: Arc<AccessStats>,
+    timed_out: bool,
+}
+
+impl Stalled {
+    fn new(
+        stream: Box<dyn Connection>,
+        timeout: Option<Duration>,
+        stats: Arc<AccessStats>,
+    ) -> Stalled {
+        Stalled {
+            stream,
+            timeout,
+            deadline: timeout.map(|timeout| Box::pin(sleep(timeout))),
+            stats,
+            timed_out: false,
+        }
+    }
+
+    fn progressed(&mut self) {
+        if let (Some(deadline), Some(timeout)) = (&mut self.deadline, self.timeout) {
+            deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    // While waiting for the client, the deadline wakes the connection up once it passed:
+    fn check(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        let passed = self
+            .deadline
+            .as_mut()
+            .is_some_and(|deadline| deadline.as_mut().poll(cx).is_ready());
+        if passed {
+            if !self.timed_out {
+                self.timed_out = true;
+                self.stats.timed_out();
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the connection stalled",
+            ));
+        }
+        Ok(())
+    }
+
+    fn waited<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        match poll {
+            Poll::Pending => match self.check(cx) {
+                Ok(()) => Poll::Pending,
+                Err(err) => Poll::Ready(Err(err)),
+            },
+            ready => {
+                self.progressed();
+                ready
+            }
+        }
+    }
+}
+
+impl AsyncRead for Stalled {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let stalled = self.get_mut();
+        let poll = Pin::new(&mut stalled.stream).poll_read(cx, buf);
+        stalled.waited(cx, poll)
+    }
+}
+
+impl AsyncWrite for Stalled {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let stalled = self.get_mut();
+        let poll = Pin::new(&mut stalled.stream).poll_write(cx, buf);
+        stalled.waited(cx, poll)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let stalled = self.get_mut();
+        let poll = Pin::new(&mut stalled.stream).poll_write_vectored(cx, bufs);
+        stalled.waited(cx, poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let stalled = self.get_mut();
+        let poll = Pin::new(&mut stalled.stream).poll_flush(cx);
+        stalled.waited(cx, poll)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+async fn serve(State(shared): State<Arc<Shared>>, request: Request) -> Response {
+    let client = client_address(request.headers());
+    let requested = format!("{} {}", request.method(), request.uri().path());
+
+    let stats = shared.stats.clone();
+    let length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let slot = shared
+        .client_limit
+        .as_ref()
+        .map(|limit| ClientLimit::take(limit, &client));
+    let response = match (shared.max_body, length) {
+        (Some(max), Some(length)) if length > max => StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+        _ if matches!(slot, Some(None)) => {
+            debug!(
+                "Refused {} from {}, it has too many requests open",
+                requested, client
+            );
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, HeaderValue::from_static("1"))],
+            )
+                .into_response()
+        }
+        _ => respond(shared, request).await,
+    };
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        warn!(
+            "Refused {} from {}, its body is too large",
+            requested, client
+        );
+    }
+    stats.request(
+        &client,
+        &format!("{} {}", requested, response.status().as_u16()),
+    );
+    stats.opened(&client);
+    response.map(|body| {
+        Body::new(Counted {
+            body,
+            stats,
+            client,
+            sent: 0,
+            _slot: slot.flatten(),
+        })
+    })
+}
+
+// Requests come in through the tunnel, the reverse proxy tells who sent them. Only the last hop
+// of X-Forwarded-For is the proxy's own, clients send whatever they like before it. X-Real-IP
+// passes through some proxies (Caddy) as the client sent it, so it's only for ones without the
+// other:
+fn client_address(headers: &HeaderMap) -> String {
+    let last = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .next_back()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    last("x-forwarded-for")
+        .or_else(|| last("x-real-ip"))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+async fn respond(shared: Arc<Shared>, mut request: Request) -> Response {
+    if let Some(opening) = shared
+        .schedule
+        .as_ref()
+        .and_then(|schedule| schedule.closed_until(&Zoned::now()))
+    {
+        return offline(&opening);
+    }
+
+    let by_secret = shared.secret_header.as_ref().is_some_and(|(name, hash)| {
+        request
+            .headers()
+            .get(name.as_str())
+            .is_some_and(|token| format!("{:x}", Sha512::digest(token.as_bytes())) == *hash)
+    });
+    if let Some(referrers) = shared.referrers.as_ref().filter(|_| !by_secret) {
+        if let Err(from) = referred(referrers, request.headers()) {
+            warn!(
+                "Refused {} from {}, {}",
+                request.uri().path(),
+                client_address(request.headers()),
+                from
+            );
+            return (
+                StatusCode::FORBIDDEN,
+                "This share only opens from the site it's embedded in.",
+            )
+                .into_response();
+        }
+    }
+    if let Some(sessions) = &shared.sessions {
+        match (request.uri().path(), request.method()) {
+            (LOGIN_PATH, &Method::POST) => return log_in(request, &shared.users, sessions).await,
+            (LOGOUT_PATH, _) => return log_out(request.headers(), sessions),
+            _ => {}
+        }
+    }
+    // Who logged in, by basic auth or the login page. Only while they're a user, removing one
+    // ends their sessions:
+    let session = shared.sessions.as_ref().and_then(|sessions| {
+        cookies(request.headers(), SESSION_COOKIE).find_map(|cookie| sessions.user(cookie))
+    });
+    let (allowed, user, by_session) = {
+        let users = shared.users.read().unwrap();
+        let basic = authorized(&users, request.headers());
+        let by_session = basic.is_none()
+            && session
+                .as_ref()
+                .is_some_and(|session| users.iter().any(|(name, _)| name == session));
+        let user = basic.or(session.filter(|_| by_session));
+        let allowed = (users.is_empty() && shared.secret_header.is_none())
+            || shared.public.as_ref().is_some_and(|public| {
+                public.is_match(&*percent_decode_str(request.uri().path()).decode_utf8_lossy())
+            })
+            || user.is_some()
+            || by_secret;
+        (allowed, user, by_session)
+    };
+    if !allowed {
+        if shared.sessions.is_some() {
+            return login_page(back_of(&request), false);
+        }
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"livetunnel\""),
+            )],
+        )
+            .into_response();
+    }
+
+    // Scripts with the secret header don't get a challenge:
+    if let Some(challenge) = shared.challenge.as_ref().filter(|_| !by_secret) {
+        if request.uri().path() == CHALLENGE_PATH && request.method() == Method::POST {
+            return solve(request, challenge).await;
+        }
+        if !passed(request.headers(), challenge) {
+            return challenge_page(challenge, back_of(&request));
+        }
+    }
+
+    let mut uri_path = request.uri().path().to_string();
+    if let (RELOAD_PATH, Some(live_reload)) = (uri_path.as_str(), &shared.live_reload) {
+        return reload_socket(request, live_reload.sender.subscribe()).await;
+    }
+    let icon = match uri_path.as_str() {
+        ICON_PATH | "/favicon.ico" => shared.favicon.as_ref(),
+        TOUCH_ICON_PATH | "/apple-touch-icon.png" | "/apple-touch-icon-precomposed.png" => {
+            shared.touch_icon.as_ref()
+        }
+        _ => None,
+    };
+    if let Some(icon) = icon {
+        return match ServeFile::new(icon).try_call(request).await {
+            Ok(response) => response.map(Body::new),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        };
+    }
+
+    let mut relative = match request_path(&uri_path) {
+        Ok(relative) => relative,
+        Err(status) => return status.into_response(),
+    };
+
+    // Pretty URLs and the rules of the config and `_redirects` ([serve] pretty_urls, redirects
+    // and redirects_file):
+    let mut page_status = None;
+    if shared.file.is_none() {
+        if shared.redirects.is_some() && relative == Path::new("_redirects") {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        let served = match route(&shared, &uri_path, &relative) {
+            Some(Route::Redirect(status, to)) => {
+                return match HeaderValue::from_str(&to) {
+                    Ok(location) => (status, [(header::LOCATION, location)]).into_response(),
+                    Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                };
+            }
+            Some(Route::Serve(served)) => served,
+            Some(Route::Page(status, page)) => {
+                page_status = Some(status);
+                page
+            }
+            None => relative.clone(),
+        };
+        if served != relative {
+            uri_path = served
+                .iter()
+                .map(|name| utf8_percent_encode(&name.to_string_lossy(), PATH_SEGMENT).to_string())
+                .fold(String::new(), |path, name| format!("{}/{}", path, name));
+            if uri_path.is_empty() || shared.root.join(&served).is_dir() {
+                uri_path.push('/');
+            }
+            let uri = match request.uri().query() {
+                Some(query) => format!("{}?{}", uri_path, query),
+                None => uri_path.clone(),
+            };
+            match uri.parse::<Uri>() {
+                Ok(uri) => *request.uri_mut() = uri,
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+            relative = served;
+        }
+    }
+
+    // By where it really is, a symlink doesn't lead around a restricted folder:
+    let target = shared
+        .root
+        .join(&relative)
+        .canonicalize()
+        .ok()
+        .and_then(|path| Some(path.strip_prefix(&shared.root).ok()?.to_path_buf()))
+        .unwrap_or_else(|| relative.clone());
+    if !may_open(&shared.folder_users, &relative, user.as_deref())
+        || !may_open(&shared.folder_users, &target, user.as_deref())
+    {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let attachment = match &shared.file {
+        Some(file) if relative.as_os_str().is_empty() => Some(file),
+        Some(file) if relative == *file => None,
+        Some(_) => return StatusCode::NOT_FOUND.into_response(),
+        None => None,
+    };
+
+    let path = shared.root.join(&relative);
+    let is_dir = shared.file.is_none() && path.is_dir();
+    // `?archive` on a directory downloads all of it as .tar.gz, not with watermarks, which it
+    // wouldn't have:
+    let archive = is_dir && request.uri().query() == Some("archive");
+    if archive && shared.watermark.is_some() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    // Nor with folders in it the user may not open:
+    let archivable = shared.watermark.is_none()
+        && !shared.folder_users.iter().any(|(folder, _)| {
+            folder.starts_with(&relative)
+                && !may_open(&shared.folder_users, folder, user.as_deref())
+        });
+    if archive && !archivable {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if is_dir && !archive {
+        if !uri_path.ends_with('/') {
+            return Redirect::permanent(&format!("{}/", uri_path)).into_response();
+        }
+
+        let uploads = shared
+            .uploads
+            .as_ref()
+            .filter(|uploads| uploads.allowed(&relative));
+        if request.method() == Method::POST {
+            return match uploads {
+                Some(uploads) => receive(request, &path, uploads).await,
+                None => StatusCode::FORBIDDEN.into_response(),
+            };
+        }
+
+        let shown =
+            |name: &str| may_open(&shared.folder_users, &relative.join(name), user.as_deref());
+        let listed = listing(
+            &path,
+            &uri_path,
+            uploads.is_some(),
+            archivable,
+            by_session,
+            shown,
+        );
+        return match listed.await {
+            Ok(html) => Html(shared.snippets.insert(&html)).into_response(),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        };
+    }
+
+    // Images and PDFs are stamped for every request, neither cached nor sent in ranges:
+    let stamp = shared
+        .watermark
+        .clone()
+        .filter(|_| matches!(*request.method(), Method::GET | Method::HEAD))
+        .and_then(|watermark| {
+            let file = match &shared.file {
+                Some(file) => shared.root.join(file),
+                None => path.clone(),
+            };
+            Some((watermark, Kind::of(&file)?, file))
+        });
+
+    // Pages link the current versions, so they are never taken from a cache unasked:
+    let versioned = shared.asset_hashes.is_some()
+        && attachment.is_none()
+        && stamp.is_none()
+        && path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+        });
+    if versioned {
+        request.headers_mut().remove(header::IF_MODIFIED_SINCE);
+    }
+    // Only the version linked is cached for good, `?v=` of any other is ignored:
+    let version = request
+        .uri()
+        .query()
+        .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("v=")))
+        .filter(|_| shared.asset_hashes.is_some() && !versioned && !archive)
+        .map(String::from);
+
+    // Files that didn't change since the client got them aren't sent again:
+    let injected = !shared.snippets.is_empty() && attachment.is_none();
+    let snippets_tag = Some(shared.snippets.tag.as_str()).filter(|_| injected);
+    let etag = match (&shared.file, archive) {
+        _ if stamp.is_some() || versioned || page_status.is_some() => None,
+        (_, true) => None,
+        (Some(file), false) => entity_tag(&shared.root.join(file), snippets_tag).await,
+        (None, false) => entity_tag(&path, snippets_tag).await,
+    };
+    let conditional = matches!(*request.method(), Method::GET | Method::HEAD);
+    if let (Some(etag), true) = (&etag, conditional) {
+        if let Some(tags) = request.headers().get(header::IF_NONE_MATCH) {
+            if tag_matches(tags, etag) {
+                return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag.clone())]).into_response();
+            }
+            // The tags decide, not the date (RFC 9110):
+            request.headers_mut().remove(header::IF_MODIFIED_SINCE);
+        }
+    }
+
+    // Only full GETs are downloads, HEAD and range requests stay free:
+    let limit = shared.downloads.as_ref().filter(|_| {
+        request.method() == Method::GET && !request.headers().contains_key(header::RANGE)
+    });
+    if let Some(limit) = limit {
+        if !limit.take() {
+            return (
+                StatusCode::GONE,
+                "This share has reached its download limit.",
+            )
+                .into_response();
+        }
+    }
+
+    let method = request.method().clone();
+    let mut response = if archive {
+        archive_response(path.clone(), &relative)
+    } else if let Some((watermark, kind, file)) = stamp {
+        let text = watermark.text(
+            user.as_deref(),
+            &client_address(request.headers()),
+            &Zoned::now().strftime("%Y-%m-%d").to_string(),
+        );
+        stamped(watermark, kind, file, text).await
+    } else {
+        let served = match &shared.file {
+            Some(file) => {
+                ServeFile::new(shared.root.join(file))
+                    .try_call(request)
+                    .await
+            }
+            None => {
+                ServeDir::new(&shared.root)
+                    .append_index_html_on_directories(false)
+                    .try_call(request)
+                    .await
+            }
+        };
+        match served {
+            Ok(response) => response.map(Body::new),
+            Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+        }
+    };
+
+    if (injected || versioned) && method == Method::GET {
+        let shared = shared.clone();
+        let directory = relative.parent().map(Path::to_path_buf).unwrap_or_default();
+        response = rewrite_html(response, move |html| {
+            let html = match injected {
+                true => shared.snippets.insert(html),
+                false => html.to_string(),
+            };
+            match (&shared.asset_hashes, versioned) {
+                (Some(hashes), true) => version_assets(&html, &directory, |relative| {
+                    hashes.of(&shared.root.join(relative))
+                }),
+                _ => html,
+            }
+        })
+        .await;
+    }
+    if versioned {
+        response.headers_mut().remove(header::LAST_MODIFIED);
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    if let (Some(version), StatusCode::OK) = (version, response.status()) {
+        let current = {
+            let shared = shared.clone();
+            let path = path.clone();
+            spawn_blocking(move || shared.asset_hashes.as_ref()?.of(&path))
+                .await
+                .ok()
+                .flatten()
+        };
+        if current.is_some_and(|current| current == version) {
+            response.headers_mut().insert(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            );
+        }
+    }
+
+    // A page of `_redirects` for a status, e.g. a custom 404:
+    if let (Some(status), StatusCode::OK) = (page_status, response.status()) {
+        *response.status_mut() = status;
+    }
+
+    let sent = matches!(
+        response.status(),
+        StatusCode::OK | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+    );
+    if let (Some(etag), true) = (&etag, sent) {
+        if let Ok(value) = HeaderValue::from_str(etag) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+    }
+
+    if let Some(name) = attachment {
+        let disposition = format!(
+            "attachment; filename*=UTF-8''{}",
+            utf8_percent_encode(&name.to_string_lossy(), FILENAME)
+        );
+        if let Ok(value) = HeaderValue::from_str(&disposition) {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_DISPOSITION, value);
+        }
+    }
+
+    match limit {
+        Some(limit) if response.status() == StatusCode::OK => {
+            let length = response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok());
+            response.map(|body| Body::new(Download::new(body, length, limit.clone())))
+        }
+        Some(limit) => {
+            limit.finish(false);
+            response
+        }
+        None => response,
+    }
+}
+
+// `file` with the watermark for a request. Files that can't be stamped aren't sent at all:
+async fn stamped(watermark: Arc<Watermark>, kind: Kind, file: PathBuf, text: String) -> Response {
+    match metadata(&file).await {
+        Ok(metadata) if metadata.is_file() && metadata.len() > WATERMARK_MAX_SIZE => {
+            warn!(
+                "Refused '{}', it's too large to be watermarked",
+                file.display()
+            );
+            return (
+                StatusCode::FORBIDDEN,
+                "This file is too large to be watermarked.",
+            )
+                .into_response();
+        }
+        Ok(metadata) if metadata.is_file() => {}
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    }
+
+    let name = file.display().to_string();
+    let stamped = spawn_blocking(move || {
+        let data = std::fs::read(&file).map_err(|err| err.to_string())?;
+        watermark.apply(kind, &data, &text)
+    })
+    .await
+    .unwrap_or_else(|err| Err(err.to_string()));
+    match stamped {
+        Ok(data) => (
+            [
+                (header::CONTENT_TYPE, kind.content_type()),
+                (header::CACHE_CONTROL, "private, no-store"),
+            ],
+            data,
+        )
+            .into_response(),
+        Err(err) => {
+            warn!("Could not watermark '{}': {}", name, err);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "This file could not be watermarked.",
+            )
+                .into_response()
+        }
+    }
+}
+
+// Strong validator of a file from its size and modification time, like nginx's. Pages with
+// snippets get other tags than the same file without, `snippets` tells which ones:
+async fn entity_tag(path: &Path, snippets: Option<&str>) -> Option<String> {
+    let metadata = metadata(path)
+        .await
+        .ok()
+        .filter(|metadata| metadata.is_file())?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "\"{:x}-{:x}{}\"",
+        modified.as_nanos(),
+        metadata.len(),
+        snippets.map(|tag| format!("-{}", tag)).unwrap_or_default()
+    ))
+}
+
+// Whether If-None-Match lists `etag` (or is `*`), weak tags count as well for it:
+fn tag_matches(tags: &HeaderValue, etag: &str) -> bool {
+    tags.to_str().is_ok_and(|tags| {
+        tags.split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+    })
+}
+
+// Outside of the schedule, tells when the share is served again:
+fn offline(opening: &Zoned) -> Response {
+    let retry = Zoned::now()
+        .duration_until(opening)
+        .as_secs()
+        .max(1)
+        .to_string();
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Share offline</title></head><body><h1>This share is offline</h1><p>It's available again on {}.</p></body></html>",
+        escape(&opening.strftime("%A, %Y-%m-%d %H:%M %Z").to_string())
+    );
+    let mut response = (StatusCode::SERVICE_UNAVAILABLE, Html(html)).into_response();
+    if let Ok(value) = HeaderValue::from_str(&retry) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
+
+// Whether a request comes from one of `allowed` or the share itself (by its Referer, else its
+// Origin), what it came from if not:
+fn referred(allowed: &GlobSet, headers: &HeaderMap) -> Result<(), String> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let from = match (
+        header(header::REFERER),
+        header(header::ORIGIN).filter(|origin| *origin != "null"),
+    ) {
+        (Some(referer), _) => referer.to_string(),
+        (None, Some(origin)) => format!("{}/", origin),
+        (None, None) => return Err(String::from("it has no referrer")),
+    };
+
+    // The reverse proxy passes the Host on:
+    let from_host = from
+        .split_once("://")
+        .and_then(|(_, rest)| rest.split('/').next());
+    if from_host.is_some_and(|from_host| Some(from_host) == header(header::HOST))
+        || allowed.is_match(&from)
+    {
+        Ok(())
+    } else {
+        Err(format!("it came from '{}'", from))
+    }
+}
+
+// Whether a cookie of the request is a pass for a solved challenge:
+fn passed(headers: &HeaderMap, challenge: &Challenge) -> bool {
+    cookies(headers, PASS_COOKIE).any(|pass| challenge.admits(pass))
+}
+
+// Values of the cookies called `name`:
+fn cookies<'a>(headers: &'a HeaderMap, name: &'a str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(move |cookie| cookie.trim().strip_prefix(name)?.strip_prefix('='))
+}
+
+// Set-Cookie for a cookie of the share, removed with `max_age` 0:
+fn set_cookie(headers: &HeaderMap, name: &str, value: &str, max_age: u64) -> String {
+    // Behind the reverse proxy the visitor may use https, where the cookie should stay:
+    let secure = headers
+        .get("x-forwarded-proto")
+        .is_some_and(|proto| proto == "https");
+    format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        name,
+        value,
+        max_age,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+// Sends the browser on to `location` of the share with a cookie:
+fn see_other(location: &str, cookie: &str) -> Response {
+    match (
+        HeaderValue::from_str(location),
+        HeaderValue::from_str(cookie),
+    ) {
+        (Ok(location), Ok(cookie)) => (
+            StatusCode::SEE_OTHER,
+            [(header::LOCATION, location), (header::SET_COOKIE, cookie)],
+        )
+            .into_response(),
+        _ => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+// Where a page that stands in for the requested one (login, challenge) leads back to:
+fn back_of(request: &Request) -> &str {
+    request
+        .uri()
+        .path_and_query()
+        .map(|back| back.as_str())
+        .unwrap_or("/")
+}
+
+// The `back` of a form, only ever to this share:
+fn back_to(form: &HashMap<String, String>) -> &str {
+    form.get("back")
+        .map(String::as_str)
+        .filter(|back| is_local_path(back))
+        .unwrap_or("/")
+}
+
+// Whether no browser takes `back` for another site: a relative URI with an absolute path, which
+// doesn't start with `//` or contain `\` (also percent-encoded) and has no control characters or
+// whitespace, which browsers drop (`/\t/evil.com` is `//evil.com` to them):
+fn is_local_path(back: &str) -> bool {
+    let decoded = percent_decode_str(back).decode_utf8_lossy();
+    back.parse::<Uri>()
+        .is_ok_and(|uri| uri.scheme().is_none() && uri.authority().is_none())
+        && !back.contains(|c: char| c.is_control() || c.is_whitespace())
+        && [back, &decoded].iter().all(|path| {
+            path.starts_with('/')
+                && !path.starts_with("//")
+                && !path.contains(|c: char| c == '\\' || c.is_control())
+        })
+}
+
+// Asks for user and password, instead of the requested page that's at `back`:
+fn login_page(back: &str, failed: bool) -> Response {
+    let html = LOGIN_PAGE.replace("{back}", &escape(back)).replace(
+        "{problem}",
+        if failed {
+            "<p>Wrong user or password.</p>"
+        } else {
+            ""
+        },
+    );
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Html(html),
+    )
+        .into_response()
+}
+
+// Starts a session for the credentials of the login page, asks again for wrong ones:
+// The fields of a form the server's own pages send, which are small. Read with a limit of their
+// own, the one of the share may be off for uploads:
+async fn read_form(request: Request) -> Result<HashMap<String, String>, StatusCode> {
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, FORM_LIMIT)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+    let request = Request::from_parts(parts, Body::from(body));
+    match Form::<HashMap<String, String>>::from_request(request, &()).await {
+        Ok(Form(form)) => Ok(form),
+        Err(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+async fn log_in(
+    request: Request,
+    users: &RwLock<Vec<(String, String)>>,
+    sessions: &Sessions,
+) -> Response {
+    let headers = request.headers().clone();
+    let form = match read_form(request).await {
+        Ok(form) => form,
+        Err(status) => return status.into_response(),
+    };
+    let back = back_to(&form);
+    let (Some(user), Some(password)) = (form.get("user"), form.get("password")) else {
+        return login_page(back, true);
+    };
+    let hash = format!("{:x}", Sha512::digest(password));
+    let known = users
+        .read()
+        .unwrap()
+        .iter()
+        .any(|(name, stored)| name == user && *stored == hash);
+    if !known {
+        return login_page(back, true);
+    }
+
+    let cookie = set_cookie(
+        &headers,
+        SESSION_COOKIE,
+        &sessions.start(user),
+        sessions.lifetime().as_secs(),
+    );
+    see_other(back, &cookie)
+}
+
+// Ends the sessions of the request's cookies, for good, and drops them from the browser:
+fn log_out(headers: &HeaderMap, sessions: &Sessions) -> Response {
+    for cookie in cookies(headers, SESSION_COOKIE) {
+        sessions.end(cookie);
+    }
+    see_other("/", &set_cookie(headers, SESSION_COOKIE, "", 0))
+}
+
+// Solves a new challenge in the browser and sends the solution, which comes back to `back`:
+fn challenge_page(challenge: &Challenge, back: &str) -> Response {
+    let html = CHALLENGE_PAGE
+        .replace("{challenge}", &challenge.issue())
+        .replace("{back}", &escape(back))
+        .replace("{bits}", &challenge.bits().to_string());
+    (
+        StatusCode::FORBIDDEN,
+        [(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))],
+        Html(html),
+    )
+        .into_response()
+}
+
+// Gives a pass for a solution, another challenge without one:
+async fn solve(request: Request, challenge: &Challenge) -> Response {
+    let headers = request.headers().clone();
+    let form = match read_form(request).await {
+        Ok(form) => form,
+        Err(status) => return status.into_response(),
+    };
+    let back = back_to(&form);
+    let solution = (form.get("challenge"), form.get("nonce"));
+    let (Some(issued), Some(nonce)) = solution else {
+        return challenge_page(challenge, back);
+    };
+    if !challenge.solved(issued, nonce) {
+        return challenge_page(challenge, back);
+    }
+
+    let cookie = set_cookie(&headers, PASS_COOKIE, &challenge.pass(), PASS_TIME);
+    see_other(back, &cookie)
+}
+
+// Keeps a page's websocket open and sends it a message for every reload:
+async fn reload_socket(request: Request, mut reloads: broadcast::Receiver<()>) -> Response {
+    let (mut parts, _) = request.into_parts();
+    let upgrade = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+        Ok(upgrade) => upgrade,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    upgrade.on_upgrade(|mut socket: WebSocket| async move {
+        // Browsers answer pings on their own, so the idle timeout leaves the socket open:
+        let mut pings = interval(RELOAD_PING);
+        loop {
+            tokio::select! {
+                _ = pings.tick() => {
+                    if socket.send(Message::Ping(Bytes::new())).await.is_err() {
+                        break;
+                    }
+                }
+                reload = reloads.recv() => {
+                    if matches!(reload, Err(broadcast::error::RecvError::Closed))
+                        || socket.send(Message::Text("reload".into())).await.is_err()
+                    {
+                        break;
+                    }
+                }
+                // Pages never send anything, this only notices when they are gone:
+                message = socket.recv() => {
+                    if !matches!(message, Some(Ok(_))) {
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+// What the server puts into every HTML page it sends, e.g. the live reload script:
+#[derive(Debug, Default)]
+struct Snippets {
+    // Before </head>:
+    head: String,
+    // Before </body>:
+    body: String,
+    // Short hash of both, for the ETags of pages with them:
+    tag: String,
+}
+
+impl Snippets {
+    fn new(options: &ServeOptions) -> Snippets {
+        let mut head = String::new();
+        if options.favicon.is_some() {
+            head.push_str(&format!("<link rel=\"icon\" href=\"{}\">", ICON_PATH));
+        }
+        if options.touch_icon.is_some() {
+            head.push_str(&format!(
+                "<link rel=\"apple-touch-icon\" href=\"{}\">",
+                TOUCH_ICON_PATH
+            ));
+        }
+        if let Some(analytics) = &options.analytics {
+            head.push_str(analytics);
+        }
+        let mut body = String::new();
+        if let Some(banner) = &options.banner {
+            body.push_str(&format!(
+                "<div style=\"position:fixed;top:0;left:0;right:0;z-index:2147483647;padding:8px 40px;background:#222;color:#fff;font:14px/1.4 system-ui,sans-serif;text-align:center\">{}<button type=\"button\" aria-label=\"Dismiss\" onclick=\"this.parentNode.remove()\" style=\"position:absolute;top:4px;right:8px;border:0;background:none;color:inherit;font-size:20px;cursor:pointer\">&times;</button></div>",
+                escape(banner)
+            ));
+        }
+        if options.live_reload.is_some() {
+            body.push_str(RELOAD_SCRIPT);
+        }
+
+        let tag = format!("{:x}", Sha512::digest(format!("{}\n{}", head, body)))[..8].to_string();
+        Snippets { head, body, tag }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.head.is_empty() && self.body.is_empty()
+    }
+
+    // Into the last </body> and the first </head> (or before <body>, where the head ends as
+    // well), at the end and the start without them:
+    fn insert(&self, html: &str) -> String {
+        let html = match html.rfind("</body>") {
+            Some(end) => format!("{}{}{}", &html[..end], self.body, &html[end..]),
+            None => format!("{}{}", html, self.body),
+        };
+        match html.find("</head>").or_else(|| html.find("<body")) {
+            Some(end) => format!("{}{}{}", &html[..end], self.head, &html[end..]),
+            None => format!("{}{}", self.head, html),
+        }
+    }
+}
+
+// Rewrites a complete HTML page, e.g. puts the snippets into it, other responses stay as they
+// are:
+async fn rewrite_html(
+    response: Response,
+    rewrite: impl FnOnce(&str) -> String + Send + 'static,
+) -> Response {
+    let headers = response.headers();
+    let is_html = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    let too_large = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok())
+        .is_none_or(|length| length > SNIPPETS_MAX_SIZE);
+    if response.status() != StatusCode::OK || !is_html || too_large {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let html = match axum::body::to_bytes(body, SNIPPETS_MAX_SIZE).await {
+        Ok(html) => html,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+    parts.headers.remove(header::CONTENT_LENGTH);
+    // Hashing the linked files reads them:
+    let body = spawn_blocking(move || match std::str::from_utf8(&html) {
+        Ok(text) => Body::from(rewrite(text)),
+        Err(_) => Body::from(html),
+    })
+    .await
+    .unwrap_or_else(|err| Body::from(err.to_string()));
+    Response::from_parts(parts, body)
+}
+
+/// Hashes of the files in a share by path, computed again once one changes.
+#[derive(Default)]
+struct AssetHashes {
+    hashes: Mutex<HashMap<PathBuf, (SystemTime, u64, String)>>,
+}
+
+impl AssetHashes {
+    // Blocks while it reads a file that changed:
+    fn of(&self, path: &Path) -> Option<String> {
+        let metadata = std::fs::metadata(path)
+            .ok()
+            .filter(|metadata| metadata.is_file())?;
+        let (modified, len) = (metadata.modified().ok()?, metadata.len());
+        if let Some((_, _, hash)) = self
+            .hashes
+            .lock()
+            .unwrap()
+            .get(path)
+            .filter(|(cached, cached_len, _)| *cached == modified && *cached_len == len)
+        {
+            return Some(hash.clone());
+        }
+
+        let mut hasher = Sha512::new();
+        io::copy(&mut std::fs::File::open(path).ok()?, &mut hasher).ok()?;
+        let hash = format!("{:x}", hasher.finalize())[..16].to_string();
+        self.hashes
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), (modified, len, hash.clone()));
+        Some(hash)
+    }
+}
+
+// Adds `?v=<hash>` to the src and href URLs of a page in `directory` that are files of the share
+// (`hash` of their path relative to it), except pages, which are never cached for good:
+fn version_assets(html: &str, directory: &Path, hash: impl Fn(&Path) -> Option<String>) -> String {
+    let mut versioned = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(at) = ["src=", "href="]
+        .iter()
+        .filter_map(|attribute| Some(rest.find(attribute)? + attribute.len()))
+        .min()
+    {
+        let (before, after) = rest.split_at(at);
+        versioned.push_str(before);
+        rest = after;
+        let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+        let Some(end) = after[1..].find(quote).map(|end| end + 1) else {
+            continue;
+        };
+        let url = &after[1..end];
+        versioned.push(quote);
+        versioned.push_str(url);
+        if let Some(version) = local_file(url, directory).and_then(|relative| hash(&relative)) {
+            let _ = write!(versioned, "?v={}", version);
+        }
+        rest = &after[end..];
+    }
+    versioned.push_str(rest);
+    versioned
+}
+
+// The path relative to the share an URL of a page in `directory` points to, if it's a plain one
+// to a file that isn't a page:
+fn local_file(url: &str, directory: &Path) -> Option<PathBuf> {
+    if url.is_empty()
+        || url.starts_with("//")
+        || url.ends_with('/')
+        || url.contains(['?', '#'])
+        || url
+            .split('/')
+            .next()
+            .is_some_and(|first| first.contains(':'))
+    {
+        return None;
+    }
+    let decoded = percent_decode_str(url).decode_utf8().ok()?;
+    let relative = match decoded.strip_prefix('/') {
+        Some(absolute) => PathBuf::from(absolute),
+        None => directory.join(&*decoded),
+    };
+    if relative.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm")
+    }) {
+        return None;
+    }
+
+    // Without `..` and `.`, none leading out of the share:
+    let mut normal = PathBuf::new();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => normal.push(name),
+            Component::CurDir => {}
+            Component::ParentDir if normal.pop() => {}
+            _ => return None,
+        }
+    }
+    Some(normal)
+}
+
+// Stores the files of a multipart upload in `directory` and shows it again. Existing files are
+// never replaced, a file over the size limit is removed again:
+async fn receive(request: Request, directory: &Path, uploads: &Uploads) -> Response {
+    let mut multipart = match Multipart::from_request(request, &()).await {
+        Ok(multipart) => multipart,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    loop {
+        let mut field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return (err.status(), err.body_text()).into_response(),
+        };
+
+        // Only the name, browsers used to send whole paths:
+        let name = match field
+            .file_name()
+            .and_then(|name| Path::new(name).file_name())
+        {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+        let path = directory.join(&name);
+
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return (
+                    StatusCode::CONFLICT,
+                    format!("'{}' already exists.", name.to_string_lossy()),
+                )
+                    .into_response();
+            }
+            Err(err) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+            }
+        };
+
+        let mut written = 0;
+        let failure = loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => {
+                    written += chunk.len() as u64;
+                    if uploads.max_size.is_some_and(|max| written > max) {
+                        break Some((
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            format!(
+                                "'{}' is larger than {}.",
+                                name.to_string_lossy(),
+                                indicatif::DecimalBytes(uploads.max_size.unwrap_or_default())
+                            ),
+                        ));
+                    }
+                    if let Err(err) = file.write_all(&chunk).await {
+                        break Some((StatusCode::INTERNAL_SERVER_ERROR, err.to_string()));
+                    }
+                }
+                Ok(None) => {
+                    break file
+                        .flush()
+                        .await
+                        .err()
+                        .map(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+                }
+                Err(err) => break Some((err.status(), err.body_text())),
+            }
+        };
+
+        if let Some(failure) = failure {
+            drop(file);
+            let _ = remove_file(&path).await;
+            return failure.into_response();
+        }
+    }
+
+    Redirect::to(".").into_response()
+}
+
+// Streams `directory` as .tar.gz while it is archived, nothing is stored in between:
+fn archive_response(directory: PathBuf, relative: &Path) -> Response {
+    let name = relative
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("share"));
+    let (sender, chunks) = mpsc::channel(8);
+
+    let folder = name.clone();
+    spawn_blocking(move || {
+        let writer = BufWriter::with_capacity(
+            64 * 1024,
+            ChunkWriter {
+                sender: sender.clone(),
+            },
+        );
+        let mut builder = Builder::new(GzEncoder::new(writer, Compression::default()));
+        builder.follow_symlinks(false);
+
+        let archived = builder
+            .append_dir_all(&folder, &directory)
+            .and_then(|()| builder.into_inner())
+            .and_then(|encoder| encoder.finish())
+            .and_then(|mut writer| writer.flush());
+        // Ends the download with an error instead of a silently truncated archive:
+        if let Err(err) = archived {
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+
+    let disposition = format!(
+        "attachment; filename*=UTF-8''{}.tar.gz",
+        utf8_percent_encode(&name, FILENAME)
+    );
+    let mut response = Body::new(Archive { chunks }).into_response();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/gzip"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&disposition) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_DISPOSITION, value);
+    }
+    response
+}
+
+// Hands what the archiver writes over to the response body:
+struct ChunkWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sender
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "download was cancelled"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Archive {
+    chunks: mpsc::Receiver<io::Result<Bytes>>,
+}
+
+impl HttpBody for Archive {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        self.get_mut()
+            .chunks
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data)))
+    }
+}
+
+// The user of the request's basic auth, if the password is right:
+fn authorized(users: &[(String, String)], headers: &HeaderMap) -> Option<String> {
+    let (user, password) = credentials(headers)?;
+
+    let hash = format!("{:x}", Sha512::digest(password));
+    users
+        .iter()
+        .any(|(name, stored)| *name == user && *stored == hash)
+        .then_some(user)
+}
+
+// Where a request goes with pretty URLs and redirects, other than to its own path:
+enum Route {
+    // Another path of the share answers for it:
+    Serve(PathBuf),
+    Redirect(StatusCode, String),
+    // A page of the share answers with the status instead:
+    Page(StatusCode, PathBuf),
+}
+
+fn route(shared: &Shared, uri_path: &str, relative: &Path) -> Option<Route> {
+    let path = shared.root.join(relative);
+    // "/about" serves about.html with pretty URLs, "/about/" leads there:
+    let mut page = relative.components().collect::<PathBuf>().into_os_string();
+    page.push(".html");
+    let page = PathBuf::from(page);
+    let page_exists = shared.pretty_urls && shared.root.join(&page).is_file();
+
+    let decoded = percent_decode_str(uri_path).decode_utf8_lossy();
+    let exists = path.exists() || page_exists;
+    if let Some(target) = shared.rules.find(&decoded, exists).or_else(|| {
+        let redirects = shared.redirects.as_ref()?;
+        redirects.rules().find(&decoded, exists)
+    }) {
+        let status = StatusCode::from_u16(target.status).ok()?;
+        return Some(match target.status {
+            300..=399 => Route::Redirect(status, target.to),
+            200 => Route::Serve(share_path(&target.to)?),
+            _ => Route::Page(status, share_path(&target.to)?),
+        });
+    }
+    if !shared.pretty_urls {
+        return None;
+    }
+
+    let permanent = |to: String| Some(Route::Redirect(StatusCode::MOVED_PERMANENTLY, to));
+    if path.is_dir() {
+        let index = relative.join("index.html");
+        return match (uri_path.ends_with('/'), shared.root.join(&index).is_file()) {
+            (false, _) => permanent(format!("{}/", uri_path)),
+            (true, true) => Some(Route::Serve(index)),
+            (true, false) => None,
+        };
+    }
+    // "/about.html" becomes "/about" and "/index.html" its directory:
+    if path.is_file() {
+        let pretty = uri_path.strip_suffix(".html")?;
+        return match pretty.strip_suffix("index") {
+            Some(directory) if directory.ends_with('/') => permanent(directory.to_string()),
+            _ => permanent(pretty.to_string()),
+        };
+    }
+    if !page_exists {
+        return None;
+    }
+    match uri_path.strip_suffix('/') {
+        Some(trimmed) => permanent(trimmed.to_string()),
+        None => Some(Route::Serve(page)),
+    }
+}
+
+// The path of a request relative to the share. Only plain names, no way out of the root:
+fn request_path(uri_path: &str) -> Result<PathBuf, StatusCode> {
+    let decoded = percent_decode_str(uri_path)
+        .decode_utf8()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let relative = PathBuf::from(decoded.trim_start_matches('/'));
+    if !relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(relative)
+}
+
+// A path of `_redirects` as relative one of the share, if it stays inside of it:
+fn share_path(to: &str) -> Option<PathBuf> {
+    let to = to.split(['?', '#']).next().unwrap_or_default();
+    let decoded = percent_decode_str(to).decode_utf8().ok()?;
+    let relative = PathBuf::from(decoded.trim_start_matches('/'));
+    relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+        .then_some(relative)
+}
+
+// The `_redirects` of a share, read again once it changes:
+struct RedirectsFile {
+    path: PathBuf,
+    cached: Mutex<Option<(SystemTime, Arc<Redirects>)>>,
+}
+
+impl RedirectsFile {
+    fn rules(&self) -> Arc<Redirects> {
+        let Ok(modified) = std::fs::metadata(&self.path).and_then(|metadata| metadata.modified())
+        else {
+            return Arc::default();
+        };
+        let mut cached = self.cached.lock().unwrap();
+        if let Some((_, redirects)) = cached.as_ref().filter(|(read, _)| *read == modified) {
+            return redirects.clone();
+        }
+
+        let text = std::fs::read_to_string(&self.path).unwrap_or_default();
+        let (redirects, problems) = Redirects::parse(&text);
+        for problem in problems {
+            warn!("Left out of _redirects, {}", problem);
+        }
+        let redirects = Arc::new(redirects);
+        *cached = Some((modified, redirects.clone()));
+        redirects
+    }
+}
+
+// Whether `user` may open `relative`, decided by the deepest of the restricted folders it's in:
+fn may_open(folder_users: &[(PathBuf, Vec<String>)], relative: &Path, user: Option<&str>) -> bool {
+    folder_users
+        .iter()
+        .filter(|(folder, _)| relative.starts_with(folder))
+        .max_by_key(|(folder, _)| folder.components().count())
+        .is_none_or(|(_, users)| user.is_some_and(|user| users.iter().any(|name| name == user)))
+}
+
+// (user, password) of basic auth:
+fn credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let decoded = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+async fn listing(
+    directory: &Path,
+    uri_path: &str,
+    upload: bool,
+    archive: bool,
+    logout: bool,
+    // Entries the user may not open aren't listed:
+    shown: impl Fn(&str) -> bool,
+) -> io::Result<String> {
+    let mut entries = Vec::new();
+    let mut dir = read_dir(directory).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        if !shown(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let is_dir = entry.file_type().await?.is_dir();
+        let size = if is_dir {
+            None
+        } else {
+            entry.metadata().await.ok().map(|metadata| metadata.len())
+        };
+        entries.push((
+            entry.file_name().to_string_lossy().into_owned(),
+            is_dir,
+            size,
+        ));
+    }
+
+    // Directories first, then alphabetically:
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let title = escape(&percent_decode_str(uri_path).decode_utf8_lossy());
+    let mut html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Index of {title}</title></head><body><h1>Index of {title}</h1><ul>"
+    );
+
+    if uri_path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>");
+    }
+
+    for (name, is_dir, size) in entries {
+        let suffix = if is_dir { "/" } else { "" };
+        let _ = write!(
+            html,
+            "<li><a href=\"{}{suffix}\">{}{suffix}</a>{}</li>",
+            utf8_percent_encode(&name, PATH_SEGMENT),
+            escape(&name),
+            size.map(|size| format!(" ({})", indicatif::DecimalBytes(size)))
+                .unwrap_or_default()
+        );
+    }
+
+    html.push_str("</ul>");
+    if archive {
+        html.push_str("<p><a href=\"?archive\">Download all as .tar.gz</a></p>");
+    }
+    if upload {
+        html.push_str("<form method=\"post\" enctype=\"multipart/form-data\"><input type=\"file\" name=\"file\" multiple> <button>Upload</button></form>");
+    }
+    if logout {
+        html.push_str("<p><a href=\"/.livetunnel/logout\">Log out</a></p>");
+    }
+    html.push_str("</body></html>");
+    Ok(html)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A started server for requests through all of it, on a socket of its own:
+    #[cfg(unix)]
+    struct Share {
+        runtime: Runtime,
+        server: Option<Server>,
+        endpoint: Endpoint,
+        directory: PathBuf,
+    }
+
+    #[cfg(unix)]
+    impl Share {
+        fn start(name: &str, users: &[(&str, &str)], options: ServeOptions) -> Share {
+            let directory = std::env::temp_dir().join(format!(
+                "livetunnel-server-{}-{}",
+                std::process::id(),
+                name
+            ));
+            std::fs::create_dir_all(directory.join("site")).unwrap();
+            std::fs::write(directory.join("site/index.html"), "<h1>Hello</h1>").unwrap();
+            let users = users
+                .iter()
+                .map(|(user, password)| {
+                    (user.to_string(), format!("{:x}", Sha512::digest(password)))
+                })
+                .collect();
+            let runtime = Runtime::new().unwrap();
+            let endpoint = Endpoint::Socket(directory.join("server.sock"));
+            let server =
+                Server::start(&runtime, &endpoint, &directory.join("site"), users, options)
+                    .unwrap();
+            Share {
+                runtime,
+                server: Some(server),
+                endpoint,
+                directory,
+            }
+        }
+
+        // The status and the whole response to a raw HTTP/1.1 request (with `Connection: close`):
+        fn send(&self, request: &str) -> (u16, String) {
+            use std::io::Read;
+            let mut stream = self.endpoint.connect().unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = vec![];
+            stream.read_to_end(&mut response).unwrap();
+            let response = String::from_utf8_lossy(&response).into_owned();
+            (response[9..12].parse().unwrap(), response)
+        }
+
+        // A GET with the headers given, each ending with "\r\n":
+        fn get(&self, path: &str, headers: &str) -> (u16, String) {
+            self.send(&format!(
+                "GET {} HTTP/1.1\r\nHost: share.example.com\r\n{}Connection: close\r\n\r\n",
+                path, headers
+            ))
+        }
+
+        // Puts a file into the shared directory, with the directories it's in:
+        fn write(&self, path: &str, contents: impl AsRef<[u8]>) {
+            let path = self.directory.join("site").join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        fn post_form(&self, path: &str, form: &str) -> (u16, String) {
+            self.send(&format!(
+                "POST {} HTTP/1.1\r\nHost: share.example.com\r\nConnection: close\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+                path,
+                form.len(),
+                form
+            ))
+        }
+    }
+
+    #[cfg(unix)]
+    impl Drop for Share {
+        fn drop(&mut self) {
+            if let Some(server) = self.server.take() {
+                server.stop(&self.runtime);
+            }
+            let _ = std::fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logging_in_sets_a_session_cookie() {
+        let share = Share::start(
+            "login",
+            &[("alice", "correct horse")],
+            ServeOptions {
+                sessions: Some(Arc::new(Sessions::new(Duration::from_secs(600)).unwrap())),
+                ..ServeOptions::default()
+            },
+        );
+
+        let (status, page) = share.send(
+            "GET /index.html HTTP/1.1\r\nHost: share.example.com\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(status, 401);
+        assert!(page.contains("value=\"/index.html\""));
+
+        let (status, page) =
+            share.post_form(LOGIN_PATH, "user=alice&password=wrong&back=%2Findex.html");
+        assert_eq!(status, 401);
+        assert!(page.contains("Wrong user or password"));
+        assert!(!page.contains(SESSION_COOKIE));
+
+        let (status, response) = share.post_form(
+            LOGIN_PATH,
+            "user=alice&password=correct+horse&back=%2Findex.html",
+        );
+        assert_eq!(status, 303);
+        assert!(response.contains("location: /index.html\r\n"));
+        let cookie = response
+            .lines()
+            .find_map(|line| line.strip_prefix("set-cookie: "))
+            .and_then(|cookie| cookie.split(';').next())
+            .unwrap();
+        assert!(cookie.starts_with("livetunnel_session="));
+
+        let (status, page) = share.send(&format!(
+            "GET /index.html HTTP/1.1\r\nHost: share.example.com\r\nCookie: {}\r\nConnection: close\r\n\r\n",
+            cookie
+        ));
+        assert_eq!(status, 200);
+        assert!(page.contains("<h1>Hello</h1>"));
+    }
+
+    // Logging out or being removed as user ends a session, basic auth keeps working:
+    #[cfg(unix)]
+    #[test]
+    fn sessions_end_with_a_logout_or_the_user() {
+        let share = Share::start(
+            "logout",
+            &[("grace", "ada1815")],
+            ServeOptions {
+                sessions: Some(Arc::new(Sessions::new(Duration::from_secs(600)).unwrap())),
+                ..ServeOptions::default()
+            },
+        );
+        let log_in = || {
+            let (_, response) = share.post_form(LOGIN_PATH, "user=grace&password=ada1815");
+            let cookie = response
+                .lines()
+                .find_map(|line| line.strip_prefix("set-cookie: "))
+                .and_then(|cookie| cookie.split(';').next())
+                .unwrap()
+                .to_string();
+            format!("Cookie: {}\r\n", cookie)
+        };
+
+        let cookie = log_in();
+        assert_eq!(share.get("/", &cookie).0, 200);
+        let (status, response) = share.get(LOGOUT_PATH, &cookie);
+        assert_eq!(status, 303);
+        assert!(response.contains("Max-Age=0"), "{}", response);
+        let (status, page) = share.get("/", &cookie);
+        assert_eq!(status, 401);
+        assert!(page.contains("<h1>Log in</h1>"));
+
+        let cookie = log_in();
+        share.server.as_ref().unwrap().set_users(vec![(
+            String::from("henry"),
+            format!("{:x}", Sha512::digest("tudor")),
+        )]);
+        assert_eq!(share.get("/", &cookie).0, 401);
+        assert_eq!(share.get("/", &basic("henry", "tudor")).0, 200);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn login_forms_are_limited_without_a_body_limit() {
+        let share = Share::start(
+            "login-limit",
+            &[("alice", "secret")],
+            ServeOptions {
+                sessions: Some(Arc::new(Sessions::new(Duration::from_secs(600)).unwrap())),
+                ..ServeOptions::default()
+            },
+        );
+        let padding = "a".repeat(FORM_LIMIT);
+
+        let (status, _) = share.post_form(
+            LOGIN_PATH,
+            &format!("user=alice&password=secret&x={}", padding),
+        );
+        assert_eq!(status, 413);
+
+        // Without a Content-Length, in chunks:
+        let chunk = format!("user=alice&password=secret&x={}", padding);
+        let (status, _) = share.send(&format!(
+            "POST {} HTTP/1.1\r\nHost: share.example.com\r\nConnection: close\r\nContent-Type: application/x-www-form-urlencoded\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+            LOGIN_PATH,
+            chunk.len(),
+            chunk
+        ));
+        assert_eq!(status, 413);
+
+        assert_eq!(
+            share.post_form(LOGIN_PATH, "user=alice&password=secret").0,
+            303
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_solved_challenge_gives_a_pass() {
+        use sha2::Sha256;
+
+        let share = Share::start(
+            "challenge",
+            &[],
+            ServeOptions {
+                challenge: Some(Arc::new(Challenge::new(8).unwrap())),
+                ..ServeOptions::default()
+            },
+        );
+
+        let (status, page) = share.send(
+            "GET /index.html HTTP/1.1\r\nHost: share.example.com\r\nConnection: close\r\n\r\n",
+        );
+        assert_eq!(status, 403);
+        let issued = page
+            .split("name=\"challenge\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap();
+        // What the page's script does, 8 bits take a few hundred tries:
+        let nonce = (0u32..)
+            .find(|nonce| {
+                let hash = Sha256::digest(format!("{}:{}", issued, nonce));
+                hash[0] == 0
+            })
+            .unwrap();
+
+        let (status, page) = share.post_form(
+            CHALLENGE_PATH,
+            &format!("challenge={}&nonce=wrong&back=%2Findex.html", issued),
+        );
+        assert_eq!(status, 403);
+        assert!(!page.contains(PASS_COOKIE));
+
+        let (status, response) = share.post_form(
+            CHALLENGE_PATH,
+            &format!("challenge={}&nonce={}&back=%2Findex.html", issued, nonce),
+        );
+        assert_eq!(status, 303);
+        assert!(response.contains("location: /index.html\r\n"));
+        assert!(response.contains("set-cookie: livetunnel_pass="));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn challenge_solutions_are_limited() {
+        let share = Share::start(
+            "challenge-limit",
+            &[],
+            ServeOptions {
+                challenge: Some(Arc::new(Challenge::new(8).unwrap())),
+                max_body: Some(1024 * 1024),
+                ..ServeOptions::default()
+            },
+        );
+        let (status, _) = share.post_form(
+            CHALLENGE_PATH,
+            &format!("challenge=x&nonce={}", "1".repeat(FORM_LIMIT)),
+        );
+        assert_eq!(status, 413);
+    }
+
+    #[test]
+    fn request_paths_are_plain_names_below_the_share() {
+        assert_eq!(request_path("/"), Ok(PathBuf::new()));
+        assert_eq!(
+            request_path("/photos/2026/beach%20day.jpg"),
+            Ok(PathBuf::from("photos/2026/beach day.jpg"))
+        );
+        assert_eq!(request_path("/notes/"), Ok(PathBuf::from("notes")));
+        assert_eq!(request_path("/caf%C3%A9"), Ok(PathBuf::from("café")));
+
+        for outside in [
+            "/../.bashrc",
+            "/photos/../../.bashrc",
+            "/%2E%2E/.bashrc",
+            "/photos%2F%2E%2E%2F%2E%2E%2F.bashrc",
+            "/./photos/beach.jpg",
+        ] {
+            assert_eq!(
+                request_path(outside),
+                Err(StatusCode::NOT_FOUND),
+                "{}",
+                outside
+            );
+        }
+        assert_eq!(request_path("/%C3%28"), Err(StatusCode::BAD_REQUEST));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_users_get_in_and_nobody_gets_out() {
+        let share = Share::start(
+            "basic-auth",
+            &[("ivy", "pa:ss wörd")],
+            ServeOptions::default(),
+        );
+        std::fs::write(share.directory.join("outside.txt"), "Not shared").unwrap();
+        let ivy = basic("ivy", "pa:ss wörd");
+
+        let (status, response) = share.get("/index.html", "");
+        assert_eq!(status, 401);
+        assert!(response.contains("www-authenticate: Basic realm=\"livetunnel\"\r\n"));
+        assert_eq!(share.get("/index.html", &basic("ivy", "pa:ss")).0, 401);
+        assert_eq!(share.get("/index.html", &basic("eve", "pa:ss wörd")).0, 401);
+        assert_eq!(
+            share.get("/index.html", "Authorization: Basic !!!\r\n").0,
+            401
+        );
+        let (status, page) = share.get("/index.html", &ivy);
+        assert_eq!(status, 200);
+        assert!(page.ends_with("<h1>Hello</h1>"));
+
+        for outside in [
+            "/../outside.txt",
+            "/%2e%2e/outside.txt",
+            "/site/..%2F..%2Foutside.txt",
+        ] {
+            let (status, response) = share.get(outside, &ivy);
+            assert_eq!(status, 404, "{}", outside);
+            assert!(!response.contains("Not shared"));
+        }
+    }
+
+    // Basic auth of `user` with `password`, as header line for `Share::get`:
+    #[cfg(unix)]
+    fn basic(user: &str, password: &str) -> String {
+        format!(
+            "Authorization: Basic {}\r\n",
+            STANDARD.encode(format!("{}:{}", user, password))
+        )
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn images_are_stamped_and_other_files_sent_as_they_are() {
+        let share = Share::start(
+            "watermark",
+            &[("dana", "hunter2")],
+            ServeOptions {
+                watermark: Some(Arc::new(
+                    Watermark::new("Confidential, {user} at {client}", None).unwrap(),
+                )),
+                ..ServeOptions::default()
+            },
+        );
+        let mut photo = std::io::Cursor::new(vec![]);
+        image::RgbImage::from_pixel(64, 64, image::Rgb([200, 40, 40]))
+            .write_to(&mut photo, image::ImageFormat::Png)
+            .unwrap();
+        share.write("gallery/red.png", photo.get_ref());
+        share.write("gallery/notes.txt", "Shot on film");
+        let dana = basic("dana", "hunter2");
+
+        let (status, response) = share.get("/gallery/red.png", &dana);
+        assert_eq!(status, 200);
+        assert!(response.contains("content-type: image/png\r\n"));
+        assert!(response.contains("cache-control: private, no-store\r\n"));
+        assert!(!response.contains("etag:"));
+        assert!(!response.contains("accept-ranges:"));
+        assert!(!response.ends_with(&*String::from_utf8_lossy(photo.get_ref())));
+
+        let (status, text) = share.get("/gallery/notes.txt", &dana);
+        assert_eq!(status, 200);
+        assert!(text.ends_with("Shot on film"));
+        assert!(text.contains("etag:"));
+
+        // An archive would take the images without:
+        assert_eq!(share.get("/gallery/?archive", &dana).0, 404);
+        assert_eq!(share.get("/gallery/missing.png", &dana).0, 404);
+    }
+
+    #[test]
+    fn the_deepest_restricted_folder_decides() {
+        let folder_users = [
+            (PathBuf::from("clients"), vec![String::from("erin")]),
+            (
+                PathBuf::from("clients/acme"),
+                vec![String::from("erin"), String::from("frank")],
+            ),
+            (PathBuf::from("clients/acme/contracts"), vec![]),
+        ];
+        let may_open = |path: &str, user| may_open(&folder_users, Path::new(path), user);
+
+        assert!(may_open("", None));
+        assert!(may_open("clientsarchive/old.zip", None));
+        assert!(may_open("clients/list.csv", Some("erin")));
+        assert!(!may_open("clients/list.csv", Some("frank")));
+        assert!(may_open("clients/acme/logo.png", Some("frank")));
+        assert!(!may_open("clients/acme/logo.png", None));
+        // Nobody may open a folder listed without users:
+        assert!(!may_open("clients/acme/contracts/2026.pdf", Some("erin")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restricted_folders_only_open_to_their_users() {
+        let share = Share::start(
+            "folder-users",
+            &[("alice", "wonderland"), ("bob", "builder")],
+            ServeOptions {
+                folder_users: vec![(PathBuf::from("alice"), vec![String::from("alice")])],
+                ..ServeOptions::default()
+            },
+        );
+        share.write("alice/diary.txt", "Dear diary");
+        share.write("shared/plan.txt", "The plan");
+        std::os::unix::fs::symlink(
+            share.directory.join("site/alice"),
+            share.directory.join("site/shortcut"),
+        )
+        .unwrap();
+        let (alice, bob) = (basic("alice", "wonderland"), basic("bob", "builder"));
+
+        assert_eq!(share.get("/alice/diary.txt", "").0, 401);
+        assert_eq!(share.get("/alice/diary.txt", &basic("bob", "wrong")).0, 401);
+        let (status, diary) = share.get("/alice/diary.txt", &alice);
+        assert_eq!(status, 200);
+        assert!(diary.ends_with("Dear diary"));
+        assert_eq!(share.get("/alice/diary.txt", &bob).0, 403);
+        assert_eq!(share.get("/alice/", &bob).0, 403);
+        // Neither through a link to it nor in an archive of its parent:
+        assert_eq!(share.get("/shortcut/diary.txt", &bob).0, 403);
+        assert_eq!(share.get("/?archive", &bob).0, 403);
+        assert_eq!(share.get("/shared/plan.txt", &bob).0, 200);
+
+        let (_, listing) = share.get("/", &bob);
+        assert!(listing.contains("shared/"));
+        assert!(!listing.contains("alice/"));
+        let (_, listing) = share.get("/", &alice);
+        assert!(listing.contains("alice/"));
+    }
+
+    #[test]
+    fn rewrites_stay_inside_the_share() {
+        assert_eq!(
+            share_path("/guides/install.html?lang=de#linux"),
+            Some(PathBuf::from("guides/install.html"))
+        );
+        assert_eq!(
+            share_path("/caf%C3%A9.html"),
+            Some(PathBuf::from("café.html"))
+        );
+        assert_eq!(share_path("/"), Some(PathBuf::new()));
+        assert_eq!(share_path("/guides/../../.ssh/id_rsa"), None);
+        assert_eq!(share_path("/%2E%2E%2Fsecrets"), None);
+        assert_eq!(share_path("/%C3"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pretty_urls_and_the_redirects_file_lead_to_pages() {
+        let share = Share::start(
+            "pretty-urls",
+            &[],
+            ServeOptions {
+                pretty_urls: true,
+                redirects_file: true,
+                ..ServeOptions::default()
+            },
+        );
+        share.write("about.html", "<p>About us</p>");
+        share.write("docs/index.html", "<p>Docs</p>");
+        share.write("404.html", "<p>Lost?</p>");
+        share.write(
+            "_redirects",
+            "/team /about 302\n/handbook/* /docs/index.html 200\n/* /404.html 404\n/escape /../../etc/passwd 200\n",
+        );
+        let location = |response: &str| {
+            response
+                .lines()
+                .find_map(|line| line.strip_prefix("location: "))
+                .map(String::from)
+        };
+
+        let (status, page) = share.get("/about", "");
+        assert_eq!(status, 200);
+        assert!(page.ends_with("<p>About us</p>"));
+        let (status, response) = share.get("/about.html", "");
+        assert_eq!(
+            (status, location(&response).as_deref()),
+            (301, Some("/about"))
+        );
+        let (status, response) = share.get("/docs/index.html", "");
+        assert_eq!(
+            (status, location(&response).as_deref()),
+            (301, Some("/docs/"))
+        );
+        let (status, response) = share.get("/docs", "");
+        assert_eq!(
+            (status, location(&response).as_deref()),
+            (301, Some("/docs/"))
+        );
+        assert!(share.get("/docs/", "").1.ends_with("<p>Docs</p>"));
+
+        let (status, response) = share.get("/team", "");
+        assert_eq!(
+            (status, location(&response).as_deref()),
+            (302, Some("/about"))
+        );
+        let (status, page) = share.get("/handbook/chapter-1", "");
+        assert_eq!(status, 200);
+        assert!(page.ends_with("<p>Docs</p>"));
+        let (status, page) = share.get("/nothing/here", "");
+        assert_eq!(status, 404);
+        assert!(page.ends_with("<p>Lost?</p>"));
+        // Neither the rules themselves nor files outside of the share are served:
+        assert_eq!(share.get("/_redirects", "").0, 404);
+        let (status, page) = share.get("/escape", "");
+        assert_eq!(status, 404);
+        assert!(!page.contains("root:"));
+    }
+
+    // Rules of the config come first, `_redirects` only gets what they leave:
+    #[cfg(unix)]
+    #[test]
+    fn config_rules_come_before_the_redirects_file() {
+        use crate::redirects::Pattern;
+
+        let mut redirects = Redirects::default();
+        redirects
+            .add(
+                &Pattern::Regex(String::from(r"^/releases/v(\d+)$")),
+                "/downloads/$1/",
+                307,
+                false,
+            )
+            .unwrap();
+        redirects
+            .add(
+                &Pattern::Glob(String::from("/internal/**")),
+                "/denied.html",
+                403,
+                true,
+            )
+            .unwrap();
+        let share = Share::start(
+            "config-redirects",
+            &[],
+            ServeOptions {
+                redirects_file: true,
+                redirects,
+                ..ServeOptions::default()
+            },
+        );
+        share.write("denied.html", "<p>Not for you</p>");
+        share.write("internal/report.txt", "Q3 numbers");
+        share.write("_redirects", "/releases/* /changelog 302\n");
+
+        let (status, response) = share.get("/releases/v3", "");
+        assert_eq!(status, 307);
+        assert!(response.contains("location: /downloads/3/\r\n"));
+        let (status, response) = share.get("/releases/latest", "");
+        assert_eq!(status, 302);
+        assert!(response.contains("location: /changelog\r\n"));
+        // Forced, even over a file that exists:
+        let (status, page) = share.get("/internal/report.txt", "");
+        assert_eq!(status, 403);
+        assert!(page.ends_with("<p>Not for you</p>"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn uploads_land_in_their_folder_only() {
+        let share = Share::start(
+            "uploads",
+            &[],
+            ServeOptions {
+                uploads: Some(Uploads {
+                    max_size: Some(1_000),
+                    folders: vec![PathBuf::from("inbox")],
+                }),
+                ..ServeOptions::default()
+            },
+        );
+        share.write("inbox/README", "Drop files here");
+        let upload = |path: &str, name: &str, contents: &str| {
+            let body = format!(
+                "--XyZ\r\nContent-Disposition: form-data; name=\"files\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n{}\r\n--XyZ--\r\n",
+                name, contents
+            );
+            share.send(&format!(
+                "POST {} HTTP/1.1\r\nHost: share.example.com\r\nConnection: close\r\nContent-Type: multipart/form-data; boundary=XyZ\r\nContent-Length: {}\r\n\r\n{}",
+                path,
+                body.len(),
+                body
+            ))
+        };
+        let inbox = share.directory.join("site/inbox");
+
+        let (status, response) = upload("/inbox/", "report.pdf", "%PDF-1.7 ...");
+        assert_eq!(status, 303);
+        assert!(response.contains("location: .\r\n"));
+        assert_eq!(
+            std::fs::read_to_string(inbox.join("report.pdf")).unwrap(),
+            "%PDF-1.7 ..."
+        );
+        // Only the name of a path counts, nothing is replaced:
+        assert_eq!(upload("/inbox/", "../../escaped.txt", "x").0, 303);
+        assert!(inbox.join("escaped.txt").is_file());
+        assert!(!share.directory.join("escaped.txt").exists());
+        assert_eq!(upload("/inbox/", "README", "Overwritten").0, 409);
+        assert_eq!(
+            std::fs::read_to_string(inbox.join("README")).unwrap(),
+            "Drop files here"
+        );
+
+        assert_eq!(upload("/inbox/", "huge.bin", &"0".repeat(1_001)).0, 413);
+        assert!(!inbox.join("huge.bin").exists());
+        assert_eq!(upload("/", "root.txt", "x").0, 403);
+        assert!(!share.directory.join("site/root.txt").exists());
+    }
+
+    #[test]
+    fn client_address_is_the_hop_the_proxy_added() {
+        let address = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.append(*name, HeaderValue::from_static(value));
+            }
+            client_address(&headers)
+        };
+
+        assert_eq!(address(&[]), "unknown");
+        assert_eq!(
+            address(&[("x-forwarded-for", "203.0.113.7")]),
+            "203.0.113.7"
+        );
+        // What the client sent itself comes first:
+        assert_eq!(
+            address(&[("x-forwarded-for", "10.0.0.1, 192.0.2.1 ,203.0.113.7")]),
+            "203.0.113.7"
+        );
+        assert_eq!(
+            address(&[
+                ("x-forwarded-for", "10.0.0.1"),
+                ("x-forwarded-for", "203.0.113.7"),
+            ]),
+            "203.0.113.7"
+        );
+        assert_eq!(
+            address(&[
+                ("x-real-ip", "10.0.0.1"),
+                ("x-forwarded-for", "203.0.113.7"),
+            ]),
+            "203.0.113.7"
+        );
+        assert_eq!(address(&[("x-real-ip", "198.51.100.4")]), "198.51.100.4");
+    }
+
+    // Per client limits go by the address, which a client can't pick by sending headers:
+    #[cfg(unix)]
+    #[test]
+    fn clients_are_counted_by_the_proxy_hop() {
+        let share = Share::start("clients", &[], ServeOptions::default());
+        for forwarded in ["10.0.0.1, 203.0.113.7", "10.0.0.2, 203.0.113.7"] {
+            let (status, _) = share.send(&format!(
+                "GET /index.html HTTP/1.1\r\nHost: share.example.com\r\nX-Real-IP: 10.9.9.9\r\nX-Forwarded-For: {}\r\nConnection: close\r\n\r\n",
+                forwarded
+            ));
+            assert_eq!(status, 200);
+        }
+        let table = share.server.as_ref().unwrap().shared.stats.table();
+        assert!(table.contains("203.0.113.7"), "{}", table);
+        assert!(!table.contains("10.0.0."), "{}", table);
+        assert!(!table.contains("10.9.9.9"), "{}", table);
+    }
+
+    #[test]
+    fn back_to_stays_on_the_share() {
+        let back = |value: &str| {
+            let form = HashMap::from([(String::from("back"), value.to_string())]);
+            back_to(&form).to_string()
+        };
+
+        assert_eq!(
+            back("/docs/index.html?page=2#top"),
+            "/docs/index.html?page=2#top"
+        );
+        assert_eq!(back("/my%20file.html"), "/my%20file.html");
+        assert_eq!(back_to(&HashMap::new()), "/");
+        for elsewhere in [
+            "https://evil.com/",
+            "//evil.com",
+            "/\\evil.com",
+            "/\t/evil.com",
+            "/\n/evil.com",
+            "/ /evil.com",
+            "/%09/evil.com",
+            "/%0a/evil.com",
+            "/%2F/evil.com",
+            "/%5cevil.com",
+            "evil.com",
+            "",
+        ] {
+            assert_eq!(back(elsewhere), "/", "{:?}", elsewhere);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logging_in_never_redirects_elsewhere() {
+        let share = Share::start(
+            "login-back",
+            &[("alice", "secret")],
+            ServeOptions {
+                sessions: Some(Arc::new(Sessions::new(Duration::from_secs(600)).unwrap())),
+                ..ServeOptions::default()
+            },
+        );
+        // As form fields, `%09` is a tab and `%2509` the text `%09`:
+        for back in ["%2F%09%2Fevil.com", "%2F%2509%2Fevil.com", "%2F%5Cevil.com"] {
+            let (status, response) = share.post_form(
+                LOGIN_PATH,
+                &format!("user=alice&password=secret&back={}", back),
+            );
+            assert_eq!(status, 303);
+            assert!(response.contains("location: /\r\n"), "{}", response);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Signs what the builtin server hands out to browsers (challenges, passes, logins) with a
+/// random key of the share, so it can tell them apart from forged ones without storing them.
+pub struct Signer {
+    // A block of SHA-256, the longest key HMAC uses as it is:
+    key: [u8; 64],
+}
+
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signer").finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    pub fn new() -> Result<Signer, String> {
+        let mut key = [0; 64];
+        getrandom::getrandom(&mut key).map_err(|err| err.to_string())?;
+        Ok(Signer { key })
+    }
+
+    /// `payload` (without a `.` at its end) with its signature appended after a `.`.
+    pub fn sign(&self, payload: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        format!(
+            "{}.{}",
+            payload,
+            URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    /// The payload of a token of [`Signer::sign`], None if it wasn't signed by this.
+    pub fn verify<'a>(&self, token: &'a str) -> Option<&'a str> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).ok()?;
+        Some(payload)
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        <Hmac<Sha256> as Mac>::new(&self.key.into())
+    }
+}
+
+/// Seconds since the epoch, what signed tokens expire by.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether a timestamp of [`now`] is still ahead.
+pub fn unexpired(expires: &str) -> bool {
+    expires.parse::<u64>().is_ok_and(|expires| expires > now())
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::HashSet,
+    env::var_os,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// What `~/.ssh/config` sets for a host alias, as far as livetunnel needs it.
+#[derive(Debug, Default)]
+pub struct SshHost {
+    pub host_name: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    pub identity_file: Option<PathBuf>,
+    pub proxy_jump: Option<Vec<String>>,
+}
+
+impl SshHost {
+    pub fn is_empty(&self) -> bool {
+        self.host_name.is_none()
+            && self.user.is_none()
+            && self.port.is_none()
+            && self.identity_file.is_none()
+            && self.proxy_jump.is_none()
+    }
+}
+
+/// Looks `host` up in the user's OpenSSH config like ssh does: for every keyword the first
+/// value of a matching `Host` block wins. `Match` blocks and `Include`s are not followed.
+pub fn resolve(host: &str) -> Option<SshHost> {
+    let home = home_dir()?;
+    let content = read_to_string(home.join(".ssh").join("config")).ok()?;
+
+    let resolved = parse(&content, host, &home);
+    (!resolved.is_empty()).then_some(resolved)
+}
+
+/// Hosts ssh knows already, to pick from instead of typing one: the aliases of `~/.ssh/config`
+/// without wildcards, then the hosts of `~/.ssh/known_hosts` that aren't hashed, as `host[:port]`.
+pub fn known_hosts() -> Vec<String> {
+    let Some(home) = home_dir() else {
+        return vec![];
+    };
+
+    let mut hosts = vec![];
+    if let Ok(content) = read_to_string(home.join(".ssh").join("config")) {
+        hosts.extend(aliases(&content));
+    }
+    if let Ok(content) = read_to_string(home.join(".ssh").join("known_hosts")) {
+        hosts.extend(content.lines().filter_map(known_host));
+    }
+
+    let mut seen = HashSet::new();
+    hosts.retain(|host| seen.insert(host.to_lowercase()));
+    hosts
+}
+
+fn home_dir() -> Option<PathBuf> {
+    Some(PathBuf::from(
+        var_os("HOME").or_else(|| var_os("USERPROFILE"))?,
+    ))
+}
+
+fn aliases(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let (keyword, value) = line
+                .trim()
+                .split_once(|c: char| c.is_whitespace() || c == '=')?;
+            keyword.eq_ignore_ascii_case("host").then_some(value)
+        })
+        .flat_map(|patterns| patterns.split_whitespace().map(unquote))
+        .filter(|pattern| !pattern.contains(['*', '?', '!']) && !pattern.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// The first name of a line like "host,192.0.2.1 ssh-ed25519 AAAA…" or "[host]:2222 …". Hashed
+// names ("|1|…") can't be read back:
+fn known_host(line: &str) -> Option<String> {
+    let mut fields = line.split_whitespace();
+    let mut names = fields.next()?;
+    // A revoked key is no reason to offer the host:
+    match names {
+        "@revoked" => return None,
+        "@cert-authority" => names = fields.next()?,
+        _ => {}
+    }
+    if names.starts_with(['#', '|']) {
+        return None;
+    }
+
+    let name = names.split(',').next()?;
+    if name.contains(['*', '?', '!']) {
+        return None;
+    }
+    match name
+        .strip_prefix('[')
+        .and_then(|name| name.split_once("]:"))
+    {
+        Some((host, "22")) => Some(String::from(host)),
+        Some((host, port)) => Some(format!("{}:{}", host, port)),
+        None => Some(String::from(name)),
+    }
+}
+
+fn parse(content: &str, host: &str, home: &Path) -> SshHost {
+    let mut resolved = SshHost::default();
+    // Lines before the first `Host` apply to every host:
+    let mut matching = true;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (keyword, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((keyword, value)) => (keyword, value.trim_start_matches([' ', '\t', '=']).trim()),
+            None => continue,
+        };
+
+        match keyword.to_ascii_lowercase().as_str() {
+            "host" => matching = host_matches(value, host),
+            "match" => matching = false,
+            _ if !matching => {}
+            "hostname" => {
+                resolved
+                    .host_name
+                    .get_or_insert_with(|| value.replace("%h", host));
+            }
+            "user" => {
+                resolved.user.get_or_insert_with(|| String::from(value));
+            }
+            "port" if resolved.port.is_none() => resolved.port = value.parse().ok(),
+            "identityfile" => {
+                resolved
+                    .identity_file
+                    .get_or_insert_with(|| expand_home(unquote(value), home));
+            }
+            "proxyjump" if !value.eq_ignore_ascii_case("none") => {
+                resolved
+                    .proxy_jump
+                    .get_or_insert_with(|| value.split(',').map(String::from).collect());
+            }
+            _ => {}
+        }
+    }
+
+    resolved
+}
+
+// `Host` takes several patterns with `*` and `?` wildcards, `!` negates one:
+fn host_matches(patterns: &str, host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split_whitespace().map(unquote) {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, host) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(pattern, host),
+        }
+    }
+    matched
+}
+
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Position after the last `*` in both, to backtrack to:
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches('"')
+}
+
+fn expand_home(path: &str, home: &Path) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None => PathBuf::from(path),
+    }
+}
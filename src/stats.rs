@@ -0,0 +1,234 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use humantime::format_rfc3339_seconds;
+use indicatif::DecimalBytes;
+
+// Clients listed in the summary, the ones with the most requests:
+const TOP_CLIENTS: usize = 10;
+// Requests kept for the request log of the dashboard (--tui):
+const RECENT_REQUESTS: usize = 200;
+// Clients count as connected for so long after their last request:
+const VISITOR_WINDOW: Duration = Duration::from_secs(30);
+
+/// Requests and bytes served during a share, per client. Kept outside the server, so restarts
+/// keep counting.
+#[derive(Debug, Default)]
+pub struct AccessStats {
+    clients: Mutex<HashMap<String, Client>>,
+    // "15:04:05 203.0.113.7 GET /notes.txt 200", the latest last:
+    recent: Mutex<VecDeque<String>>,
+    // Connections cut because the client was too slow or stalled:
+    timeouts: AtomicU64,
+    // Responses still being sent, and when the last one finished or a request came in:
+    open: AtomicU64,
+    active: Mutex<Option<Instant>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Client {
+    requests: u64,
+    bytes: u64,
+    // Responses still being sent to it:
+    open: u64,
+    last_seen: Option<Instant>,
+}
+
+impl AccessStats {
+    pub fn new() -> AccessStats {
+        AccessStats::default()
+    }
+
+    /// Counts a request, `request` as "GET /notes.txt 200".
+    pub fn request(&self, client: &str, request: &str) {
+        {
+            let mut clients = self.clients.lock().unwrap();
+            let entry = clients.entry(client.to_string()).or_default();
+            entry.requests += 1;
+            entry.last_seen = Some(Instant::now());
+        }
+        self.active();
+
+        let time = format_rfc3339_seconds(SystemTime::now()).to_string();
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_REQUESTS {
+            recent.pop_front();
+        }
+        recent.push_back(format!(
+            "{} {} {}",
+            time.get(11..19).unwrap_or(&time),
+            client,
+            request
+        ));
+    }
+
+    /// Marks the share as in use right now, e.g. once it starts.
+    pub fn active(&self) {
+        *self.active.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// A response started being sent to `client`, until [`AccessStats::finished`].
+    pub fn opened(&self, client: &str) {
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(client.to_string())
+            .or_default()
+            .open += 1;
+        self.open.fetch_add(1, Ordering::Relaxed);
+        self.active();
+    }
+
+    pub fn finished(&self, client: &str) {
+        if let Some(entry) = self.clients.lock().unwrap().get_mut(client) {
+            entry.open = entry.open.saturating_sub(1);
+            entry.last_seen = Some(Instant::now());
+        }
+        self.open.fetch_sub(1, Ordering::Relaxed);
+        self.active();
+    }
+
+    /// (clients connected right now, responses being sent). A client counts while a response to
+    /// it is sent and for a moment after its last request, like a browser keeps its connection.
+    pub fn live(&self) -> (usize, u64) {
+        let visitors = self
+            .clients
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|client| {
+                client.open > 0
+                    || client
+                        .last_seen
+                        .is_some_and(|seen| seen.elapsed() < VISITOR_WINDOW)
+            })
+            .count();
+        (visitors, self.open.load(Ordering::Relaxed))
+    }
+
+    /// How long no request came in and no response was sent, zero while one is.
+    pub fn idle_for(&self) -> Duration {
+        if self.open.load(Ordering::Relaxed) > 0 {
+            return Duration::ZERO;
+        }
+        self.active
+            .lock()
+            .unwrap()
+            .map_or(Duration::ZERO, |active| active.elapsed())
+    }
+
+    /// The last requests, the latest last, times in UTC.
+    pub fn recent(&self) -> Vec<String> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn sent(&self, client: &str, bytes: u64) {
+        self.clients
+            .lock()
+            .unwrap()
+            .entry(client.to_string())
+            .or_default()
+            .bytes += bytes;
+    }
+
+    /// Counts a connection cut because the client took too long.
+    pub fn timed_out(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// (requests, unique clients, bytes sent)
+    pub fn totals(&self) -> (u64, usize, u64) {
+        let clients = self.clients.lock().unwrap();
+        let (requests, bytes) = clients.values().fold((0, 0), |(requests, bytes), client| {
+            (requests + client.requests, bytes + client.bytes)
+        });
+        (requests, clients.len(), bytes)
+    }
+
+    /// A table of the clients with the most requests, e.g.
+    /// `  203.0.113.7      12 requests   1.20 MB`.
+    pub fn table(&self) -> String {
+        let mut clients: Vec<(String, Client)> = self
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(address, client)| (address.clone(), *client))
+            .collect();
+        clients.sort_by(|a, b| b.1.requests.cmp(&a.1.requests).then(a.0.cmp(&b.0)));
+
+        let width = clients
+            .iter()
+            .take(TOP_CLIENTS)
+            .map(|(address, _)| address.len())
+            .max()
+            .unwrap_or_default();
+        let mut table: Vec<String> = clients
+            .iter()
+            .take(TOP_CLIENTS)
+            .map(|(address, client)| {
+                format!(
+                    "  {:<width$} {:>6} request(s) {:>10}",
+                    address,
+                    client.requests,
+                    DecimalBytes(client.bytes).to_string(),
+                    width = width
+                )
+            })
+            .collect();
+        if clients.len() > TOP_CLIENTS {
+            table.push(format!("  and {} more", clients.len() - TOP_CLIENTS));
+        }
+        table.join("\n")
+    }
+}
+
+/// A request a server logged, see [`parse_access_line`].
+#[derive(Debug)]
+pub struct LoggedRequest {
+    pub client: String,
+    /// e.g. "GET /notes.txt 200"
+    pub request: String,
+    pub bytes: Option<u64>,
+}
+
+/// Reads a request from the access log line of a server: the client address, what was
+/// requested, and the bytes sent if logged. Fits the Common Log Format
+/// (`127.0.0.1 - - [...] "GET / HTTP/1.1" 200 512`) and miniserve's
+/// (`[...] 127.0.0.1 "GET / HTTP/1.1" 200 512 ...`), the client is the last address before
+/// the request.
+pub fn parse_access_line(line: &str) -> Option<LoggedRequest> {
+    let (before, rest) = line.split_once('"')?;
+    let (request, after) = rest.split_once('"')?;
+    if !request.contains(" HTTP/") {
+        return None;
+    }
+
+    let client = before
+        .split(|c: char| c.is_whitespace() || c == '[' || c == ']')
+        .filter_map(|word| word.parse::<IpAddr>().ok())
+        .next_back()?;
+
+    let mut fields = after.split_whitespace();
+    let status: u16 = fields.next()?.parse().ok()?;
+    let bytes = fields.next().and_then(|bytes| bytes.parse().ok());
+    let request = request
+        .rsplit_once(" HTTP/")
+        .map_or(request, |(request, _)| request);
+    Some(LoggedRequest {
+        client: client.to_string(),
+        request: format!("{} {}", request, status),
+        bytes,
+    })
+}
@@ -0,0 +1,164 @@
+use crate::{
+    app::App,
+    error::{Error, Result},
+    host::parse_host,
+    logging, Cli,
+};
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::sync_channel,
+        Arc,
+    },
+    thread::{spawn, JoinHandle},
+    time::Duration,
+};
+
+use tokio::sync::Notify;
+
+/// A share started from code, like `livetunnel` without arguments: the directory is served
+/// locally and forwarded through SSH until [`Tunnel::stop`] or a limit ends it.
+///
+/// Everything not set on the [`TunnelBuilder`] comes from the stored config, it never prompts
+/// and prints nothing. Events are logged through `tracing`, for the subscriber of the
+/// application.
+pub struct Tunnel {
+    url: Option<String>,
+    should_end: Arc<AtomicBool>,
+    end_notify: Arc<Notify>,
+    thread: JoinHandle<Result<()>>,
+}
+
+/// Settings of a [`Tunnel`] that override the stored config, from [`Tunnel::builder`].
+#[derive(Debug, Default)]
+pub struct TunnelBuilder {
+    cli: Cli,
+    // Reported by `spawn`, the setters can't fail:
+    invalid_host: Option<String>,
+}
+
+impl Tunnel {
+    pub fn builder() -> TunnelBuilder {
+        TunnelBuilder::default()
+    }
+
+    /// The public URL of the share, if the config has a domain or public_url.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// False once sharing ended on its own, e.g. after a duration or when SSH gave up.
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Stops sharing like CTRL+C does and waits until everything is closed.
+    pub fn stop(self) -> Result<()> {
+        self.should_end.store(true, Ordering::SeqCst);
+        self.end_notify.notify_one();
+        self.wait()
+    }
+
+    /// Waits until sharing ends on its own, with the error that ended it.
+    pub fn wait(self) -> Result<()> {
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(Error::Server(String::from("livetunnel panicked"))))
+    }
+}
+
+impl TunnelBuilder {
+    /// SSH host as `[user@]host[:port]`, an alias from `~/.ssh/config` works as well.
+    pub fn host(mut self, host: impl AsRef<str>) -> Self {
+        match parse_host(host.as_ref()) {
+            Ok(host) => self.cli.connection.host = Some(host),
+            Err(err) => self.invalid_host = Some(err),
+        }
+        self
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.cli.connection.user = Some(user.into());
+        self
+    }
+
+    pub fn keyfile(mut self, keyfile: impl Into<PathBuf>) -> Self {
+        self.cli.connection.keyfile = Some(keyfile.into());
+        self
+    }
+
+    pub fn local_port(mut self, port: u16) -> Self {
+        self.cli.connection.local_port = Some(port);
+        self
+    }
+
+    pub fn remote_port(mut self, port: u16) -> Self {
+        self.cli.connection.remote_port = Some(port);
+        self
+    }
+
+    /// The directory, or single file, to share (default: the current directory).
+    pub fn directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.cli.share.directory = Some(directory.into());
+        self
+    }
+
+    /// Requires a login of the configured users, like --secure.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.cli.share.secure = secure;
+        self
+    }
+
+    /// Stops sharing after this long, like --duration.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.cli.share.duration = Some(duration);
+        self
+    }
+
+    /// Stops sharing once this many bytes went through the tunnel, like --transfer-cap.
+    pub fn transfer_cap(mut self, bytes: u64) -> Self {
+        self.cli.share.transfer_cap = Some(bytes);
+        self
+    }
+
+    /// Connects and starts sharing in a thread of its own. Returns once the share is up, or with
+    /// the error that kept it from coming up.
+    pub fn spawn(mut self) -> Result<Tunnel> {
+        if let Some(err) = self.invalid_host {
+            return Err(Error::Config(err));
+        }
+        logging::embed();
+        self.cli.non_interactive = true;
+
+        let should_end = Arc::new(AtomicBool::new(false));
+        let end_notify = Arc::new(Notify::new());
+        let (started, url) = sync_channel(1);
+        let thread = spawn({
+            let (should_end, end_notify) = (should_end.clone(), end_notify.clone());
+            move || {
+                let mut app = App::new(self.cli, should_end, end_notify)?;
+                app.started = Some(started);
+                let result = app.run();
+                app.close();
+                result
+            }
+        });
+
+        match url.recv() {
+            Ok(url) => Ok(Tunnel {
+                url,
+                should_end,
+                end_notify,
+                thread,
+            }),
+            // It ended before the share was up:
+            Err(_) => Err(thread
+                .join()
+                .unwrap_or_else(|_| Err(Error::Server(String::from("livetunnel panicked"))))
+                .err()
+                .unwrap_or_else(|| Error::Server(String::from("livetunnel stopped right away")))),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{process::Command, sync::Mutex};
+
+use serde_json::Value as Json;
+use toml::Value;
+use tracing::debug;
+
+// What was read for each reference during this run, so every one is looked up only once and put
+// back in place of its value when the config is stored:
+static RESOLVED: Mutex<Vec<(String, String)>> = Mutex::new(vec![]);
+
+/// Whether a config value is to be read from a password manager: "op://vault/item/field"
+/// (1Password CLI) or "bw://item/field" (Bitwarden CLI, the field defaults to the password).
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with("op://") || value.starts_with("bw://")
+}
+
+/// What a reference stands for, read from the password manager once per run.
+pub fn resolve(reference: &str) -> Result<String, String> {
+    if let Some(value) = lookup(reference) {
+        return Ok(value);
+    }
+
+    let value = read(reference)?;
+    remember(reference, &value);
+    Ok(value)
+}
+
+/// Reads what a reference stands for from the CLI of the password manager, every time.
+pub fn read(reference: &str) -> Result<String, String> {
+    debug!("Reading '{}' from the password manager", reference);
+    match reference.strip_prefix("bw://") {
+        Some(path) => bitwarden(path),
+        None => run("op", &["read", "--no-newline", reference]),
+    }
+}
+
+/// The value a reference was resolved to (or [`remember`]ed as) during this run.
+pub fn lookup(reference: &str) -> Option<String> {
+    RESOLVED
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(known, _)| known == reference)
+        .map(|(_, value)| value.clone())
+}
+
+/// Reads every reference in a parsed config, in place.
+pub fn resolve_all(config: &mut Value) -> Result<(), String> {
+    match config {
+        Value::String(value) if is_reference(value) => {
+            *value = resolve(value).map_err(|err| format!("'{}': {}", value, err))?;
+        }
+        Value::Array(values) => {
+            for value in values {
+                resolve_all(value)?;
+            }
+        }
+        Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                resolve_all(value)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Puts `value` back as `reference` when the config is stored, for one derived from what was
+/// read, e.g. the hash of a password.
+pub fn remember(reference: &str, value: &str) {
+    let mut resolved = RESOLVED.lock().unwrap();
+    resolved.retain(|(known, _)| known != reference);
+    resolved.push((String::from(reference), String::from(value)));
+}
+
+/// Whether a config value was read from a password manager, it's not to be stored elsewhere.
+pub fn was_resolved(value: &str) -> bool {
+    RESOLVED
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|(_, resolved)| resolved == value)
+}
+
+/// Puts the references back in place of what was read for them, before the config is stored.
+pub fn restore(config: &mut Value) {
+    match config {
+        Value::String(value) => {
+            if let Some((reference, _)) = RESOLVED
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(_, resolved)| resolved == value)
+            {
+                *value = reference.clone();
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(restore),
+        Value::Table(table) => table.iter_mut().for_each(|(_, value)| restore(value)),
+        _ => {}
+    }
+}
+
+// "item" or "item/field", the item by name or ID. password, username, totp and notes are built
+// in, anything else is a custom field:
+fn bitwarden(path: &str) -> Result<String, String> {
+    let (item, field) = path.split_once('/').unwrap_or((path, "password"));
+    if ["password", "username", "totp", "notes"].contains(&field) {
+        return run("bw", &["get", field, item]);
+    }
+
+    let item: Json = serde_json::from_str(&run("bw", &["get", "item", item])?)
+        .map_err(|err| format!("bw returned no item: {}", err))?;
+    item["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|custom| custom["name"] == field)
+        .and_then(|custom| custom["value"].as_str())
+        .map(String::from)
+        .ok_or_else(|| format!("the item has no field '{}'", field))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(program).args(args).output().map_err(|err| {
+        format!(
+            "could not run {} ({}), is its CLI installed and in the PATH?",
+            program, err
+        )
+    })?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(match program {
+            "bw" if error.contains("locked") || error.contains("not logged in") => String::from(
+                "Bitwarden is locked, run `bw unlock` and export the BW_SESSION it prints",
+            ),
+            _ => format!("{} failed: {}", program, error.trim()),
+        });
+    }
+
+    let value = String::from_utf8_lossy(&output.stdout);
+    Ok(String::from(value.strip_suffix('\n').unwrap_or(&value)))
+}
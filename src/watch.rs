@@ -0,0 +1,119 @@
+use crate::logging;
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::mpsc::{channel, RecvTimeoutError},
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use indicatif::ProgressBar;
+use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+/// Runs a build command whenever something below the watched paths changes, so the share always
+/// serves fresh output, and tells about it (live reload). Watching stops when it is dropped.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+}
+
+impl Watcher {
+    /// Watches `paths` (relative to `directory`) recursively and runs `command` through the shell
+    /// in `directory` once no change came in for `debounce`. `pb` shows how the last build went.
+    /// `changed` is called after every successful build, or every change without a `command`.
+    pub fn start(
+        directory: &Path,
+        paths: &[PathBuf],
+        debounce: Duration,
+        command: Option<String>,
+        pb: ProgressBar,
+        changed: impl Fn() + Send + 'static,
+    ) -> notify::Result<Watcher> {
+        let (sender, changes) = channel();
+        let mut watcher = recommended_watcher(move |event: notify::Result<Event>| {
+            if event.is_ok_and(|event| !event.kind.is_access()) {
+                let _ = sender.send(());
+            }
+        })?;
+        for path in paths {
+            watcher.watch(&directory.join(path), RecursiveMode::Recursive)?;
+        }
+
+        let directory = directory.to_path_buf();
+        // Ends with the watcher, which drops the sender:
+        spawn(move || {
+            while changes.recv().is_ok() {
+                // Editors and checkouts touch many files at once, wait until it settles:
+                loop {
+                    match changes.recv_timeout(debounce) {
+                        Ok(()) => {}
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+
+                let Some(command) = &command else {
+                    pb.set_message("Change detected, watching for more");
+                    changed();
+                    continue;
+                };
+
+                pb.set_message(format!("Change detected, running '{}'", command));
+                let started = Instant::now();
+                let result = shell(command).current_dir(&directory).output();
+                if let Ok(output) = &result {
+                    logging::command_output(command, output);
+                }
+                match result {
+                    Ok(output) if output.status.success() => {
+                        pb.set_message(format!(
+                            "Rebuilt with '{}' in {:.1}s, watching for changes",
+                            command,
+                            started.elapsed().as_secs_f64()
+                        ));
+                        changed();
+                    }
+                    Ok(output) => {
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+                        pb.set_message(format!(
+                            "'{}' failed ({}){}, watching for changes",
+                            command,
+                            output.status,
+                            stderr
+                                .lines()
+                                .rfind(|line| !line.trim().is_empty())
+                                .map(|line| format!(": {}", line.trim()))
+                                .unwrap_or_default()
+                        ));
+                    }
+                    Err(err) => pb.set_message(format!(
+                        "Could not run '{}': {}, watching for changes",
+                        command, err
+                    )),
+                }
+
+                // What the build wrote itself is no change to react to:
+                sleep(debounce);
+                while changes.try_recv().is_ok() {}
+            }
+        });
+
+        Ok(Watcher { _watcher: watcher })
+    }
+}
+
+/// Runs `command` through the shell of the platform.
+pub fn shell(command: &str) -> Command {
+    #[cfg(windows)]
+    {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    }
+    #[cfg(not(windows))]
+    {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
@@ -0,0 +1,438 @@
+use std::{fs::read, io::Cursor, path::Path};
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{DynamicImage, ImageFormat, Rgba};
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Dictionary, Document, Object, ObjectId,
+};
+
+// Tried in turn for images without a font set, the usual places on Linux and macOS:
+const DEFAULT_FONTS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/usr/share/fonts/liberation/LiberationSans-Regular.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+    "/Library/Fonts/Arial.ttf",
+];
+// How much of the image shows through the text:
+const OPACITY: f32 = 0.35;
+// Slant of the text lines on PDF pages, in degrees:
+const PDF_ANGLE: f32 = 30.0;
+
+/// The files that are stamped, by their extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Png,
+    Jpeg,
+    Pdf,
+}
+
+impl Kind {
+    pub fn of(path: &Path) -> Option<Kind> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Kind::Png),
+            "jpg" | "jpeg" => Some(Kind::Jpeg),
+            "pdf" => Some(Kind::Pdf),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Kind::Png => "image/png",
+            Kind::Jpeg => "image/jpeg",
+            Kind::Pdf => "application/pdf",
+        }
+    }
+}
+
+/// Text stamped all over served images and PDF pages, to trace where a leaked file came from.
+/// `{user}` (who logged in), `{client}` (the address of the visitor) and `{date}` are filled in
+/// per request.
+pub struct Watermark {
+    template: String,
+    // Only images need one, PDFs use Helvetica of the viewer:
+    font: FontVec,
+}
+
+impl std::fmt::Debug for Watermark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Watermark")
+            .field("template", &self.template)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Watermark {
+    /// `font` is a TrueType or OpenType file for the text on images, a common system font
+    /// without.
+    pub fn new(template: &str, font: Option<&Path>) -> Result<Watermark, String> {
+        let (path, data) = match font {
+            Some(path) => (
+                path.to_path_buf(),
+                read(path).map_err(|err| format!("'{}': {}", path.display(), err))?,
+            ),
+            None => DEFAULT_FONTS
+                .iter()
+                .find_map(|path| Some((Path::new(path).to_path_buf(), read(path).ok()?)))
+                .ok_or_else(|| String::from("no font found for the text, set font"))?,
+        };
+        let font = FontVec::try_from_vec(data)
+            .map_err(|_| format!("'{}' is no TrueType or OpenType font", path.display()))?;
+
+        Ok(Watermark {
+            template: template.to_string(),
+            font,
+        })
+    }
+
+    /// The text for a request, "anonymous" without a login.
+    pub fn text(&self, user: Option<&str>, client: &str, date: &str) -> String {
+        self.template
+            .replace("{user}", user.unwrap_or("anonymous"))
+            .replace("{client}", client)
+            .replace("{date}", date)
+    }
+
+    /// `data` of a file of `kind` with `text` stamped on it, in the same format.
+    pub fn apply(&self, kind: Kind, data: &[u8], text: &str) -> Result<Vec<u8>, String> {
+        match kind {
+            Kind::Png | Kind::Jpeg => self.stamp_image(data, text),
+            Kind::Pdf => stamp_pdf(data, text).map_err(|err| err.to_string()),
+        }
+    }
+
+    // Rows of the text across the whole image, every other one shifted by half its length:
+    fn stamp_image(&self, data: &[u8], text: &str) -> Result<Vec<u8>, String> {
+        let format = image::guess_format(data).map_err(|err| err.to_string())?;
+        let mut image = image::load_from_memory_with_format(data, format)
+            .map_err(|err| err.to_string())?
+            .to_rgba8();
+        let (width, height) = image.dimensions();
+
+        let scale = PxScale::from((width.min(height) as f32 / 24.0).max(12.0));
+        let font = self.font.as_scaled(scale);
+        let mut glyphs = vec![];
+        let mut caret = 0.0;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            glyphs.push((id, caret));
+            caret += font.h_advance(id);
+        }
+        let step = caret + scale.x * 2.0;
+        let line_height = font.height() * 3.0;
+
+        let mut y = font.ascent();
+        let mut row = 0;
+        while y < height as f32 + line_height {
+            let mut x = if row % 2 == 0 { 0.0 } else { -step / 2.0 };
+            while x < width as f32 {
+                for (id, offset) in &glyphs {
+                    let glyph = id.with_scale_and_position(scale, point(x + offset, y));
+                    let Some(outlined) = self.font.outline_glyph(glyph) else {
+                        continue;
+                    };
+                    let bounds = outlined.px_bounds();
+                    // A dark shadow first, so the text shows on light and dark parts alike:
+                    for (color, shift) in [([0, 0, 0], 1), ([255, 255, 255], 0)] {
+                        outlined.draw(|gx, gy, coverage| {
+                            let px = bounds.min.x as i64 + gx as i64 + shift;
+                            let py = bounds.min.y as i64 + gy as i64 + shift;
+                            if px >= 0 && py >= 0 && px < width as i64 && py < height as i64 {
+                                blend(
+                                    image.get_pixel_mut(px as u32, py as u32),
+                                    color,
+                                    coverage * OPACITY,
+                                );
+                            }
+                        });
+                    }
+                }
+                x += step;
+            }
+            y += line_height;
+            row += 1;
+        }
+
+        let mut stamped = Cursor::new(vec![]);
+        match format {
+            ImageFormat::Png => image.write_to(&mut stamped, ImageFormat::Png),
+            ImageFormat::Jpeg => DynamicImage::ImageRgba8(image)
+                .to_rgb8()
+                .write_to(&mut stamped, ImageFormat::Jpeg),
+            format => return Err(format!("{:?} images can't be stamped", format)),
+        }
+        .map_err(|err| err.to_string())?;
+        Ok(stamped.into_inner())
+    }
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: [u8; 3], alpha: f32) {
+    for (channel, color) in pixel.0.iter_mut().zip(color) {
+        *channel = (*channel as f32 * (1.0 - alpha) + color as f32 * alpha).round() as u8;
+    }
+}
+
+// Slanted rows of the text on every page, in Helvetica (every viewer has it) and gray. The
+// page's own content is wrapped in q/Q, so whatever state it leaves doesn't move the text:
+fn stamp_pdf(data: &[u8], text: &str) -> lopdf::Result<Vec<u8>> {
+    let mut document = Document::load_mem(data)?;
+    let font = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let state = document.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => OPACITY,
+    });
+    // Helvetica has no glyphs beyond Latin-1:
+    let text: Vec<u8> = text
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect();
+
+    for page in document.get_pages().into_values() {
+        let [left, bottom, right, top] = media_box(&document, page);
+        let size = ((right - left) / 30.0).max(8.0);
+        // Helvetica is about half as wide as high:
+        let step = text.len() as f32 * size * 0.55 + size * 3.0;
+        let (sin, cos) = PDF_ANGLE.to_radians().sin_cos();
+
+        let mut operations = vec![
+            Operation::new("Q", vec![]),
+            Operation::new("q", vec![]),
+            Operation::new("gs", vec![Object::Name(b"LtWmState".to_vec())]),
+            Operation::new("g", vec![0.5.into()]),
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec![Object::Name(b"LtWmFont".to_vec()), size.into()]),
+        ];
+        // Rows from below the page to above it, so the slanted ones cover all of it:
+        let diagonal = (right - left) + (top - bottom);
+        let mut row = 0;
+        let mut y = bottom - diagonal;
+        while y < top + diagonal {
+            let mut x = left - diagonal + if row % 2 == 0 { 0.0 } else { step / 2.0 };
+            while x < right + diagonal {
+                operations.push(Operation::new(
+                    "Tm",
+                    vec![
+                        cos.into(),
+                        sin.into(),
+                        (-sin).into(),
+                        cos.into(),
+                        x.into(),
+                        y.into(),
+                    ],
+                ));
+                operations.push(Operation::new(
+                    "Tj",
+                    vec![Object::string_literal(text.clone())],
+                ));
+                x += step;
+            }
+            y += size * 4.0;
+            row += 1;
+        }
+        operations.push(Operation::new("ET", vec![]));
+        operations.push(Operation::new("Q", vec![]));
+
+        let mut resources = inherited(&document, page, b"Resources")
+            .and_then(|resources| resolve(&document, resources))
+            .unwrap_or_default();
+        add_resource(&document, &mut resources, b"Font", "LtWmFont", font);
+        add_resource(&document, &mut resources, b"ExtGState", "LtWmState", state);
+
+        let opening = document.add_object(lopdf::Stream::new(Dictionary::new(), b"q".to_vec()));
+        let stamp = document.add_object(lopdf::Stream::new(
+            Dictionary::new(),
+            Content { operations }.encode()?,
+        ));
+        let page = document
+            .get_object_mut(page)
+            .and_then(Object::as_dict_mut)?;
+        let mut contents = match page.get(b"Contents") {
+            Ok(Object::Array(contents)) => contents.clone(),
+            Ok(contents) => vec![contents.clone()],
+            Err(_) => vec![],
+        };
+        contents.insert(0, Object::Reference(opening));
+        contents.push(Object::Reference(stamp));
+        page.set("Contents", contents);
+        page.set("Resources", resources);
+    }
+
+    let mut stamped = vec![];
+    document.save_to(&mut stamped)?;
+    Ok(stamped)
+}
+
+// An entry of a page, or of the closest of its parents that has it (Resources, MediaBox):
+fn inherited<'a>(document: &'a Document, page: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = document.get_dictionary(page).ok()?;
+    // Deep enough for any real page tree, and ends a cyclic one:
+    for _ in 0..64 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+fn resolve(document: &Document, object: &Object) -> Option<Dictionary> {
+    match object {
+        Object::Dictionary(dictionary) => Some(dictionary.clone()),
+        Object::Reference(id) => document.get_dictionary(*id).ok().cloned(),
+        _ => None,
+    }
+}
+
+// [left, bottom, right, top] of a page, A4 without a valid MediaBox:
+fn media_box(document: &Document, page: ObjectId) -> [f32; 4] {
+    let numbers: Vec<f32> = inherited(document, page, b"MediaBox")
+        .and_then(|media_box| match media_box {
+            Object::Reference(id) => document.get_object(*id).ok(),
+            media_box => Some(media_box),
+        })
+        .and_then(|media_box| media_box.as_array().ok())
+        .map(|numbers| {
+            numbers
+                .iter()
+                .filter_map(|number| number.as_float().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    match numbers[..] {
+        [left, bottom, right, top] => [left, bottom, right, top],
+        _ => [0.0, 0.0, 595.0, 842.0],
+    }
+}
+
+// Adds `name` for `id` to a kind of resource, e.g. the fonts, keeping the page's own:
+fn add_resource(
+    document: &Document,
+    resources: &mut Dictionary,
+    kind: &[u8],
+    name: &str,
+    id: ObjectId,
+) {
+    let mut entries = resources
+        .get(kind)
+        .ok()
+        .and_then(|entries| resolve(document, entries))
+        .unwrap_or_default();
+    entries.set(name, Object::Reference(id));
+    resources.set(kind, entries);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, RgbaImage};
+
+    // One A5 page that says "Invoice 42" in its own font:
+    fn invoice() -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages = document.new_object_id();
+        let font = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                Operation::new("Td", vec![40.into(), 500.into()]),
+                Operation::new("Tj", vec![Object::string_literal("Invoice 42")]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content = document.add_object(lopdf::Stream::new(
+            Dictionary::new(),
+            content.encode().unwrap(),
+        ));
+        let page = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages,
+            "Contents" => content,
+        });
+        document.objects.insert(
+            pages,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page.into()],
+                "Count" => 1,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font } },
+                "MediaBox" => vec![0.into(), 0.into(), 420.into(), 595.into()],
+            }),
+        );
+        let catalog = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages });
+        document.trailer.set("Root", catalog);
+        let mut data = vec![];
+        document.save_to(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn kinds_go_by_the_extension() {
+        assert_eq!(Kind::of(Path::new("scan.PDF")), Some(Kind::Pdf));
+        assert_eq!(Kind::of(Path::new("photos/beach.jpeg")), Some(Kind::Jpeg));
+        assert_eq!(Kind::of(Path::new("photos/beach.jpg")), Some(Kind::Jpeg));
+        assert_eq!(Kind::of(Path::new("logo.png")), Some(Kind::Png));
+        assert_eq!(Kind::of(Path::new("logo.svg")), None);
+        assert_eq!(Kind::of(Path::new("png")), None);
+    }
+
+    #[test]
+    fn pdf_pages_keep_their_content_and_resources() {
+        let stamped = stamp_pdf(&invoice(), "Leaked by bob · 203.0.113.7").unwrap();
+
+        let document = Document::load_mem(&stamped).unwrap();
+        let (_, page) = document.get_pages().into_iter().next().unwrap();
+        let content = document.get_page_content(page).unwrap();
+        let has = |part: &[u8]| content.windows(part.len()).any(|window| window == part);
+        assert!(has(b"(Invoice 42) Tj"));
+        // In Latin-1 for Helvetica, where the middle dot is 0xB7:
+        assert!(has(b"(Leaked by bob \xb7 203.0.113.7) Tj"));
+        // The page's own content can't change how the stamp is drawn:
+        assert!(content.starts_with(b"q") && content.trim_ascii_end().ends_with(b"Q"));
+
+        // The inherited font of the page is still there, next to the one of the stamp:
+        let fonts = document.get_page_fonts(page).unwrap();
+        assert!(fonts.contains_key(b"F1".as_slice()));
+        assert!(fonts.contains_key(b"LtWmFont".as_slice()));
+    }
+
+    #[test]
+    fn images_keep_their_format_and_size() {
+        let watermark = Watermark::new("{user} {client} {date}", None).unwrap();
+        let photo = RgbaImage::from_pixel(320, 200, Rgba([30, 120, 200, 255]));
+        let mut png = Cursor::new(vec![]);
+        photo.write_to(&mut png, ImageFormat::Png).unwrap();
+
+        let text = watermark.text(Some("carol"), "198.51.100.4", "2026-10-16");
+        assert_eq!(text, "carol 198.51.100.4 2026-10-16");
+        let stamped = watermark.apply(Kind::Png, png.get_ref(), &text).unwrap();
+
+        assert_eq!(image::guess_format(&stamped).unwrap(), ImageFormat::Png);
+        let stamped = image::load_from_memory(&stamped).unwrap();
+        assert_eq!(stamped.dimensions(), (320, 200));
+        assert!(stamped
+            .to_rgba8()
+            .pixels()
+            .any(|pixel| *pixel != photo[(0, 0)]));
+
+        assert!(watermark
+            .apply(Kind::Jpeg, b"not a picture", &text)
+            .is_err());
+        assert_eq!(watermark.text(None, "::1", "today"), "anonymous ::1 today");
+    }
+}
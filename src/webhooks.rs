@@ -0,0 +1,112 @@
+use std::{
+    sync::Mutex,
+    thread::{spawn, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::debug;
+
+/// Where the events of a share are posted, the `[notifications]` section of the config.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    // Slack and Discord webhooks get a chat message, any other URL the event as JSON, e.g.
+    // {"event": "started", "message": "...", "url": "https://..."}:
+    pub webhooks: Vec<String>,
+    // Only these events (default: all): "started", "reconnected", "server_crashed", "shutdown":
+    pub events: Option<Vec<String>>,
+}
+
+/// Posts share events to the webhooks of the `[notifications]` section, in the background so a
+/// slow endpoint never holds up the share.
+pub struct Notifier {
+    config: NotificationConfig,
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> Notifier {
+        Notifier {
+            config,
+            pending: Mutex::new(vec![]),
+        }
+    }
+
+    /// Sends `event` (one of the names above) with a `message` for chats and `fields` for
+    /// everything else, e.g. `json!({ "url": url })`.
+    pub fn send(&self, event: &'static str, message: String, fields: Value) {
+        if self
+            .config
+            .events
+            .as_ref()
+            .is_some_and(|events| !events.iter().any(|wanted| wanted == event))
+        {
+            return;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|handle| !handle.is_finished());
+        for url in &self.config.webhooks {
+            let body = match Format::of(url) {
+                Format::Slack => json!({ "text": message }),
+                Format::Discord => json!({ "content": message }),
+                Format::Json => {
+                    let mut body = json!({ "event": event, "message": message });
+                    if let (Some(body), Value::Object(fields)) = (body.as_object_mut(), &fields) {
+                        body.extend(fields.clone());
+                    }
+                    body
+                }
+            };
+
+            let url = url.clone();
+            pending.push(spawn(move || {
+                let result = ureq::post(&url)
+                    .config()
+                    .timeout_global(Some(Duration::from_secs(10)))
+                    .build()
+                    .content_type("application/json")
+                    .send(body.to_string());
+                if let Err(err) = result {
+                    debug!(event, "Could not notify '{}': {}", url, err);
+                }
+            }));
+        }
+    }
+
+    /// Waits for what is still being sent, before livetunnel exits.
+    pub fn finish(&self) {
+        for handle in self.pending.lock().unwrap().drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+// What a webhook expects, told by its URL:
+enum Format {
+    Slack,
+    Discord,
+    Json,
+}
+
+impl Format {
+    fn of(url: &str) -> Format {
+        let host = url
+            .split("://")
+            .nth(1)
+            .unwrap_or(url)
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default();
+        if host == "hooks.slack.com" {
+            Format::Slack
+        } else if (host.ends_with("discord.com") || host.ends_with("discordapp.com"))
+            && url.contains("/api/webhooks/")
+        {
+            Format::Discord
+        } else {
+            Format::Json
+        }
+    }
+}